@@ -0,0 +1,229 @@
+//! A/A testing: every run is executed twice with the same configuration, and the second
+//! results are compared with the first using the regular regression policy.
+//!
+//! Since nothing changes between the two executions, every detected regression is a false
+//! positive, so the false-positive rate validates the regression margins, the stability
+//! of the machine, and the comparison itself. Collections must already be built.
+
+use crate::config::Config;
+use crate::error::Error;
+use crate::executor::Executor;
+use crate::identity::BaselineMeta;
+use crate::run::{compare_with_baseline, process_run, RunStatus};
+use crate::temp::{run_id, RunTempDir};
+use failure::ResultExt;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory within the work dir where the results of first executions are stored.
+pub const AA_DIR: &str = "aa";
+
+/// Outcome of A/A comparisons of a single run.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AaRunResult {
+    /// Basename of the output files of the run.
+    pub output: String,
+    /// Number of compared pairs of executions.
+    pub pairs: usize,
+    /// Number of pairs in which any regression was detected.
+    pub flagged: usize,
+    /// Total number of detected regressions.
+    pub regressions: usize,
+}
+
+/// Outcome of A/A comparisons of all runs.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AaReport {
+    /// Runs, in the order of the config.
+    pub runs: Vec<AaRunResult>,
+}
+
+impl AaReport {
+    /// Fraction of compared pairs in which a regression was detected,
+    /// or 0 if nothing was compared.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::aa::{AaReport, AaRunResult};
+    /// let run = |pairs, flagged| AaRunResult { pairs, flagged, ..AaRunResult::default() };
+    /// let report = AaReport { runs: vec![run(3, 1), run(1, 0)] };
+    /// assert!((report.false_positive_rate() - 0.25).abs() < 1e-9);
+    /// assert_eq!(AaReport::default().false_positive_rate(), 0.0);
+    /// ```
+    #[allow(clippy::cast_precision_loss)]
+    pub fn false_positive_rate(&self) -> f64 {
+        let pairs: usize = self.runs.iter().map(|run| run.pairs).sum();
+        let flagged: usize = self.runs.iter().map(|run| run.flagged).sum();
+        if pairs == 0 {
+            0.0
+        } else {
+            flagged as f64 / pairs as f64
+        }
+    }
+
+    /// Renders the report as a Markdown table, followed by the false-positive rate.
+    pub fn render_markdown(&self) -> String {
+        let mut lines = vec![
+            String::from("| run | pairs | flagged | regressions |"),
+            String::from("|---|---|---|---|"),
+        ];
+        for run in &self.runs {
+            lines.push(format!(
+                "| {} | {} | {} | {} |",
+                run.output, run.pairs, run.flagged, run.regressions
+            ));
+        }
+        lines.push(String::new());
+        lines.push(format!(
+            "False-positive rate: {:.1}%",
+            self.false_positive_rate() * 100.0
+        ));
+        lines.push(String::new());
+        lines.join("\n")
+    }
+}
+
+/// Copies all output files of a run, i.e., those named `<output>.*`,
+/// to files with the same suffixes after `prefix`.
+fn copy_outputs(output: &Path, prefix: &Path) -> Result<(), Error> {
+    let dir = output.parent().unwrap_or_else(|| Path::new("."));
+    let name = format!(
+        "{}.",
+        output.file_name().unwrap_or_default().to_string_lossy()
+    );
+    for entry in fs::read_dir(dir).with_context(|_| dir.display().to_string())? {
+        let path = entry?.path();
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        if let Some(suffix) = file_name.strip_prefix(&name) {
+            if path.is_file() {
+                fs::copy(&path, format!("{}.{}", prefix.display(), suffix))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prefix of the results of first executions of the run `run_id`.
+pub fn first_results_prefix(workdir: &Path, run_id: &str) -> PathBuf {
+    workdir.join(AA_DIR).join(run_id).join("a")
+}
+
+/// Executes each run `2 * pairs` times, comparing each even execution with the preceding one.
+///
+/// The results of the last execution are left in the output files of the run,
+/// as in a regular session. Runs of undefined collections are skipped.
+pub fn aa_test<C: Config>(
+    executor: &Executor,
+    config: &C,
+    pairs: usize,
+) -> Result<AaReport, Error> {
+    let mut report = AaReport::default();
+    for (idx, run) in config.runs().iter().enumerate() {
+        let collection = match config
+            .collections()
+            .iter()
+            .find(|collection| collection.name == run.collection)
+        {
+            Some(collection) => collection,
+            None => continue,
+        };
+        let run_id = run_id(idx, run);
+        let prefix = first_results_prefix(config.workdir(), &run_id);
+        fs::create_dir_all(prefix.parent().unwrap())?;
+        let mut result = AaRunResult {
+            output: run.output.display().to_string(),
+            ..AaRunResult::default()
+        };
+        for pair in 0..pairs {
+            for execution in &["A", "B"] {
+                info!(
+                    "A/A test of run {}: pair {}, {}",
+                    run_id,
+                    pair + 1,
+                    execution
+                );
+                let temp_dir = RunTempDir::create(config.workdir(), &run_id)?;
+                let processed = process_run(
+                    executor,
+                    run,
+                    collection,
+                    config.use_scorer(),
+                    temp_dir.path(),
+                );
+                temp_dir.finish(processed.is_err(), config.keep_temp())?;
+                processed?;
+                if *execution == "A" {
+                    copy_outputs(&run.output, &prefix)?;
+                    BaselineMeta::new(run, None, config.margin())?.write(&prefix)?;
+                }
+            }
+            result.pairs += 1;
+            if let RunStatus::Regression(count) =
+                compare_with_baseline(run, &prefix, config.margin())?
+            {
+                result.flagged += 1;
+                result.regressions += count;
+            }
+        }
+        report.runs.push(result);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RunKind;
+    use crate::tests::{mock_set_up, MockSetup};
+    use crate::{FakeBackend, RegressionMargin};
+    use std::sync::Arc;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_copy_outputs() -> Result<(), Error> {
+        let tmp = TempDir::new("aa").unwrap();
+        let output = tmp.path().join("out");
+        fs::write(tmp.path().join("out.wand.ef.0.bench"), "bench")?;
+        fs::write(tmp.path().join("out.wand.ef.0.bench.class.1"), "class")?;
+        fs::write(tmp.path().join("other.wand.ef.0.bench"), "other")?;
+        let prefix = first_results_prefix(tmp.path(), "0-out");
+        fs::create_dir_all(prefix.parent().unwrap())?;
+        copy_outputs(&output, &prefix)?;
+        let dir = prefix.parent().unwrap();
+        assert_eq!(fs::read_to_string(dir.join("a.wand.ef.0.bench"))?, "bench");
+        assert_eq!(
+            fs::read_to_string(dir.join("a.wand.ef.0.bench.class.1"))?,
+            "class"
+        );
+        assert!(!dir.join("a.other.wand.ef.0.bench").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_aa_test() -> Result<(), Error> {
+        let tmp = TempDir::new("aa").unwrap();
+        let MockSetup { mut config, .. } = mock_set_up(&tmp);
+        let backend = FakeBackend::new().with_output(
+            "queries",
+            r#"{"type":"block_simdbp","query":"wand","avg":1,"q50":1,"q90":1,"q95":1}"#,
+        );
+        let executor = Executor::new().with_backend(Arc::new(backend));
+        config
+            .0
+            .runs
+            .retain(|run| run.kind == RunKind::Benchmark && run.collection == "wapo");
+        // Wall-clock throughput of fake commands is only noise.
+        config.0.runs[0].qps_margin = Some(RegressionMargin(1.0));
+        let report = aa_test(&executor, &config, 2)?;
+        assert_eq!(report.runs.len(), 1);
+        assert_eq!(report.runs[0].pairs, 2);
+        assert_eq!(report.runs[0].flagged, 0);
+        assert!(report
+            .render_markdown()
+            .ends_with("\nFalse-positive rate: 0.0%\n"));
+        Ok(())
+    }
+}
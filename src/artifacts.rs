@@ -0,0 +1,381 @@
+//! Inventory of the files produced in a work dir, stored in `artifacts.json`.
+//!
+//! Each entry records the kind of a file, the collection and run (if any) it belongs to,
+//! the stage that produced it, as well as its size and content hash.
+//! Build stages record the indexes of each built collection, and runs their output files,
+//! so that summaries, reports, merging, garbage collection, and remote builds all operate
+//! over the same inventory instead of searching the work dir for files.
+//!
+//! Paths within the work dir are stored relative to it, so that the inventory stays valid
+//! when the work dir is copied or moved, e.g., to merge results of multiple hosts.
+
+use crate::config::{Collection, Run, Stage};
+use crate::error::Error;
+use crate::gc::derived_from;
+use crate::run::output_files;
+use crate::share::file_hash;
+use failure::ResultExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::UNIX_EPOCH;
+
+/// File within the work dir listing all produced artifacts.
+pub const ARTIFACTS_FILE: &str = "artifacts.json";
+
+/// Type of a produced file.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    /// Forward index, or one of its document and term lists.
+    ForwardIndex,
+    /// Binary document or term lexicon.
+    Lexicon,
+    /// Uncompressed inverted index.
+    InvertedIndex,
+    /// Inverted index compressed with one of the encodings.
+    CompressedIndex,
+    /// WAND metadata.
+    WandData,
+    /// Results of a benchmark.
+    BenchmarkResults,
+    /// Ranked results of an evaluation.
+    RunResults,
+    /// Measures computed by `trec_eval`, including weighted ones.
+    Evaluation,
+    /// Any other output of a run, e.g., extracted features or query times.
+    RunOutput,
+}
+
+impl ArtifactKind {
+    /// Kind of an output file of a run, determined by its suffix.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::artifacts::ArtifactKind;
+    /// # use std::path::Path;
+    /// let kind = |path: &str| ArtifactKind::of_run_output(Path::new(path));
+    /// assert_eq!(kind("wapo.wand.ef.0.bench"), ArtifactKind::BenchmarkResults);
+    /// assert_eq!(kind("wapo.wand.ef.0.trec_eval.weighted"), ArtifactKind::Evaluation);
+    /// assert_eq!(kind("wapo.wand.ef.0.results"), ArtifactKind::RunResults);
+    /// assert_eq!(kind("wapo.wand.ef.0.features.csv"), ArtifactKind::RunOutput);
+    /// ```
+    pub fn of_run_output(path: &Path) -> Self {
+        let name = path.to_string_lossy();
+        if name.ends_with(".bench") {
+            Self::BenchmarkResults
+        } else if name.ends_with(".results") {
+            Self::RunResults
+        } else if name.ends_with(".trec_eval") || name.ends_with(".trec_eval.weighted") {
+            Self::Evaluation
+        } else {
+            Self::RunOutput
+        }
+    }
+}
+
+/// Single produced file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    /// Type of the file.
+    pub kind: ArtifactKind,
+    /// Path to the file, relative to the work dir if within it.
+    pub path: PathBuf,
+    /// Collection the file belongs to.
+    pub collection: String,
+    /// ID of the run that produced the file (see [`run_id`](../temp/fn.run_id.html)),
+    /// or `None` for index files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<String>,
+    /// Stage that produced the file.
+    pub stage: Stage,
+    /// Size in bytes.
+    pub size: u64,
+    /// Hash of the content, in hexadecimal.
+    pub hash: String,
+    /// Last modification, in seconds since the Unix epoch, used to avoid hashing
+    /// unchanged files again.
+    #[serde(default)]
+    pub modified: u64,
+}
+
+/// Inventory of the artifacts of a work dir, saved after each change.
+///
+/// Can be shared among threads building different collections.
+#[derive(Debug)]
+pub struct Artifacts {
+    workdir: PathBuf,
+    entries: Mutex<Vec<Artifact>>,
+}
+
+impl Artifacts {
+    /// Path to the inventory within `workdir`.
+    pub fn path(workdir: &Path) -> PathBuf {
+        workdir.join(ARTIFACTS_FILE)
+    }
+
+    /// Loads the inventory of `workdir`, which is empty if nothing has been recorded.
+    pub fn open(workdir: &Path) -> Result<Self, Error> {
+        let path = Self::path(workdir);
+        let entries = if path.exists() {
+            let file = File::open(&path).with_context(|_| path.display().to_string())?;
+            serde_json::from_reader(file).context("Failed to parse artifacts")?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            workdir: workdir.to_path_buf(),
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Lists all artifacts, ordered by their paths.
+    pub fn list(&self) -> Vec<Artifact> {
+        self.entries().clone()
+    }
+
+    /// Full path to the file of `artifact`.
+    pub fn resolve(&self, artifact: &Artifact) -> PathBuf {
+        self.workdir.join(&artifact.path)
+    }
+
+    /// Records the existing index files of `collection`, replacing those recorded before.
+    /// Returns the number of recorded files.
+    pub fn record_collection(&self, collection: &Collection) -> Result<usize, Error> {
+        let external = collection.external_command(&self.workdir)?.is_some();
+        let builder = |stage| if external { Stage::External } else { stage };
+        let compressed: BTreeSet<_> = collection
+            .compressed_indexes()
+            .into_iter()
+            .map(|(encoding, scorer)| collection.scored_index(encoding, scorer))
+            .collect();
+        let wands = collection.wands();
+        let lexicons = [collection.term_lexicon(), collection.document_lexicon()];
+        let dirs: BTreeSet<_> = [&collection.fwd_index, &collection.inv_index]
+            .iter()
+            .copied()
+            .filter_map(|basename| basename.parent())
+            .filter(|dir| dir.is_dir())
+            .collect();
+        let mut files = Vec::new();
+        for dir in dirs {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if !path.is_file() {
+                    continue;
+                }
+                let kind = if wands.contains(&path) {
+                    (ArtifactKind::WandData, Stage::Wand)
+                } else if compressed.contains(&path) {
+                    (ArtifactKind::CompressedIndex, Stage::Compress)
+                } else if lexicons.contains(&path) {
+                    (ArtifactKind::Lexicon, builder(Stage::Parse))
+                } else if derived_from(&path, &collection.inv_index) {
+                    (ArtifactKind::InvertedIndex, builder(Stage::Invert))
+                } else if derived_from(&path, &collection.fwd_index) {
+                    (ArtifactKind::ForwardIndex, builder(Stage::Parse))
+                } else {
+                    continue;
+                };
+                files.push((kind, path));
+            }
+        }
+        self.record(&collection.name, None, files)
+    }
+
+    /// Records the existing output files of `run`, identified by `run_id`,
+    /// replacing those recorded before. Returns the number of recorded files.
+    pub fn record_run(&self, run_id: &str, run: &Run) -> Result<usize, Error> {
+        let files = output_files(run)
+            .into_iter()
+            .map(|path| ((ArtifactKind::of_run_output(&path), Stage::Run), path))
+            .collect();
+        self.record(&run.collection, Some(run_id), files)
+    }
+
+    /// Full paths to the recorded output files of the run identified by `run_id`.
+    pub fn run_files(&self, run_id: &str) -> Vec<PathBuf> {
+        self.entries()
+            .iter()
+            .filter(|artifact| artifact.run.as_deref() == Some(run_id))
+            .map(|artifact| self.resolve(artifact))
+            .collect()
+    }
+
+    /// Removes the artifacts whose files no longer exist, e.g., after garbage collection.
+    /// Returns the number of removed artifacts.
+    pub fn prune(&self) -> Result<usize, Error> {
+        let mut entries = self.entries();
+        let count = entries.len();
+        let workdir = &self.workdir;
+        entries.retain(|artifact| workdir.join(&artifact.path).is_file());
+        let pruned = count - entries.len();
+        if pruned > 0 {
+            self.save(&entries)?;
+        }
+        Ok(pruned)
+    }
+
+    /// Replaces the artifacts of `collection` and `run` with `files`. The hashes of files
+    /// whose size and modification time have not changed are reused.
+    fn record(
+        &self,
+        collection: &str,
+        run: Option<&str>,
+        files: Vec<((ArtifactKind, Stage), PathBuf)>,
+    ) -> Result<usize, Error> {
+        let mut entries = self.entries();
+        let (previous, mut kept): (Vec<_>, Vec<_>) = entries.drain(..).partition(|artifact| {
+            artifact.collection == collection && artifact.run.as_deref() == run
+        });
+        let count = files.len();
+        for ((kind, stage), path) in files {
+            let metadata = fs::metadata(&path).with_context(|_| path.display().to_string())?;
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |elapsed| elapsed.as_secs());
+            let path = path
+                .strip_prefix(&self.workdir)
+                .map_or_else(|_| path.clone(), Path::to_path_buf);
+            let unchanged = previous.iter().find(|artifact| {
+                artifact.path == path
+                    && artifact.size == metadata.len()
+                    && artifact.modified == modified
+            });
+            let hash = match unchanged {
+                Some(artifact) => artifact.hash.clone(),
+                None => format!("{:016x}", file_hash(&self.workdir.join(&path))?),
+            };
+            kept.push(Artifact {
+                kind,
+                path,
+                collection: collection.to_string(),
+                run: run.map(String::from),
+                stage,
+                size: metadata.len(),
+                hash,
+                modified,
+            });
+        }
+        kept.sort_by(|lhs, rhs| (&lhs.path, &lhs.run).cmp(&(&rhs.path, &rhs.run)));
+        *entries = kept;
+        self.save(&entries)?;
+        Ok(count)
+    }
+
+    /// The entries stay consistent even if another thread panicked while holding the lock,
+    /// since they are only replaced as a whole.
+    fn entries(&self) -> MutexGuard<'_, Vec<Artifact>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Writes the inventory to a temporary file first, so that an interruption
+    /// never leaves a corrupted inventory behind.
+    fn save(&self, entries: &[Artifact]) -> Result<(), Error> {
+        let path = Self::path(&self.workdir);
+        let json =
+            serde_json::to_string_pretty(entries).context("Failed to serialize artifacts")?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{format_output_path, Config};
+    use crate::temp::run_id;
+    use crate::tests::{mkfiles, mock_set_up, MockSetup};
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_artifacts() -> Result<(), Error> {
+        let tmp = TempDir::new("artifacts").unwrap();
+        let MockSetup { config, .. } = mock_set_up(&tmp);
+        mkfiles(
+            tmp.path(),
+            &[
+                "fwd.documents",
+                "fwd.termlex",
+                "inv.docs",
+                "inv.wand.bm25",
+                "inv.block_simdbp",
+            ],
+        )?;
+        fs::write(tmp.path().join("inv.block_simdbp"), "index")?;
+        let collection = config.collection(0);
+        let artifacts = Artifacts::open(config.workdir())?;
+        assert_eq!(artifacts.record_collection(collection)?, 5);
+        let kinds: Vec<_> = artifacts
+            .list()
+            .iter()
+            .map(|artifact| (artifact.path.clone(), artifact.kind, artifact.stage))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (
+                    PathBuf::from("fwd.documents"),
+                    ArtifactKind::ForwardIndex,
+                    Stage::Parse
+                ),
+                (
+                    PathBuf::from("fwd.termlex"),
+                    ArtifactKind::Lexicon,
+                    Stage::Parse
+                ),
+                (
+                    PathBuf::from("inv.block_simdbp"),
+                    ArtifactKind::CompressedIndex,
+                    Stage::Compress
+                ),
+                (
+                    PathBuf::from("inv.docs"),
+                    ArtifactKind::InvertedIndex,
+                    Stage::Invert
+                ),
+                (
+                    PathBuf::from("inv.wand.bm25"),
+                    ArtifactKind::WandData,
+                    Stage::Wand
+                ),
+            ]
+        );
+        let list = artifacts.list();
+        let compressed = &list[2];
+        assert_eq!(compressed.size, 5);
+        assert_eq!(
+            compressed.hash,
+            format!("{:016x}", file_hash(&tmp.path().join("inv.block_simdbp"))?)
+        );
+
+        let benchmark = &config.0.runs[2];
+        let bench = format_output_path(
+            &benchmark.output,
+            &benchmark.algorithms[0],
+            &benchmark.encodings[0],
+            0,
+            "bench",
+        );
+        fs::write(&bench, "{}")?;
+        let id = run_id(2, benchmark);
+        assert_eq!(artifacts.record_run(&id, benchmark)?, 1);
+        assert_eq!(artifacts.run_files(&id), vec![bench.clone()]);
+
+        let reopened = Artifacts::open(config.workdir())?;
+        assert_eq!(reopened.list(), artifacts.list());
+        fs::remove_file(&bench)?;
+        fs::remove_file(tmp.path().join("inv.wand.bm25"))?;
+        assert_eq!(reopened.prune()?, 2);
+        assert!(reopened.run_files(&id).is_empty());
+        assert_eq!(Artifacts::open(config.workdir())?.list().len(), 4);
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+//! Bisection of a range of commits of a git source, finding the first commit
+//! at which a run regresses compared to its baseline.
+//!
+//! Each tested commit is checked out in the local repository and compiled,
+//! and the designated run is processed and compared with its `compare_with` baseline.
+//! Collections are not rebuilt, so they must already be built, as in a regular session.
+
+use crate::config::{Config, ResolvedPathsConfig};
+use crate::error::Error;
+use crate::executor::Executor;
+use crate::run::{compare_with_baseline, process_run, RunStatus};
+use crate::temp::{run_id, RunTempDir};
+use log::info;
+
+/// Lists the commits reachable from `bad` but not from `good`, from the oldest to `bad`.
+pub fn commit_range(repo: &git2::Repository, good: &str, bad: &str) -> Result<Vec<String>, Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE);
+    revwalk.push(repo.revparse_single(bad)?.peel_to_commit()?.id())?;
+    revwalk.hide(repo.revparse_single(good)?.peel_to_commit()?.id())?;
+    let mut commits = Vec::new();
+    for oid in revwalk {
+        commits.push(oid?.to_string());
+    }
+    Ok(commits)
+}
+
+/// Finds the first of `commits`, ordered from the oldest, for which `is_bad` returns `true`,
+/// assuming that all commits after a bad one are bad too.
+///
+/// The last commit is tested first; if it is not bad, there is nothing to find.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::bisect::first_bad;
+/// let commits: Vec<String> = (0..10).map(|commit| commit.to_string()).collect();
+/// let mut tested = Vec::new();
+/// let first = first_bad(&commits, |commit| {
+///     tested.push(commit.to_string());
+///     Ok(commit.parse::<usize>().unwrap() >= 6)
+/// });
+/// assert_eq!(first.unwrap(), Some(String::from("6")));
+/// assert_eq!(tested, vec!["9", "4", "7", "6", "5"]);
+/// assert_eq!(first_bad(&commits, |_| Ok(false)).unwrap(), None);
+/// ```
+pub fn first_bad<F>(commits: &[String], mut is_bad: F) -> Result<Option<String>, Error>
+where
+    F: FnMut(&str) -> Result<bool, Error>,
+{
+    let mut last_bad = match commits.last() {
+        Some(last) if is_bad(last)? => commits.len() - 1,
+        _ => return Ok(None),
+    };
+    let mut first_candidate = 0;
+    while first_candidate < last_bad {
+        let middle = (first_candidate + last_bad) / 2;
+        if is_bad(&commits[middle])? {
+            last_bad = middle;
+        } else {
+            first_candidate = middle + 1;
+        }
+    }
+    Ok(Some(commits[last_bad].clone()))
+}
+
+/// Processes the run at index `run_idx` of `config` with `executor`, and returns `true`
+/// if it regressed compared to its baseline.
+pub fn regressed<C: Config>(
+    executor: &Executor,
+    config: &C,
+    run_idx: usize,
+) -> Result<bool, Error> {
+    let run = config
+        .runs()
+        .get(run_idx)
+        .ok_or_else(|| format!("Run not defined: {}", run_idx))?;
+    let baseline = run
+        .compare_with
+        .as_ref()
+        .ok_or_else(|| format!("Run has no baseline: {}", run.output.display()))?;
+    let collection = config
+        .collections()
+        .iter()
+        .find(|collection| collection.name == run.collection)
+        .ok_or_else(|| format!("Collection not defined: {}", run.collection))?;
+    let temp_dir = RunTempDir::create(config.workdir(), &run_id(run_idx, run))?;
+    let processed = process_run(
+        executor,
+        run,
+        collection,
+        config.use_scorer(),
+        temp_dir.path(),
+    );
+    temp_dir.finish(processed.is_err(), config.keep_temp())?;
+    processed?;
+    let status = compare_with_baseline(run, &baseline.path, config.margin())?;
+    Ok(matches!(status, RunStatus::Regression(_)))
+}
+
+/// Finds the first commit between `good` and `bad` at which the run at index `run_idx`
+/// regresses, or `None` if it does not regress at `bad`.
+///
+/// The local repository of the git source is restored to its previous head afterwards,
+/// while the compiled tools are those of the last tested commit.
+pub fn bisect(
+    config: &ResolvedPathsConfig,
+    run_idx: usize,
+    good: &str,
+    bad: &str,
+) -> Result<Option<String>, Error> {
+    let dir = config
+        .source()
+        .git_dir(config.workdir())
+        .ok_or("Bisection requires a git source")?;
+    let repo = git2::Repository::open(&dir)?;
+    let head = repo.head()?.name().map(String::from);
+    let commits = commit_range(&repo, good, bad)?;
+    info!("Bisecting {} commits", commits.len());
+    let first = first_bad(&commits, |commit| {
+        info!("Testing commit {}", commit);
+        let bad = regressed(&config.0.executor_at(commit)?, config, run_idx)?;
+        info!("Commit {} is {}", commit, if bad { "bad" } else { "good" });
+        Ok(bad)
+    });
+    if let Some(head) = head {
+        repo.set_head(&head)?;
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))?;
+    }
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn commit(repo: &git2::Repository, message: &str) -> Result<String, Error> {
+        let signature = git2::Signature::now("stdbench", "stdbench@example.com")?;
+        let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+        let parents = match repo.head() {
+            Ok(head) => vec![head.peel_to_commit()?],
+            Err(_) => vec![],
+        };
+        let parents: Vec<_> = parents.iter().collect();
+        let oid = repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )?;
+        Ok(oid.to_string())
+    }
+
+    #[test]
+    fn test_commit_range() -> Result<(), Error> {
+        let tmp = TempDir::new("bisect").unwrap();
+        let repo = git2::Repository::init(tmp.path())?;
+        let commits = ["a", "b", "c", "d"]
+            .iter()
+            .map(|message| commit(&repo, message))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            commit_range(&repo, &commits[0], &commits[3])?,
+            &commits[1..]
+        );
+        assert_eq!(commit_range(&repo, &commits[1], "HEAD")?, &commits[2..]);
+        assert!(commit_range(&repo, &commits[3], &commits[3])?.is_empty());
+        assert!(commit_range(&repo, "missing", &commits[3]).is_err());
+        let first = first_bad(&commits[1..], |commit| Ok(commit != commits[1]))?;
+        assert_eq!(first.as_deref(), Some(commits[2].as_str()));
+        Ok(())
+    }
+}
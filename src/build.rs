@@ -5,21 +5,99 @@ extern crate boolinator;
 extern crate failure;
 extern crate log;
 
-use crate::config::{resolve_files, BatchSizes, Collection, CollectionKind, Stage, Threads};
-use crate::error::Error;
-use crate::executor::Executor;
-use crate::{ensure_parent_exists, CommandDebug, Config, Resolved};
+use crate::artifacts::Artifacts;
+use crate::checkpoint::Checkpoints;
+use crate::config::{
+    resolve_files, BatchSizes, Collection, CollectionKind, Download, Encoding, Scorer, Stage,
+    Threads,
+};
+use crate::error::{Error, ErrorKind};
+use crate::executor::{check_status, Executor};
+use crate::guard::{HostLock, Isolation};
+use crate::metrics::IndexSizes;
+use crate::plan::PlannedCommand;
+use crate::registry::verify_checksum;
+use crate::sample::{filter_documents, is_sampled, RecordFormat};
+use crate::share::{share_collection, unshare};
+use crate::{ensure_parent_exists, CommandDebug, Config, RegressionMargin, Resolved};
 use boolinator::Boolinator;
 use failure::ResultExt;
-use log::{info, warn};
+use log::{error, info, warn};
 use os_pipe::pipe;
+use serde::{Deserialize, Serialize};
 use std::{
-    fs::File,
+    collections::BTreeMap,
+    fs::{self, File},
     io::{BufRead, BufReader},
-    path::Path,
+    path::{Path, PathBuf},
     process::Command,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    sync::Mutex,
+    thread,
+    time::Instant,
 };
 
+/// Directory within the work dir where build times are stored.
+pub const BUILD_TIMES_DIR: &str = "build_times";
+
+/// Durations (in seconds) of individual build steps of a collection,
+/// such as `parse`, `lexicon.terms`, `invert`, `compress.block_simdbp`, or `wand.bm25`.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct BuildTimes(pub BTreeMap<String, f64>);
+
+impl BuildTimes {
+    /// Path to the build times of `collection` within `workdir`.
+    pub fn path(workdir: &Path, collection: &str) -> PathBuf {
+        workdir
+            .join(BUILD_TIMES_DIR)
+            .join(format!("{}.json", collection))
+    }
+
+    /// Loads build times from a JSON file.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path).with_context(|_| path.display().to_string())?;
+        Ok(serde_json::from_reader(file).context("Failed to parse build times")?)
+    }
+
+    /// Writes build times to a JSON file.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        ensure_parent_exists(path)?;
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize build times")?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Runs `step`, recording its duration under `name`.
+    fn time<T, F>(&mut self, name: String, step: F) -> Result<T, Error>
+    where
+        F: FnOnce() -> Result<T, Error>,
+    {
+        let start = Instant::now();
+        let result = step()?;
+        self.0.insert(name, start.elapsed().as_secs_f64());
+        Ok(result)
+    }
+
+    /// Returns the steps that took longer than in `baseline` beyond the `margin`,
+    /// together with the baseline and current durations.
+    pub fn regressions(
+        &self,
+        baseline: &Self,
+        margin: RegressionMargin,
+    ) -> Vec<(String, f64, f64)> {
+        self.0
+            .iter()
+            .filter_map(|(step, &time)| {
+                baseline
+                    .0
+                    .get(step)
+                    .filter(|&&base| time > base * (1.0 + f64::from(margin.0)))
+                    .map(|&base| (step.clone(), base, time))
+            })
+            .collect()
+    }
+}
+
 /// Retrieves the term count of an already built collection.
 ///
 /// Internally, it counts lines of the terms file of the forward index.
@@ -41,6 +119,21 @@ fn term_count(collection: &Collection) -> Result<usize, Error> {
     Ok(count)
 }
 
+fn merge_command(
+    executor: &Executor,
+    collection: &Collection,
+    batch_count: &str,
+    document_count: &str,
+) -> Command {
+    let mut merge = executor.command("parse_collection");
+    merge
+        .args(&["--output", collection.fwd_index.to_str().unwrap()])
+        .arg("merge")
+        .args(&["--batch-count", batch_count])
+        .args(&["--document-count", document_count]);
+    merge
+}
+
 fn merge_parsed_batches(executor: &Executor, collection: &Collection) -> Result<(), Error> {
     let batch_pattern = format!("{}.batch.*documents", collection.fwd_index.display());
     let batch_doc_files = resolve_files(&batch_pattern)?;
@@ -52,32 +145,89 @@ fn merge_parsed_batches(executor: &Executor, collection: &Collection) -> Result<
             Ok(0_usize),
             |acc: Result<usize, Error>, count: Result<usize, Error>| Ok(acc? + count?),
         )?;
-    executor
-        .command("parse_collection")
-        .args(&["--output", collection.fwd_index.to_str().unwrap()])
-        .arg("merge")
-        .args(&["--batch-count", &batch_count.to_string()])
-        .args(&["--document-count", &document_count.to_string()])
-        .log()
-        .status()?
-        .success()
-        .ok_or("Failed to merge collection batches")?;
+    let mut merge = merge_command(
+        executor,
+        collection,
+        &batch_count.to_string(),
+        &document_count.to_string(),
+    );
+    let status = executor.status(&mut merge)?;
+    check_status(
+        "parse_collection",
+        status,
+        "Failed to merge collection batches",
+    )?;
     Ok(())
 }
 
+/// Batch files of the forward index left on disk by `parse_collection`,
+/// either compressed or not.
+fn batch_files(collection: &Collection, compressed: bool) -> Vec<PathBuf> {
+    let pattern = format!("{}.batch.*", collection.fwd_index.display());
+    glob::glob(&pattern)
+        .map(|paths| {
+            paths
+                .filter_map(Result::ok)
+                .filter(|path| path.extension().is_some_and(|ext| ext == "gz") == compressed)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Compresses the batches left by a failed parse with `gzip`, to save disk space
+/// until they are merged.
+fn compress_batches(collection: &Collection) -> Result<(), Error> {
+    let batches = batch_files(collection, false);
+    if batches.is_empty() {
+        return Ok(());
+    }
+    info!(
+        "[{}] [build] [parse] Compressing {} batch files",
+        collection.name,
+        batches.len()
+    );
+    let status = Command::new("gzip")
+        .args(&batches)
+        .log()
+        .status()
+        .context("Failed to run gzip")?;
+    check_status("gzip", status, "Failed to compress batches")
+}
+
+/// Decompresses the batches compressed by [`compress_batches`](fn.compress_batches.html),
+/// if any, before merging them.
+fn decompress_batches(collection: &Collection) -> Result<(), Error> {
+    let batches = batch_files(collection, true);
+    if batches.is_empty() {
+        return Ok(());
+    }
+    info!(
+        "[{}] [build] [parse] Decompressing {} batch files",
+        collection.name,
+        batches.len()
+    );
+    let status = Command::new("gunzip")
+        .args(&batches)
+        .log()
+        .status()
+        .context("Failed to run gunzip")?;
+    check_status("gunzip", status, "Failed to decompress batches")
+}
+
 fn parse_collection_cmd(
     executor: &Executor,
     fwd_index: &Path,
     format: &str,
+    stemmer: Option<&str>,
     batch_size: usize,
     threads: Option<usize>,
 ) -> Command {
     let mut cmd = executor.command("parse_collection");
-    cmd.arg("-o")
-        .arg(fwd_index)
-        .args(&["-f", format])
-        .args(&["--stemmer", "porter2"])
-        .args(&["--content-parser", "html"])
+    cmd.arg("-o").arg(fwd_index).args(&["-f", format]);
+    if let Some(stemmer) = stemmer {
+        cmd.args(&["--stemmer", stemmer]);
+    }
+    cmd.args(&["--content-parser", "html"])
         .args(&["--batch-size", &batch_size.to_string()]);
     if let Some(threads) = threads {
         cmd.args(&["-j", &threads.to_string()]);
@@ -85,6 +235,10 @@ fn parse_collection_cmd(
     cmd
 }
 
+/// `jq` filter printing the ID and the content of each JSON document on a single line,
+/// as expected by the plain text parser.
+const JSONL_FILTER: &str = r#""\(.[$id]) \(.[$content] | tostring | gsub("\\s+"; " "))""#;
+
 fn parsing_commands(
     executor: &Executor,
     collection: &Collection,
@@ -100,18 +254,58 @@ fn parsing_commands(
             &executor,
             &collection.fwd_index,
             fmt,
+            collection.stemmer(),
             batch_sizes.parse,
-            threads.parse,
+            threads.for_stage(Stage::Parse),
         )
     };
+    let input_files = |default_glob: &str| {
+        resolve_files(input_dir.join(collection.input_glob.as_deref().unwrap_or(default_glob)))
+    };
     match &collection.kind {
         CollectionKind::NewYorkTimes => {
-            let input_files = resolve_files(input_dir.join("*.plain"))?;
             let mut cat = Command::new("cat");
-            cat.args(&input_files);
+            cat.args(&input_files("*.plain")?);
+            let parse = parse_cmd("plaintext");
+            Ok((cat, parse))
+        }
+        CollectionKind::Plaintext => {
+            let mut cat = Command::new("cat");
+            cat.args(&input_files("*.tsv")?);
+            let parse = parse_cmd("plaintext");
+            Ok((cat, parse))
+        }
+        CollectionKind::MsMarco => {
+            let mut cat = Command::new("zcat");
+            cat.arg("-f").args(&input_files("*.tsv*")?);
+            let parse = parse_cmd("plaintext");
+            Ok((cat, parse))
+        }
+        CollectionKind::Jsonl {
+            id_field,
+            content_field,
+        } => {
+            let mut cat = Command::new("jq");
+            cat.args(&[
+                "-r",
+                "--arg",
+                "id",
+                id_field,
+                "--arg",
+                "content",
+                content_field,
+            ])
+            .arg(JSONL_FILTER)
+            .args(&input_files("*.jsonl")?);
             let parse = parse_cmd("plaintext");
             Ok((cat, parse))
         }
+        CollectionKind::Robust if collection.input_glob.is_some() => {
+            let mut cat = Command::new("zcat");
+            cat.args(&input_files("")?);
+            let parse = parse_cmd("trectext");
+            Ok((cat, parse))
+        }
         CollectionKind::Robust => {
             let find_output = Command::new("find")
                 .arg(input_dir)
@@ -126,132 +320,656 @@ fn parsing_commands(
                 .log()
                 .output()?;
             let find_output = String::from_utf8_lossy(&find_output.stdout);
-            let input_files: Vec<_> = find_output.split('\n').collect();
+            let input_files: Vec<_> = find_output.lines().collect();
             let mut cat = Command::new("zcat");
             cat.args(&input_files);
             let parse = parse_cmd("trectext");
             Ok((cat, parse))
         }
         CollectionKind::Warc => {
-            let input_files = resolve_files(input_dir.join("*/*.gz"))?;
             let mut cat = Command::new("zcat");
-            cat.args(&input_files);
+            cat.args(&input_files("*/*.gz")?);
             let parse = parse_cmd("warc");
             Ok((cat, parse))
         }
         CollectionKind::TrecWeb => {
-            let input_files = resolve_files(input_dir.join("*/*.gz"))?;
             let mut cat = Command::new("zcat");
-            cat.args(&input_files);
+            cat.args(&input_files("*/*.gz")?);
             let parse = parse_cmd("trecweb");
             Ok((cat, parse))
         }
         CollectionKind::WashingtonPost => {
-            let input_files = resolve_files(input_dir.join("data/*.jl"))?;
             let mut cat = Command::new("cat");
-            cat.args(&input_files);
+            cat.args(&input_files("data/*.jl")?);
             let parse = parse_cmd("wapo");
             Ok((cat, parse))
         }
     }
 }
 
+/// Creates a command downloading a collection file to `output`.
+fn download_command(download: &Download, output: &Path) -> Command {
+    let mut curl = Command::new("curl");
+    curl.args(&["-fsSL", "-o"]).arg(output).arg(&download.url);
+    curl
+}
+
+/// Downloads the files of a collection into its input directory,
+/// verifying their checksums and extracting archives.
+///
+/// Files are first downloaded under a temporary name, so that those already
+/// in the input directory are complete and not downloaded again.
+fn fetch(collection: &Collection) -> Result<(), Error> {
+    let input_dir = collection
+        .input_dir
+        .as_ref()
+        .expect("Input directory undefined");
+    fs::create_dir_all(input_dir)?;
+    for download in &collection.download {
+        let path = input_dir.join(download.file_name());
+        if path.exists() {
+            info!("Already downloaded: {}", path.display());
+            continue;
+        }
+        info!("Downloading {}", download.url);
+        let partial = path.with_file_name(format!("{}.download", download.file_name()));
+        let status = download_command(download, &partial)
+            .log()
+            .status()
+            .context("Failed to run curl")?;
+        check_status("curl", status, "Failed to download collection")?;
+        if let Some(sha256) = &download.sha256 {
+            if let Err(err) = verify_checksum(&partial, sha256) {
+                fs::remove_file(&partial)?;
+                return Err(err);
+            }
+        }
+        if download.is_archive() {
+            let status = Command::new("tar")
+                .arg("-xf")
+                .arg(&partial)
+                .arg("-C")
+                .arg(input_dir)
+                .log()
+                .status()
+                .context("Failed to run tar")?;
+            check_status("tar", status, "Failed to extract collection archive")?;
+        }
+        fs::rename(&partial, &path)?;
+    }
+    Ok(())
+}
+
+/// Runs the shell `command` of an external index builder, supervised by `executor`
+/// like the PISA tools, e.g., killed after the timeout of its stage.
+fn run_external(executor: &Executor, command: &str) -> Result<(), Error> {
+    let status = executor
+        .status_in(Command::new("sh").args(&["-c", command]), Stage::External)
+        .context("Failed to run external builder")?;
+    check_status("external builder", status, "External builder failed")
+}
+
+fn parse<C: Config + Resolved>(
+    executor: &Executor,
+    collection: &Collection,
+    config: &C,
+    times: &mut BuildTimes,
+) -> Result<(), Error> {
+    let name = &collection.name;
+    let start = Instant::now();
+    if config.enabled_for(collection, Stage::ParseBatches) {
+        info!("[{}] [build] [parse] Parsing collection", name);
+        let (mut cat, mut parse) = parsing_commands(
+            &executor,
+            &collection,
+            collection.batch_sizes.merged_over(config.batch_sizes()),
+            collection.threads.merged_over(config.threads()),
+        )?;
+        let (reader, writer) = pipe().expect("Failed opening a pipe");
+        let filter = if collection.sample_documents.is_some() || collection.shard.is_some() {
+            let (cat_reader, cat_writer) = pipe().expect("Failed opening a pipe");
+            executor.spawn(cat.stdout(cat_writer))?;
+            let format = RecordFormat::of(&collection.kind);
+            let sample = collection.sample_documents;
+            let shard = collection.shard;
+            Some(thread::spawn(move || {
+                filter_documents(BufReader::new(cat_reader), writer, format, |record| {
+                    sample.iter().all(|&sample| is_sampled(record, sample))
+                        && shard.iter().all(|shard| shard.contains(record))
+                })
+            }))
+        } else {
+            executor.spawn(cat.stdout(writer))?;
+            None
+        };
+        drop(cat);
+        parse.stdin(reader);
+        let parsed = executor
+            .status(&mut parse)
+            .map_err(Error::from)
+            .and_then(|status| check_status("parse_collection", status, "Failed to parse"))
+            .and_then(|()| match filter.map(thread::JoinHandle::join) {
+                Some(Ok(selected)) => {
+                    info!(
+                        "[{}] [build] [parse] Selected {} documents",
+                        name, selected?
+                    );
+                    Ok(())
+                }
+                Some(Err(_)) => Err(Error::from("Document selection failed")),
+                None => Ok(()),
+            });
+        if let Err(err) = parsed {
+            if config.compress_batches() {
+                if let Err(compress_err) = compress_batches(collection) {
+                    warn!("[{}] [build] [parse] {}", name, compress_err);
+                }
+            }
+            return Err(err);
+        }
+    } else {
+        warn!("[{}] [build] [parse] Only merging", name);
+        decompress_batches(collection)?;
+        merge_parsed_batches(executor, &collection)?;
+    }
+    times.time(String::from("lexicon.terms"), || {
+        executor.build_lexicon(collection.terms(), collection.term_lexicon())
+    })?;
+    times.time(String::from("lexicon.documents"), || {
+        executor.build_lexicon(collection.documents(), collection.document_lexicon())
+    })?;
+    times
+        .0
+        .insert(String::from("parse"), start.elapsed().as_secs_f64());
+    Ok(())
+}
+
+fn invert<C: Config>(
+    executor: &Executor,
+    collection: &Collection,
+    config: &C,
+) -> Result<(), Error> {
+    info!("[{}] [build] [invert] Inverting index", collection.name);
+    let term_count = term_count(collection)?;
+    executor.invert(
+        &collection.fwd_index,
+        &collection.inv_index,
+        term_count,
+        collection
+            .batch_sizes
+            .merged_over(config.batch_sizes())
+            .invert,
+        stage_threads(collection, config, Stage::Invert),
+    )
+}
+
+/// Thread count of the tool of `stage` for `collection`.
+fn stage_threads<C: Config>(collection: &Collection, config: &C, stage: Stage) -> Option<usize> {
+    collection
+        .threads
+        .merged_over(config.threads())
+        .for_stage(stage)
+}
+
+fn compress<C: Config>(
+    executor: &Executor,
+    collection: &Collection,
+    config: &C,
+    checkpoints: &Checkpoints,
+    times: &mut BuildTimes,
+) -> Result<(), Error> {
+    let threads = stage_threads(collection, config, Stage::Compress);
+    for (encoding, scorer) in collection.compressed_indexes() {
+        let step = compress_step(encoding, scorer);
+        if resumed(checkpoints, &collection.name, &step) {
+            continue;
+        }
+        let enc_index = collection.scored_index(encoding, scorer);
+        unshare(&enc_index)?;
+        times.time(step.clone(), || match scorer {
+            Some(scorer) => executor.quantized_compress(
+                &collection.inv_index,
+                &enc_index,
+                encoding,
+                collection.wand(Some(scorer)),
+                scorer,
+                threads,
+            ),
+            None => executor.compress(&collection.inv_index, &enc_index, encoding, threads),
+        })?;
+        checkpoints.complete_step(&collection.name, &step)?;
+    }
+    Ok(())
+}
+
+/// Checkpoint step compressing the index with `encoding`, quantized for `scorer` if any.
+fn compress_step(encoding: &Encoding, scorer: Option<&Scorer>) -> String {
+    match scorer {
+        Some(scorer) => format!("compress.{}.{}", encoding, scorer),
+        None => format!("compress.{}", encoding),
+    }
+}
+
+fn wand_data<C: Config>(
+    executor: &Executor,
+    collection: &Collection,
+    config: &C,
+    checkpoints: &Checkpoints,
+    times: &mut BuildTimes,
+) -> Result<(), Error> {
+    let name = &collection.name;
+    let threads = stage_threads(collection, config, Stage::Wand);
+    for scorer in &collection.scorers {
+        let step = format!("wand.{}", scorer);
+        if resumed(checkpoints, name, &step) {
+            continue;
+        }
+        info!(
+            "[{}] [build] [wand] Creating WAND data for {}",
+            name, &scorer
+        );
+        let scorer = if config.use_scorer() {
+            Some(scorer)
+        } else {
+            None
+        };
+        unshare(&collection.wand(scorer))?;
+        times.time(step.clone(), || {
+            executor.create_wand_data(
+                &collection.inv_index,
+                collection.wand(scorer),
+                scorer,
+                collection.quantize,
+                threads,
+            )
+        })?;
+        checkpoints.complete_step(name, &step)?;
+    }
+    Ok(())
+}
+
+/// Returns `true` if `step` of collection `name` was completed in the previous session.
+fn resumed(checkpoints: &Checkpoints, name: &str, step: &str) -> bool {
+    let done = checkpoints.step_done(name, step);
+    if done {
+        info!(
+            "[{}] [build] [{}] Completed in previous session",
+            name, step
+        );
+    }
+    done
+}
+
 /// Builds a requeested collection, using a given executor.
+///
+/// Errors are of kind [`ErrorKind::Build`](../enum.ErrorKind.html), identifying the failed stage.
+///
+/// Durations of all build steps are stored in the work dir
+/// (see [`BuildTimes`](struct.BuildTimes.html)), and so are the sizes of the built
+/// indexes (see [`IndexSizes`](../metrics/struct.IndexSizes.html)).
+///
+/// Each completed step is recorded in `checkpoints`; steps already recorded
+/// there (when resuming a session) are skipped.
 pub fn collection<C: Config + Resolved>(
     executor: &Executor,
     collection: &Collection,
     config: &C,
+    checkpoints: &Checkpoints,
 ) -> Result<(), Error> {
     info!(
         "Processing collection: {}/{:?}",
         collection.name, collection.kind
     );
     let name = &collection.name;
-    if config.enabled(Stage::BuildIndex) {
+    let failed = |stage| {
+        move |err: Error| {
+            err.wrap(ErrorKind::Build {
+                stage,
+                collection: name.clone(),
+            })
+        }
+    };
+    let resumed = |step: &str| resumed(checkpoints, name, step);
+    let inject = |stage| {
+        config
+            .check_injected_failure(stage, Some(name))
+            .map_err(failed(stage))
+    };
+    if config.enabled_for(collection, Stage::BuildIndex) {
         info!("[{}] [build] Building index", name);
-        ensure_parent_exists(&collection.fwd_index)?;
-        ensure_parent_exists(&collection.inv_index)?;
-        if config.enabled(Stage::Parse) {
-            if config.enabled(Stage::ParseBatches) {
-                info!("[{}] [build] [parse] Parsing collection", name);
-                let (mut cat, mut parse) = parsing_commands(
-                    &executor,
-                    &collection,
-                    config.batch_sizes(),
-                    config.threads(),
-                )?;
-                let (reader, writer) = pipe().expect("Failed opening a pipe");
-                cat.log().stdout(writer).spawn()?;
-                drop(cat);
-                parse.stdin(reader);
-                parse.log().status()?.success().ok_or("Failed to parse")?;
+        inject(Stage::BuildIndex)?;
+        let times_path = BuildTimes::path(config.workdir(), name);
+        let mut times = BuildTimes::load(&times_path).unwrap_or_default();
+        ensure_parent_exists(&collection.fwd_index).map_err(failed(Stage::BuildIndex))?;
+        ensure_parent_exists(&collection.inv_index).map_err(failed(Stage::BuildIndex))?;
+        if !collection.download.is_empty() {
+            if config.enabled_for(collection, Stage::Fetch) {
+                if !resumed("fetch") {
+                    inject(Stage::Fetch)?;
+                    info!("[{}] [build] [fetch] Downloading collection", name);
+                    fetch(collection)
+                        .and_then(|()| checkpoints.complete_step(name, "fetch"))
+                        .map_err(failed(Stage::Fetch))?;
+                }
+            } else {
+                warn!("[{}] [build] [fetch] Suppressed", name);
+            }
+        }
+        let external_command = collection
+            .external_command(config.workdir())
+            .map_err(failed(Stage::External))?;
+        if let Some(command) = external_command {
+            if config.enabled_for(collection, Stage::External) {
+                if !resumed("external") {
+                    inject(Stage::External)?;
+                    info!("[{}] [build] [external] Running external builder", name);
+                    times
+                        .time(String::from("external"), || {
+                            run_external(executor, &command)
+                        })
+                        .and_then(|()| checkpoints.complete_step(name, "external"))
+                        .map_err(failed(Stage::External))?;
+                }
+            } else {
+                warn!("[{}] [build] [external] Suppressed", name);
+            }
+        } else {
+            if config.enabled_for(collection, Stage::Parse) {
+                if !resumed("parse") {
+                    inject(Stage::Parse)?;
+                    parse(executor, collection, config, &mut times)
+                        .and_then(|()| checkpoints.complete_step(name, "parse"))
+                        .map_err(failed(Stage::Parse))?;
+                }
+            } else {
+                warn!("[{}] [build] [parse] Suppressed", name);
+            }
+            if config.enabled_for(collection, Stage::Invert) {
+                if !resumed("invert") {
+                    inject(Stage::Invert)?;
+                    times
+                        .time(String::from("invert"), || {
+                            invert(executor, collection, config)
+                        })
+                        .and_then(|()| checkpoints.complete_step(name, "invert"))
+                        .map_err(failed(Stage::Invert))?;
+                }
+            } else {
+                warn!("[{}] [build] [invert] Suppressed", name);
+            }
+        }
+        let compress_stage = |times: &mut BuildTimes| {
+            if config.enabled_for(collection, Stage::Compress) {
+                info!("[{}] [build] [compress] Compressing index", name);
+                inject(Stage::Compress)?;
+                compress(executor, collection, config, checkpoints, times)
+                    .map_err(failed(Stage::Compress))
+            } else {
+                warn!("[{}] [build] [compress] Suppressed", name);
+                Ok(())
+            }
+        };
+        let wand_stage = |times: &mut BuildTimes| {
+            if config.enabled_for(collection, Stage::Wand) {
+                inject(Stage::Wand)?;
+                wand_data(executor, collection, config, checkpoints, times)
+                    .map_err(failed(Stage::Wand))
             } else {
-                warn!("[{}] [build] [parse] Only merging", name);
-                merge_parsed_batches(executor, &collection)?;
+                warn!("[{}] [build] [wand] Suppressed", name);
+                Ok(())
             }
-            executor.build_lexicon(collection.terms(), collection.term_lexicon())?;
-            executor.build_lexicon(collection.documents(), collection.document_lexicon())?;
+        };
+        // Quantized indexes are compressed with the scores of the WAND data.
+        if collection.quantize {
+            wand_stage(&mut times)?;
+            compress_stage(&mut times)?;
         } else {
-            warn!("[{}] [build] [parse] Suppressed", name);
+            compress_stage(&mut times)?;
+            wand_stage(&mut times)?;
+        }
+        times.write(&times_path)?;
+        IndexSizes::measure(collection).write(config.workdir(), name)?;
+        if config.share_indexes() {
+            share_collection(config.workdir(), collection)?;
+        }
+    } else {
+        warn!("[{}] [build] Suppressed", name);
+    }
+    Ok(())
+}
+
+/// Lists the commands that [`collection`](fn.collection.html) would execute,
+/// without executing anything.
+///
+/// Steps completed according to `checkpoints` are omitted. Values known only after
+/// previous steps, such as the term count, are shown as placeholders.
+pub fn plan<C: Config + Resolved>(
+    executor: &Executor,
+    collection: &Collection,
+    config: &C,
+    checkpoints: Option<&Checkpoints>,
+) -> Result<Vec<PlannedCommand>, Error> {
+    let name = &collection.name;
+    let mut plan = Vec::new();
+    if !config.enabled_for(collection, Stage::BuildIndex) {
+        return Ok(plan);
+    }
+    let pending = |stage: Stage, step: &str| {
+        config.enabled_for(collection, stage)
+            && !checkpoints.is_some_and(|checkpoints| checkpoints.step_done(name, step))
+    };
+    if pending(Stage::Fetch, "fetch") {
+        if let Some(input_dir) = &collection.input_dir {
+            for download in &collection.download {
+                let curl = download_command(download, &input_dir.join(download.file_name()));
+                plan.push(PlannedCommand::new(name, Stage::Fetch, &curl));
+            }
+        }
+    }
+    if let Some(command) = collection.external_command(config.workdir())? {
+        if pending(Stage::External, "external") {
+            let mut sh = Command::new("sh");
+            sh.args(&["-c", &command]);
+            plan.push(PlannedCommand::new(name, Stage::External, &sh));
+        }
+    } else {
+        if pending(Stage::Parse, "parse") {
+            if config.enabled_for(collection, Stage::ParseBatches) {
+                let (cat, parse) = parsing_commands(
+                    executor,
+                    collection,
+                    collection.batch_sizes.merged_over(config.batch_sizes()),
+                    collection.threads.merged_over(config.threads()),
+                )?;
+                plan.push(PlannedCommand::pipeline(
+                    name,
+                    Stage::Parse,
+                    &[&cat, &parse],
+                ));
+            } else {
+                let merge =
+                    merge_command(executor, collection, "<batch-count>", "<document-count>");
+                plan.push(PlannedCommand::new(name, Stage::Join, &merge));
+            }
+            for (input, output) in &[
+                (collection.terms(), collection.term_lexicon()),
+                (collection.documents(), collection.document_lexicon()),
+            ] {
+                let lexicon = executor.build_lexicon_command(input, output);
+                plan.push(PlannedCommand::new(name, Stage::Parse, &lexicon));
+            }
         }
-        if config.enabled(Stage::Invert) {
-            info!("[{}] [build] [invert] Inverting index", name);
-            executor.invert(
+        if pending(Stage::Invert, "invert") {
+            let invert = executor.invert_command(
                 &collection.fwd_index,
                 &collection.inv_index,
-                term_count(&collection)?,
-                config.batch_sizes().invert,
-            )?;
-        } else {
-            warn!("[{}] [build] [invert] Suppressed", name);
+                "<term-count>",
+                collection
+                    .batch_sizes
+                    .merged_over(config.batch_sizes())
+                    .invert,
+                stage_threads(collection, config, Stage::Invert),
+            );
+            plan.push(PlannedCommand::new(name, Stage::Invert, &invert));
         }
-        if config.enabled(Stage::Compress) {
-            info!("[{}] [build] [compress] Compressing index", name);
-            for encoding in &collection.encodings {
-                executor.compress(
+    }
+    let mut compress_plan = Vec::new();
+    for (encoding, scorer) in collection.compressed_indexes() {
+        if pending(Stage::Compress, &compress_step(encoding, scorer)) {
+            let enc_index = collection.scored_index(encoding, scorer);
+            let compress = match scorer {
+                Some(scorer) => executor.quantized_compress_command(
                     &collection.inv_index,
-                    collection.enc_index(encoding),
+                    enc_index,
                     encoding,
-                )?;
-            }
-        } else {
-            warn!("[{}] [build] [compress] Suppressed", name);
-        }
-        if config.enabled(Stage::Wand) {
-            for scorer in &collection.scorers {
-                info!(
-                    "[{}] [build] [wand] Creating WAND data for {}",
-                    name, &scorer
-                );
-                executor.create_wand_data(
+                    collection.wand(Some(scorer)),
+                    scorer,
+                    stage_threads(collection, config, Stage::Compress),
+                ),
+                None => executor.compress_command(
                     &collection.inv_index,
-                    collection.wand(),
-                    if config.use_scorer() {
-                        Some(&scorer)
-                    } else {
-                        None
-                    },
-                )?;
+                    enc_index,
+                    encoding,
+                    stage_threads(collection, config, Stage::Compress),
+                ),
+            };
+            compress_plan.push(PlannedCommand::new(name, Stage::Compress, &compress));
+        }
+    }
+    let mut wand_plan = Vec::new();
+    for scorer in &collection.scorers {
+        if pending(Stage::Wand, &format!("wand.{}", scorer)) {
+            let scorer = if config.use_scorer() {
+                Some(scorer)
+            } else {
+                None
+            };
+            let wand = executor.create_wand_data_command(
+                &collection.inv_index,
+                collection.wand(scorer),
+                scorer,
+                collection.quantize,
+                stage_threads(collection, config, Stage::Wand),
+            );
+            wand_plan.push(PlannedCommand::new(name, Stage::Wand, &wand));
+        }
+    }
+    if collection.quantize {
+        plan.extend(wand_plan);
+        plan.extend(compress_plan);
+    } else {
+        plan.extend(compress_plan);
+        plan.extend(wand_plan);
+    }
+    Ok(plan)
+}
+
+/// Builds all collections, up to `config.build_threads()` of them concurrently.
+///
+/// Each build holds the host lock as a parallelizable task
+/// (see [`guard`](../guard/index.html)), waiting for measurement runs to finish.
+///
+/// After each collection is built, `stop` is called; once it returns `true`,
+/// no new builds are started. Returns whether the builds were stopped early.
+///
+/// If any build fails, no new builds are started either, but those already running
+/// are finished. All failures are logged, and the first one, in the order of
+/// collections, is returned.
+///
+/// Completed steps are recorded in `checkpoints` (see [`collection`](fn.collection.html)),
+/// and the index files of each built collection in the
+/// [inventory of artifacts](../artifacts/index.html).
+pub fn collections<C, F>(
+    executor: &Executor,
+    config: &C,
+    checkpoints: &Checkpoints,
+    stop: F,
+) -> Result<bool, Error>
+where
+    C: Config + Resolved + Sync,
+    F: Fn() -> Result<bool, Error> + Sync,
+{
+    let collections = config.collections();
+    let artifacts = Artifacts::open(config.workdir())?;
+    let next = AtomicUsize::new(0);
+    let stopped = AtomicBool::new(false);
+    let failures: Mutex<Vec<(usize, Error)>> = Mutex::new(Vec::new());
+    let worker = || {
+        while !stopped.load(Ordering::SeqCst) {
+            let idx = next.fetch_add(1, Ordering::SeqCst);
+            let coll = match collections.get(idx) {
+                Some(coll) => coll,
+                None => break,
+            };
+            let built = HostLock::acquire(&config.host_lock(), Isolation::Parallelizable)
+                .and_then(|_lock| collection(executor, coll, config, checkpoints))
+                .and_then(|()| artifacts.record_collection(coll).map(|_| ()));
+            match built.and_then(|()| stop()) {
+                Ok(false) => {}
+                Ok(true) => stopped.store(true, Ordering::SeqCst),
+                Err(err) => {
+                    stopped.store(true, Ordering::SeqCst);
+                    failures.lock().unwrap().push((idx, err));
+                }
             }
-        } else {
-            warn!("[{}] [build] [wand] Suppressed", name);
         }
+    };
+    let threads = config.build_threads().min(collections.len());
+    if threads > 1 {
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(worker);
+            }
+        });
     } else {
-        warn!("[{}] [build] Suppressed", name);
+        worker();
+    }
+    let mut failures = failures.into_inner().unwrap();
+    failures.sort_by_key(|(idx, _)| *idx);
+    let mut failures = failures.into_iter().map(|(_, err)| err);
+    match failures.next() {
+        Some(first) => {
+            for err in failures {
+                error!("{}", err);
+            }
+            Err(first)
+        }
+        None => Ok(stopped.load(Ordering::SeqCst)),
     }
-    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::tests::{mkfiles, mock_set_up, MockSetup};
-    use crate::CommandDebug;
+    use crate::{CommandDebug, FakeBackend};
     use std::collections::HashSet;
     use std::fs;
     use std::path::PathBuf;
+    use std::sync::Arc;
     use tempdir::TempDir;
 
+    #[test]
+    #[cfg_attr(target_family, unix)]
+    fn test_compress_batches() -> Result<(), Error> {
+        let tmp = TempDir::new("build").unwrap();
+        let MockSetup { config, .. } = mock_set_up(&tmp);
+        let collection = config.collection(0);
+        mkfiles(tmp.path(), &["fwd.batch.0", "fwd.batch.0.documents"])?;
+        fs::write(tmp.path().join("fwd.batch.1.terms"), "a\nb\n")?;
+        compress_batches(collection)?;
+        assert!(batch_files(collection, false).is_empty());
+        assert_eq!(batch_files(collection, true).len(), 3);
+        assert!(tmp.path().join("fwd.batch.1.terms.gz").exists());
+        decompress_batches(collection)?;
+        assert!(batch_files(collection, true).is_empty());
+        assert_eq!(
+            fs::read_to_string(tmp.path().join("fwd.batch.1.terms"))?,
+            "a\nb\n"
+        );
+        Ok(())
+    }
+
     #[test]
     #[cfg_attr(target_family, unix)]
     fn test_term_count() {
@@ -317,7 +1035,13 @@ mod tests {
             outputs,
             term_count,
         } = mock_set_up(&tmp);
-        collection(&executor, &config.collection(0), &config).unwrap();
+        collection(
+            &executor,
+            &config.collection(0),
+            &config,
+            &Checkpoints::open(tmp.path(), false).unwrap(),
+        )
+        .unwrap();
         assert_eq!(
             std::fs::read_to_string(outputs.get("parse_collection").unwrap()).unwrap(),
             format!(
@@ -349,7 +1073,7 @@ mod tests {
         assert_eq!(
             std::fs::read_to_string(outputs.get("create_wand_data").unwrap()).unwrap(),
             format!(
-                "{0} -c {1} -o {1}.wand --scorer bm25\n",
+                "{0} -c {1} -o {1}.wand.bm25 --scorer bm25\n",
                 programs.get("create_wand_data").unwrap().display(),
                 tmp.path().join("inv").display(),
             )
@@ -363,6 +1087,309 @@ mod tests {
                 tmp.path().join("fwd").display(),
             )
         );
+        let times = BuildTimes::load(&BuildTimes::path(tmp.path(), "wapo")).unwrap();
+        let steps: Vec<_> = times.0.keys().map(String::as_str).collect();
+        assert_eq!(
+            steps,
+            vec![
+                "compress.block_qmx",
+                "compress.block_simdbp",
+                "invert",
+                "lexicon.documents",
+                "lexicon.terms",
+                "parse",
+                "wand.bm25"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stage_threads() {
+        let tmp = TempDir::new("build").unwrap();
+        let MockSetup {
+            mut config,
+            executor,
+            ..
+        } = mock_set_up(&tmp);
+        config.0.threads.default = Some(8);
+        config.0.collections[0].threads.wand = Some(2);
+        let plan = plan(&executor, &config.collection(0), &config, None).unwrap();
+        let threads: Vec<_> = plan
+            .iter()
+            .filter(|command| command.stage != Stage::Parse)
+            .map(|command| (command.stage, command.command.ends_with("--threads 8")))
+            .collect();
+        assert_eq!(
+            threads,
+            vec![
+                (Stage::Invert, true),
+                (Stage::Compress, true),
+                (Stage::Compress, true),
+                (Stage::Wand, false),
+            ]
+        );
+        assert!(plan.last().unwrap().command.ends_with("--threads 2"));
+        assert!(plan[0].command.contains("-j 8"));
+    }
+
+    #[test]
+    fn test_plan() {
+        let tmp = TempDir::new("build").unwrap();
+        let MockSetup {
+            config,
+            executor,
+            programs,
+            outputs,
+            ..
+        } = mock_set_up(&tmp);
+        let plan = plan(&executor, &config.collection(0), &config, None).unwrap();
+        let bin = |name: &str| programs.get(name).unwrap().display().to_string();
+        let fwd = tmp.path().join("fwd").display().to_string();
+        let inv = tmp.path().join("inv").display().to_string();
+        let lines: Vec<_> = plan.iter().map(PlannedCommand::to_string).collect();
+        assert_eq!(
+            lines,
+            vec![
+                format!(
+                    "[wapo] [parse] cat {} | {} -o {} -f wapo --stemmer porter2 \
+                     --content-parser html --batch-size 10000",
+                    tmp.path().join("coll").join("data").join("f.jl").display(),
+                    bin("parse_collection"),
+                    fwd
+                ),
+                format!(
+                    "[wapo] [parse] {} build {1}.terms {1}.termlex",
+                    bin("lexicon"),
+                    fwd
+                ),
+                format!(
+                    "[wapo] [parse] {} build {1}.documents {1}.doclex",
+                    bin("lexicon"),
+                    fwd
+                ),
+                format!(
+                    "[wapo] [invert] {} -i {} -o {} --term-count '<term-count>' --batch-size 10000",
+                    bin("invert"),
+                    fwd,
+                    inv
+                ),
+                format!(
+                    "[wapo] [compress] {} -t block_simdbp -c {1} -o {1}.block_simdbp --check",
+                    bin("create_freq_index"),
+                    inv
+                ),
+                format!(
+                    "[wapo] [compress] {} -t block_qmx -c {1} -o {1}.block_qmx --check",
+                    bin("create_freq_index"),
+                    inv
+                ),
+                format!(
+                    "[wapo] [wand] {} -c {1} -o {1}.wand.bm25 --scorer bm25",
+                    bin("create_wand_data"),
+                    inv
+                ),
+            ]
+        );
+        assert!(!outputs.get("parse_collection").unwrap().exists());
+        assert!(!outputs.get("invert").unwrap().exists());
+
+        let mut quantized = config.collection(0).clone();
+        quantized.quantize = true;
+        let plan = super::plan(&executor, &quantized, &config, None).unwrap();
+        let lines: Vec<_> = plan[plan.len() - 3..]
+            .iter()
+            .map(PlannedCommand::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                format!(
+                    "[wapo] [wand] {} -c {1} -o {1}.wand.bm25 --scorer bm25 --quantize",
+                    bin("create_wand_data"),
+                    inv
+                ),
+                format!(
+                    "[wapo] [compress] {} -t block_simdbp -c {1} -o {1}.block_simdbp.bm25 --check \
+                     -w {1}.wand.bm25 --scorer bm25 --quantize",
+                    bin("create_freq_index"),
+                    inv
+                ),
+                format!(
+                    "[wapo] [compress] {} -t block_qmx -c {1} -o {1}.block_qmx.bm25 --check \
+                     -w {1}.wand.bm25 --scorer bm25 --quantize",
+                    bin("create_freq_index"),
+                    inv
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_external_builder() {
+        let tmp = TempDir::new("build").unwrap();
+        let MockSetup {
+            mut config,
+            executor,
+            outputs,
+            ..
+        } = mock_set_up(&tmp);
+        config.0.collections[0].builder = crate::config::Builder::External {
+            command: String::from("echo {name} {inv_index} > {workdir}/external.out"),
+        };
+        collection(
+            &executor,
+            &config.collection(0),
+            &config,
+            &Checkpoints::open(tmp.path(), false).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(tmp.path().join("external.out")).unwrap(),
+            format!("wapo {}\n", tmp.path().join("inv").display())
+        );
+        assert!(!outputs.get("parse_collection").unwrap().exists());
+        assert!(!outputs.get("invert").unwrap().exists());
+        assert!(outputs.get("create_freq_index").unwrap().exists());
+        let times = BuildTimes::load(&BuildTimes::path(tmp.path(), "wapo")).unwrap();
+        assert!(times.0.contains_key("external"));
+    }
+
+    #[test]
+    fn test_external_builder_timeout() {
+        let tmp = TempDir::new("build").unwrap();
+        let MockSetup {
+            mut config,
+            executor,
+            ..
+        } = mock_set_up(&tmp);
+        config.0.collections[0].builder = crate::config::Builder::External {
+            command: String::from("sleep 30"),
+        };
+        let executor = executor.with_timeouts(crate::config::Timeouts {
+            default: Some(1),
+            ..crate::config::Timeouts::default()
+        });
+        let start = Instant::now();
+        let err = collection(
+            &executor,
+            &config.collection(0),
+            &config,
+            &Checkpoints::open(tmp.path(), false).unwrap(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("sh timed out after 1 s"));
+        assert!(start.elapsed().as_secs() < 10);
+    }
+
+    #[test]
+    fn test_parallel_collections() {
+        let tmp = TempDir::new("build").unwrap();
+        let MockSetup {
+            mut config,
+            executor,
+            ..
+        } = mock_set_up(&tmp);
+        let built = tmp.path().join("built");
+        let set_commands = |config: &mut crate::ResolvedPathsConfig, failing: &str| {
+            for coll in &mut config.0.collections {
+                let command = if coll.name == failing {
+                    String::from("exit 1")
+                } else {
+                    String::from("echo {name} >> {workdir}/built")
+                };
+                coll.builder = crate::config::Builder::External { command };
+            }
+        };
+        let built_names = || {
+            let mut names: Vec<_> = std::fs::read_to_string(&built)
+                .unwrap()
+                .lines()
+                .map(String::from)
+                .collect();
+            names.sort();
+            names
+        };
+
+        let checkpoints = || Checkpoints::open(tmp.path(), false).unwrap();
+        set_commands(&mut config, "");
+        config.0.build_threads = Some(3);
+        assert!(!collections(&executor, &config, &checkpoints(), || Ok(false)).unwrap());
+        assert_eq!(built_names(), vec!["cw09b", "gov2", "wapo"]);
+
+        std::fs::remove_file(&built).unwrap();
+        config.0.build_threads = None;
+        assert!(collections(&executor, &config, &checkpoints(), || Ok(true)).unwrap());
+        assert_eq!(built_names(), vec!["wapo"]);
+
+        set_commands(&mut config, "gov2");
+        config.0.build_threads = Some(3);
+        let err = collections(&executor, &config, &checkpoints(), || Ok(false))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::Build {
+                stage: Stage::External,
+                collection: String::from("gov2")
+            }
+        );
+    }
+
+    #[test]
+    fn test_resume_collection() {
+        let tmp = TempDir::new("build").unwrap();
+        let MockSetup {
+            config,
+            executor,
+            programs,
+            outputs,
+            ..
+        } = mock_set_up(&tmp);
+        let checkpoints = Checkpoints::open(tmp.path(), false).unwrap();
+        checkpoints.complete_step("wapo", "parse").unwrap();
+        checkpoints
+            .complete_step("wapo", "compress.block_simdbp")
+            .unwrap();
+        collection(&executor, &config.collection(0), &config, &checkpoints).unwrap();
+        assert!(!outputs.get("parse_collection").unwrap().exists());
+        assert!(outputs.get("invert").unwrap().exists());
+        assert_eq!(
+            std::fs::read_to_string(outputs.get("create_freq_index").unwrap()).unwrap(),
+            format!(
+                "{0} -t block_qmx -c {1} -o {1}.block_qmx --check\n",
+                programs.get("create_freq_index").unwrap().display(),
+                tmp.path().join("inv").display(),
+            )
+        );
+        for output in outputs.values() {
+            let _ = std::fs::remove_file(output);
+        }
+        let resumed = Checkpoints::open(tmp.path(), true).unwrap();
+        collection(&executor, &config.collection(0), &config, &resumed).unwrap();
+        assert!(outputs.values().all(|output| !output.exists()));
+    }
+
+    #[test]
+    fn test_build_time_regressions() {
+        let times = |wand: f64| {
+            BuildTimes(
+                vec![
+                    (String::from("lexicon.terms"), 10.0),
+                    (String::from("wand.bm25"), wand),
+                ]
+                .into_iter()
+                .collect(),
+            )
+        };
+        let margin = RegressionMargin(0.1);
+        assert!(times(10.5).regressions(&times(10.0), margin).is_empty());
+        assert_eq!(
+            times(12.0).regressions(&times(10.0), margin),
+            vec![(String::from("wand.bm25"), 10.0, 12.0)]
+        );
+        assert!(times(12.0)
+            .regressions(&BuildTimes::default(), margin)
+            .is_empty());
     }
 
     #[test]
@@ -375,7 +1402,13 @@ mod tests {
             ..
         } = mock_set_up(&tmp);
         config.disable(Stage::BuildIndex);
-        collection(&executor, &config.collection(0), &config).unwrap();
+        collection(
+            &executor,
+            &config.collection(0),
+            &config,
+            &Checkpoints::open(tmp.path(), false).unwrap(),
+        )
+        .unwrap();
         assert!(!outputs.get("parse_collection").unwrap().exists());
         assert!(!outputs.get("invert").unwrap().exists());
         assert!(!outputs.get("create_freq_index").unwrap().exists());
@@ -383,6 +1416,72 @@ mod tests {
         assert!(!outputs.get("lexicon").unwrap().exists());
     }
 
+    #[test]
+    fn test_injected_failure() {
+        let tmp = TempDir::new("build").unwrap();
+        let MockSetup {
+            mut config,
+            executor,
+            outputs,
+            ..
+        } = mock_set_up(&tmp);
+        config.0.injected_failures = vec!["stage=compress,collection=wapo".parse().unwrap()];
+        let checkpoints = Checkpoints::open(tmp.path(), false).unwrap();
+        let err = collection(&executor, &config.collection(0), &config, &checkpoints)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::Build {
+                stage: Stage::Compress,
+                collection: String::from("wapo")
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Failed to build wapo at stage compress: Injected failure of compress for wapo"
+        );
+        assert!(checkpoints.step_done("wapo", "invert"));
+        assert!(!outputs.get("create_freq_index").unwrap().exists());
+    }
+
+    #[test]
+    fn test_suppressed_collection_stage() {
+        let tmp = TempDir::new("build").unwrap();
+        let MockSetup {
+            mut config,
+            executor,
+            outputs,
+            ..
+        } = mock_set_up(&tmp);
+        config.0.collections[0].stages.insert(Stage::Wand, false);
+        config.0.collections[0].stages.insert(Stage::Compress, true);
+        config.0.stages.insert(Stage::Compress, false);
+        collection(
+            &executor,
+            &config.collection(0),
+            &config,
+            &Checkpoints::open(tmp.path(), false).unwrap(),
+        )
+        .unwrap();
+        assert!(outputs.get("parse_collection").unwrap().exists());
+        assert!(outputs.get("create_freq_index").unwrap().exists());
+        assert!(!outputs.get("create_wand_data").unwrap().exists());
+
+        std::fs::remove_file(outputs.get("create_freq_index").unwrap()).unwrap();
+        config.disable(Stage::Compress);
+        assert!(!config.enabled_for(config.collection(0), Stage::Compress));
+        assert!(!config.enabled_for(config.collection(1), Stage::Compress));
+        collection(
+            &executor,
+            &config.collection(0),
+            &config,
+            &Checkpoints::open(tmp.path(), false).unwrap(),
+        )
+        .unwrap();
+        assert!(!outputs.get("create_freq_index").unwrap().exists());
+    }
+
     #[test]
     fn test_suppressed_parse_and_invert() {
         let tmp = TempDir::new("build").unwrap();
@@ -394,7 +1493,13 @@ mod tests {
         } = mock_set_up(&tmp);
         config.disable(Stage::Parse);
         config.disable(Stage::Invert);
-        collection(&executor, &config.collection(0), &config).unwrap();
+        collection(
+            &executor,
+            &config.collection(0),
+            &config,
+            &Checkpoints::open(tmp.path(), false).unwrap(),
+        )
+        .unwrap();
         assert!(!outputs.get("parse_collection").unwrap().exists());
         assert!(!outputs.get("parse_collection").unwrap().exists());
         assert!(!outputs.get("invert").unwrap().exists());
@@ -418,7 +1523,13 @@ mod tests {
         ))
         .unwrap();
         config.disable(Stage::ParseBatches);
-        collection(&executor, &config.collection(0), &config).unwrap();
+        collection(
+            &executor,
+            &config.collection(0),
+            &config,
+            &Checkpoints::open(tmp.path(), false).unwrap(),
+        )
+        .unwrap();
         let parse_out = std::fs::read_to_string(outputs.get("parse_collection").unwrap()).unwrap();
         assert!(parse_out.find("merge").is_some());
         assert!(outputs.get("invert").unwrap().exists());
@@ -443,6 +1554,18 @@ mod tests {
             inv_index: PathBuf::from("inv"),
             encodings: vec![],
             scorers: crate::config::default_scorers(),
+            batch_sizes: crate::config::BatchSizesOverride::default(),
+            threads: Threads::default(),
+            input_glob: None,
+            preset: None,
+            builder: crate::config::Builder::Pisa,
+            stages: Default::default(),
+            stemmer: None,
+            sample_documents: None,
+            shards: 1,
+            shard: None,
+            quantize: false,
+            download: vec![],
         };
         let (cat, parse) = parsing_commands(
             &executor,
@@ -450,7 +1573,7 @@ mod tests {
             BatchSizes::default(),
             Threads {
                 parse: Some(4),
-                invert: None,
+                ..Threads::default()
             },
         )?;
         assert_eq!(cat.to_string(), format!("cat {}", data_file.display()));
@@ -465,6 +1588,41 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_stemmer() -> Result<(), Error> {
+        let tmp = TempDir::new("build").unwrap();
+        let MockSetup { mut config, .. } = mock_set_up(&tmp);
+        let backend = Arc::new(FakeBackend::new());
+        let executor = Executor::new().with_backend(backend.clone());
+        let parse_command = |collection: &Collection| -> Result<String, Error> {
+            let (_, parse) = parsing_commands(
+                &executor,
+                collection,
+                BatchSizes::default(),
+                Threads::default(),
+            )?;
+            Ok(parse.to_string())
+        };
+        let encoding = &config.collection(0).encodings[0].clone();
+        for (stemmer, expected) in &[("krovetz", Some("krovetz")), ("none", None)] {
+            config.0.collections[0].stemmer = Some(String::from(*stemmer));
+            let collection = config.collection(0);
+            let parse = parse_command(collection)?;
+            executor.evaluate_queries(collection, encoding, &"wand".into(), "topics", None)?;
+            let query_args = backend.invocations().pop().unwrap().args;
+            let query_stemmer = query_args
+                .iter()
+                .position(|arg| arg == "--stemmer")
+                .map(|pos| query_args[pos + 1].as_str());
+            assert_eq!(query_stemmer, *expected);
+            match expected {
+                Some(stemmer) => assert!(parse.contains(&format!("--stemmer {} ", stemmer))),
+                None => assert!(!parse.contains("--stemmer")),
+            }
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_make_files() {
         let tmp = TempDir::new("tmp").unwrap();
@@ -557,6 +1715,18 @@ mod tests {
             inv_index: PathBuf::from("inv"),
             encodings: vec![],
             scorers: crate::config::default_scorers(),
+            batch_sizes: crate::config::BatchSizesOverride::default(),
+            threads: Threads::default(),
+            input_glob: None,
+            preset: None,
+            builder: crate::config::Builder::Pisa,
+            stages: Default::default(),
+            stemmer: None,
+            sample_documents: None,
+            shards: 1,
+            shard: None,
+            quantize: false,
+            download: vec![],
         };
         let (cat, parse) = parsing_commands(
             &executor,
@@ -612,6 +1782,18 @@ mod tests {
             inv_index: PathBuf::from("inv"),
             encodings: vec![],
             scorers: crate::config::default_scorers(),
+            batch_sizes: crate::config::BatchSizesOverride::default(),
+            threads: Threads::default(),
+            input_glob: None,
+            preset: None,
+            builder: crate::config::Builder::Pisa,
+            stages: Default::default(),
+            stemmer: None,
+            sample_documents: None,
+            shards: 1,
+            shard: None,
+            quantize: false,
+            download: vec![],
         };
         let (cat, parse) = parsing_commands(
             &executor,
@@ -634,6 +1816,218 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_parsing_command_msmarco() -> Result<(), Error> {
+        let tmp = TempDir::new("tmp").unwrap();
+        mkfiles(
+            tmp.path(),
+            &["collection.tsv", "msmarco-docs.tsv.gz", "qrels.txt"],
+        )
+        .unwrap();
+
+        let executor = Executor::default();
+        let collection = Collection {
+            name: "msmarco".to_string(),
+            kind: CollectionKind::MsMarco,
+            input_dir: Some(tmp.path().to_path_buf()),
+            fwd_index: PathBuf::from("fwd"),
+            inv_index: PathBuf::from("inv"),
+            encodings: vec![],
+            scorers: crate::config::default_scorers(),
+            batch_sizes: crate::config::BatchSizesOverride::default(),
+            threads: Threads::default(),
+            input_glob: None,
+            preset: None,
+            builder: crate::config::Builder::Pisa,
+            stages: Default::default(),
+            stemmer: None,
+            sample_documents: None,
+            shards: 1,
+            shard: None,
+            quantize: false,
+            download: vec![],
+        };
+        let (cat, parse) = parsing_commands(
+            &executor,
+            &collection,
+            BatchSizes::default(),
+            Threads::default(),
+        )?;
+        assert_eq!(
+            cat.to_string(),
+            format!(
+                "zcat -f {} {}",
+                tmp.path().join("collection.tsv").display(),
+                tmp.path().join("msmarco-docs.tsv.gz").display()
+            )
+        );
+        assert_eq!(
+            parse.to_string(),
+            [
+                "parse_collection -o fwd -f plaintext --stemmer porter2",
+                "--content-parser html --batch-size 10000"
+            ]
+            .join(" ")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parsing_command_jsonl() -> Result<(), Error> {
+        let tmp = TempDir::new("tmp").unwrap();
+        mkfiles(tmp.path(), &["a.jsonl", "b.jsonl", "b.json"]).unwrap();
+
+        let executor = Executor::default();
+        let collection = Collection {
+            name: "corpus".to_string(),
+            kind: CollectionKind::Jsonl {
+                id_field: String::from("docid"),
+                content_field: String::from("text"),
+            },
+            input_dir: Some(tmp.path().to_path_buf()),
+            fwd_index: PathBuf::from("fwd"),
+            inv_index: PathBuf::from("inv"),
+            encodings: vec![],
+            scorers: crate::config::default_scorers(),
+            batch_sizes: crate::config::BatchSizesOverride::default(),
+            threads: Threads::default(),
+            input_glob: None,
+            preset: None,
+            builder: crate::config::Builder::Pisa,
+            stages: Default::default(),
+            stemmer: None,
+            sample_documents: None,
+            shards: 1,
+            shard: None,
+            quantize: false,
+            download: vec![],
+        };
+        let (cat, parse) = parsing_commands(
+            &executor,
+            &collection,
+            BatchSizes::default(),
+            Threads::default(),
+        )?;
+        assert_eq!(cat.get_program(), "jq");
+        assert_eq!(
+            cat.get_args().collect::<Vec<_>>(),
+            vec![
+                "-r".as_ref(),
+                "--arg".as_ref(),
+                "id".as_ref(),
+                "docid".as_ref(),
+                "--arg".as_ref(),
+                "content".as_ref(),
+                "text".as_ref(),
+                JSONL_FILTER.as_ref(),
+                tmp.path().join("a.jsonl").as_os_str(),
+                tmp.path().join("b.jsonl").as_os_str(),
+            ]
+        );
+        assert_eq!(
+            parse.to_string(),
+            [
+                "parse_collection -o fwd -f plaintext --stemmer porter2",
+                "--content-parser html --batch-size 10000"
+            ]
+            .join(" ")
+        );
+        let kind: CollectionKind = serde_yaml::from_str("jsonl: {content_field: body}").unwrap();
+        assert_eq!(
+            kind,
+            CollectionKind::Jsonl {
+                id_field: String::from("id"),
+                content_field: String::from("body"),
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parsing_command_input_glob() -> Result<(), Error> {
+        let tmp = TempDir::new("tmp").unwrap();
+        mkfiles(tmp.path(), &["collection.tsv", "queries.tsv"]).unwrap();
+
+        let executor = Executor::default();
+        let collection = Collection {
+            name: "msmarco".to_string(),
+            kind: CollectionKind::Plaintext,
+            input_dir: Some(tmp.path().to_path_buf()),
+            fwd_index: PathBuf::from("fwd"),
+            inv_index: PathBuf::from("inv"),
+            encodings: vec![],
+            scorers: crate::config::default_scorers(),
+            batch_sizes: crate::config::BatchSizesOverride::default(),
+            threads: Threads::default(),
+            input_glob: Some(String::from("collection.tsv")),
+            preset: None,
+            builder: crate::config::Builder::Pisa,
+            stages: Default::default(),
+            stemmer: None,
+            sample_documents: None,
+            shards: 1,
+            shard: None,
+            quantize: false,
+            download: vec![],
+        };
+        let (cat, parse) = parsing_commands(
+            &executor,
+            &collection,
+            BatchSizes::default(),
+            Threads::default(),
+        )?;
+        assert_eq!(
+            cat.to_string(),
+            format!("cat {}", tmp.path().join("collection.tsv").display())
+        );
+        assert!(parse.to_string().contains("-f plaintext"));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_family, unix)]
+    fn test_fetch() -> Result<(), Error> {
+        let tmp = TempDir::new("tmp").unwrap();
+        let source = tmp.path().join("source");
+        fs::create_dir(&source)?;
+        fs::write(source.join("docs.txt"), "abc")?;
+        let input_dir = tmp.path().join("input");
+        let mut collection = Collection {
+            name: "docs".to_string(),
+            kind: CollectionKind::Plaintext,
+            input_dir: Some(input_dir.clone()),
+            fwd_index: PathBuf::from("fwd"),
+            inv_index: PathBuf::from("inv"),
+            encodings: vec![],
+            scorers: crate::config::default_scorers(),
+            batch_sizes: crate::config::BatchSizesOverride::default(),
+            threads: Threads::default(),
+            input_glob: None,
+            preset: None,
+            builder: crate::config::Builder::Pisa,
+            stages: Default::default(),
+            stemmer: None,
+            sample_documents: None,
+            shards: 1,
+            shard: None,
+            quantize: false,
+            download: vec![Download {
+                url: format!("file://{}", source.join("docs.txt").display()),
+                sha256: Some(String::from("0000")),
+            }],
+        };
+        assert!(fetch(&collection).is_err());
+        assert!(!input_dir.join("docs.txt").exists());
+        assert!(!input_dir.join("docs.txt.download").exists());
+
+        collection.download[0].sha256 = Some(String::from(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        ));
+        fetch(&collection)?;
+        assert_eq!(fs::read_to_string(input_dir.join("docs.txt"))?, "abc");
+        Ok(())
+    }
+
     #[test]
     fn test_parsing_command_warc() -> Result<(), Error> {
         let tmp = TempDir::new("tmp").unwrap();
@@ -664,6 +2058,18 @@ mod tests {
             inv_index: PathBuf::from("inv"),
             encodings: vec![],
             scorers: crate::config::default_scorers(),
+            batch_sizes: crate::config::BatchSizesOverride::default(),
+            threads: Threads::default(),
+            input_glob: None,
+            preset: None,
+            builder: crate::config::Builder::Pisa,
+            stages: Default::default(),
+            stemmer: None,
+            sample_documents: None,
+            shards: 1,
+            shard: None,
+            quantize: false,
+            download: vec![],
         };
         let (cat, parse) = parsing_commands(
             &executor,
@@ -723,6 +2129,18 @@ mod tests {
             inv_index: PathBuf::from("inv"),
             encodings: vec![],
             scorers: crate::config::default_scorers(),
+            batch_sizes: crate::config::BatchSizesOverride::default(),
+            threads: Threads::default(),
+            input_glob: None,
+            preset: None,
+            builder: crate::config::Builder::Pisa,
+            stages: Default::default(),
+            stemmer: None,
+            sample_documents: None,
+            shards: 1,
+            shard: None,
+            quantize: false,
+            download: vec![],
         };
         let (cat, parse) = parsing_commands(
             &executor,
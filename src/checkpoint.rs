@@ -0,0 +1,147 @@
+//! Checkpoints of completed build steps and runs, allowing an interrupted
+//! session to be resumed without redoing finished work.
+
+use crate::ensure_parent_exists;
+use crate::error::Error;
+use failure::ResultExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Directory within the work dir where the session state is stored.
+pub const STATE_DIR: &str = ".stdbench";
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+struct State {
+    /// Completed build steps, such as `parse` or `compress.block_simdbp`, per collection.
+    #[serde(default)]
+    collections: BTreeMap<String, BTreeSet<String>>,
+    /// IDs of completed runs (see [`run_id`](../temp/fn.run_id.html)).
+    #[serde(default)]
+    runs: BTreeSet<String>,
+}
+
+/// Completed stages of a session, saved after each completed step,
+/// so that they can be skipped when the session is resumed.
+///
+/// Can be shared among threads building different collections.
+#[derive(Debug)]
+pub struct Checkpoints {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl Checkpoints {
+    /// Path to the session state within `workdir`.
+    pub fn path(workdir: &Path) -> PathBuf {
+        workdir.join(STATE_DIR).join("state.json")
+    }
+
+    /// Opens checkpoints in `workdir`. If `resume` is `true`, the checkpoints
+    /// of the previous session are loaded (if any); otherwise, they are discarded.
+    pub fn open(workdir: &Path, resume: bool) -> Result<Self, Error> {
+        let path = Self::path(workdir);
+        let state = if resume && path.exists() {
+            let file = File::open(&path).with_context(|_| path.display().to_string())?;
+            serde_json::from_reader(file).context("Failed to parse session state")?
+        } else {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+            State::default()
+        };
+        Ok(Self {
+            path,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Returns `true` if `step` of `collection` has been completed.
+    pub fn step_done(&self, collection: &str, step: &str) -> bool {
+        self.state()
+            .collections
+            .get(collection)
+            .is_some_and(|steps| steps.contains(step))
+    }
+
+    /// Records `step` of `collection` as completed.
+    pub fn complete_step(&self, collection: &str, step: &str) -> Result<(), Error> {
+        let mut state = self.state();
+        state
+            .collections
+            .entry(collection.to_string())
+            .or_default()
+            .insert(step.to_string());
+        self.save(&state)
+    }
+
+    /// Returns `true` if the run identified by `run_id` has been completed.
+    pub fn run_done(&self, run_id: &str) -> bool {
+        self.state().runs.contains(run_id)
+    }
+
+    /// Records the run identified by `run_id` as completed.
+    pub fn complete_run(&self, run_id: &str) -> Result<(), Error> {
+        let mut state = self.state();
+        state.runs.insert(run_id.to_string());
+        self.save(&state)
+    }
+
+    /// Writes the current state, e.g., when the session is interrupted,
+    /// so that it can be resumed even if no step has been completed yet.
+    pub fn flush(&self) -> Result<(), Error> {
+        self.save(&self.state())
+    }
+
+    /// The state stays consistent even if another thread panicked while holding the lock,
+    /// since it is only modified by single insertions.
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Writes the state to a temporary file first, so that an interruption
+    /// never leaves a corrupted state behind.
+    fn save(&self, state: &State) -> Result<(), Error> {
+        ensure_parent_exists(&self.path)?;
+        let json =
+            serde_json::to_string_pretty(state).context("Failed to serialize session state")?;
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_resume() -> Result<(), Error> {
+        let tmp = TempDir::new("checkpoints").unwrap();
+        let checkpoints = Checkpoints::open(tmp.path(), false)?;
+        assert!(!checkpoints.step_done("wapo", "parse"));
+        checkpoints.complete_step("wapo", "parse")?;
+        checkpoints.complete_run("0-wapo.eval")?;
+        assert!(checkpoints.step_done("wapo", "parse"));
+        assert!(!checkpoints.step_done("robust", "parse"));
+        assert!(checkpoints.run_done("0-wapo.eval"));
+
+        let resumed = Checkpoints::open(tmp.path(), true)?;
+        assert!(resumed.step_done("wapo", "parse"));
+        assert!(!resumed.step_done("wapo", "invert"));
+        assert!(resumed.run_done("0-wapo.eval"));
+
+        let flushed = Checkpoints::open(tmp.path(), false)?;
+        flushed.flush()?;
+        assert!(Checkpoints::path(tmp.path()).exists());
+
+        let restarted = Checkpoints::open(tmp.path(), false)?;
+        assert!(!restarted.step_done("wapo", "parse"));
+        assert!(!Checkpoints::path(tmp.path()).exists());
+        Ok(())
+    }
+}
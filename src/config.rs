@@ -1,18 +1,22 @@
 //! This module contains all the config definitions that are deserialized
 //! from a YAML configuration file.
 
-use crate::{CommandDebug, Error, Executor, RegressionMargin};
+use crate::sample::Shard;
+use crate::version::PisaVersion;
+use crate::{guard, registry, CommandDebug, Error, ErrorKind, Executor, RegressionMargin};
 use boolinator::Boolinator;
 use failure::{bail, format_err, ResultExt};
 use itertools::iproduct;
-use log::warn;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::{Into, TryFrom};
+use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str::FromStr;
-use std::{fmt, fs, mem};
+use std::time::Duration;
+use std::{env, fmt, fs, mem};
 use strum_macros::{Display, EnumIter, EnumString};
 
 fn process(args: &'static str) -> Command {
@@ -47,6 +51,9 @@ pub enum Stage {
     /// Index building, including parsing, inverting, and compression.
     #[strum(serialize = "build_index")]
     BuildIndex,
+    /// Downloading collection files into the input directory, a subset of `BuildIndex`.
+    #[strum(serialize = "fetch")]
+    Fetch,
     /// Parsing index, a subset of `BuildIndex`.
     #[strum(serialize = "parse")]
     Parse,
@@ -59,6 +66,10 @@ pub enum Stage {
     /// Inverting forward index, a subset of `BuildIndex`.
     #[strum(serialize = "invert")]
     Invert,
+    /// Running an external index builder instead of parsing and inverting,
+    /// a subset of `BuildIndex`.
+    #[strum(serialize = "external")]
+    External,
     /// Extracting WAND metadata index, a subset of `BuildIndex`.
     #[strum(serialize = "wand")]
     Wand,
@@ -83,6 +94,7 @@ fn default_stages() -> HashMap<Stage, bool> {
     [
         Compile,
         BuildIndex,
+        Fetch,
         Parse,
         ParseBatches,
         Join,
@@ -163,6 +175,383 @@ impl Into<String> for CMakeVar {
     }
 }
 
+/// Size in bytes, parsed from a string with an optional unit, such as `800GB`.
+///
+/// Units are powers of 1024: `B`, `KB`, `MB`, `GB`, `TB`.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::ByteSize;
+/// assert_eq!("800GB".parse::<ByteSize>().unwrap(), ByteSize(800 << 30));
+/// assert_eq!("1.5 KB".parse::<ByteSize>().unwrap(), ByteSize(1536));
+/// assert_eq!("42".parse::<ByteSize>().unwrap(), ByteSize(42));
+/// assert!("42XB".parse::<ByteSize>().is_err());
+/// ```
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = Error;
+    fn from_str(size: &str) -> Result<Self, Self::Err> {
+        let size = size.trim();
+        let pos = size
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(size.len());
+        let (number, unit) = size.split_at(pos);
+        let shift = match unit.trim().to_uppercase().as_str() {
+            "" | "B" => 0,
+            "K" | "KB" => 10,
+            "M" | "MB" => 20,
+            "G" | "GB" => 30,
+            "T" | "TB" => 40,
+            _ => return Err(Error::from(format!("Invalid size unit: {}", size))),
+        };
+        let number: f64 = number
+            .parse()
+            .map_err(|_| Error::from(format!("Invalid size: {}", size)))?;
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let bytes = (number * (1_u64 << shift) as f64) as u64;
+        Ok(Self(bytes))
+    }
+}
+
+impl TryFrom<String> for ByteSize {
+    type Error = Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}B", self.0)
+    }
+}
+
+impl Into<String> for ByteSize {
+    fn into(self) -> String {
+        format!("{}", self)
+    }
+}
+
+/// I/O scheduling class and level, as set by `ionice`.
+///
+/// Levels range from 0 (highest) to 7 (lowest) and default to 4.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::IoPriority;
+/// assert_eq!("idle".parse::<IoPriority>().unwrap(), IoPriority::Idle);
+/// assert_eq!("best-effort".parse::<IoPriority>().unwrap(), IoPriority::BestEffort(4));
+/// assert_eq!("realtime:0".parse::<IoPriority>().unwrap(), IoPriority::Realtime(0));
+/// assert!("best-effort:8".parse::<IoPriority>().is_err());
+/// assert!("low".parse::<IoPriority>().is_err());
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum IoPriority {
+    /// Served first regardless of other processes; requires privileges.
+    Realtime(u8),
+    /// The default class of processes.
+    BestEffort(u8),
+    /// Served only when no other process needs the disk.
+    Idle,
+}
+
+impl IoPriority {
+    /// Value passed to the `ioprio_set` system call.
+    pub fn ioprio(self) -> i32 {
+        match self {
+            Self::Realtime(level) => (1 << 13) | i32::from(level),
+            Self::BestEffort(level) => (2 << 13) | i32::from(level),
+            Self::Idle => 3 << 13,
+        }
+    }
+}
+
+impl FromStr for IoPriority {
+    type Err = Error;
+    fn from_str(priority: &str) -> Result<Self, Self::Err> {
+        let mut parts = priority.trim().splitn(2, ':');
+        let class = parts.next().unwrap_or_default();
+        let level = match parts.next() {
+            Some(level) => level
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|&level| level <= 7)
+                .ok_or_else(|| format_err!("Invalid I/O priority level: {}", priority))?,
+            None => 4,
+        };
+        match class.trim() {
+            "realtime" => Ok(Self::Realtime(level)),
+            "best-effort" => Ok(Self::BestEffort(level)),
+            "idle" => Ok(Self::Idle),
+            _ => Err(Error::from(format!(
+                "Invalid I/O priority class: {}",
+                priority
+            ))),
+        }
+    }
+}
+
+impl TryFrom<String> for IoPriority {
+    type Error = Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for IoPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Realtime(level) => write!(f, "realtime:{}", level),
+            Self::BestEffort(level) => write!(f, "best-effort:{}", level),
+            Self::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+impl Into<String> for IoPriority {
+    fn into(self) -> String {
+        format!("{}", self)
+    }
+}
+
+/// CPU and I/O priority of a process; unset values are inherited.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Priority {
+    /// Niceness, from -20 (highest priority) to 19 (lowest priority).
+    #[serde(default)]
+    pub nice: Option<i32>,
+    /// I/O scheduling class and level.
+    #[serde(default)]
+    pub ionice: Option<IoPriority>,
+}
+
+/// Priorities of index building and of measurements, set separately so that
+/// builds running on the same machine do not contaminate measured times.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Priorities {
+    /// Priority of all build stages.
+    #[serde(default)]
+    pub build: Priority,
+    /// Priority of runs.
+    #[serde(default)]
+    pub run: Priority,
+}
+
+impl Priorities {
+    /// Priority of processes executed in `stage`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::config::{Priorities, Priority};
+    /// # use stdbench::Stage;
+    /// let priorities = Priorities {
+    ///     build: Priority { nice: Some(19), ionice: None },
+    ///     run: Priority::default(),
+    /// };
+    /// assert_eq!(priorities.for_stage(Stage::Compress).nice, Some(19));
+    /// assert_eq!(priorities.for_stage(Stage::Run).nice, None);
+    /// ```
+    pub fn for_stage(&self, stage: Stage) -> Priority {
+        match stage {
+            Stage::Run | Stage::Compare => self.run,
+            _ => self.build,
+        }
+    }
+}
+
+/// CPUs and NUMA node to which the `queries` commands of benchmarks are bound,
+/// so that measured latencies are not affected by migrations across sockets.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct Pinning {
+    /// CPU list in the format of `taskset -c`, e.g., `0-3,8`.
+    #[serde(default)]
+    pub cpus: Option<String>,
+    /// NUMA node whose CPUs and memory are used, through `numactl`.
+    #[serde(default)]
+    pub numa_node: Option<u32>,
+}
+
+impl Pinning {
+    /// Wrapper program and its arguments to prefix pinned commands with,
+    /// or an empty vector if nothing is pinned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::config::Pinning;
+    /// let cpus = Pinning { cpus: Some(String::from("0-3")), numa_node: None };
+    /// assert_eq!(cpus.prefix(), vec!["taskset", "-c", "0-3"]);
+    /// let node = Pinning { cpus: None, numa_node: Some(1) };
+    /// assert_eq!(node.prefix(), vec!["numactl", "--cpunodebind=1", "--membind=1"]);
+    /// let both = Pinning { cpus: Some(String::from("0-3")), numa_node: Some(0) };
+    /// assert_eq!(both.prefix(), vec!["numactl", "--physcpubind=0-3", "--membind=0"]);
+    /// assert!(Pinning::default().prefix().is_empty());
+    /// ```
+    pub fn prefix(&self) -> Vec<String> {
+        match (&self.cpus, self.numa_node) {
+            (None, None) => vec![],
+            (Some(cpus), None) => vec![String::from("taskset"), String::from("-c"), cpus.clone()],
+            (None, Some(node)) => vec![
+                String::from("numactl"),
+                format!("--cpunodebind={}", node),
+                format!("--membind={}", node),
+            ],
+            (Some(cpus), Some(node)) => vec![
+                String::from("numactl"),
+                format!("--physcpubind={}", cpus),
+                format!("--membind={}", node),
+            ],
+        }
+    }
+}
+
+/// Seconds in a day.
+const DAY_SECONDS: u32 = 24 * 60 * 60;
+
+/// Daily time window, written as `HH:MM-HH:MM` in local time.
+///
+/// The start is inclusive and the end exclusive; a window ending before it starts
+/// spans midnight.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::TimeWindow;
+/// let window: TimeWindow = "22:00-06:00".parse().unwrap();
+/// assert_eq!(window.to_string(), "22:00-06:00");
+/// assert!(window.contains(23 * 3600));
+/// assert!(window.contains(3600));
+/// assert!(!window.contains(6 * 3600));
+/// assert!("06:00-06:00".parse::<TimeWindow>().is_err());
+/// assert!("24:00-06:00".parse::<TimeWindow>().is_err());
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeWindow {
+    /// Start, in minutes after midnight.
+    pub start: u32,
+    /// End, in minutes after midnight.
+    pub end: u32,
+}
+
+impl TimeWindow {
+    /// Whether the time `seconds` after midnight is within the window.
+    pub fn contains(self, seconds: u32) -> bool {
+        let (start, end) = (self.start * 60, self.end * 60);
+        if start < end {
+            start <= seconds && seconds < end
+        } else {
+            seconds >= start || seconds < end
+        }
+    }
+
+    /// Seconds from the time `seconds` after midnight until the window opens,
+    /// or zero if it is already open.
+    pub fn wait_seconds(self, seconds: u32) -> u32 {
+        if self.contains(seconds) {
+            0
+        } else {
+            (self.start * 60 + DAY_SECONDS - seconds) % DAY_SECONDS
+        }
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = Error;
+    fn from_str(window: &str) -> Result<Self, Self::Err> {
+        let invalid = || format_err!("Invalid time window: {}", window);
+        let minutes = |time: &str| -> Result<u32, Error> {
+            let mut parts = time.trim().splitn(2, ':');
+            let hours = parts.next().and_then(|h| h.parse::<u32>().ok());
+            let minutes = parts.next().and_then(|m| m.parse::<u32>().ok());
+            match (hours, minutes) {
+                (Some(hours), Some(minutes)) if hours < 24 && minutes < 60 => {
+                    Ok(hours * 60 + minutes)
+                }
+                _ => Err(invalid().into()),
+            }
+        };
+        let mut parts = window.splitn(2, '-');
+        let start = minutes(parts.next().unwrap_or_default())?;
+        let end = minutes(parts.next().ok_or_else(invalid)?)?;
+        (start != end).ok_or_else(|| format!("Empty time window: {}", window))?;
+        Ok(Self { start, end })
+    }
+}
+
+impl TryFrom<String> for TimeWindow {
+    type Error = Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )
+    }
+}
+
+impl Into<String> for TimeWindow {
+    fn into(self) -> String {
+        format!("{}", self)
+    }
+}
+
+/// Time windows in which builds and measurements may execute, so that shared
+/// machines are not loaded during working hours. Stages without a window run anytime.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Schedule {
+    /// Window of all build stages.
+    #[serde(default)]
+    pub build: Option<TimeWindow>,
+    /// Window of runs.
+    #[serde(default)]
+    pub run: Option<TimeWindow>,
+}
+
+impl Schedule {
+    /// Window of processes executed in `stage`, if restricted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::config::Schedule;
+    /// # use stdbench::Stage;
+    /// let schedule = Schedule {
+    ///     build: None,
+    ///     run: Some("00:00-06:00".parse().unwrap()),
+    /// };
+    /// assert_eq!(schedule.for_stage(Stage::Compress), None);
+    /// assert_eq!(schedule.for_stage(Stage::Run).unwrap().to_string(), "00:00-06:00");
+    /// ```
+    pub fn for_stage(&self, stage: Stage) -> Option<TimeWindow> {
+        match stage {
+            Stage::Run | Stage::Compare => self.run,
+            _ => self.build,
+        }
+    }
+}
+
 /// Batch sizes for building index.
 ///
 /// # Examples
@@ -197,10 +586,57 @@ impl Default for BatchSizes {
     }
 }
 
+/// Per-collection overrides of [`BatchSizes`](struct.BatchSizes.html).
+///
+/// Missing values fall back on to the global batch sizes.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::{BatchSizes, BatchSizesOverride};
+/// let overrides = BatchSizesOverride {
+///     parse: Some(50_000),
+///     invert: None,
+/// };
+/// let batch_sizes = overrides.merged_over(BatchSizes::default());
+/// assert_eq!(batch_sizes.parse, 50_000);
+/// assert_eq!(batch_sizes.invert, 10_000);
+/// ```
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct BatchSizesOverride {
+    /// Batch size for `parse_collection`.
+    #[serde(default)]
+    pub parse: Option<usize>,
+    /// Batch size for `invert`.
+    #[serde(default)]
+    pub invert: Option<usize>,
+}
+
+impl BatchSizesOverride {
+    /// Returns the batch sizes with the overridden values replaced.
+    pub fn merged_over(self, global: BatchSizes) -> BatchSizes {
+        BatchSizes {
+            parse: self.parse.unwrap_or(global.parse),
+            invert: self.invert.unwrap_or(global.invert),
+        }
+    }
+}
+
 /// Thread counts for building index.
 ///
 /// By default, all are equal to `None`, which will cause the tools to be called
 /// without `--threads` parameter, and the thread pool will be calculated by TBB.
+/// The `default` count applies to the tools without their own.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::{Stage, Threads};
+/// let threads: Threads = serde_yaml::from_str("{default: 8, wand: 2}").unwrap();
+/// assert_eq!(threads.for_stage(Stage::Wand), Some(2));
+/// assert_eq!(threads.for_stage(Stage::Compress), Some(8));
+/// assert_eq!(Threads::default().for_stage(Stage::Compress), None);
+/// ```
 #[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct Threads {
     /// Thread count for `parse_collection`.
@@ -209,6 +645,15 @@ pub struct Threads {
     /// Thread count for `invert`.
     #[serde(default)]
     pub invert: Option<usize>,
+    /// Thread count for `create_freq_index`.
+    #[serde(default)]
+    pub compress: Option<usize>,
+    /// Thread count for `create_wand_data`.
+    #[serde(default)]
+    pub wand: Option<usize>,
+    /// Thread count for the tools without their own.
+    #[serde(default)]
+    pub default: Option<usize>,
 }
 
 impl Default for Threads {
@@ -216,8 +661,90 @@ impl Default for Threads {
         Self {
             parse: None,
             invert: None,
+            compress: None,
+            wand: None,
+            default: None,
+        }
+    }
+}
+
+impl Threads {
+    /// Returns the thread counts with values missing in `self` taken from `global`.
+    pub fn merged_over(self, global: Self) -> Self {
+        Self {
+            parse: self.parse.or(global.parse),
+            invert: self.invert.or(global.invert),
+            compress: self.compress.or(global.compress),
+            wand: self.wand.or(global.wand),
+            default: self.default.or(global.default),
         }
     }
+
+    /// Thread count of the tool of a build `stage`, or `default` if it has none.
+    pub fn for_stage(self, stage: Stage) -> Option<usize> {
+        let threads = match stage {
+            Stage::Parse | Stage::ParseBatches => self.parse,
+            Stage::Invert => self.invert,
+            Stage::Compress => self.compress,
+            Stage::Wand => self.wand,
+            _ => None,
+        };
+        threads.or(self.default)
+    }
+}
+
+/// Time limits of tool processes, in seconds, after which they are killed together
+/// with their children, e.g., when a tool hangs in an unattended session.
+///
+/// By default, all are equal to `None`, and processes run without limit.
+/// The `default` limit applies to the stages without their own; the `run` limit
+/// applies to each command of a run, and can be overridden by the run's `timeout`.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::{Stage, Timeouts};
+/// # use std::time::Duration;
+/// let timeouts: Timeouts = serde_yaml::from_str("{default: 3600, run: 600}").unwrap();
+/// assert_eq!(timeouts.for_stage(Stage::Run), Some(Duration::from_secs(600)));
+/// assert_eq!(timeouts.for_stage(Stage::Join), Some(Duration::from_secs(3600)));
+/// assert_eq!(Timeouts::default().for_stage(Stage::Invert), None);
+/// ```
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+pub struct Timeouts {
+    /// Limit of `parse_collection`, including joining batches.
+    #[serde(default)]
+    pub parse: Option<u64>,
+    /// Limit of `invert`.
+    #[serde(default)]
+    pub invert: Option<u64>,
+    /// Limit of `create_freq_index`.
+    #[serde(default)]
+    pub compress: Option<u64>,
+    /// Limit of `create_wand_data`.
+    #[serde(default)]
+    pub wand: Option<u64>,
+    /// Limit of each command of a run.
+    #[serde(default)]
+    pub run: Option<u64>,
+    /// Limit of the stages without their own.
+    #[serde(default)]
+    pub default: Option<u64>,
+}
+
+impl Timeouts {
+    /// Time limit of the processes of `stage`, or `default` if it has none.
+    pub fn for_stage(self, stage: Stage) -> Option<Duration> {
+        let seconds = match stage {
+            Stage::Parse | Stage::ParseBatches | Stage::Join => self.parse,
+            Stage::Invert => self.invert,
+            Stage::Compress => self.compress,
+            Stage::Wand => self.wand,
+            Stage::Run | Stage::Compare => self.run,
+            _ => None,
+        };
+        seconds.or(self.default).map(Duration::from_secs)
+    }
 }
 
 /// Main config interface.
@@ -234,18 +761,90 @@ pub trait Config {
     fn disable(&mut self, stage: Stage);
     /// Returns `true` if a given stage is effectively enabled.
     fn enabled(&self, stage: Stage) -> bool;
+    /// Returns `true` if a given stage is effectively enabled for `collection`:
+    /// its own setting takes precedence over the global one.
+    fn enabled_for(&self, collection: &Collection, stage: Stage) -> bool {
+        collection
+            .stages
+            .get(&stage)
+            .copied()
+            .unwrap_or_else(|| self.enabled(stage))
+    }
     /// Construct an executor for a set of PISA tools.
     fn executor(&self) -> Result<Executor, Error>;
+    /// Construct an executor for the tools of the source named `name` in `sources`.
+    fn source_executor(&self, name: &str) -> Result<Executor, Error>;
+    /// Construct an executor resolving the same programs as [`executor`](#tymethod.executor),
+    /// but without compiling or building anything, for planning commands.
+    fn planned_executor(&self) -> Executor;
     /// Use `--scorer`. `false` for legacy PISA code before `ql3`.
     fn use_scorer(&self) -> bool;
     /// Clean up before running: remove work dir.
     fn clean(&self) -> bool;
+    /// Retain temporary files of failed runs.
+    fn keep_temp(&self) -> bool;
+    /// Skip build steps and runs completed in the previous session.
+    fn resume(&self) -> bool;
+    /// Path to write an HTML report of all results to, if any.
+    fn report(&self) -> Option<&Path>;
+    /// Path to write a JSON summary of the session to, if any.
+    fn summary(&self) -> Option<&Path>;
+    /// Path to write a status badge of the session to, if any.
+    fn badge(&self) -> Option<&Path>;
+    /// SQLite database accumulating the results of all sessions, if any.
+    fn results_db(&self) -> Option<&Path>;
+    /// URL of a Prometheus Pushgateway to push the results to, if any.
+    fn push_metrics(&self) -> Option<&str>;
+    /// Replace absolute paths in reports and summaries with placeholders.
+    fn redact_paths(&self) -> bool;
+    /// Local port on which the execution state is served over HTTP, if any.
+    fn status_port(&self) -> Option<u16>;
     /// Batch size of a particular batched job.
     fn batch_sizes(&self) -> BatchSizes;
     /// Thread counts of a particular batched job.
     fn threads(&self) -> Threads;
     /// Performance regression margin.
     fn margin(&self) -> RegressionMargin;
+    /// Work directory of a baseline session to compare build times with.
+    fn compare_build_times_with(&self) -> Option<&Path>;
+    /// Maximum aggregate size of the work directory.
+    fn max_workdir_size(&self) -> Option<ByteSize>;
+    /// Number of collections built concurrently.
+    fn build_threads(&self) -> usize;
+    /// Compress batches left by a failed parse until they are merged.
+    fn compress_batches(&self) -> bool;
+    /// Hard-link identical compressed indexes and WAND data of different collections.
+    fn share_indexes(&self) -> bool;
+    /// Fail if CPU settings of the host make latencies unstable, instead of warning.
+    fn strict_environment(&self) -> bool;
+    /// Failures forced for testing the harness itself.
+    fn injected_failures(&self) -> &[FailureInjection];
+    /// Priorities of build and run processes.
+    fn priorities(&self) -> Priorities;
+    /// Time windows in which build and run processes may execute.
+    fn schedule(&self) -> Schedule;
+    /// Lock file isolating measurement runs from other tasks on the host
+    /// (see [`guard`](../guard/index.html)).
+    fn host_lock(&self) -> PathBuf;
+    /// Host on which indexes are built instead of the local one, if any.
+    fn build_host(&self) -> Option<&BuildHost>;
+
+    /// Fails if a failure of `stage` is injected for `collection`
+    /// (`None` for stages not bound to a collection, such as `compile`).
+    fn check_injected_failure(&self, stage: Stage, collection: Option<&str>) -> Result<(), Error> {
+        if self
+            .injected_failures()
+            .iter()
+            .any(|injection| injection.matches(stage, collection))
+        {
+            Err(Error::from(match collection {
+                Some(collection) => format!("Injected failure of {} for {}", stage, collection),
+                None => format!("Injected failure of {}", stage),
+            }))
+        } else {
+            Ok(())
+        }
+    }
 
     /// Retrieve a collection at a given index.
     ///
@@ -280,13 +879,19 @@ pub trait Resolved {}
 /// On the other hand, the config validation step will fail if a value is absent
 /// from both global and run configuration.
 #[derive(Serialize, Deserialize, Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct RawConfig {
     /// All relative paths will fall back on to this directory.
     pub workdir: PathBuf,
     /// Source of the PISA tools.
     #[serde(default)]
     pub source: Source,
+    /// Additional named sources, e.g., other branches, whose tools execute runs
+    /// selecting them or produce baselines in the same session.
+    #[serde(default)]
+    pub sources: BTreeMap<String, Source>,
     /// List of collections.
+    #[serde(deserialize_with = "deserialize_collections")]
     pub collections: Vec<Collection>,
     /// List of experiments.
     #[serde(default)]
@@ -300,6 +905,35 @@ pub struct RawConfig {
     /// Clean up before running: remove work dir.
     #[serde(default)]
     pub clean: bool,
+    /// Retain temporary files of failed runs for debugging.
+    #[serde(default)]
+    pub keep_temp: bool,
+    /// Skip build steps and runs completed in the previous session.
+    #[serde(default)]
+    pub resume: bool,
+    /// Path to write an HTML report of all results to, if any.
+    #[serde(default)]
+    pub report: Option<PathBuf>,
+    /// Path to write a JSON summary of the session to, in addition to the work directory.
+    #[serde(default)]
+    pub summary: Option<PathBuf>,
+    /// Path to write a status badge of the session to, in addition to the work directory.
+    #[serde(default)]
+    pub badge: Option<PathBuf>,
+    /// SQLite database to insert the results of each run into, tracking their history
+    /// across sessions (see [`database`](../database/index.html)).
+    #[serde(default)]
+    pub results_db: Option<PathBuf>,
+    /// URL of a Prometheus Pushgateway to push the results to at the end of a session
+    /// (see [`prometheus`](../prometheus/index.html)).
+    #[serde(default)]
+    pub push_metrics: Option<String>,
+    /// Replace absolute paths in reports and summaries with placeholders.
+    #[serde(default)]
+    pub redact_paths: bool,
+    /// Local port on which the execution state is served over HTTP (see [`status`](../status/index.html)).
+    #[serde(default)]
+    pub status_port: Option<u16>,
     /// Batch sizes.
     #[serde(default)]
     pub batch_sizes: BatchSizes,
@@ -315,34 +949,628 @@ pub struct RawConfig {
     #[serde(default)]
     /// Performance regression margin.
     pub margin: RegressionMargin,
+    /// Template variables substituted for `{{name}}` in the config file.
+    #[serde(default)]
+    pub vars: HashMap<String, String>,
+    /// Work directory of a baseline session to compare lexicon and WAND data
+    /// build times with.
+    #[serde(default)]
+    pub compare_build_times_with: Option<PathBuf>,
+    /// Encodings supported by particular algorithms; algorithms not listed
+    /// are assumed to support all encodings.
+    #[serde(default)]
+    pub compatibility: HashMap<Algorithm, Vec<Encoding>>,
+    /// Maximum aggregate size of the work directory; when exceeded,
+    /// no new builds or runs are scheduled.
+    #[serde(default)]
+    pub max_workdir_size: Option<ByteSize>,
+    /// Number of collections built concurrently (1 by default).
+    #[serde(default)]
+    pub build_threads: Option<usize>,
+    /// Compress forward index batches left on disk by a failed parse with `gzip`,
+    /// until they are merged in a later session.
+    #[serde(default)]
+    pub compress_batches: bool,
+    /// Replace compressed indexes and WAND data identical to those of another collection
+    /// with hard links, so that they are stored once.
+    #[serde(default)]
+    pub share_indexes: bool,
+    /// Priorities of build and run processes.
+    #[serde(default)]
+    pub priorities: Priorities,
+    /// Time limits of tool processes, after which they are killed.
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    /// Time windows in which build and run processes may execute.
+    #[serde(default)]
+    pub schedule: Schedule,
+    /// CPUs and NUMA node to which benchmarks are bound.
+    #[serde(default)]
+    pub pinning: Pinning,
+    /// Fail instead of warning when CPU settings of the host make latencies unstable,
+    /// such as active SMT (see [`host`](../host/index.html)).
+    #[serde(default)]
+    pub strict_environment: bool,
+    /// Paths of individual tools used instead of those of the source,
+    /// e.g., a locally patched `create_wand_data`.
+    #[serde(default)]
+    pub tool_overrides: BTreeMap<String, PathBuf>,
+    /// Location of the `trec_eval` executable.
+    #[serde(default)]
+    pub trec_eval: TrecEval,
+    /// Implementation evaluating the results of runs.
+    #[serde(default)]
+    pub evaluator: Evaluator,
+    /// Rebuild the tools of git sources even if a complete build of the same commit is cached
+    /// under `workdir/builds`.
+    #[serde(default)]
+    pub force_rebuild: bool,
+    /// Show the standard output and error of PISA tools run to completion,
+    /// such as `invert` or `compress`; hidden by default.
+    #[serde(default)]
+    pub show_tool_output: bool,
+    /// Environment variables of all executed commands, such as `OMP_NUM_THREADS`.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Lock file shared by all sessions on the host, with which measurement runs
+    /// never execute concurrently with builds or other runs
+    /// (`stdbench.lock` in the system temporary directory by default).
+    #[serde(default)]
+    pub host_lock: Option<PathBuf>,
+    /// Host on which indexes are built over SSH and copied from, so that runs are measured
+    /// on the local host, while builds execute elsewhere.
+    #[serde(default)]
+    pub build_host: Option<BuildHost>,
+    /// Oldest PISA version the tools may have; older tools are refused
+    /// (see [`version`](../version/index.html)).
+    #[serde(default)]
+    pub min_pisa_version: Option<PisaVersion>,
+    /// Keys that were not recognized when parsing, in non-strict mode.
+    #[serde(skip)]
+    pub unknown_keys: Vec<String>,
+    /// Failures forced for testing the harness itself; only set in the command line.
+    #[serde(skip)]
+    pub injected_failures: Vec<FailureInjection>,
 }
 
-struct CMake<'a> {
-    cmake_vars: &'a [CMakeVar],
-    dir: &'a Path,
+fn default_remote_stdbench() -> PathBuf {
+    PathBuf::from("stdbench")
 }
 
-impl<'a> CMake<'a> {
-    fn new(cmake_vars: &'a [CMakeVar], dir: &'a Path) -> Self {
-        Self { cmake_vars, dir }
+/// Host on which indexes are built (see [`remote`](../remote/index.html)).
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::BuildHost;
+/// # use std::path::PathBuf;
+/// let build_host: BuildHost = serde_yaml::from_str("host: bench@builder").unwrap();
+/// assert_eq!(build_host.host, "bench@builder");
+/// assert_eq!(build_host.stdbench, PathBuf::from("stdbench"));
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BuildHost {
+    /// SSH destination, e.g., `user@builder`.
+    pub host: String,
+    /// Path to the `stdbench` executable on the build host.
+    #[serde(default = "default_remote_stdbench")]
+    pub stdbench: PathBuf,
+}
+
+/// Variable definition passed in the command line, such as `branch=dev`,
+/// which overrides the value in the `vars` section of the config.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ConfigVar {
+    name: String,
+    value: String,
+}
+
+impl FromStr for ConfigVar {
+    type Err = Error;
+    fn from_str(var: &str) -> Result<Self, Self::Err> {
+        if let Some(pos) = var.find('=') {
+            Ok(Self {
+                name: String::from(var[..pos].trim()),
+                value: String::from(&var[pos + 1..]),
+            })
+        } else {
+            Err(Error::from("Variable definition must contain `=`."))
+        }
     }
-    fn configure(&self) -> Result<(), Error> {
-        let mut cmd = Command::new("cmake");
+}
+
+/// Failure of a stage forced in the command line, such as `stage=compress,collection=gov2`,
+/// to exercise how the harness handles failures, e.g., when resuming or reporting.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::FailureInjection;
+/// # use stdbench::Stage;
+/// let injection: FailureInjection = "stage=compress,collection=gov2".parse().unwrap();
+/// assert!(injection.matches(Stage::Compress, Some("gov2")));
+/// assert!(!injection.matches(Stage::Compress, Some("wapo")));
+/// assert!(!injection.matches(Stage::Wand, Some("gov2")));
+/// let injection: FailureInjection = "stage=compile".parse().unwrap();
+/// assert!(injection.matches(Stage::Compile, None));
+/// assert!("collection=gov2".parse::<FailureInjection>().is_err());
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FailureInjection {
+    /// Stage to fail.
+    pub stage: Stage,
+    /// Collection to fail the stage for; all collections if `None`.
+    pub collection: Option<String>,
+}
+
+impl FailureInjection {
+    /// Returns `true` if the failure applies to `stage` of `collection`.
+    pub fn matches(&self, stage: Stage, collection: Option<&str>) -> bool {
+        self.stage == stage
+            && self
+                .collection
+                .as_deref()
+                .is_none_or(|name| collection == Some(name))
+    }
+}
+
+impl FromStr for FailureInjection {
+    type Err = Error;
+    fn from_str(injection: &str) -> Result<Self, Self::Err> {
+        let mut stage = None;
+        let mut collection = None;
+        for field in injection.split(',') {
+            match field.split_once('=') {
+                Some(("stage", value)) => {
+                    stage = Some(
+                        value
+                            .trim()
+                            .parse::<Stage>()
+                            .map_err(|_| format_err!("Unknown stage: {}", value))?,
+                    );
+                }
+                Some(("collection", value)) => collection = Some(value.trim().to_string()),
+                _ => return Err(Error::from(format!("Invalid failure field: {}", field))),
+            }
+        }
+        Ok(Self {
+            stage: stage.ok_or("Failure injection must define `stage`.")?,
+            collection,
+        })
+    }
+}
+
+/// Replaces all occurrences of `{{name}}` with the value of `name` in `vars`,
+/// except in comments, which start with `#` at the beginning of a line or after whitespace.
+///
+/// Within a quoted string, the value is escaped according to the quotes,
+/// and elsewhere it is inserted verbatim, so values that are not plain YAML scalars,
+/// e.g., containing `: ` or ` #`, must be referenced within quotes.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::substitute_vars;
+/// # use std::collections::HashMap;
+/// let mut vars = HashMap::new();
+/// vars.insert(String::from("k"), String::from("10"));
+/// vars.insert(String::from("q"), String::from(r#"say "hi""#));
+/// assert_eq!(substitute_vars("k: {{k}}", &vars).unwrap(), "k: 10");
+/// assert_eq!(substitute_vars("k: {{ k }}", &vars).unwrap(), "k: 10");
+/// assert_eq!(substitute_vars("k: 1 # {{x}}", &vars).unwrap(), "k: 1 # {{x}}");
+/// assert_eq!(substitute_vars(r#"q: "{{q}}""#, &vars).unwrap(), r#"q: "say \"hi\"""#);
+/// assert!(substitute_vars("branch: {{branch}}", &vars).is_err());
+/// ```
+pub fn substitute_vars(template: &str, vars: &HashMap<String, String>) -> Result<String, Error> {
+    let mut output = String::with_capacity(template.len());
+    for line in template.split_inclusive('\n') {
+        substitute_line(line, vars, &mut output)?;
+    }
+    Ok(output)
+}
+
+/// Substitutes the variables of a single line for [`substitute_vars`](fn.substitute_vars.html).
+fn substitute_line(
+    line: &str,
+    vars: &HashMap<String, String>,
+    output: &mut String,
+) -> Result<(), Error> {
+    let mut quote: Option<char> = None;
+    let mut prev: Option<char> = None;
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("{{") {
+            let end = rest
+                .find("}}")
+                .ok_or_else(|| format_err!("Unclosed variable: {}", rest.trim_end()))?;
+            let name = rest[2..end].trim();
+            let value = vars
+                .get(name)
+                .ok_or_else(|| format_err!("Undefined variable: {}", name))?;
+            match quote {
+                Some('"') => output.push_str(&value.replace('\\', "\\\\").replace('"', "\\\"")),
+                Some(_) => output.push_str(&value.replace('\'', "''")),
+                None => output.push_str(value),
+            }
+            prev = Some('}');
+            rest = &rest[end + 2..];
+            continue;
+        }
+        let mut len = c.len_utf8();
+        match quote {
+            Some('"') if c == '\\' => len += rest[1..].chars().next().map_or(0, char::len_utf8),
+            Some('\'') if rest.starts_with("''") => len += 1,
+            Some(q) if c == q => quote = None,
+            None if c == '#' && prev.is_none_or(char::is_whitespace) => {
+                output.push_str(rest);
+                return Ok(());
+            }
+            None if (c == '"' || c == '\'')
+                && prev.is_none_or(|p| p.is_whitespace() || "[{,:-?".contains(p)) =>
+            {
+                quote = Some(c);
+            }
+            _ => {}
+        }
+        output.push_str(&rest[..len]);
+        prev = Some(c);
+        rest = &rest[len..];
+    }
+    Ok(())
+}
+
+impl RawConfig {
+    /// Parses a YAML config, first substituting the variables defined in its
+    /// `vars` section, overridden by `overrides`.
+    ///
+    /// Unknown keys, such as misspelled field names, are ignored
+    /// and stored in [`unknown_keys`](#structfield.unknown_keys).
+    pub fn parse(yaml: &str, overrides: &[ConfigVar]) -> Result<Self, Error> {
+        Self::parse_with(yaml, overrides, false).map_err(|err| err.wrap(ErrorKind::Config))
+    }
+
+    /// Same as [`parse`](#method.parse) but fails if the config contains any unknown keys.
+    pub fn parse_strict(yaml: &str, overrides: &[ConfigVar]) -> Result<Self, Error> {
+        Self::parse_with(yaml, overrides, true).map_err(|err| err.wrap(ErrorKind::Config))
+    }
+
+    fn parse_with(yaml: &str, overrides: &[ConfigVar], strict: bool) -> Result<Self, Error> {
+        #[derive(Deserialize)]
+        struct Vars {
+            #[serde(default)]
+            vars: HashMap<String, String>,
+        }
+        let mut vars = serde_yaml::from_str::<Vars>(yaml)
+            .context("Failed to parse config variables")?
+            .vars;
+        for var in overrides {
+            vars.insert(var.name.clone(), var.value.clone());
+        }
+        let yaml = substitute_vars(yaml, &vars)?;
+        let yaml: serde_yaml::Value =
+            serde_yaml::from_str(&yaml).context("Failed to parse config")?;
+        let mut unknown = Vec::new();
+        let mut config: Self = serde_ignored::deserialize(yaml, |path| {
+            unknown.push(path.to_string());
+        })
+        .context("Failed to parse config")?;
+        config.vars = vars;
+        if !unknown.is_empty() {
+            if strict {
+                return Err(Error::from(format!(
+                    "Unknown config keys: {}",
+                    unknown.join(", ")
+                )));
+            }
+            config.unknown_keys = unknown;
+        }
+        Ok(config)
+    }
+
+    /// Applies the tool overrides, evaluator, environment, and other execution settings
+    /// of the config to an executor of compiled tools.
+    fn configure_executor(&self, executor: Executor) -> Result<Executor, Error> {
+        let executor = executor.with_tool_overrides(self.tool_overrides.clone());
+        let trec_eval = match self.evaluator {
+            Evaluator::TrecEval => self.trec_eval.resolve(&self.workdir, &executor)?,
+            Evaluator::Internal => None,
+        };
+        let executor = match trec_eval {
+            Some(trec_eval) => executor.with_trec_eval(trec_eval),
+            None => executor,
+        };
+        Ok(executor
+            .with_evaluator(self.evaluator)
+            .with_tool_output(self.show_tool_output)
+            .with_env(&self.env)
+            .with_priorities(self.priorities)
+            .with_timeouts(self.timeouts)
+            .with_schedule(self.schedule)
+            .with_pinning(self.pinning.clone()))
+    }
+
+    /// Returns an executor of the tools found in the system, configured as in a session,
+    /// e.g., for evaluating stored results without compiling PISA.
+    pub fn system_executor(&self) -> Result<Executor, Error> {
+        self.configure_executor(Executor::new())
+    }
+
+    /// Fetches and compiles the tools of `source`, unless compilation is suppressed,
+    /// returning an executor of them.
+    fn compile(&self, source: &Source) -> Result<Executor, Error> {
+        Ok(match source {
+            Source::System => Executor::new(),
+            Source::Git { branch, url, .. } => {
+                let dir = source.git_dir(&self.workdir).unwrap();
+                let repo = if dir.exists() {
+                    git2::Repository::open(&dir)?
+                } else {
+                    git2::Repository::clone_recurse(&url, &dir).map_err(|_| "git-clone failed")?
+                };
+                let build_dir = if self.stages.get(&Stage::Compile).cloned().unwrap_or(true) {
+                    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))?;
+                    let commit = update_repo(&repo, &branch)?;
+                    self.build_commit(source, &dir, commit)?
+                } else {
+                    warn!("Compilation has been suppressed");
+                    dir.join("build")
+                };
+                Executor::from(build_dir.join("bin"))?
+            }
+            Source::Path(path) => Executor::from(path.to_path_buf())?,
+            Source::Docker(_) => unimplemented!(),
+        })
+    }
+
+    /// Checks out `commit` in the local repository of a git source, which must have been
+    /// cloned already, and compiles the tools, returning an executor of them.
+    ///
+    /// The repository is left detached at `commit`.
+    pub fn executor_at(&self, commit: &str) -> Result<Executor, Error> {
+        let dir = self
+            .source
+            .git_dir(&self.workdir)
+            .ok_or("Building a commit requires a git source")?;
+        let repo = git2::Repository::open(&dir)?;
+        let commit = repo.revparse_single(commit)?.peel_to_commit()?;
+        repo.checkout_tree(
+            commit.as_object(),
+            Some(git2::build::CheckoutBuilder::new().force()),
+        )?;
+        repo.set_head_detached(commit.id())?;
+        let build_dir = self.build_commit(&self.source, &dir, commit.id())?;
+        self.configure_executor(Executor::from(build_dir.join("bin"))?)
+    }
+
+    /// Compiles the code of the git `source` checked out in `dir` at `commit` in the build
+    /// directory cached for the commit, `workdir/builds/<commit>`, and links `dir/build` to it.
+    ///
+    /// A complete cached build with the same CMake variables and generator is reused,
+    /// unless `force_rebuild` is set.
+    fn build_commit(
+        &self,
+        source: &Source,
+        dir: &Path,
+        commit: git2::Oid,
+    ) -> Result<PathBuf, Error> {
+        let build_dir = self.workdir.join(BUILDS_DIR).join(commit.to_string());
+        let complete = build_dir.join(BUILD_COMPLETE);
+        let cmake = source
+            .cmake(dir, &build_dir)
+            .ok_or("Compiling requires a git source")?;
+        let key = cmake.cache_key();
+        if !self.force_rebuild && fs::read_to_string(&complete).is_ok_and(|stored| stored == key) {
+            info!("Reusing build of commit {}", commit);
+        } else {
+            fs::create_dir_all(&build_dir).context("Could not create build directory")?;
+            if complete.exists() {
+                fs::remove_file(&complete)?;
+            }
+            cmake.remove_stale_cache()?;
+            cmake.configure()?;
+            cmake.build()?;
+            fs::write(&complete, key)?;
+        }
+        let link = dir.join("build");
+        match fs::symlink_metadata(&link) {
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&link)?,
+            Ok(_) => fs::remove_file(&link)?,
+            Err(_) => {}
+        }
+        std::os::unix::fs::symlink(fs::canonicalize(&build_dir)?, &link)
+            .with_context(|_| format!("Failed to link {}", link.display()))?;
+        Ok(link)
+    }
+}
+
+/// Directory within the work dir caching the builds of git sources by commit.
+pub const BUILDS_DIR: &str = "builds";
+
+/// File marking a cached build as complete, storing its CMake variables and generator.
+const BUILD_COMPLETE: &str = ".stdbench-complete";
+
+/// CMake targets of the PISA tools executed by stdbench.
+pub const TOOL_TARGETS: &[&str] = &[
+    "parse_collection",
+    "invert",
+    "create_freq_index",
+    "create_wand_data",
+    "queries",
+    "evaluate_queries",
+    "lexicon",
+    "extract_topics",
+];
+
+fn has_trec_topics(topics: &Topics) -> bool {
+    match topics {
+        Topics::Trec { .. } => true,
+        Topics::Combined { sources } => {
+            sources.iter().any(|source| has_trec_topics(&source.topics))
+        }
+        _ => false,
+    }
+}
+
+/// Lists the PISA tools a session of `config` executes with the tools of `source`,
+/// or of the main source if `None`, in the order of [`TOOL_TARGETS`](constant.TOOL_TARGETS.html).
+///
+/// Build tools are required only from the main source, and only if indexes are built locally.
+pub fn required_tools<C: Config>(config: &C, source: Option<&str>) -> Vec<&'static str> {
+    let mut required = HashSet::new();
+    if source.is_none() && config.build_host().is_none() {
+        for collection in config.collections() {
+            let enabled = |stage| config.enabled_for(collection, stage);
+            if !enabled(Stage::BuildIndex) {
+                continue;
+            }
+            if matches!(collection.builder, Builder::Pisa) {
+                if enabled(Stage::Parse) {
+                    required.extend(&["parse_collection", "lexicon"]);
+                }
+                if enabled(Stage::Invert) {
+                    required.insert("invert");
+                }
+            }
+            if enabled(Stage::Compress) {
+                required.insert("create_freq_index");
+            }
+            if enabled(Stage::Wand) {
+                required.insert("create_wand_data");
+            }
+        }
+    }
+    if config.enabled(Stage::Run) {
+        for run in config.runs() {
+            let baseline = run.source_baseline();
+            for run in std::iter::once(run).chain(baseline.as_ref()) {
+                if run.source.as_deref() != source {
+                    continue;
+                }
+                match run.kind {
+                    RunKind::Evaluate { .. } => {
+                        required.insert("evaluate_queries");
+                    }
+                    RunKind::Benchmark => {
+                        required.insert("queries");
+                    }
+                    RunKind::External { .. } => {}
+                }
+                if run.topics.iter().any(has_trec_topics) {
+                    required.insert("extract_topics");
+                }
+            }
+        }
+    }
+    TOOL_TARGETS
+        .iter()
+        .copied()
+        .filter(|tool| required.contains(tool))
+        .collect()
+}
+
+/// Generator of CMake when none is given.
+const DEFAULT_GENERATOR: &str = "Unix Makefiles";
+
+/// Lists the programs other than PISA tools that a session of `config` executes,
+/// such as `jq` to parse collections of kind `jsonl`.
+pub fn required_programs<C: Config>(config: &C) -> Vec<&'static str> {
+    let parses_jsonl = config.build_host().is_none()
+        && config.collections().iter().any(|collection| {
+            matches!(collection.kind, CollectionKind::Jsonl { .. })
+                && matches!(collection.builder, Builder::Pisa)
+                && config.enabled_for(collection, Stage::BuildIndex)
+                && config.enabled_for(collection, Stage::Parse)
+        });
+    if parses_jsonl {
+        vec!["jq"]
+    } else {
+        vec![]
+    }
+}
+
+struct CMake<'a> {
+    cmake_vars: &'a [CMakeVar],
+    source_dir: &'a Path,
+    dir: &'a Path,
+    launcher: Option<&'a Path>,
+    generator: Option<&'a str>,
+    build_tool_args: &'a [String],
+    targets: &'a [String],
+    threads: usize,
+}
+
+impl<'a> CMake<'a> {
+    /// Identifies the configuration of a build, so that it is redone when it changes.
+    fn cache_key(&self) -> String {
+        let mut lines: Vec<_> = self.cmake_vars.iter().map(ToString::to_string).collect();
+        if let Some(generator) = self.generator {
+            lines.push(format!("-G {}", generator));
+        }
+        if !self.targets.is_empty() {
+            lines.push(format!("--target {}", self.targets.join(" ")));
+        }
+        lines.join("\n")
+    }
+    /// Removes the CMake cache of the build directory if it was configured with
+    /// another generator, which CMake refuses to reconfigure.
+    fn remove_stale_cache(&self) -> Result<(), Error> {
+        let cache = self.dir.join("CMakeCache.txt");
+        let generator = format!(
+            "CMAKE_GENERATOR:INTERNAL={}",
+            self.generator.unwrap_or(DEFAULT_GENERATOR)
+        );
+        if let Ok(content) = fs::read_to_string(&cache) {
+            if !content.lines().any(|line| line == generator) {
+                fs::remove_file(&cache)?;
+                let files = self.dir.join("CMakeFiles");
+                if files.exists() {
+                    fs::remove_dir_all(files)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn configure_command(&self) -> Command {
+        let mut cmd = Command::new("cmake");
+        if let Some(generator) = self.generator {
+            cmd.arg("-G").arg(generator);
+        }
         for var in self.cmake_vars {
             cmd.arg(format!("-D{}", var.to_string()));
         }
-        cmd.arg("..")
-            .current_dir(self.dir)
+        if let Some(launcher) = self.launcher {
+            for language in &["C", "CXX"] {
+                cmd.arg(format!(
+                    "-DCMAKE_{}_COMPILER_LAUNCHER={}",
+                    language,
+                    launcher.display()
+                ));
+            }
+        }
+        cmd.arg(self.source_dir).current_dir(self.dir);
+        cmd
+    }
+    fn configure(&self) -> Result<(), Error> {
+        self.configure_command()
             .log()
             .status()?
             .success()
             .ok_or("cmake failed")?;
         Ok(())
     }
-    fn build(&self, threads: usize) -> Result<(), Error> {
-        process("cmake --build . -- -j")
-            .arg(threads.to_string())
-            .current_dir(self.dir)
+    fn build_command(&self) -> Command {
+        let mut cmd = process("cmake --build .");
+        if !self.targets.is_empty() {
+            cmd.arg("--target").args(self.targets);
+        }
+        cmd.args(&["--", "-j"])
+            .arg(self.threads.to_string())
+            .args(self.build_tool_args)
+            .current_dir(self.dir);
+        cmd
+    }
+    fn build(&self) -> Result<(), Error> {
+        self.build_command()
             .log()
             .status()?
             .success()
@@ -351,7 +1579,9 @@ impl<'a> CMake<'a> {
     }
 }
 
-fn update_repo(repo: &git2::Repository, refname: &str) -> Result<(), Error> {
+/// Fetches `refname` (a branch, tag, or commit) and checks it out,
+/// returning the checked out commit.
+fn update_repo(repo: &git2::Repository, refname: &str) -> Result<git2::Oid, Error> {
     let mut oid: Option<git2::Oid> = None;
     {
         let mut cb = git2::RemoteCallbacks::new();
@@ -370,7 +1600,7 @@ fn update_repo(repo: &git2::Repository, refname: &str) -> Result<(), Error> {
     if let Some(oid) = oid {
         let obj = repo.find_object(oid, None)?;
         repo.checkout_tree(&obj, Some(git2::build::CheckoutBuilder::new().force()))?;
-        return Ok(());
+        return Ok(obj.peel_to_commit()?.id());
     }
 
     if let Ok(reference) = repo.resolve_reference_from_short_name(refname) {
@@ -385,23 +1615,25 @@ fn update_repo(repo: &git2::Repository, refname: &str) -> Result<(), Error> {
                 None,
                 Some(git2::build::CheckoutBuilder::new().use_theirs(true).force()),
             )?;
+            Ok(origin_commit.id())
         } else if reference.is_tag() {
             repo.checkout_tree(
                 &reference.peel(git2::ObjectType::Any)?,
                 Some(git2::build::CheckoutBuilder::new().force()),
             )?;
+            Ok(reference.peel_to_commit()?.id())
         } else {
-            return Err(Error::from(format!(
+            Err(Error::from(format!(
                 "Reference is not a tag or a branch: {}",
                 reference.name().unwrap_or("invalid-utf8")
-            )));
+            )))
         }
     } else {
         let oid = git2::Oid::from_str(refname)?;
         let obj = repo.find_object(oid, None)?;
         repo.checkout_tree(&obj, Some(git2::build::CheckoutBuilder::new().force()))?;
+        Ok(obj.peel_to_commit()?.id())
     }
-    Ok(())
 }
 
 impl Config for RawConfig {
@@ -417,8 +1649,12 @@ impl Config for RawConfig {
     fn runs(&self) -> &[Run] {
         &self.runs
     }
+    /// Disables `stage` globally, dropping any collection-level setting of it.
     fn disable(&mut self, stage: Stage) {
         self.stages.insert(stage, false);
+        for collection in &mut self.collections {
+            collection.stages.remove(&stage);
+        }
     }
     fn enabled(&self, stage: Stage) -> bool {
         self.stages.get(&stage).cloned().unwrap_or(true)
@@ -429,6 +1665,33 @@ impl Config for RawConfig {
     fn clean(&self) -> bool {
         self.clean
     }
+    fn keep_temp(&self) -> bool {
+        self.keep_temp
+    }
+    fn resume(&self) -> bool {
+        self.resume
+    }
+    fn report(&self) -> Option<&Path> {
+        self.report.as_deref()
+    }
+    fn summary(&self) -> Option<&Path> {
+        self.summary.as_deref()
+    }
+    fn badge(&self) -> Option<&Path> {
+        self.badge.as_deref()
+    }
+    fn results_db(&self) -> Option<&Path> {
+        self.results_db.as_deref()
+    }
+    fn push_metrics(&self) -> Option<&str> {
+        self.push_metrics.as_deref()
+    }
+    fn redact_paths(&self) -> bool {
+        self.redact_paths
+    }
+    fn status_port(&self) -> Option<u16> {
+        self.status_port
+    }
     fn batch_sizes(&self) -> BatchSizes {
         self.batch_sizes
     }
@@ -438,42 +1701,66 @@ impl Config for RawConfig {
     fn margin(&self) -> RegressionMargin {
         self.margin
     }
+    fn compare_build_times_with(&self) -> Option<&Path> {
+        self.compare_build_times_with.as_ref().map(PathBuf::as_path)
+    }
+    fn max_workdir_size(&self) -> Option<ByteSize> {
+        self.max_workdir_size
+    }
+    fn build_threads(&self) -> usize {
+        self.build_threads.unwrap_or(1).max(1)
+    }
+    fn compress_batches(&self) -> bool {
+        self.compress_batches
+    }
+    fn share_indexes(&self) -> bool {
+        self.share_indexes
+    }
+    fn strict_environment(&self) -> bool {
+        self.strict_environment
+    }
+    fn injected_failures(&self) -> &[FailureInjection] {
+        &self.injected_failures
+    }
+    fn priorities(&self) -> Priorities {
+        self.priorities
+    }
+    fn schedule(&self) -> Schedule {
+        self.schedule
+    }
+    fn host_lock(&self) -> PathBuf {
+        self.host_lock
+            .clone()
+            .unwrap_or_else(|| env::temp_dir().join(guard::DEFAULT_LOCK_FILE))
+    }
+    fn build_host(&self) -> Option<&BuildHost> {
+        self.build_host.as_ref()
+    }
 
     fn executor(&self) -> Result<Executor, Error> {
-        match &self.source {
-            Source::System => Ok(Executor::new()),
-            Source::Git {
-                branch,
-                url,
-                cmake_vars,
-                local_path,
-                compile_threads,
-            } => {
-                let dir = if local_path.is_absolute() {
-                    local_path.to_path_buf()
-                } else {
-                    self.workdir.join(&local_path)
-                };
-                let repo = if dir.exists() {
-                    git2::Repository::open(&dir)?
-                } else {
-                    git2::Repository::clone_recurse(&url, &dir).map_err(|_| "git-clone failed")?
-                };
-                let build_dir = dir.join("build");
-                fs::create_dir_all(&build_dir).context("Could not create build directory")?;
-                if self.stages.get(&Stage::Compile).cloned().unwrap_or(true) {
-                    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))?;
-                    update_repo(&repo, &branch)?;
-                    let cmake = CMake::new(&cmake_vars, &build_dir);
-                    cmake.configure()?;
-                    cmake.build(*compile_threads)?;
-                } else {
-                    warn!("Compilation has been suppressed");
-                }
-                Ok(Executor::from(build_dir.join("bin"))?)
-            }
-            Source::Path(path) => Ok(Executor::from(path.to_path_buf())?),
-            Source::Docker(_) => unimplemented!(),
+        self.configure_executor(self.compile(&self.source)?)
+    }
+
+    fn source_executor(&self, name: &str) -> Result<Executor, Error> {
+        let source = self
+            .sources
+            .get(name)
+            .ok_or_else(|| format!("Source not defined: {}", name))?;
+        self.configure_executor(self.compile(source)?)
+    }
+
+    fn planned_executor(&self) -> Executor {
+        let executor = match self.source.bin_dir(&self.workdir) {
+            Some(dir) => Executor::unchecked(dir),
+            None => Executor::new(),
+        };
+        let executor = executor
+            .with_tool_overrides(self.tool_overrides.clone())
+            .with_evaluator(self.evaluator)
+            .with_env(&self.env);
+        match self.trec_eval.path(&self.workdir, &executor) {
+            Some(trec_eval) => executor.with_trec_eval(trec_eval),
+            None => executor,
         }
     }
 }
@@ -534,6 +1821,105 @@ pub(crate) fn format_output_path(
     ))
 }
 
+/// Output paths in layouts of earlier versions, most recent first,
+/// used to find baselines produced before the layout changed.
+///
+/// # Examples
+///
+/// Before runs supported multiple topics, the topics index was omitted:
+/// ```
+/// # use stdbench::config::legacy_output_paths;
+/// # use std::path::{Path, PathBuf};
+/// let paths = legacy_output_paths(Path::new("out"), &"wand".into(), &"pef".into(), 0, "bench");
+/// assert_eq!(paths, vec![PathBuf::from("out.wand.pef.bench")]);
+/// assert!(legacy_output_paths(Path::new("out"), &"wand".into(), &"pef".into(), 1, "bench")
+///     .is_empty());
+/// ```
+pub fn legacy_output_paths(
+    base: &Path,
+    algorithm: &Algorithm,
+    encoding: &Encoding,
+    topics_file_idx: usize,
+    suffix: &str,
+) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if topics_file_idx == 0 {
+        paths.push(PathBuf::from(format!(
+            "{}.{}.{}.{}",
+            base.display(),
+            algorithm,
+            encoding,
+            suffix
+        )));
+    }
+    paths
+}
+
+/// Path to a result of the baseline at `compare_with`.
+///
+/// If it does not exist, falls back to the first existing path in a layout of
+/// an earlier version (see [`legacy_output_paths`](fn.legacy_output_paths.html)),
+/// so that baselines survive upgrades.
+pub(crate) fn baseline_path(
+    compare_with: &Path,
+    algorithm: &Algorithm,
+    encoding: &Encoding,
+    topics_file_idx: usize,
+    suffix: &str,
+) -> PathBuf {
+    let path = format_output_path(compare_with, algorithm, encoding, topics_file_idx, suffix);
+    if path.exists() {
+        return path;
+    }
+    legacy_output_paths(compare_with, algorithm, encoding, topics_file_idx, suffix)
+        .into_iter()
+        .find(|legacy| legacy.exists())
+        .map_or(path, |legacy| {
+            warn!("Using baseline with legacy name: {}", legacy.display());
+            legacy
+        })
+}
+
+/// Replaces standard topics and topics given by URL with the files downloaded
+/// to `workdir`, collecting paths to their qrels.
+fn resolve_topics(workdir: &Path, topics: Topics) -> Result<Topics, Error> {
+    match topics {
+        Topics::Standard { standard } => {
+            let standard = registry::standard_topics(&standard)?;
+            standard.fetch(workdir)?;
+            Ok(standard.topics(workdir))
+        }
+        Topics::Url {
+            url,
+            sha256,
+            field,
+            qrels,
+        } => {
+            let path = registry::fetch_url(workdir, &url, sha256.as_deref())?;
+            let qrels = match qrels {
+                Some(Qrels { url, sha256 }) => {
+                    Some(registry::fetch_url(workdir, &url, sha256.as_deref())?)
+                }
+                None => None,
+            };
+            Ok(Topics::Trec { path, field, qrels })
+        }
+        Topics::Combined { sources } => {
+            let sources: Result<_, Error> = sources
+                .into_iter()
+                .map(|source| {
+                    Ok(TopicSource {
+                        topics: resolve_topics(workdir, source.topics)?,
+                        ..source
+                    })
+                })
+                .collect();
+            Ok(Topics::Combined { sources: sources? })
+        }
+        topics => Ok(topics),
+    }
+}
+
 impl ResolvedPathsConfig {
     fn resolve_run_with<'a>(
         workdir: &'a Path,
@@ -542,7 +1928,43 @@ impl ResolvedPathsConfig {
     ) -> impl 'a + FnMut(Run) -> Result<Run, failure::Error> {
         move |mut r: Run| {
             r.output = resolve_path(workdir, r.output);
-            r.compare_with = r.compare_with.map(|p| resolve_path(&workdir, p));
+            r.query_options.thresholds = r
+                .query_options
+                .thresholds
+                .map(|thresholds| resolve_path(workdir, thresholds));
+            if let Some(selective) = &mut r.selective_search {
+                selective.resource_selection =
+                    resolve_path(workdir, mem::take(&mut selective.resource_selection));
+            }
+            if let Some(baseline) = &r.compare_with {
+                if baseline.source.is_none() && baseline.path.as_os_str().is_empty() {
+                    bail!("Missing baseline path in run: {}", r.output.display());
+                }
+            }
+            let output = &r.output;
+            r.compare_with = r.compare_with.map(|baseline| match &baseline.source {
+                Some(source) if baseline.path.as_os_str().is_empty() => Baseline {
+                    path: source_baseline_path(workdir, source, output),
+                    ..baseline
+                },
+                _ => Baseline {
+                    path: resolve_path(&workdir, baseline.path),
+                    ..baseline
+                },
+            });
+            let topics: Result<_, Error> = r
+                .topics
+                .into_iter()
+                .map(|topics| resolve_topics(workdir, topics))
+                .collect();
+            r.topics = topics?;
+            if let RunKind::Evaluate { qrels, .. } | RunKind::External { qrels, .. } = &r.kind {
+                if qrels.as_os_str().is_empty()
+                    && r.topics.iter().any(|topics| topics.qrels().is_none())
+                {
+                    bail!("Missing qrels in run: {}", r.output.display());
+                }
+            }
             if r.algorithms.is_empty() {
                 if let Some(algorithms) = algorithms {
                     r.algorithms.extend(algorithms.iter().cloned());
@@ -564,10 +1986,36 @@ impl ResolvedPathsConfig {
     fn resolve_collection_with<'a>(
         workdir: &'a Path,
         encodings: &'a Option<Vec<Encoding>>,
+        batch_sizes: BatchSizes,
+        threads: Threads,
     ) -> impl 'a + FnMut(Collection) -> Result<Collection, failure::Error> {
         move |mut c: Collection| {
             c.fwd_index = resolve_path(&workdir, c.fwd_index);
             c.inv_index = resolve_path(&workdir, c.inv_index);
+            let merged = c.batch_sizes.merged_over(batch_sizes);
+            c.batch_sizes = BatchSizesOverride {
+                parse: Some(merged.parse),
+                invert: Some(merged.invert),
+            };
+            c.threads = c.threads.merged_over(threads);
+            if let Some(sample) = c.sample_documents {
+                if sample.fraction <= 0.0 || sample.fraction > 1.0 {
+                    bail!(
+                        "Sampled fraction of collection {} not in (0, 1]: {}",
+                        c.name,
+                        sample.fraction
+                    );
+                }
+            }
+            if c.shards == 0 {
+                bail!("Number of shards of collection {} must be positive", c.name);
+            }
+            if c.shards > 1 && !matches!(c.builder, Builder::Pisa) {
+                bail!(
+                    "Collection {} cannot be sharded with an external builder",
+                    c.name
+                );
+            }
             if c.encodings.is_empty() {
                 if let Some(encodings) = encodings {
                     c.encodings.extend(encodings.iter().cloned());
@@ -580,66 +2028,281 @@ impl ResolvedPathsConfig {
     }
 
     /// Resolves all relative paths with respect to the work dir.
-    pub fn from(mut config: RawConfig) -> Result<Self, Error> {
+    pub fn from(config: RawConfig) -> Result<Self, Error> {
+        Self::resolve(config).map_err(|err| err.wrap(ErrorKind::Config))
+    }
+
+    fn resolve(mut config: RawConfig) -> Result<Self, Error> {
         let algorithms = mem::replace(&mut config.algorithms, None);
         let encodings = mem::replace(&mut config.encodings, None);
         let workdir = config.workdir().to_path_buf();
         let resolve_run = Self::resolve_run_with(&workdir, &algorithms, &encodings);
-        let runs: Result<_, _> = config.runs.into_iter().map(resolve_run).collect();
-        let resolve_coll = Self::resolve_collection_with(&workdir, &encodings);
-        let collections: Result<_, _> = config.collections.into_iter().map(resolve_coll).collect();
+        let runs: Vec<Run> = config
+            .runs
+            .into_iter()
+            .map(resolve_run)
+            .collect::<Result<_, _>>()?;
+        let resolve_coll =
+            Self::resolve_collection_with(&workdir, &encodings, config.batch_sizes, config.threads);
+        let collections: Vec<Collection> = config
+            .collections
+            .into_iter()
+            .map(resolve_coll)
+            .collect::<Result<_, _>>()?;
+        let shards = |name: &str| {
+            collections
+                .iter()
+                .find(|c| c.name == name)
+                .map_or(1, |c| c.shards)
+        };
+        for run in runs.iter().filter(|run| run.selective_search.is_some()) {
+            let output = run.output.display();
+            let unsupported = if shards(&run.collection) == 1 {
+                Some("requires a sharded collection")
+            } else {
+                match run.kind {
+                    RunKind::Evaluate { .. } => None,
+                    RunKind::Benchmark if run.query_times => None,
+                    RunKind::Benchmark => Some("requires query_times"),
+                    RunKind::External { .. } => Some("is not supported by external runs"),
+                }
+            };
+            if let Some(reason) = unsupported {
+                return Err(Error::from(format!(
+                    "Selective search of run {} {}",
+                    output, reason
+                )));
+            }
+        }
+        let runs = runs
+            .into_iter()
+            .flat_map(|run| match shards(&run.collection) {
+                1 => vec![run],
+                count => (0..count).map(|index| run.shard(index, count)).collect(),
+            })
+            .collect();
+        let collections = collections
+            .iter()
+            .flat_map(|c| match c.shards {
+                1 => vec![c.clone()],
+                count => (0..count).map(|index| c.shard(index)).collect(),
+            })
+            .collect();
+        let compare_build_times_with = config
+            .compare_build_times_with
+            .take()
+            .map(|p| resolve_path(&workdir, p));
+        let tool_overrides = mem::take(&mut config.tool_overrides)
+            .into_iter()
+            .map(|(tool, path)| (tool, resolve_path(&workdir, path)))
+            .collect();
+        let mut sources = mem::take(&mut config.sources);
+        for (name, source) in &mut sources {
+            if let Source::Git { local_path, .. } = source {
+                if *local_path == default_local_path() {
+                    *local_path = PathBuf::from(format!("pisa-{}", name));
+                }
+            }
+        }
         let config = Self(RawConfig {
-            collections: collections?,
-            runs: runs?,
+            collections,
+            runs,
+            compare_build_times_with,
+            tool_overrides,
+            sources,
             ..config
         });
         config.verify()?;
         Ok(config)
     }
 
+    fn verify_topics(topics: &[Topics]) -> Result<(), Error> {
+        for topics in topics {
+            match topics {
+                Topics::Trec { path, .. } | Topics::Simple { path, .. } => {
+                    path.exists_or("Topics not found")?;
+                }
+                Topics::Combined { sources } => {
+                    for source in sources {
+                        match &source.topics {
+                            Topics::Trec { path, .. } | Topics::Simple { path, .. } => {
+                                path.exists_or("Topics not found")?;
+                            }
+                            Topics::Combined { .. } => {
+                                return Err(Error::from(
+                                    "Nested combined topics are not supported",
+                                ));
+                            }
+                            Topics::Standard { standard } => {
+                                return Err(Error::from(format!(
+                                    "Unresolved standard topics: {}",
+                                    standard
+                                )));
+                            }
+                            Topics::Url { url, .. } => {
+                                return Err(Error::from(format!("Unresolved topics URL: {}", url)));
+                            }
+                        }
+                        (source.weight > 0.0 && source.weight <= 1.0).ok_or_else(|| {
+                            format_err!("Topic weight must be in (0, 1]: {}", source.weight)
+                        })?;
+                    }
+                }
+                Topics::Standard { standard } => {
+                    return Err(Error::from(format!(
+                        "Unresolved standard topics: {}",
+                        standard
+                    )));
+                }
+                Topics::Url { url, .. } => {
+                    return Err(Error::from(format!("Unresolved topics URL: {}", url)));
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn verify(&self) -> Result<(), Error> {
+        let priorities = self.priorities();
+        for nice in priorities.build.nice.iter().chain(&priorities.run.nice) {
+            (-20..=19)
+                .contains(nice)
+                .ok_or_else(|| format_err!("Niceness out of range [-20, 19]: {}", nice))?;
+        }
+        for (tool, path) in &self.0.tool_overrides {
+            if !TOOL_TARGETS.contains(&tool.as_str()) {
+                return Err(Error::from(format!(
+                    "Unknown tool override: {} (expected one of: {})",
+                    tool,
+                    TOOL_TARGETS.join(", ")
+                )));
+            }
+            path.exists_or("Tool override not found")?;
+        }
         let mut collection_names: HashSet<&str> = HashSet::new();
         for collection in self.collections() {
-            collection.input_dir.as_ref().map_or_else(
-                || collection.verify_index_exists(),
-                |p| p.exists_or("Collection dir not found"),
-            )?;
+            for stage in collection.stages.keys() {
+                if let Stage::Compile | Stage::Run | Stage::Compare = stage {
+                    return Err(Error::from(format!(
+                        "Stage {} cannot be set for collection: {}",
+                        stage, collection.name
+                    )));
+                }
+            }
+            if collection.quantize && !self.use_scorer() {
+                return Err(Error::from(format!(
+                    "Quantized collection requires --scorer support: {}",
+                    collection.name
+                )));
+            }
+            if !collection.download.is_empty() {
+                collection.input_dir.is_some().ok_or_else(|| {
+                    format_err!("Download defined without input_dir: {}", collection.name)
+                })?;
+            } else if collection.external_command(self.workdir())?.is_some() {
+                if let Some(dir) = &collection.input_dir {
+                    dir.exists_or("Collection dir not found")?;
+                }
+            } else {
+                collection.input_dir.as_ref().map_or_else(
+                    || collection.verify_index_exists(),
+                    |p| p.exists_or("Collection dir not found"),
+                )?;
+            }
             collection_names.insert(&collection.name);
         }
         for run in self.runs() {
             collection_names
                 .contains(&run.collection.as_ref())
                 .ok_or_else(|| format_err!("Collection not defined: {}", run.collection))?;
-            if let RunKind::Evaluate { qrels } = &run.kind {
-                qrels.exists_or("Qrels file not found")?;
+            for tid in 0..run.topics.len() {
+                if let Some(qrels) = run.qrels(tid) {
+                    qrels.exists_or("Qrels file not found")?;
+                }
             }
-            for topics in &run.topics {
-                let topics_path = match topics {
-                    Topics::Trec { path, .. } | Topics::Simple { path } => path,
-                };
-                topics_path.exists_or("Topics not found")?;
+            if let Some(collection) = self.collections().iter().find(|c| c.name == run.collection) {
+                for (algorithm, encoding) in iproduct!(&run.algorithms, &run.encodings) {
+                    run.external_command(collection, algorithm, encoding, "", Path::new(""))?;
+                }
             }
-            if let Some(compare_with) = &run.compare_with {
-                for (algorithm, encoding, topics_idx) in
-                    iproduct!(&run.algorithms, &run.encodings, 0..run.topics.len())
-                {
-                    match run.kind {
-                        RunKind::Evaluate { .. } => format_output_path(
-                            compare_with,
+            match run.kind {
+                RunKind::Benchmark => run.min_measures.is_empty().ok_or_else(|| {
+                    format_err!(
+                        "Minimum measures defined for a benchmark run: {}",
+                        run.output.display()
+                    )
+                })?,
+                RunKind::Evaluate { .. } => (run.max_avg_ms.is_none() && run.max_p95_ms.is_none())
+                    .ok_or_else(|| {
+                        format_err!(
+                            "Maximum latency defined for an evaluation run: {}",
+                            run.output.display()
+                        )
+                    })?,
+                RunKind::External { .. } => run.max_p95_ms.is_none().ok_or_else(|| {
+                    format_err!(
+                        "Maximum latency quantile defined for an external run: {}",
+                        run.output.display()
+                    )
+                })?,
+            }
+            for (algorithm, encoding) in iproduct!(&run.algorithms, &run.encodings) {
+                if let Some(supported) = self.0.compatibility.get(algorithm) {
+                    supported.contains(encoding).ok_or_else(|| {
+                        format_err!(
+                            "Algorithm {} is incompatible with encoding {} in run: {}",
                             algorithm,
                             encoding,
-                            topics_idx,
-                            "trec_eval",
+                            run.output.display()
                         )
-                        .exists_or("Missing baseline")?,
-                        RunKind::Benchmark => format_output_path(
-                            compare_with,
-                            algorithm,
-                            encoding,
-                            topics_idx,
-                            "bench",
-                        )
-                        .exists_or("Missing baseline")?,
+                    })?;
+                }
+            }
+            if let Some(thresholds) = &run.query_options.thresholds {
+                thresholds.exists_or("Thresholds file not found")?;
+            } else if run.query_options.safe {
+                bail!(
+                    "Safe query processing requires thresholds in run: {}",
+                    run.output.display()
+                );
+            }
+            (run.repeats > 0).ok_or_else(|| {
+                format_err!("Repeats must be positive in run: {}", run.output.display())
+            })?;
+            Self::verify_topics(&run.topics)?;
+            if let Some(source) = &run.source {
+                self.0
+                    .sources
+                    .contains_key(source)
+                    .ok_or_else(|| format_err!("Source not defined: {}", source))?;
+            }
+            if let Some(Baseline {
+                path: compare_with,
+                tolerances,
+                source,
+            }) = &run.compare_with
+            {
+                if !tolerances.is_empty() && run.kind == RunKind::Benchmark {
+                    bail!(
+                        "Tolerances defined for benchmark run: {}",
+                        run.output.display()
+                    );
+                }
+                if let Some(source) = source {
+                    self.0
+                        .sources
+                        .contains_key(source)
+                        .ok_or_else(|| format_err!("Source not defined: {}", source))?;
+                } else {
+                    for (algorithm, encoding, topics_idx) in
+                        iproduct!(&run.algorithms, &run.encodings, 0..run.topics.len())
+                    {
+                        let suffix = match run.kind {
+                            RunKind::Evaluate { .. } | RunKind::External { .. } => "trec_eval",
+                            RunKind::Benchmark => "bench",
+                        };
+                        baseline_path(compare_with, algorithm, encoding, topics_idx, suffix)
+                            .exists_or("Missing baseline")?;
                     }
                 }
             }
@@ -673,9 +2336,42 @@ impl Config for ResolvedPathsConfig {
     fn clean(&self) -> bool {
         self.0.clean()
     }
+    fn keep_temp(&self) -> bool {
+        self.0.keep_temp()
+    }
+    fn resume(&self) -> bool {
+        self.0.resume()
+    }
+    fn report(&self) -> Option<&Path> {
+        self.0.report()
+    }
+    fn summary(&self) -> Option<&Path> {
+        self.0.summary()
+    }
+    fn badge(&self) -> Option<&Path> {
+        self.0.badge()
+    }
+    fn results_db(&self) -> Option<&Path> {
+        self.0.results_db()
+    }
+    fn push_metrics(&self) -> Option<&str> {
+        self.0.push_metrics()
+    }
+    fn redact_paths(&self) -> bool {
+        self.0.redact_paths()
+    }
+    fn status_port(&self) -> Option<u16> {
+        self.0.status_port()
+    }
     fn executor(&self) -> Result<Executor, Error> {
         self.0.executor()
     }
+    fn source_executor(&self, name: &str) -> Result<Executor, Error> {
+        self.0.source_executor(name)
+    }
+    fn planned_executor(&self) -> Executor {
+        self.0.planned_executor()
+    }
     fn batch_sizes(&self) -> BatchSizes {
         self.0.batch_sizes()
     }
@@ -685,6 +2381,39 @@ impl Config for ResolvedPathsConfig {
     fn margin(&self) -> RegressionMargin {
         self.0.margin()
     }
+    fn compare_build_times_with(&self) -> Option<&Path> {
+        self.0.compare_build_times_with()
+    }
+    fn max_workdir_size(&self) -> Option<ByteSize> {
+        self.0.max_workdir_size()
+    }
+    fn build_threads(&self) -> usize {
+        self.0.build_threads()
+    }
+    fn compress_batches(&self) -> bool {
+        self.0.compress_batches()
+    }
+    fn share_indexes(&self) -> bool {
+        self.0.share_indexes()
+    }
+    fn strict_environment(&self) -> bool {
+        self.0.strict_environment()
+    }
+    fn injected_failures(&self) -> &[FailureInjection] {
+        self.0.injected_failures()
+    }
+    fn priorities(&self) -> Priorities {
+        self.0.priorities()
+    }
+    fn schedule(&self) -> Schedule {
+        self.0.schedule()
+    }
+    fn host_lock(&self) -> PathBuf {
+        self.0.host_lock()
+    }
+    fn build_host(&self) -> Option<&BuildHost> {
+        self.0.build_host()
+    }
 }
 
 impl Resolved for ResolvedPathsConfig {}
@@ -725,6 +2454,23 @@ pub enum Source {
         /// Use this many threads when calling `make`.
         #[serde(default = "default_no_threads")]
         compile_threads: usize,
+        /// Compile through `ccache`, so that repeated builds, e.g., of different branches
+        /// or commits, reuse unchanged object files.
+        #[serde(default)]
+        use_ccache: bool,
+        /// Compiler launcher other than `ccache`, e.g., a path to `sccache`;
+        /// implies `use_ccache`.
+        #[serde(default)]
+        compiler_launcher: Option<PathBuf>,
+        /// CMake generator other than the default one, e.g., `Ninja`.
+        #[serde(default)]
+        generator: Option<String>,
+        /// Additional arguments of the native build tool, e.g., `make` or `ninja`.
+        #[serde(default)]
+        build_tool_args: Vec<String>,
+        /// CMake targets to build instead of all, e.g., those in [`TOOL_TARGETS`](constant.TOOL_TARGETS.html).
+        #[serde(default)]
+        targets: Vec<String>,
     },
     /// Executables in a given directory.
     Path(PathBuf),
@@ -740,9 +2486,185 @@ impl Default for Source {
     }
 }
 
+/// Location of the `trec_eval` executable.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TrecEval {
+    /// Executable on the system `PATH`.
+    #[default]
+    System,
+    /// Executable in the same directory as the PISA tools.
+    Executor,
+    /// Executable at a given path; partial paths will be rooted at the working directory.
+    Path(PathBuf),
+    /// Built from source cloned to `workdir/trec_eval`.
+    Git {
+        /// HTTPS URL of the repository.
+        #[serde(default = "default_trec_eval_url")]
+        url: String,
+    },
+}
+
+/// Implementation evaluating the results of evaluation and external runs.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::Evaluator;
+/// assert_eq!(Evaluator::default(), Evaluator::TrecEval);
+/// let evaluator: Evaluator = serde_yaml::from_str("internal").unwrap();
+/// assert_eq!(evaluator, Evaluator::Internal);
+/// ```
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Evaluator {
+    /// The `trec_eval` executable (see [`TrecEval`](enum.TrecEval.html)).
+    #[default]
+    TrecEval,
+    /// Built-in implementation of common measures (see [`eval`](../eval/index.html)),
+    /// requiring no external executable.
+    Internal,
+}
+
+fn default_trec_eval_url() -> String {
+    String::from("https://github.com/usnistgov/trec_eval.git")
+}
+
+impl TrecEval {
+    /// Path to the executable, or `None` if it should be found on the system path.
+    pub fn path(&self, workdir: &Path, executor: &Executor) -> Option<PathBuf> {
+        match self {
+            Self::System => None,
+            Self::Executor => Some(PathBuf::from(executor.program("trec_eval"))),
+            Self::Path(path) => Some(resolve_path(workdir, path.clone())),
+            Self::Git { .. } => Some(workdir.join("trec_eval").join("trec_eval")),
+        }
+    }
+
+    /// Resolves the path to the executable, or `None` if it should be found on the system path.
+    ///
+    /// A `Git` source is cloned and built with `make` unless the executable already exists.
+    pub fn resolve(&self, workdir: &Path, executor: &Executor) -> Result<Option<PathBuf>, Error> {
+        if let Self::Git { url } = self {
+            let dir = workdir.join("trec_eval");
+            if !dir.exists() {
+                git2::Repository::clone(url, &dir).map_err(|_| "git-clone failed")?;
+            }
+            if !dir.join("trec_eval").exists() {
+                Command::new("make")
+                    .current_dir(&dir)
+                    .log()
+                    .status()?
+                    .success()
+                    .ok_or("Failed to build trec_eval")?;
+            }
+        }
+        Ok(self.path(workdir, executor))
+    }
+}
+
+impl Source {
+    /// Directory of the executables, or `None` if they are on the system path.
+    pub fn bin_dir(&self, workdir: &Path) -> Option<PathBuf> {
+        match self {
+            Self::Git { .. } => self
+                .git_dir(workdir)
+                .map(|dir| dir.join("build").join("bin")),
+            Self::Path(path) => Some(path.clone()),
+            Self::Docker(_) | Self::System => None,
+        }
+    }
+
+    /// Commands compiling the code of a Git source; empty for other sources.
+    pub fn compile_commands(&self, workdir: &Path) -> Vec<Command> {
+        match self.git_dir(workdir) {
+            Some(dir) => {
+                let build_dir = dir.join("build");
+                self.cmake(&dir, &build_dir).map_or_else(Vec::new, |cmake| {
+                    vec![cmake.configure_command(), cmake.build_command()]
+                })
+            }
+            None => vec![],
+        }
+    }
+
+    /// CMake wrapper of a Git source, building the code in `source_dir` in `dir`.
+    fn cmake<'a>(&'a self, source_dir: &'a Path, dir: &'a Path) -> Option<CMake<'a>> {
+        match self {
+            Self::Git {
+                cmake_vars,
+                compile_threads,
+                generator,
+                build_tool_args,
+                targets,
+                ..
+            } => Some(CMake {
+                cmake_vars,
+                source_dir,
+                dir,
+                launcher: self.compiler_launcher(),
+                generator: generator.as_deref(),
+                build_tool_args,
+                targets,
+                threads: *compile_threads,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Compiler launcher of a Git source, or `None` if it compiles directly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::Source;
+    /// # use std::path::Path;
+    /// let source: Source = serde_yaml::from_str("git: {branch: master, url: x}").unwrap();
+    /// assert_eq!(source.compiler_launcher(), None);
+    /// let source: Source =
+    ///     serde_yaml::from_str("git: {branch: master, url: x, use_ccache: true}").unwrap();
+    /// assert_eq!(source.compiler_launcher(), Some(Path::new("ccache")));
+    /// let source: Source =
+    ///     serde_yaml::from_str("git: {branch: master, url: x, compiler_launcher: sccache}").unwrap();
+    /// assert_eq!(source.compiler_launcher(), Some(Path::new("sccache")));
+    /// ```
+    pub fn compiler_launcher(&self) -> Option<&Path> {
+        match self {
+            Self::Git {
+                compiler_launcher: Some(launcher),
+                ..
+            } => Some(launcher.as_path()),
+            Self::Git {
+                use_ccache: true, ..
+            } => Some(Path::new("ccache")),
+            _ => None,
+        }
+    }
+
+    /// Local directory of the code of a Git source, or `None` for other sources.
+    pub fn git_dir(&self, workdir: &Path) -> Option<PathBuf> {
+        if let Self::Git { local_path, .. } = self {
+            Some(resolve_path(workdir, local_path.clone()))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Git { branch, url, .. } => write!(f, "git {} ({})", url, branch),
+            Self::Path(path) => write!(f, "path {}", path.display()),
+            Self::Docker(image) => write!(f, "docker {}", image),
+            Self::System => write!(f, "system"),
+        }
+    }
+}
+
 /// Supported types of collections:
 /// <https://pisa.readthedocs.io/en/latest/parsing.html#supported-formats>
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub enum CollectionKind {
     /// -f trecweb
@@ -758,10 +2680,36 @@ pub enum CollectionKind {
     WashingtonPost,
     /// -f warc
     Warc,
+    /// One document per line, its ID followed by the text, e.g., MS MARCO passages.
+    /// Uses `-f plaintext`, reading `*.tsv` files in the directory.
+    Plaintext,
+    /// MS MARCO passage or document ranking collection, in TSV format with a document ID
+    /// in the first column. Uses `-f plaintext`, reading `*.tsv` and `*.tsv.gz` files
+    /// in the directory.
+    #[serde(alias = "msmarco")]
+    MsMarco,
+    /// Arbitrary corpus stored as JSON lines, reading `*.jsonl` files in the directory.
+    /// Documents are converted to plain text with `jq` and parsed with `-f plaintext`.
+    Jsonl {
+        /// Field holding the document ID.
+        #[serde(default = "default_id_field")]
+        id_field: String,
+        /// Field holding the document content.
+        #[serde(default = "default_content_field")]
+        content_field: String,
+    },
+}
+
+fn default_id_field() -> String {
+    String::from("id")
+}
+
+fn default_content_field() -> String {
+    String::from("contents")
 }
 
 /// Algorithm name.
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
 pub struct Algorithm(String);
 
 impl From<&str> for Algorithm {
@@ -770,115 +2718,613 @@ impl From<&str> for Algorithm {
     }
 }
 
-impl fmt::Display for Algorithm {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AsRef<str> for Algorithm {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+/// Posting list encoding, with optional extra parameters passed to `create_freq_index`.
+///
+/// It is given either as a name alone, or as a map with the name and the parameters.
+/// Encodings are identified by their names: index files are named after them,
+/// and runs refer to encodings of collections by name only.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::Encoding;
+/// let encoding: Encoding = serde_yaml::from_str("block_simdbp").unwrap();
+/// assert_eq!(encoding, Encoding::from("block_simdbp"));
+/// let encoding: Encoding = serde_yaml::from_str("
+/// name: block_simdbp
+/// params: [--block-size, '256']").unwrap();
+/// assert_eq!(encoding.name, "block_simdbp");
+/// assert_eq!(encoding.params, vec!["--block-size", "256"]);
+/// assert_eq!(encoding, Encoding::from("block_simdbp"));
+/// ```
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(from = "EncodingDef", into = "EncodingDef")]
+pub struct Encoding {
+    /// Encoding name, e.g., `block_simdbp`.
+    pub name: String,
+    /// Extra arguments of `create_freq_index`, e.g., `[--block-size, 256]`.
+    pub params: Vec<String>,
+}
+
+/// Encoding given either as a name alone or with parameters.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum EncodingDef {
+    Name(String),
+    Full {
+        name: String,
+        #[serde(default)]
+        params: Vec<String>,
+    },
+}
+
+impl From<EncodingDef> for Encoding {
+    fn from(def: EncodingDef) -> Self {
+        match def {
+            EncodingDef::Name(name) => Self {
+                name,
+                params: Vec::new(),
+            },
+            EncodingDef::Full { name, params } => Self { name, params },
+        }
+    }
+}
+
+impl From<Encoding> for EncodingDef {
+    fn from(encoding: Encoding) -> Self {
+        if encoding.params.is_empty() {
+            Self::Name(encoding.name)
+        } else {
+            Self::Full {
+                name: encoding.name,
+                params: encoding.params,
+            }
+        }
+    }
+}
+
+impl PartialEq for Encoding {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Encoding {}
+
+impl Hash for Encoding {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = Error;
+    fn from_str(encoding: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from(encoding))
+    }
+}
+
+impl From<&str> for Encoding {
+    fn from(encoding: &str) -> Self {
+        Self {
+            name: String::from(encoding),
+            params: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl AsRef<str> for Encoding {
+    fn as_ref(&self) -> &str {
+        self.name.as_ref()
+    }
+}
+
+/// Posting list encoding name.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Scorer(pub String);
+
+impl From<&str> for Scorer {
+    fn from(encoding: &str) -> Self {
+        Self(String::from(encoding))
+    }
+}
+
+impl fmt::Display for Scorer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AsRef<str> for Scorer {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+/// Field to use when using TREC topic format.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TopicField {
+    /// Field `<title>`
+    Title,
+    /// Field `<desc>`
+    Desc,
+    /// Field `<narr>`
+    Narr,
+}
+
+impl fmt::Display for TopicField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Title => write!(f, "title"),
+            Self::Desc => write!(f, "desc"),
+            Self::Narr => write!(f, "narr"),
+        }
+    }
+}
+
+/// File with query topics.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Topics {
+    /// Colon-delimited query format.
+    Simple {
+        /// File path.
+        path: PathBuf,
+        /// Relevance judgments of these topics, overriding `qrels` of the run.
+        #[serde(default)]
+        qrels: Option<PathBuf>,
+    },
+    /// TREC format
+    Trec {
+        /// File path.
+        path: PathBuf,
+        /// TREC field to use.
+        field: TopicField,
+        /// Relevance judgments of these topics, overriding `qrels` of the run.
+        #[serde(default)]
+        qrels: Option<PathBuf>,
+    },
+    /// Concatenation of multiple topic sets into a single query stream.
+    Combined {
+        /// Topic sets to concatenate, in order.
+        sources: Vec<TopicSource>,
+    },
+    /// Standard topics from the [registry](../registry/index.html),
+    /// downloaded to the work directory when the config is resolved.
+    Standard {
+        /// Registered name, e.g., `trec-dl-2019`.
+        standard: String,
+    },
+    /// TREC topics downloaded to the work directory when the config is resolved.
+    Url {
+        /// Download URL; gzipped if ending with `.gz`.
+        url: String,
+        /// Expected SHA-256 checksum of the downloaded file (optional).
+        #[serde(default)]
+        sha256: Option<String>,
+        /// TREC field to use.
+        #[serde(default = "default_topic_field")]
+        field: TopicField,
+        /// Relevance judgments of the topics, overriding `qrels` of the run.
+        #[serde(default)]
+        qrels: Option<Qrels>,
+    },
+}
+
+fn default_topic_field() -> TopicField {
+    TopicField::Title
+}
+
+impl Topics {
+    /// Relevance judgments paired with these topics, if any.
+    ///
+    /// Combined topics are paired with qrels only if all their sources share the same.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::config::Topics;
+    /// # use std::path::Path;
+    /// let topics = Topics::Simple { path: "topics".into(), qrels: Some("qrels".into()) };
+    /// assert_eq!(topics.qrels(), Some(Path::new("qrels")));
+    /// ```
+    pub fn qrels(&self) -> Option<&Path> {
+        match self {
+            Self::Simple { qrels, .. } | Self::Trec { qrels, .. } => qrels.as_deref(),
+            Self::Combined { sources } => {
+                let mut qrels = sources.iter().map(|source| source.topics.qrels());
+                let first = qrels.next()??;
+                if qrels.all(|q| q == Some(first)) {
+                    Some(first)
+                } else {
+                    None
+                }
+            }
+            Self::Standard { .. } | Self::Url { .. } => None,
+        }
+    }
+}
+
+/// Relevance judgments downloaded to the work directory along with their topics.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::{Qrels, TopicField, Topics};
+/// let topics: Topics = serde_yaml::from_str("
+/// kind: url
+/// url: https://example.com/topics.301-450.gz
+/// qrels:
+///     url: https://example.com/qrels.301-450
+///     sha256: 9f86d081").unwrap();
+/// assert_eq!(topics, Topics::Url {
+///     url: String::from("https://example.com/topics.301-450.gz"),
+///     sha256: None,
+///     field: TopicField::Title,
+///     qrels: Some(Qrels {
+///         url: String::from("https://example.com/qrels.301-450"),
+///         sha256: Some(String::from("9f86d081")),
+///     }),
+/// });
+/// ```
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Qrels {
+    /// Download URL; gzipped if ending with `.gz`.
+    pub url: String,
+    /// Expected SHA-256 checksum of the downloaded file (optional).
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+/// Deserializes run topics, which can be a list or a single entry,
+/// where standard topics and topics given by URL can be given without `kind`,
+/// e.g., `{standard: trec-dl-2019}`.
+fn deserialize_topics<'de, D>(deserializer: D) -> Result<Vec<Topics>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error as _;
+    use serde_yaml::Value;
+    fn entry(mut value: Value) -> Result<Topics, serde_yaml::Error> {
+        if let Value::Mapping(map) = &mut value {
+            let kind = Value::from("kind");
+            if !map.contains_key(&kind) {
+                for implicit in &["standard", "url"] {
+                    let implicit = Value::from(*implicit);
+                    if map.contains_key(&implicit) {
+                        map.insert(kind, implicit);
+                        break;
+                    }
+                }
+            }
+        }
+        serde_yaml::from_value(value)
+    }
+    let topics = match Value::deserialize(deserializer)? {
+        Value::Sequence(entries) => entries.into_iter().map(entry).collect(),
+        value => entry(value).map(|topics| vec![topics]),
+    };
+    topics.map_err(D::Error::custom)
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+/// Topic set being part of a combined query stream.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::{TopicSource, Topics};
+/// let source: TopicSource = serde_yaml::from_str("
+/// kind: simple
+/// path: /topics
+/// weight: 0.5").unwrap();
+/// assert_eq!(source.topics, Topics::Simple { path: "/topics".into(), qrels: None });
+/// assert_eq!(source.weight, 0.5);
+/// ```
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct TopicSource {
+    /// Topic set.
+    #[serde(flatten)]
+    pub topics: Topics,
+    /// Fraction of the queries sampled from this set, in the range `(0, 1]`.
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+}
+
+/// Normalization applied to queries before passing them to PISA tools.
+///
+/// By default, queries are left untouched.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct QueryNormalization {
+    /// Remove queries identical to one that appeared earlier (after normalization).
+    #[serde(default)]
+    pub dedup: bool,
+    /// Convert queries to lowercase.
+    #[serde(default)]
+    pub lowercase: bool,
+    /// Replace punctuation with whitespace.
+    #[serde(default)]
+    pub strip_punctuation: bool,
+}
+
+impl QueryNormalization {
+    /// Returns `true` if no normalization is requested.
+    pub fn is_noop(&self) -> bool {
+        !(self.dedup || self.lowercase || self.strip_punctuation)
     }
 }
 
-impl AsRef<str> for Algorithm {
-    fn as_ref(&self) -> &str {
-        self.0.as_ref()
-    }
+/// Query processing options passed to the `queries` and `evaluate_queries` tools,
+/// e.g., to exercise approximate algorithms in efficiency experiments.
+///
+/// By default, no options are passed.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct QueryOptions {
+    /// Treat repeated query terms as term weights (`--weighted`).
+    #[serde(default)]
+    pub weighted: bool,
+    /// File with initial score thresholds, one per query (`--thresholds`).
+    #[serde(default)]
+    pub thresholds: Option<PathBuf>,
+    /// Reprocess queries without the thresholds if they yield fewer than `k` results (`--safe`).
+    #[serde(default)]
+    pub safe: bool,
+    /// Other arguments, appended verbatim.
+    #[serde(default)]
+    pub extra_args: Vec<String>,
 }
 
-/// Posting list encoding name.
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
-pub struct Encoding(pub String);
-
-impl FromStr for Encoding {
-    type Err = Error;
-    fn from_str(encoding: &str) -> Result<Self, Self::Err> {
-        Ok(Self::from(encoding))
+impl QueryOptions {
+    /// Command-line arguments corresponding to the options.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::config::QueryOptions;
+    /// let options = QueryOptions {
+    ///     weighted: true,
+    ///     thresholds: Some("thresholds.txt".into()),
+    ///     safe: true,
+    ///     extra_args: vec![String::from("--quantized")],
+    /// };
+    /// assert_eq!(
+    ///     options.args(),
+    ///     vec!["--weighted", "--thresholds", "thresholds.txt", "--safe", "--quantized"]
+    /// );
+    /// assert!(QueryOptions::default().args().is_empty());
+    /// ```
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.weighted {
+            args.push(String::from("--weighted"));
+        }
+        if let Some(thresholds) = &self.thresholds {
+            args.push(String::from("--thresholds"));
+            args.push(thresholds.display().to_string());
+        }
+        if self.safe {
+            args.push(String::from("--safe"));
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args
     }
 }
 
-impl From<&str> for Encoding {
-    fn from(encoding: &str) -> Self {
-        Self(String::from(encoding))
-    }
+/// Class of a query based on the number of its terms.
+#[derive(
+    Clone, Copy, Serialize, Deserialize, Debug, Hash, PartialEq, Eq, EnumIter, EnumString, Display,
+)]
+pub enum QueryClass {
+    /// Single-term queries.
+    #[serde(rename = "1")]
+    #[strum(serialize = "1")]
+    One,
+    /// Two-term queries.
+    #[serde(rename = "2")]
+    #[strum(serialize = "2")]
+    Two,
+    /// Queries with 3 or 4 terms.
+    #[serde(rename = "3-4")]
+    #[strum(serialize = "3-4")]
+    ThreeToFour,
+    /// Queries with 5 or more terms.
+    #[serde(rename = "5+")]
+    #[strum(serialize = "5+")]
+    FiveOrMore,
 }
 
-impl fmt::Display for Encoding {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
+impl QueryClass {
+    /// Classifies a query with `term_count` terms; empty queries have no class.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::config::QueryClass;
+    /// assert_eq!(QueryClass::of(0), None);
+    /// assert_eq!(QueryClass::of(1), Some(QueryClass::One));
+    /// assert_eq!(QueryClass::of(4), Some(QueryClass::ThreeToFour));
+    /// assert_eq!(QueryClass::of(10), Some(QueryClass::FiveOrMore));
+    /// ```
+    pub fn of(term_count: usize) -> Option<Self> {
+        match term_count {
+            0 => None,
+            1 => Some(Self::One),
+            2 => Some(Self::Two),
+            3 | 4 => Some(Self::ThreeToFour),
+            _ => Some(Self::FiveOrMore),
+        }
     }
 }
 
-impl AsRef<str> for Encoding {
-    fn as_ref(&self) -> &str {
-        self.0.as_ref()
-    }
+pub(crate) fn default_scorers() -> Vec<Scorer> {
+    vec![Scorer::from("bm25")]
 }
 
-/// Posting list encoding name.
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-pub struct Scorer(pub String);
-
-impl From<&str> for Scorer {
-    fn from(encoding: &str) -> Self {
-        Self(String::from(encoding))
+/// Fills in fields of a collection that are missing in `map` from its preset.
+fn apply_preset(
+    map: &mut serde_yaml::Mapping,
+    preset: &registry::CollectionPreset,
+) -> Result<(), serde_yaml::Error> {
+    use serde_yaml::Value;
+    let name = Value::from("name");
+    if !map.contains_key(&name) {
+        map.insert(name.clone(), Value::from(preset.name));
     }
-}
-
-impl fmt::Display for Scorer {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
+    let name = map[&name].as_str().unwrap_or(preset.name).to_string();
+    let mut defaults = vec![
+        ("kind", serde_yaml::to_value(&preset.kind)?),
+        ("fwd_index", Value::from(format!("fwd/{}", name))),
+        ("inv_index", Value::from(format!("inv/{}", name))),
+        (
+            "encodings",
+            Value::Sequence(preset.encodings.iter().map(|&e| Value::from(e)).collect()),
+        ),
+    ];
+    if let Some(glob) = preset.input_glob {
+        defaults.push(("input_glob", Value::from(glob)));
+    }
+    for (key, value) in defaults {
+        let key = Value::from(key);
+        if !map.contains_key(&key) {
+            map.insert(key, value);
+        }
     }
+    Ok(())
 }
 
-impl AsRef<str> for Scorer {
-    fn as_ref(&self) -> &str {
-        self.0.as_ref()
-    }
+/// Deserializes collections, filling in the defaults of their presets.
+fn deserialize_collections<'de, D>(deserializer: D) -> Result<Vec<Collection>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error as _;
+    use serde_yaml::Value;
+    let collections: Vec<Value> = Vec::deserialize(deserializer)?;
+    collections
+        .into_iter()
+        .map(|mut value| {
+            if let Value::Mapping(map) = &mut value {
+                let preset = map.get(&Value::from("preset")).and_then(Value::as_str);
+                if let Some(preset) = preset {
+                    let preset = registry::collection_preset(preset).map_err(D::Error::custom)?;
+                    apply_preset(map, preset).map_err(D::Error::custom)?;
+                }
+            }
+            serde_yaml::from_value(value).map_err(D::Error::custom)
+        })
+        .collect()
 }
 
-/// Field to use when using TREC topic format.
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-#[serde(rename_all = "lowercase")]
-pub enum TopicField {
-    /// Field `<title>`
-    Title,
-    /// Field `<desc>`
-    Desc,
-    /// Field `<narr>`
-    Narr,
+fn unknown_placeholder(placeholder: &str) -> Error {
+    Error::from(format!("Unknown placeholder: {{{}}}", placeholder))
 }
 
-impl fmt::Display for TopicField {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Title => write!(f, "title"),
-            Self::Desc => write!(f, "desc"),
-            Self::Narr => write!(f, "narr"),
-        }
+/// Replaces each `{placeholder}` in a command template with its `value`.
+fn render_template<F>(template: &str, value: F) -> Result<String, Error>
+where
+    F: Fn(&str) -> Result<String, Error>,
+{
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format_err!("Unclosed placeholder: {}", &rest[start..]))?;
+        output.push_str(&value(&rest[start + 1..start + end])?);
+        rest = &rest[start + end + 1..];
     }
+    output.push_str(rest);
+    Ok(output)
 }
 
-/// File with query topics.
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-#[serde(tag = "kind", rename_all = "lowercase")]
-pub enum Topics {
-    /// Colon-delimited query format.
-    Simple {
-        /// File path.
-        path: PathBuf,
-    },
-    /// TREC format
-    Trec {
-        /// File path.
-        path: PathBuf,
-        /// TREC field to use.
-        field: TopicField,
+/// Tool producing the inverted index of a collection.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Builder {
+    /// Parsing and inverting with PISA tools.
+    #[default]
+    Pisa,
+    /// External command producing the inverted index and lexicons, e.g., a CIFF import,
+    /// which are then compressed with PISA tools.
+    External {
+        /// Shell command template, where `{name}`, `{input_dir}`, `{fwd_index}`,
+        /// `{inv_index}`, and `{workdir}` are replaced with the collection's values.
+        command: String,
     },
 }
 
-pub(crate) fn default_scorers() -> Vec<Scorer> {
-    vec![Scorer::from("bm25")]
+/// File of a collection downloaded into its input directory in the `fetch` stage.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Download {
+    /// Download URL; `.tar`, `.tar.gz`, and `.tgz` archives are extracted after downloading.
+    pub url: String,
+    /// Expected SHA-256 checksum of the downloaded file (optional).
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+impl Download {
+    /// Name of the downloaded file in the input directory: the last segment of the URL.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::config::Download;
+    /// let download = Download {
+    ///     url: String::from("https://example.com/data/robust04.tar.gz"),
+    ///     sha256: None,
+    /// };
+    /// assert_eq!(download.file_name(), "robust04.tar.gz");
+    /// assert!(download.is_archive());
+    /// ```
+    pub fn file_name(&self) -> &str {
+        self.url
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or(&self.url)
+    }
+
+    /// Whether the file is an archive extracted into the input directory.
+    pub fn is_archive(&self) -> bool {
+        let name = self.file_name();
+        [".tar", ".tar.gz", ".tgz"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+    }
 }
 
+/// Stemmer of collections that do not define one.
+pub const DEFAULT_STEMMER: &str = "porter2";
+
+/// Value of `stemmer` disabling stemming.
+pub const NO_STEMMER: &str = "none";
+
 /// Collection built before experiments.
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Collection {
     /// Name indentifier.
     pub name: String,
@@ -887,6 +3333,12 @@ pub struct Collection {
     /// Directory where the collection resides.
     #[serde(default)]
     pub input_dir: Option<PathBuf>,
+    /// Pattern of input files relative to `input_dir`, overriding the default of `kind`.
+    #[serde(default)]
+    pub input_glob: Option<String>,
+    /// Name of the [preset](../registry/index.html) providing defaults of other fields.
+    #[serde(default)]
+    pub preset: Option<String>,
     /// Basename for forward index.
     pub fwd_index: PathBuf,
     /// Basename for inverted index.
@@ -897,6 +3349,60 @@ pub struct Collection {
     /// List of scorers for which to build WAND data.
     #[serde(default = "default_scorers")]
     pub scorers: Vec<Scorer>,
+    /// Whether to quantize the scores of each scorer, in its WAND data and in a compressed
+    /// index per encoding and scorer (requires `--scorer` support).
+    #[serde(default)]
+    pub quantize: bool,
+    /// Overrides of the global batch sizes.
+    #[serde(default)]
+    pub batch_sizes: BatchSizesOverride,
+    /// Overrides of the global thread counts.
+    #[serde(default)]
+    pub threads: Threads,
+    /// Tool producing the inverted index.
+    #[serde(default)]
+    pub builder: Builder,
+    /// Enabled/disabled build stages of this collection, overriding the global ones.
+    #[serde(default)]
+    pub stages: HashMap<Stage, bool>,
+    /// Stemmer applied to documents and queries: `porter2` if not defined,
+    /// or `none` to disable stemming.
+    #[serde(default)]
+    pub stemmer: Option<String>,
+    /// Deterministic sample of the documents to index instead of all of them
+    /// (see [`sample`](../sample/index.html)).
+    #[serde(default)]
+    pub sample_documents: Option<DocumentSample>,
+    /// Number of shards the documents are split into, each with its own indexes
+    /// (see [`shard`](#method.shard)).
+    #[serde(default = "default_shards")]
+    pub shards: usize,
+    /// Shard of a sharded collection that this collection indexes, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard: Option<Shard>,
+    /// Files downloaded into `input_dir` before parsing.
+    #[serde(default)]
+    pub download: Vec<Download>,
+}
+
+fn default_shards() -> usize {
+    1
+}
+
+/// Suffix of the names, indexes, and run outputs of the shard at `index`.
+fn shard_suffix(index: usize) -> String {
+    format!(".{:03}", index)
+}
+
+/// Deterministic sample of the documents of a collection.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DocumentSample {
+    /// Fraction of the documents in the sample, greater than 0 and at most 1.
+    pub fraction: f64,
+    /// Seed selecting the sample; with the same seed, smaller samples are contained
+    /// in larger ones.
+    #[serde(default)]
+    pub seed: u64,
 }
 
 impl Collection {
@@ -905,6 +3411,42 @@ impl Collection {
         file_name.push(extension);
         path.as_ref().with_file_name(file_name)
     }
+
+    /// Collection indexing the shard at `index` of this collection, with its own name and indexes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::config::Collection;
+    /// # use stdbench::sample::Shard;
+    /// # use std::path::Path;
+    /// let collection: Collection = serde_yaml::from_str("
+    /// name: wapo
+    /// kind: washington-post
+    /// fwd_index: /fwd/wapo
+    /// inv_index: /inv/wapo
+    /// shards: 4").unwrap();
+    /// let shard = collection.shard(2);
+    /// assert_eq!(shard.name, "wapo.002");
+    /// assert_eq!(shard.fwd_index, Path::new("/fwd/wapo.002"));
+    /// assert_eq!(shard.inv_index, Path::new("/inv/wapo.002"));
+    /// assert_eq!(shard.shard, Some(Shard { index: 2, count: 4 }));
+    /// assert_eq!(shard.shards, 1);
+    /// ```
+    pub fn shard(&self, index: usize) -> Self {
+        let suffix = shard_suffix(index);
+        Self {
+            name: format!("{}{}", self.name, suffix),
+            fwd_index: Self::with_appended(&self.fwd_index, &suffix),
+            inv_index: Self::with_appended(&self.inv_index, &suffix),
+            shards: 1,
+            shard: Some(Shard {
+                index,
+                count: self.shards,
+            }),
+            ..self.clone()
+        }
+    }
     pub(crate) fn documents(&self) -> PathBuf {
         Self::with_appended(&self.fwd_index, ".documents")
     }
@@ -917,20 +3459,168 @@ impl Collection {
     pub(crate) fn term_lexicon(&self) -> PathBuf {
         Self::with_appended(&self.fwd_index, ".termlex")
     }
-    pub(crate) fn wand(&self) -> PathBuf {
-        Self::with_appended(&self.inv_index, ".wand")
+    /// Chooses between the binary lexicon and the plain text file
+    /// (expected by older PISA versions), depending on which one exists.
+    fn detect_lexicon(lexicon: PathBuf, text: PathBuf) -> PathBuf {
+        if !lexicon.exists() && text.exists() {
+            text
+        } else {
+            lexicon
+        }
+    }
+    /// Term lexicon passed to query processing tools.
+    pub(crate) fn query_term_lexicon(&self) -> PathBuf {
+        Self::detect_lexicon(self.term_lexicon(), self.terms())
+    }
+    /// Document lexicon passed to query processing tools.
+    pub(crate) fn query_document_lexicon(&self) -> PathBuf {
+        Self::detect_lexicon(self.document_lexicon(), self.documents())
+    }
+    /// Stemmer passed to PISA tools, or `None` if stemming is disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::config::Collection;
+    /// let mut collection: Collection = serde_yaml::from_str("
+    /// name: robust
+    /// kind: robust
+    /// fwd_index: /fwd/robust
+    /// inv_index: /inv/robust").unwrap();
+    /// assert_eq!(collection.stemmer(), Some("porter2"));
+    /// collection.stemmer = Some(String::from("krovetz"));
+    /// assert_eq!(collection.stemmer(), Some("krovetz"));
+    /// collection.stemmer = Some(String::from("none"));
+    /// assert_eq!(collection.stemmer(), None);
+    /// ```
+    pub fn stemmer(&self) -> Option<&str> {
+        match self.stemmer.as_deref() {
+            None => Some(DEFAULT_STEMMER),
+            Some(NO_STEMMER) => None,
+            Some(stemmer) => Some(stemmer),
+        }
+    }
+    /// WAND data for `scorer`, e.g., `inv.wand.bm25`, or `inv.wand` for tools
+    /// without `--scorer` support, which always use BM25.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::config::{Collection, Encoding, Scorer};
+    /// # use std::path::Path;
+    /// let mut collection: Collection = serde_yaml::from_str("
+    /// name: robust
+    /// kind: robust
+    /// fwd_index: /fwd/robust
+    /// inv_index: /inv/robust").unwrap();
+    /// let bm25 = Scorer::from("bm25");
+    /// let simdbp = Encoding::from("block_simdbp");
+    /// assert_eq!(collection.wand(Some(&bm25)), Path::new("/inv/robust.wand.bm25"));
+    /// assert_eq!(collection.wand(None), Path::new("/inv/robust.wand"));
+    /// assert_eq!(
+    ///     collection.scored_index(&simdbp, Some(&bm25)),
+    ///     Path::new("/inv/robust.block_simdbp")
+    /// );
+    /// collection.quantize = true;
+    /// assert_eq!(
+    ///     collection.scored_index(&simdbp, Some(&bm25)),
+    ///     Path::new("/inv/robust.block_simdbp.bm25")
+    /// );
+    /// ```
+    pub fn wand(&self, scorer: Option<&Scorer>) -> PathBuf {
+        match scorer {
+            Some(scorer) => Self::with_appended(&self.inv_index, &format!(".wand.{}", scorer)),
+            None => Self::with_appended(&self.inv_index, ".wand"),
+        }
+    }
+    /// All WAND data files of the collection, with and without scorers.
+    pub(crate) fn wands(&self) -> Vec<PathBuf> {
+        self.scorers
+            .iter()
+            .map(|scorer| self.wand(Some(scorer)))
+            .chain(std::iter::once(self.wand(None)))
+            .collect()
     }
     pub(crate) fn enc_index(&self, encoding: &Encoding) -> PathBuf {
         Self::with_appended(&self.inv_index, &format!(".{}", encoding))
     }
+    /// Index compressed with `encoding` and queried with `scorer`: quantized for the scorer,
+    /// e.g., `inv.block_simdbp.bm25`, if the collection is quantized.
+    pub fn scored_index(&self, encoding: &Encoding, scorer: Option<&Scorer>) -> PathBuf {
+        match scorer {
+            Some(scorer) if self.quantize => {
+                Self::with_appended(&self.inv_index, &format!(".{}.{}", encoding, scorer))
+            }
+            _ => self.enc_index(encoding),
+        }
+    }
+    /// Encodings and, if quantized, scorers of all compressed indexes of the collection.
+    pub(crate) fn compressed_indexes(&self) -> Vec<(&Encoding, Option<&Scorer>)> {
+        if self.quantize {
+            iproduct!(&self.encodings, &self.scorers)
+                .map(|(encoding, scorer)| (encoding, Some(scorer)))
+                .collect()
+        } else {
+            self.encodings
+                .iter()
+                .map(|encoding| (encoding, None))
+                .collect()
+        }
+    }
+    /// Renders the command of an external builder, if defined.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::config::{Builder, Collection};
+    /// # use std::path::Path;
+    /// let mut collection: Collection = serde_yaml::from_str("
+    /// name: robust
+    /// kind: robust
+    /// fwd_index: /fwd/robust
+    /// inv_index: /inv/robust").unwrap();
+    /// assert_eq!(collection.external_command(Path::new("/workdir")).unwrap(), None);
+    /// collection.builder = Builder::External {
+    ///     command: String::from("ciff2pisa --ciff-file {workdir}/{name}.ciff --output {inv_index}"),
+    /// };
+    /// assert_eq!(
+    ///     collection.external_command(Path::new("/workdir")).unwrap().unwrap(),
+    ///     "ciff2pisa --ciff-file /workdir/robust.ciff --output /inv/robust"
+    /// );
+    /// ```
+    pub fn external_command(&self, workdir: &Path) -> Result<Option<String>, Error> {
+        let template = match &self.builder {
+            Builder::Pisa => return Ok(None),
+            Builder::External { command } => command,
+        };
+        let command = render_template(template, |placeholder| match placeholder {
+            "name" => Ok(self.name.clone()),
+            "input_dir" => Ok(self
+                .input_dir
+                .as_ref()
+                .ok_or("Placeholder {input_dir} used without input_dir")?
+                .display()
+                .to_string()),
+            "fwd_index" => Ok(self.fwd_index.display().to_string()),
+            "inv_index" => Ok(self.inv_index.display().to_string()),
+            "workdir" => Ok(workdir.display().to_string()),
+            _ => Err(unknown_placeholder(placeholder)),
+        })?;
+        Ok(Some(command))
+    }
     fn verify_index_exists(&self) -> Result<(), Error> {
         self.document_lexicon()
             .exists()
             .ok_or("Document lexicon missing")?;
         self.term_lexicon().exists().ok_or("Term lexicon missing")?;
-        self.wand().exists().ok_or("WAND data missing")?;
-        for encoding in &self.encodings {
-            self.enc_index(encoding)
+        (self.wand(None).exists()
+            || self
+                .scorers
+                .iter()
+                .all(|scorer| self.wand(Some(scorer)).exists()))
+        .ok_or("WAND data missing")?;
+        for (encoding, scorer) in self.compressed_indexes() {
+            self.scored_index(encoding, scorer)
                 .exists()
                 .ok_or_else(|| format!("Missing index encoded with: {}", encoding))?;
         }
@@ -939,24 +3629,50 @@ impl Collection {
 }
 
 /// Type of experiment.
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum RunKind {
     /// Query effectiveness evaluation.
     Evaluate {
-        /// Path to query relevance file in TREC format.
+        /// Path to query relevance file in TREC format, used for topics
+        /// without their own qrels; may be omitted if all topics have them.
+        #[serde(default)]
         qrels: PathBuf,
+        /// Minimum relevance level for a document to be considered relevant,
+        /// binarizing graded judgments (passed to `trec_eval -l`).
+        #[serde(default)]
+        min_relevance: Option<u32>,
     },
     /// Query speed performance.
     Benchmark,
+    /// Queries processed by another engine, e.g., Anserini, whose results are evaluated
+    /// and whose processing time is measured, for comparison with PISA.
+    External {
+        /// Shell command template producing a TREC run file, where `{queries}`, `{output}`,
+        /// `{collection}`, `{fwd_index}`, `{inv_index}`, `{algorithm}`, and `{encoding}`
+        /// are replaced with the values of each evaluated combination.
+        command: String,
+        /// Path to query relevance file in TREC format, used for topics
+        /// without their own qrels; may be omitted if all topics have them.
+        #[serde(default)]
+        qrels: PathBuf,
+        /// Minimum relevance level for a document to be considered relevant.
+        #[serde(default)]
+        min_relevance: Option<u32>,
+    },
 }
 
 pub(crate) fn default_scorer() -> Scorer {
     Scorer::from("bm25")
 }
 
+fn default_repeats() -> usize {
+    1
+}
+
 /// An experimental run.
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Run {
     /// Collection name.
     pub collection: String,
@@ -971,13 +3687,309 @@ pub struct Run {
     /// A basename for output files.
     pub output: PathBuf,
     /// A list of topic/query files.
+    #[serde(deserialize_with = "deserialize_topics")]
     pub topics: Vec<Topics>,
     /// Ranking scoring function.
     #[serde(default = "default_scorer")]
     pub scorer: Scorer,
-    /// A path prefix to results of another run.
+    /// Baseline: a path prefix to results of another run, with optional tolerances.
+    #[serde(default)]
+    pub compare_with: Option<Baseline>,
+    /// Query normalization policy.
+    #[serde(default)]
+    pub normalize: QueryNormalization,
+    /// Query processing options, such as score thresholds.
+    #[serde(default)]
+    pub query_options: QueryOptions,
+    /// Break down query latencies by query class (benchmark runs only).
+    #[serde(default)]
+    pub per_class: bool,
+    /// Regression margins for particular query classes, overriding the global margin.
+    #[serde(default)]
+    pub class_margins: HashMap<QueryClass, RegressionMargin>,
+    /// Additionally report measures (of evaluation and external runs)
+    /// or latencies (of benchmark runs) for each detected query language.
+    #[serde(default)]
+    pub per_language: bool,
+    /// Export per-query features and latencies to a CSV file (benchmark runs only).
+    #[serde(default)]
+    pub export_features: bool,
+    /// Collect hardware counters with `perf stat` (benchmark runs only).
+    #[serde(default)]
+    pub perf_counters: bool,
+    /// Additionally measure with a cold cache, right after evicting the index
+    /// from the page cache, and with a warm cache, after a warm-up pass
+    /// (benchmark runs only).
+    #[serde(default)]
+    pub cold_and_warm: bool,
+    /// Measure the time it takes to load the index of each encoding (benchmark runs only).
+    #[serde(default)]
+    pub load_time: bool,
+    /// Export per-query latencies reported by `queries --extract` to a CSV file
+    /// (benchmark runs only).
+    #[serde(default)]
+    pub query_times: bool,
+    /// Minimum values of `trec_eval` measures, e.g., `map: 0.25`, checked regardless
+    /// of any baseline (evaluation and external runs only).
+    #[serde(default)]
+    pub min_measures: BTreeMap<String, f64>,
+    /// Maximum average query latency in milliseconds, checked regardless of any baseline
+    /// (benchmark and external runs only).
+    #[serde(default)]
+    pub max_avg_ms: Option<f32>,
+    /// Maximum 95th percentile of query latency in milliseconds, checked regardless
+    /// of any baseline (benchmark runs only, since external runs measure no quantiles).
+    #[serde(default)]
+    pub max_p95_ms: Option<f32>,
+    /// Environment variables of the commands of this run, overriding global ones.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Number of discarded executions of `queries` before measuring (benchmark runs only).
+    #[serde(default)]
+    pub warmup: usize,
+    /// Number of measured executions of `queries`, whose results are averaged
+    /// (benchmark runs only).
+    #[serde(default = "default_repeats")]
+    pub repeats: usize,
+    /// Tolerated drop of query throughput with respect to the baseline,
+    /// overriding the global margin (benchmark runs only).
+    #[serde(default)]
+    pub qps_margin: Option<RegressionMargin>,
+    /// Name of the source in `sources` whose tools execute this run, instead of the main source.
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Store the unprocessed standard output of `queries` and `evaluate_queries` next to
+    /// the results, so that it can be reprocessed without rerunning
+    /// (benchmark and evaluation runs only).
+    #[serde(default)]
+    pub keep_raw_output: bool,
+    /// Time limit of each command of this run in seconds, overriding the global `run` timeout.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// Selective search over the shards of a sharded collection (evaluation and benchmark
+    /// runs only, see [`selective`](../selective/index.html)).
+    #[serde(default)]
+    pub selective_search: Option<SelectiveSearch>,
+    /// Shard of a sharded collection that this run queries, if any (see [`shard`](#method.shard)).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard: Option<Shard>,
+}
+
+/// Selective search of a run, searching each query only on some shards of its collection.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SelectiveSearch {
+    /// Resource-selection file: each line holds a query ID followed by the indexes
+    /// of the shards to search for it (see [`ResourceSelection`](../selective/struct.ResourceSelection.html)).
+    pub resource_selection: PathBuf,
+}
+
+/// Directory within the work dir where the results of runs executed by named sources
+/// are written, as baselines of the runs executed by the main source.
+pub const SOURCES_DIR: &str = "sources";
+
+/// Default path prefix of the baseline results of a run with basename `output`,
+/// produced by the named `source`.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::source_baseline_path;
+/// # use std::path::{Path, PathBuf};
+/// assert_eq!(
+///     source_baseline_path(Path::new("/work"), "develop", Path::new("/work/runs/bench")),
+///     PathBuf::from("/work/sources/develop/bench")
+/// );
+/// ```
+pub fn source_baseline_path(workdir: &Path, source: &str, output: &Path) -> PathBuf {
+    workdir
+        .join(SOURCES_DIR)
+        .join(source)
+        .join(output.file_name().unwrap_or_default())
+}
+
+/// Results of another run that a run is compared with.
+///
+/// Evaluation results must be identical to the baseline, unless tolerances are defined
+/// for some `trec_eval` measures: then only these measures are compared over all queries,
+/// and each may drop below its baseline value by at most its tolerance.
+///
+/// A baseline can also be produced in the same session by another `source`,
+/// e.g., a second branch: the run is then executed with the tools of both sources.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::Baseline;
+/// # use std::path::PathBuf;
+/// let baseline: Baseline = serde_yaml::from_str("/baseline/eval").unwrap();
+/// assert_eq!(baseline, Baseline::from(PathBuf::from("/baseline/eval")));
+/// let baseline: Baseline = serde_yaml::from_str("
+/// path: /baseline/eval
+/// tolerances:
+///     map: 0.001").unwrap();
+/// assert_eq!(baseline.path, PathBuf::from("/baseline/eval"));
+/// assert_eq!(baseline.tolerances.get("map"), Some(&0.001));
+/// let baseline: Baseline = serde_yaml::from_str("source: develop").unwrap();
+/// assert_eq!(baseline.source.as_deref(), Some("develop"));
+/// assert!(baseline.path.as_os_str().is_empty());
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "BaselineDef")]
+pub struct Baseline {
+    /// Path prefix of the baseline results.
+    pub path: PathBuf,
+    /// Maximum drops of `trec_eval` measures, e.g., `map: 0.001`.
     #[serde(default)]
-    pub compare_with: Option<PathBuf>,
+    pub tolerances: BTreeMap<String, f64>,
+    /// Name of a source in `sources` whose tools execute the same run in the session,
+    /// writing the baseline results to `path`, by default
+    /// [`source_baseline_path`](fn.source_baseline_path.html).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// Baseline given either as a path alone or with tolerances.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BaselineDef {
+    Path(PathBuf),
+    Full {
+        #[serde(default)]
+        path: PathBuf,
+        #[serde(default)]
+        tolerances: BTreeMap<String, f64>,
+        #[serde(default)]
+        source: Option<String>,
+    },
+}
+
+impl From<BaselineDef> for Baseline {
+    fn from(def: BaselineDef) -> Self {
+        match def {
+            BaselineDef::Path(path) => Self::from(path),
+            BaselineDef::Full {
+                path,
+                tolerances,
+                source,
+            } => Self {
+                path,
+                tolerances,
+                source,
+            },
+        }
+    }
+}
+
+impl From<PathBuf> for Baseline {
+    fn from(path: PathBuf) -> Self {
+        Self {
+            path,
+            tolerances: BTreeMap::new(),
+            source: None,
+        }
+    }
+}
+
+impl Run {
+    /// The same run executed by the source of its baseline, writing its results
+    /// to the baseline path, if the baseline is produced by a source.
+    pub fn source_baseline(&self) -> Option<Self> {
+        let baseline = self.compare_with.as_ref()?;
+        let source = baseline.source.clone()?;
+        Some(Self {
+            output: baseline.path.clone(),
+            compare_with: None,
+            source: Some(source),
+            ..self.clone()
+        })
+    }
+
+    /// The same run on the shard at `index` of the `count` shards of its collection,
+    /// writing its results and comparing them to files suffixed with the shard index, e.g., `.000`.
+    pub fn shard(&self, index: usize, count: usize) -> Self {
+        let suffix = shard_suffix(index);
+        Self {
+            collection: format!("{}{}", self.collection, suffix),
+            output: Collection::with_appended(&self.output, &suffix),
+            compare_with: self.compare_with.as_ref().map(|baseline| Baseline {
+                path: Collection::with_appended(&baseline.path, &suffix),
+                ..baseline.clone()
+            }),
+            shard: Some(Shard { index, count }),
+            ..self.clone()
+        }
+    }
+
+    /// The run on the whole collection that this run on one of its shards was derived from
+    /// with [`shard`](#method.shard), or the same run if it does not query a shard.
+    pub fn unsharded(&self) -> Self {
+        let shard = match self.shard {
+            Some(shard) => shard,
+            None => return self.clone(),
+        };
+        let suffix = shard_suffix(shard.index);
+        let strip = |path: &Path| {
+            path.to_str()
+                .and_then(|path| path.strip_suffix(&suffix))
+                .map_or_else(|| path.to_path_buf(), PathBuf::from)
+        };
+        Self {
+            collection: self
+                .collection
+                .strip_suffix(&suffix)
+                .unwrap_or(&self.collection)
+                .to_string(),
+            output: strip(&self.output),
+            compare_with: self.compare_with.as_ref().map(|baseline| Baseline {
+                path: strip(&baseline.path),
+                ..baseline.clone()
+            }),
+            shard: None,
+            ..self.clone()
+        }
+    }
+
+    /// Renders the command of an external run for the given combination,
+    /// if the run is external.
+    pub fn external_command(
+        &self,
+        collection: &Collection,
+        algorithm: &Algorithm,
+        encoding: &Encoding,
+        queries: &str,
+        output: &Path,
+    ) -> Result<Option<String>, Error> {
+        let template = match &self.kind {
+            RunKind::External { command, .. } => command,
+            _ => return Ok(None),
+        };
+        let command = render_template(template, |placeholder| match placeholder {
+            "queries" => Ok(queries.to_string()),
+            "output" => Ok(output.display().to_string()),
+            "collection" => Ok(collection.name.clone()),
+            "fwd_index" => Ok(collection.fwd_index.display().to_string()),
+            "inv_index" => Ok(collection.inv_index.display().to_string()),
+            "algorithm" => Ok(algorithm.to_string()),
+            "encoding" => Ok(encoding.to_string()),
+            _ => Err(unknown_placeholder(placeholder)),
+        })?;
+        Ok(Some(command))
+    }
+
+    /// Relevance judgments used to evaluate the topics at index `tid`:
+    /// the ones paired with the topics, or else `qrels` of the run.
+    /// Returns `None` for benchmark runs.
+    pub fn qrels(&self, tid: usize) -> Option<&Path> {
+        match &self.kind {
+            RunKind::Evaluate { qrels, .. } | RunKind::External { qrels, .. } => Some(
+                self.topics
+                    .get(tid)
+                    .and_then(Topics::qrels)
+                    .unwrap_or(qrels.as_path()),
+            ),
+            RunKind::Benchmark => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1054,6 +4066,11 @@ mod test {
                 }],
                 local_path: PathBuf::from("pisa"),
                 compile_threads: 1_usize,
+                use_ccache: false,
+                compiler_launcher: None,
+                generator: None,
+                build_tool_args: vec![],
+                targets: vec![],
             }
         );
 
@@ -1092,15 +4109,96 @@ mod test {
                 ],
                 local_path: PathBuf::from("pisa-master"),
                 compile_threads: 2,
+                use_ccache: false,
+                compiler_launcher: None,
+                generator: None,
+                build_tool_args: vec![],
+                targets: vec![],
             }
         );
 
-        let source: Source = serde_yaml::from_str("path: /path/to/bin")?;
-        assert_eq!(source, Source::Path(PathBuf::from("/path/to/bin")));
+        let source: Source = serde_yaml::from_str("path: /path/to/bin")?;
+        assert_eq!(source, Source::Path(PathBuf::from("/path/to/bin")));
+
+        let source: Source = serde_yaml::from_str("docker: tag")?;
+        assert_eq!(source, Source::Docker(String::from("tag")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compiler_launcher() -> Result<(), serde_yaml::Error> {
+        let source: Source = serde_yaml::from_str(
+            "git:
+  branch: master
+  url: https://github.com/pisa-engine/pisa.git
+  use_ccache: true",
+        )?;
+        let workdir = Path::new("/workdir");
+        let configure = source.compile_commands(workdir)[0].to_string();
+        assert_eq!(
+            configure,
+            "cmake -DCMAKE_BUILD_TYPE=Release -DCMAKE_C_COMPILER_LAUNCHER=ccache \
+             -DCMAKE_CXX_COMPILER_LAUNCHER=ccache /workdir/pisa"
+        );
+        let source: Source = serde_yaml::from_str(
+            "git:
+  branch: master
+  url: https://github.com/pisa-engine/pisa.git",
+        )?;
+        assert_eq!(
+            source.compile_commands(workdir)[0].to_string(),
+            "cmake -DCMAKE_BUILD_TYPE=Release /workdir/pisa"
+        );
+        assert_eq!(
+            source.compile_commands(workdir)[1].to_string(),
+            "cmake --build . -- -j 1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_generator() -> Result<(), Error> {
+        let source: Source = serde_yaml::from_str(
+            "git:
+  branch: master
+  url: https://github.com/pisa-engine/pisa.git
+  generator: Ninja
+  build_tool_args: [-k, '0']
+  compile_threads: 4",
+        )
+        .unwrap();
+        let commands = source.compile_commands(Path::new("/workdir"));
+        assert_eq!(
+            commands[0].to_string(),
+            "cmake -G Ninja -DCMAKE_BUILD_TYPE=Release /workdir/pisa"
+        );
+        assert_eq!(commands[1].to_string(), "cmake --build . -- -j 4 -k 0");
 
-        let source: Source = serde_yaml::from_str("docker: tag")?;
-        assert_eq!(source, Source::Docker(String::from("tag")));
+        let source: Source = serde_yaml::from_str(
+            "git:
+  branch: master
+  url: https://github.com/pisa-engine/pisa.git
+  targets: [queries, evaluate_queries]",
+        )
+        .unwrap();
+        assert_eq!(
+            source.compile_commands(Path::new("/workdir"))[1].to_string(),
+            "cmake --build . --target queries evaluate_queries -- -j 1"
+        );
 
+        let tmp = TempDir::new("generator").unwrap();
+        let cmake = source.cmake(tmp.path(), tmp.path()).unwrap();
+        assert_eq!(cmake.cache_key(), "CMAKE_BUILD_TYPE=Release\n-G Ninja");
+        let cache = tmp.path().join("CMakeCache.txt");
+        fs::write(&cache, "CMAKE_GENERATOR:INTERNAL=Ninja\n")?;
+        cmake.remove_stale_cache()?;
+        assert!(cache.exists());
+        fs::write(&cache, "CMAKE_GENERATOR:INTERNAL=Unix Makefiles\n")?;
+        fs::create_dir(tmp.path().join("CMakeFiles"))?;
+        cmake.remove_stale_cache()?;
+        assert!(!cache.exists());
+        assert!(!tmp.path().join("CMakeFiles").exists());
         Ok(())
     }
 
@@ -1129,7 +4227,8 @@ mod test {
 path: /path/to/topics"
             )?,
             Topics::Simple {
-                path: PathBuf::from("/path/to/topics")
+                path: PathBuf::from("/path/to/topics"),
+                qrels: None
             }
         );
         assert_eq!(
@@ -1140,9 +4239,69 @@ path: /path/to/topics"
             )?,
             Topics::Trec {
                 field: TopicField::Title,
-                path: PathBuf::from("/path/to/topics")
+                path: PathBuf::from("/path/to/topics"),
+                qrels: None
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_collection_preset() -> Result<(), serde_yaml::Error> {
+        #[derive(Deserialize)]
+        struct Collections {
+            #[serde(deserialize_with = "deserialize_collections")]
+            collections: Vec<Collection>,
+        }
+        let collections = serde_yaml::from_str::<Collections>(
+            "collections:
+  - preset: gov2
+    input_dir: /data/gov2
+  - preset: msmarco-passage
+    name: msmarco
+    input_dir: /data/msmarco
+    encodings: [ef]",
+        )?
+        .collections;
+        assert_eq!(
+            collections[0],
+            Collection {
+                name: String::from("gov2"),
+                kind: CollectionKind::TrecWeb,
+                input_dir: Some(PathBuf::from("/data/gov2")),
+                fwd_index: PathBuf::from("fwd/gov2"),
+                inv_index: PathBuf::from("inv/gov2"),
+                encodings: vec![
+                    Encoding::from("block_simdbp"),
+                    Encoding::from("block_optpfor"),
+                    Encoding::from("ef")
+                ],
+                scorers: default_scorers(),
+                batch_sizes: BatchSizesOverride::default(),
+                threads: Threads::default(),
+                input_glob: Some(String::from("GX*/*.gz")),
+                preset: Some(String::from("gov2")),
+                builder: Builder::Pisa,
+                stages: Default::default(),
+                stemmer: None,
+                sample_documents: None,
+                shards: 1,
+                shard: None,
+                quantize: false,
+                download: vec![],
             }
         );
+        assert_eq!(collections[1].name, "msmarco");
+        assert_eq!(collections[1].kind, CollectionKind::MsMarco);
+        assert_eq!(collections[1].fwd_index, PathBuf::from("fwd/msmarco"));
+        assert_eq!(collections[1].encodings, vec![Encoding::from("ef")]);
+        assert!(
+            serde_yaml::from_str::<Collections>("collections: [{preset: gov3}]")
+                .err()
+                .unwrap()
+                .to_string()
+                .contains("Unknown collection preset: gov3")
+        );
         Ok(())
     }
 
@@ -1167,11 +4326,67 @@ encodings:
                 inv_index: PathBuf::from("/path/to/inv"),
                 encodings: vec![Encoding::from("block_simdbp"), Encoding::from("ef")],
                 scorers: default_scorers(),
+                batch_sizes: BatchSizesOverride::default(),
+                threads: Threads::default(),
+                input_glob: None,
+                preset: None,
+                builder: Builder::Pisa,
+                stages: Default::default(),
+                stemmer: None,
+                sample_documents: None,
+                shards: 1,
+                shard: None,
+                quantize: false,
+                download: vec![],
             }
         );
         Ok(())
     }
 
+    #[test]
+    fn test_query_lexicon_detection() {
+        let tmp = TempDir::new("lexicon").unwrap();
+        let collection = Collection {
+            name: String::from("wapo"),
+            kind: CollectionKind::WashingtonPost,
+            input_dir: None,
+            fwd_index: tmp.path().join("fwd"),
+            inv_index: tmp.path().join("inv"),
+            encodings: vec![],
+            scorers: default_scorers(),
+            batch_sizes: BatchSizesOverride::default(),
+            threads: Threads::default(),
+            input_glob: None,
+            preset: None,
+            builder: Builder::Pisa,
+            stages: Default::default(),
+            stemmer: None,
+            sample_documents: None,
+            shards: 1,
+            shard: None,
+            quantize: false,
+            download: vec![],
+        };
+        assert_eq!(
+            collection.query_term_lexicon(),
+            tmp.path().join("fwd.termlex")
+        );
+        mkfiles(tmp.path(), &["fwd.terms", "fwd.documents"]).unwrap();
+        assert_eq!(
+            collection.query_term_lexicon(),
+            tmp.path().join("fwd.terms")
+        );
+        assert_eq!(
+            collection.query_document_lexicon(),
+            tmp.path().join("fwd.documents")
+        );
+        mkfiles(tmp.path(), &["fwd.termlex"]).unwrap();
+        assert_eq!(
+            collection.query_term_lexicon(),
+            tmp.path().join("fwd.termlex")
+        );
+    }
+
     #[test]
     fn test_parse_run() -> Result<(), serde_yaml::Error> {
         assert_eq!(
@@ -1197,22 +4412,47 @@ topics:
             Run {
                 collection: String::from("wapo"),
                 kind: RunKind::Evaluate {
-                    qrels: PathBuf::from("/path/to/qrels")
+                    qrels: PathBuf::from("/path/to/qrels"),
+                    min_relevance: None,
                 },
                 encodings: vec![Encoding::from("block_simdbp"), Encoding::from("ef")],
                 algorithms: vec![Algorithm::from("and"), Algorithm::from("wand")],
                 topics: vec![
                     Topics::Simple {
-                        path: PathBuf::from("/path/to/simple/topics")
+                        path: PathBuf::from("/path/to/simple/topics"),
+                        qrels: None
                     },
                     Topics::Trec {
                         field: TopicField::Narr,
-                        path: PathBuf::from("/path/to/trec/topics")
+                        path: PathBuf::from("/path/to/trec/topics"),
+                        qrels: None
                     },
                 ],
                 output: "/path/to/output".into(),
                 scorer: default_scorer(),
                 compare_with: None,
+                normalize: QueryNormalization::default(),
+                query_options: QueryOptions::default(),
+                per_class: false,
+                class_margins: HashMap::new(),
+                per_language: false,
+                export_features: false,
+                perf_counters: false,
+                cold_and_warm: false,
+                load_time: false,
+                query_times: false,
+                min_measures: Default::default(),
+                max_avg_ms: None,
+                max_p95_ms: None,
+                env: Default::default(),
+                warmup: 0,
+                repeats: 1,
+                qps_margin: None,
+                source: None,
+                keep_raw_output: false,
+                timeout: None,
+                selective_search: None,
+                shard: None,
             }
         );
         Ok(())
@@ -1261,6 +4501,18 @@ topics:
                     inv_index: workdir.join("inv"),
                     encodings: vec![Encoding::from("ef")],
                     scorers: default_scorers(),
+                    batch_sizes: BatchSizesOverride::default(),
+                    threads: Threads::default(),
+                    input_glob: None,
+                    preset: None,
+                    builder: Builder::Pisa,
+                    stages: Default::default(),
+                    stemmer: None,
+                    sample_documents: None,
+                    shards: 1,
+                    shard: None,
+                    quantize: false,
+                    download: vec![],
                 },
                 Collection {
                     name: String::from("wapo2"),
@@ -1270,6 +4522,18 @@ topics:
                     inv_index: workdir.join("inv"),
                     encodings: vec![Encoding::from("ef")],
                     scorers: default_scorers(),
+                    batch_sizes: BatchSizesOverride::default(),
+                    threads: Threads::default(),
+                    input_glob: None,
+                    preset: None,
+                    builder: Builder::Pisa,
+                    stages: Default::default(),
+                    stemmer: None,
+                    sample_documents: None,
+                    shards: 1,
+                    shard: None,
+                    quantize: false,
+                    download: vec![],
                 },
             ],
             runs: vec![
@@ -1280,10 +4544,33 @@ topics:
                     algorithms: vec![Algorithm::from("and")],
                     topics: vec![Topics::Simple {
                         path: workdir.join("simple_topics"),
+                        qrels: None,
                     }],
                     output: workdir.join("output"),
                     scorer: default_scorer(),
                     compare_with: None,
+                    normalize: QueryNormalization::default(),
+                    query_options: QueryOptions::default(),
+                    per_class: false,
+                    class_margins: HashMap::new(),
+                    per_language: false,
+                    export_features: false,
+                    perf_counters: false,
+                    cold_and_warm: false,
+                    load_time: false,
+                    query_times: false,
+                    min_measures: Default::default(),
+                    max_avg_ms: None,
+                    max_p95_ms: None,
+                    env: Default::default(),
+                    warmup: 0,
+                    repeats: 1,
+                    qps_margin: None,
+                    source: None,
+                    keep_raw_output: false,
+                    timeout: None,
+                    selective_search: None,
+                    shard: None,
                 },
                 Run {
                     collection: String::from("wapo"),
@@ -1292,24 +4579,71 @@ topics:
                     algorithms: vec![Algorithm::from("and")],
                     topics: vec![Topics::Simple {
                         path: workdir.join("simple_topics"),
+                        qrels: None,
                     }],
                     output: "output".into(),
                     scorer: default_scorer(),
-                    compare_with: Some(workdir.join("compare")),
+                    compare_with: Some(workdir.join("compare").into()),
+                    normalize: QueryNormalization::default(),
+                    query_options: QueryOptions::default(),
+                    per_class: false,
+                    class_margins: HashMap::new(),
+                    per_language: false,
+                    export_features: false,
+                    perf_counters: false,
+                    cold_and_warm: false,
+                    load_time: false,
+                    query_times: false,
+                    min_measures: Default::default(),
+                    max_avg_ms: None,
+                    max_p95_ms: None,
+                    env: Default::default(),
+                    warmup: 0,
+                    repeats: 1,
+                    qps_margin: None,
+                    source: None,
+                    keep_raw_output: false,
+                    timeout: None,
+                    selective_search: None,
+                    shard: None,
                 },
                 Run {
                     collection: String::from("wapo"),
                     kind: RunKind::Evaluate {
                         qrels: workdir.join("qrels"),
+                        min_relevance: None,
                     },
                     encodings: vec![Encoding::from("ef")],
                     algorithms: vec![Algorithm::from("and")],
                     topics: vec![Topics::Simple {
                         path: workdir.join("simple_topics"),
+                        qrels: None,
                     }],
                     output: "output".into(),
                     scorer: default_scorer(),
-                    compare_with: Some(tmp.path().join("compare")),
+                    compare_with: Some(tmp.path().join("compare").into()),
+                    normalize: QueryNormalization::default(),
+                    query_options: QueryOptions::default(),
+                    per_class: false,
+                    class_margins: HashMap::new(),
+                    per_language: false,
+                    export_features: false,
+                    perf_counters: false,
+                    cold_and_warm: false,
+                    load_time: false,
+                    query_times: false,
+                    min_measures: Default::default(),
+                    max_avg_ms: None,
+                    max_p95_ms: None,
+                    env: Default::default(),
+                    warmup: 0,
+                    repeats: 1,
+                    qps_margin: None,
+                    source: None,
+                    keep_raw_output: false,
+                    timeout: None,
+                    selective_search: None,
+                    shard: None,
                 },
             ],
             source: Source::System,
@@ -1339,11 +4673,452 @@ topics:
         assert_eq!(config.collection(1).inv_index, workdir.join("inv"));
         assert_eq!(config.run(0).output, workdir.join("output"));
         assert_eq!(config.run(1).output, workdir.join("output"));
-        assert_eq!(config.run(1).compare_with, Some(workdir.join("compare")));
+        assert_eq!(
+            config.run(1).compare_with,
+            Some(workdir.join("compare").into())
+        );
         assert_eq!(config.source(), &Source::System);
         assert!(config.clean());
     }
 
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_resolve_collection_overrides(mut resolve_fixture: ResolveFixture) {
+        resolve_fixture.config.batch_sizes.invert = 20;
+        resolve_fixture.config.threads.parse = Some(4);
+        resolve_fixture.config.collections[0].batch_sizes.parse = Some(50_000);
+        resolve_fixture.config.collections[0].threads.invert = Some(2);
+        let config = ResolvedPathsConfig::from(resolve_fixture.config).unwrap();
+        assert_eq!(
+            config.collection(0).batch_sizes,
+            BatchSizesOverride {
+                parse: Some(50_000),
+                invert: Some(20)
+            }
+        );
+        assert_eq!(
+            config.collection(0).threads,
+            Threads {
+                parse: Some(4),
+                invert: Some(2),
+                ..Threads::default()
+            }
+        );
+        assert_eq!(
+            config.collection(1).batch_sizes,
+            BatchSizesOverride {
+                parse: Some(10_000),
+                invert: Some(20)
+            }
+        );
+    }
+
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_resolve_incompatible_encoding(mut resolve_fixture: ResolveFixture) {
+        resolve_fixture
+            .config
+            .compatibility
+            .insert(Algorithm::from("and"), vec![Encoding::from("block_simdbp")]);
+        assert_eq!(
+            ResolvedPathsConfig::from(resolve_fixture.config)
+                .err()
+                .unwrap()
+                .to_string(),
+            format!(
+                "Invalid config: Algorithm and is incompatible with encoding ef in run: {}",
+                resolve_fixture.workdir.join("output").display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_standard_topics() {
+        let run: Run = serde_yaml::from_str(
+            "
+collection: msmarco
+kind:
+    evaluate: {}
+output: runs/dl19
+topics: {standard: trec-dl-2019}",
+        )
+        .unwrap();
+        assert_eq!(
+            run.topics,
+            vec![Topics::Standard {
+                standard: String::from("trec-dl-2019")
+            }]
+        );
+        assert_eq!(
+            run.kind,
+            RunKind::Evaluate {
+                qrels: PathBuf::new(),
+                min_relevance: None
+            }
+        );
+        let run: Run = serde_yaml::from_str(
+            "
+collection: msmarco
+kind: benchmark
+output: runs/dl
+topics:
+    - standard: trec-dl-2019
+    - kind: simple
+      path: /topics
+    - url: https://example.com/topics",
+        )
+        .unwrap();
+        assert_eq!(
+            run.topics,
+            vec![
+                Topics::Standard {
+                    standard: String::from("trec-dl-2019")
+                },
+                Topics::Simple {
+                    path: PathBuf::from("/topics"),
+                    qrels: None
+                },
+                Topics::Url {
+                    url: String::from("https://example.com/topics"),
+                    sha256: None,
+                    field: TopicField::Title,
+                    qrels: None
+                }
+            ]
+        );
+    }
+
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_resolve_url_topics(mut resolve_fixture: ResolveFixture) {
+        let workdir = resolve_fixture.workdir.clone();
+        let topics_url = "https://example.com/topics.301-450.gz";
+        let qrels_url = "https://example.com/qrels.301-450";
+        let topics_path = registry::downloaded_path(&workdir, topics_url);
+        let qrels_path = registry::downloaded_path(&workdir, qrels_url);
+        for path in &[&topics_path, &qrels_path] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        resolve_fixture.config.runs[2].kind = RunKind::Evaluate {
+            qrels: PathBuf::new(),
+            min_relevance: None,
+        };
+        resolve_fixture.config.runs[2].compare_with = None;
+        resolve_fixture.config.runs[2].topics = vec![Topics::Url {
+            url: String::from(topics_url),
+            sha256: None,
+            field: TopicField::Desc,
+            qrels: Some(Qrels {
+                url: String::from(qrels_url),
+                sha256: None,
+            }),
+        }];
+        let config = ResolvedPathsConfig::from(resolve_fixture.config).unwrap();
+        assert_eq!(
+            config.run(2).topics,
+            vec![Topics::Trec {
+                path: topics_path,
+                field: TopicField::Desc,
+                qrels: Some(qrels_path)
+            }]
+        );
+    }
+
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_run_qrels_per_topics(mut resolve_fixture: ResolveFixture) {
+        let run = &mut resolve_fixture.config.runs[2];
+        run.kind = RunKind::Evaluate {
+            qrels: PathBuf::from("qrels"),
+            min_relevance: None,
+        };
+        run.topics = vec![
+            Topics::Simple {
+                path: PathBuf::from("topics.1"),
+                qrels: Some(PathBuf::from("qrels.1")),
+            },
+            Topics::Simple {
+                path: PathBuf::from("topics.2"),
+                qrels: None,
+            },
+        ];
+        assert_eq!(run.qrels(0), Some(Path::new("qrels.1")));
+        assert_eq!(run.qrels(1), Some(Path::new("qrels")));
+        run.kind = RunKind::Benchmark;
+        assert_eq!(run.qrels(0), None);
+    }
+
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_resolve_standard_topics(mut resolve_fixture: ResolveFixture) {
+        let workdir = resolve_fixture.workdir.clone();
+        mkfiles(
+            &workdir,
+            &[
+                "standard/",
+                "standard/robust04/",
+                "standard/robust04/topics",
+                "standard/robust04/qrels",
+            ],
+        )
+        .unwrap();
+        resolve_fixture.config.runs[2].kind = RunKind::Evaluate {
+            qrels: PathBuf::new(),
+            min_relevance: None,
+        };
+        resolve_fixture.config.runs[2].compare_with = None;
+        resolve_fixture.config.runs[2].topics = vec![Topics::Standard {
+            standard: String::from("robust04"),
+        }];
+        let config = ResolvedPathsConfig::from(resolve_fixture.config).unwrap();
+        assert_eq!(
+            config.run(2).topics,
+            vec![Topics::Trec {
+                path: workdir.join("standard/robust04/topics"),
+                field: TopicField::Title,
+                qrels: Some(workdir.join("standard/robust04/qrels"))
+            }]
+        );
+        assert_eq!(
+            config.run(2).qrels(0),
+            Some(workdir.join("standard/robust04/qrels").as_path())
+        );
+    }
+
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_resolve_external_builder(mut resolve_fixture: ResolveFixture) {
+        resolve_fixture.config.collections[1].input_dir = None;
+        resolve_fixture.config.collections[1].builder = Builder::External {
+            command: String::from("ciff2pisa --output {inv_index} {ciff}"),
+        };
+        assert_eq!(
+            ResolvedPathsConfig::from(resolve_fixture.config)
+                .err()
+                .unwrap()
+                .to_string(),
+            "Invalid config: Unknown placeholder: {ciff}"
+        );
+    }
+
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_resolve_document_sample(mut resolve_fixture: ResolveFixture) {
+        let name = resolve_fixture.config.collections[0].name.clone();
+        resolve_fixture.config.collections[0].sample_documents = Some(DocumentSample {
+            fraction: 1.5,
+            seed: 0,
+        });
+        assert_eq!(
+            ResolvedPathsConfig::from(resolve_fixture.config)
+                .err()
+                .unwrap()
+                .to_string(),
+            format!(
+                "Invalid config: Sampled fraction of collection {} not in (0, 1]: 1.5",
+                name
+            )
+        );
+        let collection: Collection = serde_yaml::from_str(
+            "
+name: robust-10
+kind: robust
+fwd_index: fwd
+inv_index: inv
+sample_documents: {fraction: 0.1}",
+        )
+        .unwrap();
+        assert_eq!(
+            collection.sample_documents,
+            Some(DocumentSample {
+                fraction: 0.1,
+                seed: 0
+            })
+        );
+    }
+
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_resolve_shards(mut resolve_fixture: ResolveFixture) {
+        let workdir = resolve_fixture.workdir.clone();
+        resolve_fixture.config.collections[0].shards = 2;
+        let config = ResolvedPathsConfig::from(resolve_fixture.config).unwrap();
+        let names: Vec<_> = config
+            .collections()
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["wapo.000", "wapo.001", "wapo2"]);
+        let shard = &config.collections()[1];
+        assert_eq!(shard.fwd_index, workdir.join("fwd.001"));
+        assert_eq!(shard.inv_index, workdir.join("inv.001"));
+        assert_eq!(shard.shard, Some(Shard { index: 1, count: 2 }));
+        assert_eq!(config.collections()[2].shard, None);
+        assert_eq!(config.runs().len(), 6);
+        let run = config.run(3);
+        assert_eq!(run.collection, "wapo.001");
+        assert_eq!(run.output, workdir.join("output.001"));
+        assert_eq!(
+            run.compare_with.as_ref().map(|b| b.path.clone()),
+            Some(workdir.join("compare.001"))
+        );
+        assert_eq!(config.run(0).output, workdir.join("output.000"));
+
+        let mut config = config.0;
+        config.collections[2].shards = 2;
+        config.collections[2].builder = Builder::External {
+            command: String::from("import {inv_index}"),
+        };
+        assert_eq!(
+            ResolvedPathsConfig::from(config).err().unwrap().to_string(),
+            "Invalid config: Collection wapo2 cannot be sharded with an external builder"
+        );
+    }
+
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_resolve_selective_search(mut resolve_fixture: ResolveFixture) {
+        let workdir = resolve_fixture.workdir.clone();
+        resolve_fixture.config.collections[0].shards = 2;
+        resolve_fixture.config.runs[0].query_times = true;
+        resolve_fixture.config.runs[0].selective_search = Some(SelectiveSearch {
+            resource_selection: PathBuf::from("selection"),
+        });
+        let config = ResolvedPathsConfig::from(resolve_fixture.config).unwrap();
+        let run = config.run(1);
+        assert_eq!(run.shard, Some(Shard { index: 1, count: 2 }));
+        assert_eq!(
+            run.selective_search,
+            Some(SelectiveSearch {
+                resource_selection: workdir.join("selection"),
+            })
+        );
+        let unsharded = run.unsharded();
+        assert_eq!(unsharded.collection, "wapo");
+        assert_eq!(unsharded.output, workdir.join("output"));
+        assert_eq!(unsharded.shard, None);
+
+        let config = config.0;
+        assert_eq!(
+            ResolvedPathsConfig::from(config).err().unwrap().to_string(),
+            format!(
+                "Invalid config: Selective search of run {} requires a sharded collection",
+                workdir.join("output.000").display()
+            )
+        );
+    }
+
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_resolve_selective_search_without_query_times(mut resolve_fixture: ResolveFixture) {
+        let workdir = resolve_fixture.workdir.clone();
+        resolve_fixture.config.collections[0].shards = 2;
+        resolve_fixture.config.runs[0].selective_search = Some(SelectiveSearch {
+            resource_selection: PathBuf::from("selection"),
+        });
+        assert_eq!(
+            ResolvedPathsConfig::from(resolve_fixture.config)
+                .err()
+                .unwrap()
+                .to_string(),
+            format!(
+                "Invalid config: Selective search of run {} requires query_times",
+                workdir.join("output").display()
+            )
+        );
+    }
+
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_resolve_no_shards(mut resolve_fixture: ResolveFixture) {
+        resolve_fixture.config.collections[0].shards = 0;
+        assert_eq!(
+            ResolvedPathsConfig::from(resolve_fixture.config)
+                .err()
+                .unwrap()
+                .to_string(),
+            "Invalid config: Number of shards of collection wapo must be positive"
+        );
+    }
+
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_resolve_source_baseline(mut resolve_fixture: ResolveFixture) {
+        let workdir = resolve_fixture.workdir.clone();
+        resolve_fixture.config.sources.insert(
+            String::from("develop"),
+            Source::Git {
+                branch: String::from("develop"),
+                url: String::from("https://github.com/pisa-engine/pisa.git"),
+                cmake_vars: default_cmake_vars(),
+                local_path: default_local_path(),
+                compile_threads: default_no_threads(),
+                use_ccache: false,
+                compiler_launcher: None,
+                generator: None,
+                build_tool_args: vec![],
+                targets: vec![],
+            },
+        );
+        resolve_fixture.config.runs[1].compare_with = Some(Baseline {
+            path: PathBuf::new(),
+            tolerances: BTreeMap::new(),
+            source: Some(String::from("develop")),
+        });
+        let config = ResolvedPathsConfig::from(resolve_fixture.config).unwrap();
+        assert_eq!(
+            config.0.sources["develop"].git_dir(&workdir),
+            Some(workdir.join("pisa-develop"))
+        );
+        let baseline_path = workdir.join("sources").join("develop").join("output");
+        let baseline = config.run(1).source_baseline().unwrap();
+        assert_eq!(baseline.output, baseline_path);
+        assert_eq!(baseline.source.as_deref(), Some("develop"));
+        assert!(baseline.compare_with.is_none());
+        assert!(config.run(0).source_baseline().is_none());
+
+        let mut config = config.0;
+        config.runs[0].source = Some(String::from("missing"));
+        assert_eq!(
+            ResolvedPathsConfig::from(config).err().unwrap().to_string(),
+            "Invalid config: Source not defined: missing"
+        );
+    }
+
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_resolve_collection_stages(mut resolve_fixture: ResolveFixture) {
+        resolve_fixture.config.collections[1].stages =
+            serde_yaml::from_str("{wand: false, run: false}").unwrap();
+        assert_eq!(
+            ResolvedPathsConfig::from(resolve_fixture.config)
+                .err()
+                .unwrap()
+                .to_string(),
+            "Invalid config: Stage run cannot be set for collection: wapo2"
+        );
+    }
+
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_resolve_missing_qrels(mut resolve_fixture: ResolveFixture) {
+        resolve_fixture.config.runs[2].kind = RunKind::Evaluate {
+            qrels: PathBuf::new(),
+            min_relevance: None,
+        };
+        assert_eq!(
+            ResolvedPathsConfig::from(resolve_fixture.config)
+                .err()
+                .unwrap()
+                .to_string(),
+            format!(
+                "Invalid config: Missing qrels in run: {}",
+                resolve_fixture.workdir.join("output").display()
+            )
+        );
+    }
+
     #[rstest]
     #[allow(clippy::needless_pass_by_value)]
     fn test_resolve_paths_global_algorithms_and_encodings(mut resolve_fixture: ResolveFixture) {
@@ -1363,7 +5138,10 @@ topics:
         assert_eq!(config.collection(1).inv_index, workdir.join("inv"));
         assert_eq!(config.run(0).output, workdir.join("output"));
         assert_eq!(config.run(1).output, workdir.join("output"));
-        assert_eq!(config.run(1).compare_with, Some(workdir.join("compare")));
+        assert_eq!(
+            config.run(1).compare_with,
+            Some(workdir.join("compare").into())
+        );
         assert_eq!(config.source(), &Source::System);
         assert!(config.clean());
     }
@@ -1378,7 +5156,7 @@ topics:
             .err()
             .unwrap()
             .to_string()
-            .starts_with("Missing algorithms"));
+            .starts_with("Invalid config: Missing algorithms"));
     }
 
     #[rstest]
@@ -1391,7 +5169,7 @@ topics:
             .err()
             .unwrap()
             .to_string()
-            .starts_with("Missing encodings"));
+            .starts_with("Invalid config: Missing encodings"));
     }
 
     #[rstest]
@@ -1404,7 +5182,40 @@ topics:
             .err()
             .unwrap()
             .to_string()
-            .starts_with("Missing encodings"));
+            .starts_with("Invalid config: Missing encodings"));
+    }
+
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_resolve_paths_unknown_tool_override(mut resolve_fixture: ResolveFixture) {
+        resolve_fixture
+            .config
+            .tool_overrides
+            .insert(String::from("create_wand"), PathBuf::from("bin/wand"));
+        assert!(ResolvedPathsConfig::from(resolve_fixture.config)
+            .err()
+            .unwrap()
+            .to_string()
+            .starts_with("Invalid config: Unknown tool override: create_wand"));
+    }
+
+    #[rstest]
+    #[allow(clippy::needless_pass_by_value)]
+    fn test_resolve_paths_external_run_p95(mut resolve_fixture: ResolveFixture) {
+        let qrels = resolve_fixture.config.workdir.join("qrels");
+        let run = &mut resolve_fixture.config.runs[0];
+        run.kind = RunKind::External {
+            command: String::from("search {queries} > {output}"),
+            qrels,
+            min_relevance: None,
+        };
+        run.max_avg_ms = Some(5.0);
+        run.max_p95_ms = Some(20.0);
+        assert!(ResolvedPathsConfig::from(resolve_fixture.config)
+            .err()
+            .unwrap()
+            .to_string()
+            .starts_with("Invalid config: Maximum latency quantile defined for an external run"));
     }
 
     #[rstest]
@@ -1427,6 +5238,18 @@ topics:
                 inv_index: index_dir.join("inv"),
                 encodings: vec![Encoding::from("ef")],
                 scorers: default_scorers(),
+                batch_sizes: BatchSizesOverride::default(),
+                threads: Threads::default(),
+                input_glob: None,
+                preset: None,
+                builder: Builder::Pisa,
+                stages: Default::default(),
+                stemmer: None,
+                sample_documents: None,
+                shards: 1,
+                shard: None,
+                quantize: false,
+                download: vec![],
             },
         );
         let config = ResolvedPathsConfig::from(resolve_fixture.config).unwrap();
@@ -1472,23 +5295,134 @@ invert: 9"
             )?,
             Threads {
                 parse: Some(10),
-                invert: Some(9)
+                invert: Some(9),
+                ..Threads::default()
             }
         );
         assert_eq!(
             serde_yaml::from_str::<Threads>("parse: 10")?,
             Threads {
                 parse: Some(10),
-                invert: None
+                invert: None,
+                ..Threads::default()
             }
         );
         assert_eq!(
             serde_yaml::from_str::<Threads>("invert: 9")?,
             Threads {
                 parse: None,
-                invert: Some(9)
+                invert: Some(9),
+                ..Threads::default()
+            }
+        );
+        assert_eq!(
+            serde_yaml::from_str::<Threads>("{compress: 4, wand: 2, default: 8}")?,
+            Threads {
+                compress: Some(4),
+                wand: Some(2),
+                default: Some(8),
+                ..Threads::default()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_schedule() -> Result<(), serde_yaml::Error> {
+        let schedule = serde_yaml::from_str::<Schedule>("run: 22:00-06:30")?;
+        assert_eq!(schedule.build, None);
+        let window = schedule.for_stage(Stage::Run).unwrap();
+        assert_eq!(
+            window,
+            TimeWindow {
+                start: 22 * 60,
+                end: 6 * 60 + 30
             }
         );
+        assert_eq!(window.wait_seconds(23 * 3600), 0);
+        assert_eq!(window.wait_seconds(6 * 3600 + 30 * 60), 15 * 3600 + 30 * 60);
+        assert_eq!(window.wait_seconds(21 * 3600 + 59 * 60 + 59), 1);
+        assert!(serde_yaml::from_str::<Schedule>("build: 8:00").is_err());
+        assert!(serde_yaml::from_str::<Schedule>("build: 08:00-08:60").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_with_vars() -> Result<(), Error> {
+        let yaml = "
+vars:
+    dir: /work
+    branch: master
+    k: 10
+workdir: \"{{dir}}\"
+source:
+    git:
+        branch: \"{{ branch }}\"
+        url: https://github.com/pisa-engine/pisa.git
+collections: []
+runs:
+  - collection: wapo
+    kind: benchmark
+    output: \"{{dir}}/out-{{k}}\"
+    topics: []
+";
+        let config = RawConfig::parse(yaml, &[])?;
+        assert_eq!(config.workdir, PathBuf::from("/work"));
+        assert_eq!(config.runs[0].output, PathBuf::from("/work/out-10"));
+        let config = RawConfig::parse(yaml, &["branch=dev".parse()?, "k=5".parse()?])?;
+        assert_eq!(config.runs[0].output, PathBuf::from("/work/out-5"));
+        match config.source {
+            Source::Git { branch, .. } => assert_eq!(branch, "dev"),
+            _ => panic!(),
+        }
+        assert_eq!(config.vars["k"], "5");
+        assert!(RawConfig::parse("workdir: \"{{dir}}\"\ncollections: []", &[]).is_err());
+        let config = RawConfig::parse(
+            "# Set {{unset}} with --set\nworkdir: \"{{dir}}\" # {{unset}}\ncollections: []",
+            &["dir=/a \"b\": c #d".parse()?],
+        )?;
+        assert_eq!(config.workdir, PathBuf::from("/a \"b\": c #d"));
+        assert!("k".parse::<ConfigVar>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_strict() -> Result<(), Error> {
+        let yaml = "
+workdir: /work
+stages:
+    compile: false
+collections:
+  - name: wapo
+    kind: washington-post
+    fwd_index: fwd
+    inv_index: inv
+    encoddings:
+      - block_simdbp
+runs:
+  - collection: wapo
+    kind:
+        evaluate:
+            qrels: /path/to/qrels
+    output: out
+    topics:
+      - kind: simple
+        path: topics
+    class_margins:
+        1: 0.1
+";
+        assert_eq!(
+            RawConfig::parse(yaml, &[])?.unknown_keys,
+            vec![String::from("collections.0.encoddings")]
+        );
+        assert_eq!(
+            RawConfig::parse_strict(yaml, &[])
+                .err()
+                .unwrap()
+                .to_string(),
+            "Invalid config: Unknown config keys: collections.0.encoddings"
+        );
+        assert!(RawConfig::parse_strict(&yaml.replace("encoddings", "encodings"), &[]).is_ok());
         Ok(())
     }
 }
@@ -0,0 +1,219 @@
+//! SQLite database of results accumulated over sessions, for tracking
+//! long-term performance history.
+//!
+//! Each row of the `results` table is a single value of a measured combination of a run,
+//! e.g., the average latency of `wand` on `block_simdbp`, or the `map` of an evaluation,
+//! together with the PISA commit and the time at which it was recorded.
+//! The table can be queried directly, e.g., with the `sqlite3` shell:
+//!
+//! ```sql
+//! SELECT timestamp, commit_hash, value FROM results
+//! WHERE algorithm = 'wand' AND encoding = 'block_simdbp' AND measure = 'avg';
+//! ```
+
+use crate::config::{format_output_path, Run, RunKind};
+use crate::ensure_parent_exists;
+use crate::error::Error;
+use crate::run::{load_benchmark_results, load_eval_results};
+use failure::ResultExt;
+use itertools::iproduct;
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS results (
+    id INTEGER PRIMARY KEY,
+    timestamp INTEGER NOT NULL,
+    commit_hash TEXT,
+    collection TEXT NOT NULL,
+    output TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    algorithm TEXT NOT NULL,
+    encoding TEXT NOT NULL,
+    topics INTEGER NOT NULL,
+    measure TEXT NOT NULL,
+    value REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS results_combination
+    ON results (collection, algorithm, encoding, measure);
+";
+
+/// Single value of a measured combination of a run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultRow {
+    /// Query processing algorithm.
+    pub algorithm: String,
+    /// Posting list encoding.
+    pub encoding: String,
+    /// Index of the topics of the run.
+    pub topics: usize,
+    /// Measure name: `avg`, `q50`, `q90`, or `q95` for benchmarks (in microseconds),
+    /// and `qps` if the throughput was measured, or a `trec_eval` measure for evaluations.
+    pub measure: String,
+    /// Measured value.
+    pub value: f64,
+}
+
+fn kind_name(run: &Run) -> &'static str {
+    match run.kind {
+        RunKind::Evaluate { .. } => "evaluate",
+        RunKind::Benchmark => "benchmark",
+        RunKind::External { .. } => "external",
+    }
+}
+
+/// Collects the overall values of all measured combinations of `run` from its output files.
+/// Combinations without output files are skipped.
+pub fn run_results(run: &Run) -> Result<Vec<ResultRow>, Error> {
+    let mut rows = Vec::new();
+    for (algorithm, encoding, tid) in
+        iproduct!(&run.algorithms, &run.encodings, 0..run.topics.len())
+    {
+        let row = |measure: &str, value: f64| ResultRow {
+            algorithm: algorithm.to_string(),
+            encoding: encoding.to_string(),
+            topics: tid,
+            measure: measure.to_string(),
+            value,
+        };
+        match run.kind {
+            RunKind::Benchmark => {
+                let path = format_output_path(&run.output, algorithm, encoding, tid, "bench");
+                if !path.exists() {
+                    continue;
+                }
+                let results = load_benchmark_results(&path)?;
+                rows.push(row("avg", f64::from(results.avg_time)));
+                for (measure, quantile) in &[
+                    ("q50", results.quantile_50),
+                    ("q90", results.quantile_90),
+                    ("q95", results.quantile_95),
+                ] {
+                    if let Some(quantile) = quantile {
+                        rows.push(row(measure, f64::from(*quantile)));
+                    }
+                }
+                if let Some(qps) = results.qps {
+                    rows.push(row("qps", qps));
+                }
+            }
+            RunKind::Evaluate { .. } | RunKind::External { .. } => {
+                let path = format_output_path(&run.output, algorithm, encoding, tid, "trec_eval");
+                if !path.exists() {
+                    continue;
+                }
+                for line in load_eval_results(&path)?.lines() {
+                    let fields: Vec<_> = line.split_whitespace().collect();
+                    if let [measure, "all", value] = fields.as_slice() {
+                        if let Ok(value) = value.parse() {
+                            rows.push(row(*measure, value));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(rows)
+}
+
+/// Inserts the results of `run`, measured at `commit`, into the database at `path`,
+/// creating it if necessary. Returns the number of inserted rows.
+#[allow(clippy::cast_possible_wrap)]
+pub fn record(path: &Path, run: &Run, commit: Option<&str>) -> Result<usize, Error> {
+    let rows = run_results(run)?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    ensure_parent_exists(path)?;
+    let mut connection = Connection::open(path)
+        .with_context(|_| format!("Failed to open results database: {}", path.display()))?;
+    connection.execute_batch(SCHEMA)?;
+    let transaction = connection.transaction()?;
+    {
+        let mut insert = transaction.prepare(
+            "INSERT INTO results (timestamp, commit_hash, collection, output, kind,
+                algorithm, encoding, topics, measure, value)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        )?;
+        let output = run.output.display().to_string();
+        for row in &rows {
+            insert.execute(params![
+                timestamp as i64,
+                commit,
+                run.collection,
+                output,
+                kind_name(run),
+                row.algorithm,
+                row.encoding,
+                row.topics as i64,
+                row.measure,
+                row.value,
+            ])?;
+        }
+    }
+    transaction.commit()?;
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{mock_set_up, MockSetup};
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    #[allow(clippy::cast_possible_wrap)]
+    fn test_record() -> Result<(), Error> {
+        let tmp = TempDir::new("database").unwrap();
+        let MockSetup { config, .. } = mock_set_up(&tmp);
+        let evaluate = &config.0.runs[0];
+        let benchmark = &config.0.runs[2];
+        for (algorithm, encoding, tid) in iproduct!(
+            &evaluate.algorithms,
+            &evaluate.encodings,
+            0..evaluate.topics.len()
+        ) {
+            let path = format_output_path(&evaluate.output, algorithm, encoding, tid, "trec_eval");
+            fs::write(path, "runid all x\nmap all 0.25\nmap 301 0.5\n")?;
+        }
+        for (algorithm, encoding, tid) in iproduct!(
+            &benchmark.algorithms,
+            &benchmark.encodings,
+            0..benchmark.topics.len()
+        ) {
+            let path = format_output_path(&benchmark.output, algorithm, encoding, tid, "bench");
+            fs::write(
+                path,
+                format!(
+                    r#"{{"type":"{}","query":"{}","avg":1,"q50":1,"q90":2,"q95":3}}"#,
+                    encoding, algorithm
+                ),
+            )?;
+        }
+        let combinations =
+            |run: &Run| run.algorithms.len() * run.encodings.len() * run.topics.len();
+        let evaluated = run_results(evaluate)?;
+        assert_eq!(evaluated.len(), combinations(evaluate));
+        assert!(evaluated.iter().all(|row| row.measure == "map"));
+
+        let db = tmp.path().join("history").join("results.db");
+        assert_eq!(record(&db, evaluate, Some("abc"))?, combinations(evaluate));
+        assert_eq!(record(&db, benchmark, None)?, 4 * combinations(benchmark));
+        assert_eq!(record(&db, benchmark, None)?, 4 * combinations(benchmark));
+        let connection = Connection::open(&db)?;
+        let count = |sql: &str| -> Result<i64, Error> {
+            Ok(connection.query_row(sql, params![], |row| row.get(0))?)
+        };
+        assert_eq!(
+            count("SELECT COUNT(*) FROM results WHERE kind = 'benchmark' AND measure = 'q95'")?,
+            2 * combinations(benchmark) as i64
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM results WHERE commit_hash = 'abc'")?,
+            combinations(evaluate) as i64
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,326 @@
+//! Human-readable description of the experiment design of a config,
+//! e.g., for reproducibility appendices of papers.
+//!
+//! The description lists the source of the PISA tools, the collections,
+//! the matrix of runs (collections, topics, algorithms, encodings, and scorers),
+//! and how each run is checked: its baseline, regression margins, and thresholds.
+
+use crate::config::{Config, Run, RunKind, Topics};
+use crate::error::Error;
+use crate::summary::redact_paths;
+use failure::ResultExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use strum_macros::{Display, EnumString};
+
+/// Output format of a description.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+pub enum DescriptionFormat {
+    /// Markdown document with tables.
+    #[strum(serialize = "md")]
+    Markdown,
+    /// JSON object, e.g., for further processing.
+    #[strum(serialize = "json")]
+    Json,
+}
+
+/// Description of a collection.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CollectionDescription {
+    /// Collection name.
+    pub name: String,
+    /// Collection format, e.g., `washington-post`.
+    pub format: String,
+    /// Posting list encodings.
+    pub encodings: Vec<String>,
+    /// Scorers with WAND data.
+    pub scorers: Vec<String>,
+    /// Stemmer applied to documents and queries.
+    pub stemmer: String,
+}
+
+/// Description of a run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RunDescription {
+    /// Collection name.
+    pub collection: String,
+    /// Kind of run: `evaluate`, `benchmark`, or `external`.
+    pub kind: String,
+    /// Basename of the output files.
+    pub output: String,
+    /// Topic sets, with their format or TREC field.
+    pub topics: Vec<String>,
+    /// Query processing algorithms.
+    pub algorithms: Vec<String>,
+    /// Posting list encodings.
+    pub encodings: Vec<String>,
+    /// Ranking scoring function.
+    pub scorer: String,
+    /// Number of measured combinations of topics, algorithms, and encodings.
+    pub combinations: usize,
+    /// Path prefix of the baseline results, if any.
+    pub baseline: Option<String>,
+    /// Tolerated drops of `trec_eval` measures with respect to the baseline.
+    pub tolerances: BTreeMap<String, f64>,
+    /// Regression margins of query classes, overriding the global margin.
+    pub class_margins: BTreeMap<String, f32>,
+    /// Minimum values of `trec_eval` measures.
+    pub min_measures: BTreeMap<String, f64>,
+    /// Maximum average query latency in milliseconds.
+    pub max_avg_ms: Option<f32>,
+    /// Maximum 95th percentile of query latency in milliseconds.
+    pub max_p95_ms: Option<f32>,
+}
+
+/// Description of the experiment design of a config.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Description {
+    /// Source of the PISA tools.
+    pub source: String,
+    /// Performance regression margin.
+    pub margin: f32,
+    /// Collections.
+    pub collections: Vec<CollectionDescription>,
+    /// Runs, in the order of the config.
+    pub runs: Vec<RunDescription>,
+}
+
+fn strings<T: ToString>(values: &[T]) -> Vec<String> {
+    values.iter().map(ToString::to_string).collect()
+}
+
+/// Serialized name of a unit variant, e.g., `washington-post`.
+fn serialized_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+fn display_path(path: &Path) -> String {
+    path.display().to_string()
+}
+
+fn describe_topics(topics: &Topics) -> String {
+    match topics {
+        Topics::Simple { path, .. } => format!("{} (simple)", display_path(path)),
+        Topics::Trec { path, field, .. } => format!("{} ({})", display_path(path), field),
+        Topics::Combined { sources } => sources
+            .iter()
+            .map(|source| format!("{} × {}", describe_topics(&source.topics), source.weight))
+            .collect::<Vec<_>>()
+            .join(" + "),
+        Topics::Standard { standard } => format!("{} (standard)", standard),
+        Topics::Url { url, field, .. } => format!("{} ({})", url, field),
+    }
+}
+
+impl RunDescription {
+    fn new(run: &Run) -> Self {
+        let kind = match run.kind {
+            RunKind::Evaluate { .. } => "evaluate",
+            RunKind::Benchmark => "benchmark",
+            RunKind::External { .. } => "external",
+        };
+        Self {
+            collection: run.collection.clone(),
+            kind: String::from(kind),
+            output: display_path(&run.output),
+            topics: run.topics.iter().map(describe_topics).collect(),
+            algorithms: strings(&run.algorithms),
+            encodings: strings(&run.encodings),
+            scorer: run.scorer.to_string(),
+            combinations: run.topics.len() * run.algorithms.len() * run.encodings.len(),
+            baseline: run
+                .compare_with
+                .as_ref()
+                .map(|baseline| display_path(&baseline.path)),
+            tolerances: run
+                .compare_with
+                .as_ref()
+                .map(|baseline| baseline.tolerances.clone())
+                .unwrap_or_default(),
+            class_margins: run
+                .class_margins
+                .iter()
+                .map(|(class, margin)| (class.to_string(), margin.0))
+                .collect(),
+            min_measures: run.min_measures.clone(),
+            max_avg_ms: run.max_avg_ms,
+            max_p95_ms: run.max_p95_ms,
+        }
+    }
+
+    /// Lists how the run is checked, one item per criterion.
+    fn criteria(&self) -> Vec<String> {
+        let mut criteria = Vec::new();
+        if let Some(baseline) = &self.baseline {
+            criteria.push(format!("compared with baseline: {}", baseline));
+        }
+        for (measure, tolerance) in &self.tolerances {
+            criteria.push(format!("`{}` may drop by at most {}", measure, tolerance));
+        }
+        for (class, margin) in &self.class_margins {
+            criteria.push(format!("regression margin of class {}: {}", class, margin));
+        }
+        for (measure, min) in &self.min_measures {
+            criteria.push(format!("`{}` at least {}", measure, min));
+        }
+        if let Some(max) = self.max_avg_ms {
+            criteria.push(format!("average latency at most {} ms", max));
+        }
+        if let Some(max) = self.max_p95_ms {
+            criteria.push(format!("95th percentile latency at most {} ms", max));
+        }
+        criteria
+    }
+}
+
+impl Description {
+    /// Describes the experiment design of `config`.
+    pub fn new<C: Config>(config: &C) -> Self {
+        Self {
+            source: config.source().to_string(),
+            margin: config.margin().0,
+            collections: config
+                .collections()
+                .iter()
+                .map(|collection| CollectionDescription {
+                    name: collection.name.clone(),
+                    format: serialized_name(&collection.kind),
+                    encodings: strings(&collection.encodings),
+                    scorers: strings(&collection.scorers),
+                    stemmer: collection
+                        .stemmer
+                        .clone()
+                        .unwrap_or_else(|| String::from("porter2")),
+                })
+                .collect(),
+            runs: config.runs().iter().map(RunDescription::new).collect(),
+        }
+    }
+
+    /// Renders the description as a Markdown document.
+    pub fn render_markdown(&self) -> String {
+        let mut lines = vec![
+            String::from("# Experiment Design"),
+            String::new(),
+            format!("- PISA source: {}", self.source),
+            format!("- regression margin: {}", self.margin),
+            String::new(),
+            String::from("## Collections"),
+            String::new(),
+            String::from("| collection | format | encodings | scorers | stemmer |"),
+            String::from("|---|---|---|---|---|"),
+        ];
+        for c in &self.collections {
+            lines.push(format!(
+                "| {} | {} | {} | {} | {} |",
+                c.name,
+                c.format,
+                c.encodings.join(", "),
+                c.scorers.join(", "),
+                c.stemmer
+            ));
+        }
+        lines.extend(vec![
+            String::new(),
+            String::from("## Runs"),
+            String::new(),
+            String::from(
+                "| # | collection | kind | topics | algorithms | encodings | scorer | combinations |",
+            ),
+            String::from("|---|---|---|---|---|---|---|---|"),
+        ]);
+        for (idx, r) in self.runs.iter().enumerate() {
+            lines.push(format!(
+                "| {} | {} | {} | {} | {} | {} | {} | {} |",
+                idx + 1,
+                r.collection,
+                r.kind,
+                r.topics.join(", "),
+                r.algorithms.join(", "),
+                r.encodings.join(", "),
+                r.scorer,
+                r.combinations
+            ));
+        }
+        let total: usize = self.runs.iter().map(|r| r.combinations).sum();
+        lines.push(String::new());
+        lines.push(format!("Total combinations: {}", total));
+        lines.push(String::new());
+        lines.push(String::from("## Baselines and Thresholds"));
+        lines.push(String::new());
+        for (idx, r) in self.runs.iter().enumerate() {
+            let criteria = r.criteria();
+            if criteria.is_empty() {
+                lines.push(format!("- run {}: not checked", idx + 1));
+            } else {
+                lines.push(format!("- run {}:", idx + 1));
+                lines.extend(criteria.into_iter().map(|c| format!("  - {}", c)));
+            }
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+}
+
+/// Renders the experiment design of `config` in `format`.
+///
+/// If `redact` is `true`, absolute paths are replaced with placeholders
+/// (see [`redact_paths`](../summary/fn.redact_paths.html)).
+pub fn describe<C: Config>(
+    config: &C,
+    format: DescriptionFormat,
+    redact: bool,
+) -> Result<String, Error> {
+    let description = Description::new(config);
+    let output = match format {
+        DescriptionFormat::Markdown => description.render_markdown(),
+        DescriptionFormat::Json => {
+            serde_json::to_string_pretty(&description).context("Failed to serialize description")?
+        }
+    };
+    Ok(if redact {
+        redact_paths(&output, config.workdir())
+    } else {
+        output
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{mock_set_up, MockSetup};
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_describe() -> Result<(), Error> {
+        let tmp = TempDir::new("describe").unwrap();
+        let MockSetup { mut config, .. } = mock_set_up(&tmp);
+        config.0.runs[0]
+            .min_measures
+            .insert(String::from("map"), 0.25);
+        let description = Description::new(&config);
+        assert_eq!(description.collections[0].format, "washington-post");
+        assert_eq!(description.collections[0].stemmer, "porter2");
+        let run = &description.runs[0];
+        assert_eq!(
+            run.combinations,
+            run.topics.len() * run.algorithms.len() * run.encodings.len()
+        );
+        let markdown = describe(&config, DescriptionFormat::Markdown, true)?;
+        assert!(markdown.starts_with("# Experiment Design\n"));
+        assert!(markdown
+            .contains("| wapo | washington-post | block_simdbp, block_qmx | bm25 | porter2 |"));
+        assert!(markdown.contains("  - `map` at least 0.25\n"));
+        assert!(!markdown.contains(&tmp.path().display().to_string()));
+        let json = describe(&config, DescriptionFormat::Json, false)?;
+        assert_eq!(
+            serde_json::from_str::<Description>(&json).unwrap(),
+            description
+        );
+        Ok(())
+    }
+}
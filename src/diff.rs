@@ -0,0 +1,116 @@
+//! Per-query visualization of differences between ranked result lists.
+
+use std::collections::BTreeMap;
+
+/// Ranked list of `(docid, score)` pairs of a single query.
+type ResultList = Vec<(String, String)>;
+
+/// Parses results in the TREC run format (`qid Q0 docid rank score run`),
+/// grouping them by query, in the order of appearance.
+fn parse_results(results: &str) -> BTreeMap<String, ResultList> {
+    let mut queries: BTreeMap<String, ResultList> = BTreeMap::new();
+    for line in results.lines() {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        if fields.len() >= 5 {
+            queries
+                .entry(fields[0].to_string())
+                .or_default()
+                .push((fields[2].to_string(), fields[4].to_string()));
+        }
+    }
+    queries
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn cell(entry: Option<&(String, String)>) -> String {
+    entry.map_or_else(
+        || String::from("<td></td><td></td>"),
+        |(docid, score)| format!("<td>{}</td><td>{}</td>", escape(docid), escape(score)),
+    )
+}
+
+/// Renders an HTML page showing, for each query whose results differ from the baseline,
+/// the ranked lists side by side, with changed positions highlighted.
+///
+/// Both inputs are in the TREC run format. Returns `None` if no query differs.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::diff::render_html_diff;
+/// let results = "1 Q0 d1 0 2.5 R\n1 Q0 d2 1 1.5 R\n2 Q0 d3 0 1.0 R\n";
+/// let baseline = "1 Q0 d2 0 2.5 R\n1 Q0 d1 1 1.5 R\n2 Q0 d3 0 1.0 R\n";
+/// let html = render_html_diff(results, baseline).unwrap();
+/// assert!(html.contains("<h2>Query 1</h2>"));
+/// assert!(!html.contains("<h2>Query 2</h2>"));
+/// assert!(render_html_diff(results, results).is_none());
+/// ```
+pub fn render_html_diff(results: &str, baseline: &str) -> Option<String> {
+    let results = parse_results(results);
+    let baseline = parse_results(baseline);
+    let empty = ResultList::new();
+    let mut qids: Vec<&String> = results.keys().chain(baseline.keys()).collect();
+    qids.sort();
+    qids.dedup();
+    let mut body = String::new();
+    for qid in qids {
+        let run = results.get(qid).unwrap_or(&empty);
+        let base = baseline.get(qid).unwrap_or(&empty);
+        if run == base {
+            continue;
+        }
+        body.push_str(&format!("<h2>Query {}</h2>\n<table>\n", escape(qid)));
+        body.push_str(
+            "<tr><th>rank</th><th>docid</th><th>score</th>\
+             <th>baseline docid</th><th>baseline score</th></tr>\n",
+        );
+        for rank in 0..run.len().max(base.len()) {
+            let (lhs, rhs) = (run.get(rank), base.get(rank));
+            let class = if lhs == rhs { "" } else { " class=\"changed\"" };
+            body.push_str(&format!(
+                "<tr{}><td>{}</td>{}{}</tr>\n",
+                class,
+                rank + 1,
+                cell(lhs),
+                cell(rhs)
+            ));
+        }
+        body.push_str("</table>\n");
+    }
+    if body.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Result differences</title>\n\
+         <style>table {{ border-collapse: collapse; }} td, th {{ padding: 2px 8px; }} \
+         .changed {{ background: #fdd; }}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        body
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_html_diff() {
+        let results = "1 Q0 d1 0 2.5 R\n1 Q0 d<2> 1 1.5 R\n";
+        let baseline = "1 Q0 d1 0 2.5 R\n1 Q0 d3 1 1.5 R\n1 Q0 d4 2 1.0 R\n";
+        let html = render_html_diff(results, baseline).unwrap();
+        assert!(html.contains("<tr><td>1</td><td>d1</td><td>2.5</td><td>d1</td><td>2.5</td></tr>"));
+        assert!(html.contains(
+            "<tr class=\"changed\"><td>2</td><td>d&lt;2&gt;</td><td>1.5</td>\
+             <td>d3</td><td>1.5</td></tr>"
+        ));
+        assert!(html.contains(
+            "<tr class=\"changed\"><td>3</td><td></td><td></td><td>d4</td><td>1.0</td></tr>"
+        ));
+    }
+}
@@ -2,17 +2,149 @@
 
 extern crate failure;
 
+use crate::config::{Algorithm, Stage};
+use crate::executor::TimedOut;
 use failure::{Context, Fail};
 use std::fmt::{self, Display};
 
+/// Kind of an error, which library users can match on.
+///
+/// Kinds with an empty description (`Io`, `Git`, `External`) are only wrappers
+/// around the original error, which is available as the error's cause.
+#[derive(Clone, Debug, PartialEq, Eq, Fail)]
+pub enum ErrorKind {
+    /// An error described by a message.
+    #[fail(display = "{}", _0)]
+    Message(String),
+    /// An I/O error.
+    #[fail(display = "")]
+    Io,
+    /// An error coming from `git2`.
+    #[fail(display = "")]
+    Git,
+    /// An error coming from another library.
+    #[fail(display = "")]
+    External,
+    /// Invalid or inconsistent configuration.
+    #[fail(display = "Invalid config")]
+    Config,
+    /// A failed build stage of a collection.
+    #[fail(display = "Failed to build {} at stage {}", collection, stage)]
+    Build {
+        /// The stage that failed.
+        stage: Stage,
+        /// Collection name.
+        collection: String,
+    },
+    /// A failed run.
+    #[fail(display = "Run {} failed for {}", run, algorithm)]
+    Run {
+        /// Run output basename.
+        run: String,
+        /// The algorithm that failed.
+        algorithm: Algorithm,
+    },
+    /// A process killed by a signal, e.g., by the OOM killer.
+    #[fail(display = "{} killed by signal {}", program, signal)]
+    Killed {
+        /// Program name.
+        program: String,
+        /// Signal number.
+        signal: i32,
+        /// Maximum resident set size of the killed process in kB, if available.
+        max_rss_kb: Option<u64>,
+    },
+    /// A process killed after exceeding its time limit.
+    #[fail(display = "{} timed out after {} s", program, seconds)]
+    Timeout {
+        /// Program name.
+        program: String,
+        /// Time limit in seconds.
+        seconds: u64,
+    },
+    /// A required program, other than a PISA tool, that is missing or broken.
+    #[fail(display = "Missing or broken program: {}", program)]
+    MissingProgram {
+        /// Program name.
+        program: String,
+    },
+    /// A failed comparison with baseline.
+    #[fail(display = "Failed to compare {} with baseline", run)]
+    Comparison {
+        /// Run output basename.
+        run: String,
+    },
+}
+
+impl ErrorKind {
+    /// Returns a hint for the user on how to fix the problem, if available.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Self::Config => Some("Verify the config file; use --strict-config to detect typos"),
+            Self::Build { .. } => Some(
+                "Inspect the output of the failing tool; \
+                 already finished stages can be skipped with --suppress",
+            ),
+            Self::Run { .. } => {
+                Some("Make sure the index was built for all encodings and scorers of the run")
+            }
+            Self::Killed { .. } => Some(
+                "The process may have run out of memory; \
+                 try lowering batch_sizes or threads in the config",
+            ),
+            Self::Timeout { .. } => Some(
+                "The tool may hang or need more time; \
+                 adjust timeouts in the config or the timeout of the run",
+            ),
+            Self::MissingProgram { .. } => Some(
+                "Install the program and make sure it is on the PATH; \
+                 e.g., jq is needed to parse collections of kind jsonl",
+            ),
+            Self::Comparison { .. } => Some(
+                "Make sure the baseline was produced with the same encodings, \
+                 algorithms, and topics",
+            ),
+            _ => None,
+        }
+    }
+}
+
 /// This error type is extensively used throughout the codebase.
-/// Any external errors are converted to this one using `convert()` method
-/// from [`failure`](https://docs.rs/failure/0.1.5/failure/) crate.
-/// The context is a string for simplicity's sake, since the only thing we
-/// care about is printing the error to the user.
+/// Any external errors are converted to this one, preserving the original
+/// error as the cause.
+/// The [`kind`](#method.kind) describes what failed.
 #[derive(Debug)]
 pub struct Error {
-    inner: Context<String>,
+    inner: Context<ErrorKind>,
+}
+
+impl Error {
+    /// Returns the kind of this error.
+    pub fn kind(&self) -> &ErrorKind {
+        self.inner.get_context()
+    }
+
+    /// Returns the hint of the most specific error in the chain that has one.
+    pub fn hint(&self) -> Option<&'static str> {
+        let mut hint = self.kind().hint();
+        let mut cause = self.cause();
+        while let Some(fail) = cause {
+            if let Some(err) = fail.downcast_ref::<Self>() {
+                hint = err.kind().hint().or(hint);
+            } else if let Some(kind) = fail.downcast_ref::<std::io::Error>().and_then(timeout) {
+                hint = kind.hint().or(hint);
+            }
+            cause = fail.cause();
+        }
+        hint
+    }
+
+    /// Wraps this error in a new one of the given kind, with this as the cause.
+    pub fn wrap(self, kind: ErrorKind) -> Self {
+        Self {
+            inner: Fail::context(self, kind),
+        }
+    }
 }
 
 impl PartialEq for Error {
@@ -29,50 +161,77 @@ impl Fail for Error {
 
 impl Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", &self.inner)?;
-        if let Some(cause) = self.cause() {
-            for cause in cause.iter_chain() {
-                write!(f, ": {}", &cause)?;
-            }
+        let causes = self.cause().into_iter().flat_map(Fail::iter_chain);
+        let messages: Vec<String> = std::iter::once(self.kind().to_string())
+            .chain(causes.map(|cause| {
+                cause
+                    .downcast_ref::<Self>()
+                    .map_or_else(|| cause.to_string(), |err| err.kind().to_string())
+            }))
+            .filter(|msg| !msg.is_empty())
+            .collect();
+        write!(f, "{}", messages.join(": "))
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Self {
+            inner: Context::new(kind),
         }
-        write!(f, "")
+    }
+}
+
+impl From<Context<ErrorKind>> for Error {
+    fn from(inner: Context<ErrorKind>) -> Self {
+        Self { inner }
     }
 }
 
 impl From<&'static str> for Error {
     fn from(msg: &'static str) -> Self {
-        Self {
-            inner: Context::new(msg.to_string()),
-        }
+        Self::from(ErrorKind::Message(msg.to_string()))
     }
 }
 
 impl From<String> for Error {
     fn from(msg: String) -> Self {
-        Self {
-            inner: Context::new(msg),
-        }
+        Self::from(ErrorKind::Message(msg))
     }
 }
 
 impl From<Context<String>> for Error {
     fn from(inner: Context<String>) -> Self {
-        Self { inner }
+        Self {
+            inner: inner.map(ErrorKind::Message),
+        }
     }
 }
 
 impl From<Context<&'static str>> for Error {
     fn from(inner: Context<&'static str>) -> Self {
         Self {
-            inner: inner.map(String::from),
+            inner: inner.map(|msg| ErrorKind::Message(msg.to_string())),
         }
     }
 }
 
+/// Kind of an I/O error of a process killed after a timeout, if it is one.
+fn timeout(e: &std::io::Error) -> Option<ErrorKind> {
+    TimedOut::from_io(e).map(|timed_out| ErrorKind::Timeout {
+        program: timed_out.program.clone(),
+        seconds: timed_out.timeout.as_secs(),
+    })
+}
+
+/// Timeouts of processes are converted to their own kind, other I/O errors are wrapped.
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
+        if let Some(kind) = timeout(&e) {
+            return Self::from(kind);
+        }
         Self {
-            inner: Context::new(e.to_string()),
+            inner: e.context(ErrorKind::Io),
         }
     }
 }
@@ -80,7 +239,7 @@ impl From<std::io::Error> for Error {
 impl From<failure::Error> for Error {
     fn from(e: failure::Error) -> Self {
         Self {
-            inner: Context::new(e.to_string()),
+            inner: e.context(ErrorKind::External),
         }
     }
 }
@@ -88,7 +247,15 @@ impl From<failure::Error> for Error {
 impl From<git2::Error> for Error {
     fn from(e: git2::Error) -> Self {
         Self {
-            inner: Context::new(e.to_string()),
+            inner: e.context(ErrorKind::Git),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Self {
+            inner: e.context(ErrorKind::External),
         }
     }
 }
@@ -142,4 +309,55 @@ mod tests {
             "error message".to_string()
         );
     }
+
+    #[test]
+    fn test_kinds() {
+        let io = std::io::Error::new(std::io::ErrorKind::Other, "A");
+        let err = Error::from(io).wrap(ErrorKind::Build {
+            stage: Stage::Invert,
+            collection: String::from("wapo"),
+        });
+        assert_eq!(err.to_string(), "Failed to build wapo at stage invert: A");
+        assert!(err.kind().hint().is_some());
+        let err = Error::from("B").wrap(ErrorKind::Config);
+        assert_eq!(err.kind(), &ErrorKind::Config);
+        assert_eq!(err.to_string(), "Invalid config: B");
+        assert!(Error::from("B").kind().hint().is_none());
+
+        let timed_out = || {
+            std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                TimedOut {
+                    program: String::from("invert"),
+                    timeout: std::time::Duration::from_secs(60),
+                },
+            )
+        };
+        let err = Error::from(timed_out());
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::Timeout {
+                program: String::from("invert"),
+                seconds: 60
+            }
+        );
+        assert_eq!(err.to_string(), "invert timed out after 60 s");
+        let err = Error::from(
+            Err::<(), _>(timed_out())
+                .context("Failed to execute: invert")
+                .unwrap_err(),
+        );
+        assert_eq!(
+            err.to_string(),
+            "Failed to execute: invert: invert timed out after 60 s"
+        );
+        assert_eq!(
+            err.hint(),
+            ErrorKind::Timeout {
+                program: String::new(),
+                seconds: 0
+            }
+            .hint()
+        );
+    }
 }
@@ -0,0 +1,315 @@
+//! Internal evaluation of TREC results, an alternative to the `trec_eval` executable.
+//!
+//! The output follows the format of `trec_eval -q`: a line `<measure>\t<qid>\t<value>`
+//! per measure of each query, followed by the measures over all queries, so that it can
+//! be compared with baselines and checked against thresholds in the same way.
+//! Only queries with at least one relevant document are evaluated.
+
+use crate::error::Error;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+/// Cutoffs of precision measures, e.g., `P_10`.
+pub const PRECISION_CUTOFFS: &[usize] = &[5, 10, 15, 20, 30, 100, 200, 500, 1000];
+
+/// Cutoffs of recall measures, e.g., `recall_1000`.
+pub const RECALL_CUTOFFS: &[usize] = &[5, 10, 15, 20, 30, 100, 200, 500, 1000];
+
+/// Cutoffs of truncated nDCG measures, e.g., `ndcg_cut_10`.
+pub const NDCG_CUTOFFS: &[usize] = &[5, 10, 15, 20, 30, 100, 200, 500, 1000];
+
+/// Relevance judgments: query IDs mapped to judged documents and their relevance levels.
+type Judgments = HashMap<String, HashMap<String, i32>>;
+
+/// Parses qrels in the TREC format, `<qid> <iter> <docid> <relevance>`.
+fn parse_qrels(qrels: &str) -> Result<Judgments, Error> {
+    let mut judgments = Judgments::new();
+    for line in qrels.lines().filter(|line| !line.trim().is_empty()) {
+        match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [qid, _, docid, relevance] => {
+                let relevance = relevance
+                    .parse()
+                    .map_err(|_| format!("Invalid relevance in qrels line: {}", line))?;
+                judgments
+                    .entry((*qid).to_string())
+                    .or_default()
+                    .insert((*docid).to_string(), relevance);
+            }
+            _ => return Err(Error::from(format!("Invalid qrels line: {}", line))),
+        }
+    }
+    Ok(judgments)
+}
+
+/// Parses results in the TREC format, `<qid> <iter> <docid> <rank> <score> <run>`,
+/// and ranks the documents of each query as `trec_eval` does: by decreasing score,
+/// with ties broken by decreasing document ID. Returns the ranked documents of each query,
+/// and the run tag.
+fn parse_results(results: &str) -> Result<(BTreeMap<String, Vec<String>>, String), Error> {
+    let mut scored: BTreeMap<String, Vec<(f64, String)>> = BTreeMap::new();
+    let mut tag = String::new();
+    for line in results.lines().filter(|line| !line.trim().is_empty()) {
+        match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [qid, _, docid, _, score, run] => {
+                let score = score
+                    .parse()
+                    .map_err(|_| format!("Invalid score in results line: {}", line))?;
+                scored
+                    .entry((*qid).to_string())
+                    .or_default()
+                    .push((score, (*docid).to_string()));
+                tag = (*run).to_string();
+            }
+            _ => return Err(Error::from(format!("Invalid results line: {}", line))),
+        }
+    }
+    let ranked = scored
+        .into_iter()
+        .map(|(qid, mut documents)| {
+            documents.sort_by(|(lscore, ldoc), (rscore, rdoc)| {
+                rscore
+                    .partial_cmp(lscore)
+                    .unwrap_or(Ordering::Equal)
+                    .then_with(|| rdoc.cmp(ldoc))
+            });
+            (qid, documents.into_iter().map(|(_, docid)| docid).collect())
+        })
+        .collect();
+    Ok((ranked, tag))
+}
+
+/// Discounted cumulative gain of `gains` in rank order.
+#[allow(clippy::cast_precision_loss)]
+fn dcg<I: IntoIterator<Item = f64>>(gains: I) -> f64 {
+    gains
+        .into_iter()
+        .enumerate()
+        .map(|(rank, gain)| gain / (rank as f64 + 2.0).log2())
+        .sum()
+}
+
+/// Normalized DCG of the first `cutoff` gains, given the gains of all judged documents.
+fn ndcg(gains: &[f64], ideal: &[f64], cutoff: usize) -> f64 {
+    let ideal = dcg(ideal.iter().copied().take(cutoff));
+    if ideal > 0.0 {
+        dcg(gains.iter().copied().take(cutoff)) / ideal
+    } else {
+        0.0
+    }
+}
+
+/// Measures of a single query, in the order of `trec_eval` output.
+#[allow(clippy::cast_precision_loss)]
+fn query_measures(
+    ranking: &[String],
+    judged: &HashMap<String, i32>,
+    min_relevance: i32,
+) -> Vec<(String, f64)> {
+    let relevant = |docid: &String| judged.get(docid).is_some_and(|&rel| rel >= min_relevance);
+    let num_rel = judged.values().filter(|&&rel| rel >= min_relevance).count();
+    let mut measures = vec![
+        (String::from("num_ret"), ranking.len() as f64),
+        (String::from("num_rel"), num_rel as f64),
+        (
+            String::from("num_rel_ret"),
+            ranking.iter().filter(|docid| relevant(docid)).count() as f64,
+        ),
+    ];
+    let mut found = 0_u32;
+    let mut precision_sum = 0.0;
+    for (rank, docid) in ranking.iter().enumerate() {
+        if relevant(docid) {
+            found += 1;
+            precision_sum += f64::from(found) / (rank + 1) as f64;
+        }
+    }
+    measures.push((String::from("map"), precision_sum / num_rel as f64));
+    let relevant_at = |cutoff: usize| {
+        ranking
+            .iter()
+            .take(cutoff)
+            .filter(|docid| relevant(docid))
+            .count() as f64
+    };
+    for &cutoff in PRECISION_CUTOFFS {
+        measures.push((format!("P_{}", cutoff), relevant_at(cutoff) / cutoff as f64));
+    }
+    for &cutoff in RECALL_CUTOFFS {
+        measures.push((
+            format!("recall_{}", cutoff),
+            relevant_at(cutoff) / num_rel as f64,
+        ));
+    }
+    let gain = |docid: &String| f64::from(judged.get(docid).copied().unwrap_or(0).max(0));
+    let gains: Vec<f64> = ranking.iter().map(gain).collect();
+    let mut ideal: Vec<f64> = judged.values().map(|&rel| f64::from(rel.max(0))).collect();
+    ideal.sort_by(|lhs, rhs| rhs.partial_cmp(lhs).unwrap_or(Ordering::Equal));
+    measures.push((String::from("ndcg"), ndcg(&gains, &ideal, usize::MAX)));
+    for &cutoff in NDCG_CUTOFFS {
+        measures.push((format!("ndcg_cut_{}", cutoff), ndcg(&gains, &ideal, cutoff)));
+    }
+    measures
+}
+
+/// Formats a measure as `trec_eval` does: counts as integers, and other values
+/// with four decimal digits.
+fn format_measure(output: &mut String, name: &str, qid: &str, value: f64) {
+    if name.starts_with("num_") {
+        writeln!(output, "{:<22}\t{}\t{:.0}", name, qid, value).unwrap();
+    } else {
+        writeln!(output, "{:<22}\t{}\t{:.4}", name, qid, value).unwrap();
+    }
+}
+
+/// Aggregates the per-query measures of `eval_results`, in the format of `trec_eval -q`,
+/// over the queries whose IDs are `selected`: counts are summed, and other measures
+/// are averaged. Returns the aggregates in the same format, as measures of `all` queries.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::eval::aggregate;
+/// let eval_results = "map\t1\t0.5000\nmap\t2\t0.2000\nmap\t3\t0.3000\nmap\tall\t0.3333\n";
+/// let aggregates = aggregate(eval_results, |qid| qid != "1");
+/// assert!(aggregates.contains("num_q                 \tall\t2\n"));
+/// assert!(aggregates.contains("map                   \tall\t0.2500\n"));
+/// ```
+#[allow(clippy::cast_precision_loss)]
+pub fn aggregate<F: Fn(&str) -> bool>(eval_results: &str, selected: F) -> String {
+    let mut totals: Vec<(String, f64)> = Vec::new();
+    let mut queries = BTreeSet::new();
+    for line in eval_results.lines() {
+        if let [name, qid, value] = line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            if *qid == "all" || !selected(qid) {
+                continue;
+            }
+            if let Ok(value) = value.parse::<f64>() {
+                queries.insert(*qid);
+                match totals.iter_mut().find(|(measure, _)| measure == name) {
+                    Some((_, total)) => *total += value,
+                    None => totals.push(((*name).to_string(), value)),
+                }
+            }
+        }
+    }
+    let mut output = String::new();
+    writeln!(output, "{:<22}\tall\t{}", "num_q", queries.len()).unwrap();
+    for (name, total) in &totals {
+        let value = if name.starts_with("num_") {
+            *total
+        } else {
+            total / queries.len() as f64
+        };
+        format_measure(&mut output, name, "all", value);
+    }
+    output
+}
+
+/// Evaluates TREC `results` against `qrels` (both given as file contents),
+/// considering documents judged at least `min_relevance` (1 by default) relevant.
+///
+/// Returns the measures in the format of `trec_eval -q`: MAP, precision and recall
+/// at [cutoffs](constant.PRECISION_CUTOFFS.html), and nDCG, both over all documents
+/// and truncated, with relevance levels as gains.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::eval::evaluate;
+/// let qrels = "1 0 d1 1\n1 0 d2 0\n1 0 d3 2\n";
+/// let results = "1 Q0 d1 1 3.0 run\n1 Q0 d2 2 2.0 run\n1 Q0 d3 3 1.0 run\n";
+/// let measures = evaluate(qrels, results, None).unwrap();
+/// assert!(measures.contains("map                   \t1\t0.8333\n"));
+/// assert!(measures.contains("P_5                   \tall\t0.4000\n"));
+/// assert!(measures.contains("num_rel_ret           \tall\t2\n"));
+/// ```
+pub fn evaluate(qrels: &str, results: &str, min_relevance: Option<u32>) -> Result<String, Error> {
+    let min_relevance = i32::try_from(min_relevance.unwrap_or(1))
+        .map_err(|_| Error::from("Minimum relevance out of range"))?;
+    let judgments = parse_qrels(qrels)?;
+    let (rankings, tag) = parse_results(results)?;
+    let mut output = String::new();
+    let mut totals: Vec<(String, f64)> = Vec::new();
+    let mut num_q = 0_u32;
+    for (qid, ranking) in &rankings {
+        let judged = match judgments.get(qid) {
+            Some(judged) if judged.values().any(|&rel| rel >= min_relevance) => judged,
+            _ => continue,
+        };
+        let measures = query_measures(ranking, judged, min_relevance);
+        for (name, value) in &measures {
+            format_measure(&mut output, name, qid, *value);
+        }
+        if totals.is_empty() {
+            totals = measures;
+        } else {
+            for ((_, total), (_, value)) in totals.iter_mut().zip(measures) {
+                *total += value;
+            }
+        }
+        num_q += 1;
+    }
+    writeln!(output, "{:<22}\tall\t{}", "runid", tag).unwrap();
+    writeln!(output, "{:<22}\tall\t{}", "num_q", num_q).unwrap();
+    for (name, total) in &totals {
+        let value = if name.starts_with("num_") {
+            *total
+        } else {
+            total / f64::from(num_q)
+        };
+        format_measure(&mut output, name, "all", value);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::overall_measure;
+
+    #[test]
+    fn test_evaluate() -> Result<(), Error> {
+        let qrels = "1 0 d1 1\n1 0 d2 2\n2 0 d3 1\n3 0 d4 0\n";
+        let results = "\
+            1 Q0 d9 1 4.0 run\n\
+            1 Q0 d1 2 3.0 run\n\
+            1 Q0 d2 3 3.0 run\n\
+            2 Q0 d3 1 1.0 run\n\
+            3 Q0 d4 1 1.0 run\n";
+        let output = evaluate(qrels, results, None)?;
+        // Ties are broken by decreasing document ID: d9, d2, d1.
+        let map1 = (1.0 / 2.0 + 2.0 / 3.0) / 2.0;
+        assert!(output.contains(&format!("map                   \t1\t{:.4}\n", map1)));
+        assert!(output.contains("num_q                 \tall\t2\n"));
+        assert!(output.contains("num_ret               \tall\t4\n"));
+        let map = overall_measure(&output, "map").unwrap();
+        assert!((map - (map1 + 1.0) / 2.0).abs() < 1e-4);
+        let ndcg1 = (2.0 / 3_f64.log2() + 1.0 / 4_f64.log2()) / (2.0 + 1.0 / 3_f64.log2());
+        let ndcg = overall_measure(&output, "ndcg").unwrap();
+        assert!((ndcg - (ndcg1 + 1.0) / 2.0).abs() < 1e-4);
+        assert_eq!(overall_measure(&output, "P_5"), Some(0.3));
+        assert_eq!(overall_measure(&output, "recall_5"), Some(1.0));
+
+        let output = evaluate(qrels, results, Some(2))?;
+        assert!(output.contains("num_q                 \tall\t1\n"));
+        assert_eq!(overall_measure(&output, "map"), Some(0.5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert_eq!(
+            evaluate("1 0 d1\n", "", None).err().unwrap().to_string(),
+            "Invalid qrels line: 1 0 d1"
+        );
+        assert_eq!(
+            evaluate("", "1 Q0 d1 1 x run\n", None)
+                .err()
+                .unwrap()
+                .to_string(),
+            "Invalid score in results line: 1 Q0 d1 1 x run"
+        );
+    }
+}
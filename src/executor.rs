@@ -1,29 +1,807 @@
 //! Objects and functions dealing with executing PISA command line tools.
 
-use crate::{Algorithm, Collection, CommandDebug, Encoding, Error, Scorer};
+use crate::config::{Evaluator, Pinning, Priorities, Priority, QueryOptions, Schedule, Timeouts};
+use crate::identity::RESULTS_K;
+use crate::interrupt;
+use crate::version::PisaVersion;
+use crate::{Algorithm, Collection, CommandDebug, Encoding, Error, ErrorKind, Scorer, Stage};
 use boolinator::Boolinator;
 use failure::ResultExt;
+use log::{error, info, warn};
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
 use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+use strum::IntoEnumIterator;
 
-use std::process::Command;
+/// Hardware events collected with `perf stat` when requested.
+pub const PERF_EVENTS: &str = "cycles,instructions,LLC-load-misses";
+
+thread_local! {
+    /// Exit status and resource usage of the last child process reaped by this thread
+    /// with [`wait_with_usage`], if it was killed by a signal.
+    static KILLED_CHILD: Cell<Option<(ExitStatus, ResourceUsage)>> = const { Cell::new(None) };
+}
+
+/// Returns the maximum resident set size (in kB) of the child process last reaped
+/// by this thread, if it terminated with `status` after being killed by a signal.
+fn killed_child_max_rss_kb(status: ExitStatus) -> Option<u64> {
+    KILLED_CHILD
+        .with(Cell::take)
+        .filter(|(killed, _)| *killed == status)
+        .map(|(_, usage)| usage.max_rss_kb)
+}
+
+/// Advises the kernel to drop cached pages of the given files, so that they are
+/// subsequently read with a cold cache.
+///
+/// This is best-effort: failures are only logged, and dirty pages are not dropped.
+pub(crate) fn evict_from_page_cache(paths: &[PathBuf]) {
+    for path in paths {
+        let advised = File::open(path).map(|file| unsafe {
+            libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED)
+        });
+        match advised {
+            Ok(0) => {}
+            Ok(errno) => warn!(
+                "Unable to evict {} from page cache: error {}",
+                path.display(),
+                errno
+            ),
+            Err(err) => warn!(
+                "Unable to evict {} from page cache: {}",
+                path.display(),
+                err
+            ),
+        }
+    }
+}
+
+/// Makes `command` set its niceness and I/O priority before executing the program.
+pub(crate) fn set_priority(command: &mut Command, priority: Priority) -> &mut Command {
+    if priority == Priority::default() {
+        return command;
+    }
+    let Priority { nice, ionice } = priority;
+    // Only async-signal-safe calls are allowed between fork and exec.
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(nice) = nice {
+                if libc::setpriority(libc::PRIO_PROCESS as _, 0, nice) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(ionice) = ionice {
+                if libc::syscall(libc::SYS_ioprio_set, 1, 0, ionice.ioprio()) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Returns the current local time in seconds after midnight.
+fn local_seconds() -> u32 {
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    unsafe { libc::localtime_r(std::ptr::addr_of!(now), std::ptr::addr_of_mut!(tm)) };
+    u32::try_from(tm.tm_hour * 3600 + tm.tm_min * 60 + tm.tm_sec).unwrap_or_default()
+}
+
+/// Resources used by a terminated process.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ResourceUsage {
+    /// CPU time spent in user mode, in seconds.
+    pub user_seconds: f64,
+    /// CPU time spent in kernel mode, in seconds.
+    pub system_seconds: f64,
+    /// Maximum resident set size, in kB.
+    pub max_rss_kb: u64,
+}
+
+impl From<&libc::rusage> for ResourceUsage {
+    #[allow(clippy::cast_precision_loss)]
+    fn from(usage: &libc::rusage) -> Self {
+        let seconds = |time: libc::timeval| time.tv_sec as f64 + time.tv_usec as f64 / 1e6;
+        Self {
+            user_seconds: seconds(usage.ru_utime),
+            system_seconds: seconds(usage.ru_stime),
+            max_rss_kb: u64::try_from(usage.ru_maxrss).unwrap_or_default(),
+        }
+    }
+}
+
+/// Waits for the child process `pid` to terminate, collecting its resource usage.
+fn wait_with_usage(pid: u32) -> io::Result<(ExitStatus, ResourceUsage)> {
+    let pid =
+        libc::pid_t::try_from(pid).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        let waited = unsafe {
+            libc::wait4(
+                pid,
+                std::ptr::addr_of_mut!(status),
+                0,
+                std::ptr::addr_of_mut!(usage),
+            )
+        };
+        if waited != -1 {
+            let status = ExitStatus::from_raw(status);
+            let usage = ResourceUsage::from(&usage);
+            KILLED_CHILD.with(|killed| killed.set(status.signal().map(|_| (status, usage))));
+            return Ok((status, usage));
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Waits for the child process `pid` to terminate without reaping it, so that its
+/// process ID cannot be reused before it is reaped with [`wait_with_usage`].
+fn wait_exited(pid: u32) -> io::Result<()> {
+    let pid = libc::id_t::from(pid);
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    loop {
+        let waited = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                pid,
+                std::ptr::addr_of_mut!(info),
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+        if waited != -1 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// A process killed after exceeding its time limit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedOut {
+    /// Program name.
+    pub program: String,
+    /// Time limit.
+    pub timeout: Duration,
+}
+
+impl TimedOut {
+    /// Returns the timeout wrapped in `err`, if any.
+    pub fn from_io(err: &io::Error) -> Option<&Self> {
+        err.get_ref()?.downcast_ref::<Self>()
+    }
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} timed out after {} s",
+            self.program,
+            self.timeout.as_secs()
+        )
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Error of `command` killed after exceeding `timeout`.
+fn timed_out(command: &Command, timeout: Duration) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        TimedOut {
+            program: Invocation::from(command).program,
+            timeout,
+        },
+    )
+}
+
+/// Kills the process group of a child process if it is still running after a timeout,
+/// so that the processes it started, e.g., `queries` under `perf`, are killed too.
+struct Watchdog {
+    done: mpsc::Sender<()>,
+    killer: thread::JoinHandle<bool>,
+}
+
+impl Watchdog {
+    /// Starts watching the child process `pid`, which leads its own process group.
+    fn start(pid: u32, timeout: Duration) -> io::Result<Self> {
+        let pgid =
+            libc::pid_t::try_from(pid).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        let (done, finished) = mpsc::channel();
+        let killer = thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(timeout) {
+                unsafe { libc::kill(-pgid, libc::SIGKILL) };
+                true
+            } else {
+                false
+            }
+        });
+        Ok(Self { done, killer })
+    }
+
+    /// Stops watching, returning `true` if the process group was killed.
+    fn stop(self) -> bool {
+        // The killer also stops if the receiver is gone, so the result does not matter.
+        let _ = self.done.send(());
+        self.killer.join().unwrap_or(false)
+    }
+}
+
+/// Runs `child`, spawned by `command`, to termination, killing its process group
+/// if it does not terminate within `timeout`. The child is also killed if the session
+/// is interrupted (see [`interrupt`](../interrupt/index.html)). `read` collects the output
+/// of the child, and returns once its pipes are closed.
+fn supervise<T, F>(
+    command: &Command,
+    child: &mut Child,
+    timeout: Option<Duration>,
+    read: F,
+) -> io::Result<(T, ExitStatus, ResourceUsage)>
+where
+    F: FnOnce(&mut Child) -> io::Result<T>,
+{
+    let watchdog = timeout
+        .map(|timeout| Watchdog::start(child.id(), timeout))
+        .transpose()?;
+    interrupt::register(child.id(), timeout.is_some());
+    let read = read(child);
+    let exited = wait_exited(child.id());
+    interrupt::unregister(child.id());
+    if let (Some(watchdog), Some(timeout)) = (watchdog, timeout) {
+        if watchdog.stop() {
+            wait_with_usage(child.id())?;
+            return Err(timed_out(command, timeout));
+        }
+    }
+    exited?;
+    let read = read?;
+    let (status, usage) = wait_with_usage(child.id())?;
+    Ok((read, status, usage))
+}
+
+/// Spawns `command`, in its own process group if it may be killed after a timeout.
+fn spawn_supervised(command: &mut Command, timeout: Option<Duration>) -> io::Result<Child> {
+    interrupt::check()?;
+    if timeout.is_some() {
+        command.process_group(0);
+    }
+    command.spawn()
+}
+
+/// File within the work dir where the stage times of the last session are stored.
+pub const STAGE_TIMES_FILE: &str = "stage_times.csv";
+
+/// Header of the stage times file.
+pub const STAGE_TIMES_CSV_HEADER: &str =
+    "stage,commands,seconds,user_seconds,system_seconds,max_rss_mb";
+
+/// Time and resources used by all commands of a stage.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StageUsage {
+    /// Number of commands executed.
+    pub commands: usize,
+    /// Total wall-clock time, in seconds.
+    pub seconds: f64,
+    /// Total CPU time spent in user mode, in seconds.
+    pub user_seconds: f64,
+    /// Total CPU time spent in kernel mode, in seconds.
+    pub system_seconds: f64,
+    /// Maximum resident set size of any command, in kB, if known.
+    pub max_rss_kb: Option<u64>,
+}
+
+/// Time and resources used by commands executed in each stage.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StageTimes(pub HashMap<Stage, StageUsage>);
+
+impl StageTimes {
+    /// Path to the stage times within `workdir`.
+    pub fn path(workdir: &Path) -> PathBuf {
+        workdir.join(STAGE_TIMES_FILE)
+    }
+
+    /// Adds a command executed in `stage` for `seconds`, with its resource usage if known.
+    pub fn record(&mut self, stage: Stage, seconds: f64, usage: Option<ResourceUsage>) {
+        let entry = self.0.entry(stage).or_default();
+        entry.commands += 1;
+        entry.seconds += seconds;
+        if let Some(usage) = usage {
+            entry.user_seconds += usage.user_seconds;
+            entry.system_seconds += usage.system_seconds;
+            entry.max_rss_kb = entry.max_rss_kb.max(Some(usage.max_rss_kb));
+        }
+    }
+
+    /// Formats the stage times as CSV, with stages in the order of execution.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::{ResourceUsage, StageTimes};
+    /// # use stdbench::Stage;
+    /// let mut times = StageTimes::default();
+    /// let usage = ResourceUsage { user_seconds: 1.5, system_seconds: 0.5, max_rss_kb: 2048 };
+    /// times.record(Stage::Invert, 2.0, Some(usage));
+    /// times.record(Stage::Compress, 1.0, None);
+    /// assert_eq!(
+    ///     times.csv(),
+    ///     "stage,commands,seconds,user_seconds,system_seconds,max_rss_mb\n\
+    ///      invert,1,2.000,1.500,0.500,2\n\
+    ///      compress,1,1.000,0.000,0.000,\n"
+    /// );
+    /// ```
+    pub fn csv(&self) -> String {
+        let mut csv = format!("{}\n", STAGE_TIMES_CSV_HEADER);
+        for stage in Stage::iter() {
+            if let Some(usage) = self.0.get(&stage) {
+                csv.push_str(&format!(
+                    "{},{},{:.3},{:.3},{:.3},{}\n",
+                    stage,
+                    usage.commands,
+                    usage.seconds,
+                    usage.user_seconds,
+                    usage.system_seconds,
+                    usage
+                        .max_rss_kb
+                        .map_or_else(String::new, |kb| (kb / 1024).to_string())
+                ));
+            }
+        }
+        csv
+    }
+
+    /// Writes the stage times as CSV.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, self.csv()).with_context(|_| path.display().to_string())?;
+        Ok(())
+    }
+}
+
+/// Returns an error if `program` was killed by a signal, e.g., by the OOM killer.
+///
+/// The peak memory usage of the process is reported if it was run to completion
+/// by an [`Executor`](struct.Executor.html) on the current thread.
+pub(crate) fn killed(program: &str, status: ExitStatus) -> Option<Error> {
+    status.signal().map(|signal| {
+        let max_rss_kb = killed_child_max_rss_kb(status);
+        // Processes killed on interrupt are expected, and reported by the session.
+        if !interrupt::interrupted() {
+            error!(
+                "{} killed by signal {}; max memory used: {}",
+                program,
+                signal,
+                max_rss_kb
+                    .map_or_else(|| String::from("unknown"), |kb| format!("{} MB", kb / 1024))
+            );
+        }
+        Error::from(ErrorKind::Killed {
+            program: program.to_string(),
+            signal,
+            max_rss_kb,
+        })
+    })
+}
+
+/// Checks the exit status of `program`, distinguishing processes killed by a signal
+/// from regular failures, which are reported with `message`.
+pub(crate) fn check_status(
+    program: &str,
+    status: ExitStatus,
+    message: &'static str,
+) -> Result<(), Error> {
+    if let Some(err) = killed(program, status) {
+        return Err(err);
+    }
+    status.success().ok_or(message)?;
+    Ok(())
+}
+
+/// Passes `--threads` to a PISA tool if the thread count is set.
+fn threads_arg(command: &mut Command, threads: Option<usize>) {
+    if let Some(threads) = threads {
+        command.args(&["--threads", &threads.to_string()]);
+    }
+}
+
+/// Runs commands built by an [`Executor`](struct.Executor.html).
+///
+/// The default [`ProcessBackend`](struct.ProcessBackend.html) spawns system processes;
+/// [`FakeBackend`](struct.FakeBackend.html) only records the commands, which allows
+/// testing the orchestration logic without installing PISA tools.
+pub trait ExecBackend: fmt::Debug + Send + Sync {
+    /// Starts `command` without waiting for it to finish.
+    fn spawn(&self, command: &mut Command) -> io::Result<()>;
+
+    /// Runs `command` to completion, collecting its standard output and error.
+    fn output(&self, command: &mut Command) -> io::Result<Output>;
+
+    /// Runs `command` to completion, inheriting its standard output and error.
+    fn status(&self, command: &mut Command) -> io::Result<ExitStatus>;
+
+    /// Runs `command` like [`output`](#tymethod.output), also returning its resource usage
+    /// if known; by default, it is not. If it runs longer than `timeout`, it is killed
+    /// and an error of kind `TimedOut` is returned; by default, it is never killed.
+    fn output_with_usage(
+        &self,
+        command: &mut Command,
+        _timeout: Option<Duration>,
+    ) -> io::Result<(Output, Option<ResourceUsage>)> {
+        self.output(command).map(|output| (output, None))
+    }
+
+    /// Runs `command` like [`status`](#tymethod.status), also returning its resource usage
+    /// if known and killing it after `timeout` like
+    /// [`output_with_usage`](#method.output_with_usage).
+    fn status_with_usage(
+        &self,
+        command: &mut Command,
+        _timeout: Option<Duration>,
+    ) -> io::Result<(ExitStatus, Option<ResourceUsage>)> {
+        self.status(command).map(|status| (status, None))
+    }
+}
+
+/// Executes commands as system processes.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ProcessBackend;
+
+impl ExecBackend for ProcessBackend {
+    /// Spawned commands are not killed on interrupt, since they only feed
+    /// the commands run to completion, which are.
+    fn spawn(&self, command: &mut Command) -> io::Result<()> {
+        interrupt::check()?;
+        command.spawn().map(drop)
+    }
+
+    fn output(&self, command: &mut Command) -> io::Result<Output> {
+        command.output()
+    }
+
+    fn status(&self, command: &mut Command) -> io::Result<ExitStatus> {
+        command.status()
+    }
+
+    /// Standard input is null, and standard output and error are captured,
+    /// as in `Command::output`.
+    ///
+    /// A command with a timeout runs in its own process group, which is killed
+    /// as a whole when the timeout expires.
+    fn output_with_usage(
+        &self,
+        command: &mut Command,
+        timeout: Option<Duration>,
+    ) -> io::Result<(Output, Option<ResourceUsage>)> {
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut child = spawn_supervised(command, timeout)?;
+        let ((stdout, stderr), status, usage) = supervise(command, &mut child, timeout, |child| {
+            let mut stderr_pipe = child.stderr.take().expect("stderr not captured");
+            let stderr_reader = thread::spawn(move || {
+                let mut stderr = Vec::new();
+                stderr_pipe.read_to_end(&mut stderr).map(|_| stderr)
+            });
+            let mut stdout = Vec::new();
+            child
+                .stdout
+                .take()
+                .expect("stdout not captured")
+                .read_to_end(&mut stdout)?;
+            let stderr = stderr_reader.join().expect("stderr reader panicked")?;
+            Ok((stdout, stderr))
+        })?;
+        Ok((
+            Output {
+                status,
+                stdout,
+                stderr,
+            },
+            Some(usage),
+        ))
+    }
+
+    fn status_with_usage(
+        &self,
+        command: &mut Command,
+        timeout: Option<Duration>,
+    ) -> io::Result<(ExitStatus, Option<ResourceUsage>)> {
+        let mut child = spawn_supervised(command, timeout)?;
+        let ((), status, usage) = supervise(command, &mut child, timeout, |_| Ok(()))?;
+        Ok((status, Some(usage)))
+    }
+}
+
+/// Command executed through a [`FakeBackend`](struct.FakeBackend.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    /// File name of the program, without the executor path.
+    pub program: String,
+    /// Command line arguments.
+    pub args: Vec<String>,
+    /// Environment variables set for the command.
+    pub env: BTreeMap<String, String>,
+}
+
+impl Invocation {
+    pub(crate) fn from(command: &Command) -> Self {
+        let program = Path::new(command.get_program());
+        Self {
+            program: program
+                .file_name()
+                .unwrap_or(program.as_os_str())
+                .to_string_lossy()
+                .to_string(),
+            args: command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().to_string())
+                .collect(),
+            env: command
+                .get_envs()
+                .filter_map(|(key, value)| {
+                    value.map(|value| {
+                        (
+                            key.to_string_lossy().to_string(),
+                            value.to_string_lossy().to_string(),
+                        )
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Determines the stage of `command` from the PISA tool it executes, identifying tools
+    /// replaced with `tool_overrides` by their override path rather than their file name.
+    pub(crate) fn stage_of(command: &Command, tool_overrides: &BTreeMap<String, PathBuf>) -> Stage {
+        let mut invocation = Self::from(command);
+        if let Some((tool, _)) = tool_overrides
+            .iter()
+            .find(|(_, path)| path.as_os_str() == command.get_program())
+        {
+            invocation.program = tool.clone();
+        }
+        invocation.stage()
+    }
+
+    /// Determines the stage of a command from the PISA tool or helper program it executes.
+    ///
+    /// Shell commands are those of external builders, unless executed in another stage
+    /// explicitly. Any other program is accounted to `BuildIndex`, which encloses
+    /// all steps of building an index, rather than attributed to one of them.
+    pub(crate) fn stage(&self) -> Stage {
+        match self.program.as_str() {
+            "git" | "cmake" | "make" | "ninja" => Stage::Compile,
+            "curl" | "tar" | "sha256sum" => Stage::Fetch,
+            "parse_collection" if self.args.iter().any(|arg| arg == "merge") => Stage::Join,
+            "parse_collection" | "lexicon" | "cat" | "zcat" | "find" | "jq" | "gzip" | "gunzip"
+            | "wc" => Stage::Parse,
+            "invert" => Stage::Invert,
+            "create_freq_index" => Stage::Compress,
+            "create_wand_data" => Stage::Wand,
+            "sh" => Stage::External,
+            "evaluate_queries" | "queries" | "perf" | "extract_topics" | "trec_eval"
+            | "taskset" | "numactl" => Stage::Run,
+            _ => Stage::BuildIndex,
+        }
+    }
+}
+
+/// In-memory backend recording all invocations instead of executing them.
+///
+/// Programs succeed with empty output, unless configured otherwise
+/// with [`with_output`](#method.with_output), [`with_failure`](#method.with_failure),
+/// or [`with_hang`](#method.with_hang).
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::{Executor, FakeBackend};
+/// # use std::sync::Arc;
+/// let backend = Arc::new(FakeBackend::new().with_failure("invert", 1, "out of memory"));
+/// let executor = Executor::new().with_backend(backend.clone());
+/// assert!(executor.invert("fwd", "inv", 10, 100, None).is_err());
+/// assert_eq!(backend.invocations()[0].program, "invert");
+/// assert_eq!(backend.programs(), vec!["invert"]);
+/// ```
+#[derive(Debug, Default)]
+pub struct FakeBackend {
+    outputs: HashMap<String, (i32, String, String)>,
+    hanging: HashSet<String>,
+    invocations: Mutex<Vec<Invocation>>,
+}
+
+impl FakeBackend {
+    /// Creates a backend with which all programs succeed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes `program` succeed, printing `stdout`.
+    pub fn with_output(mut self, program: &str, stdout: &str) -> Self {
+        self.outputs
+            .insert(program.to_string(), (0, stdout.to_string(), String::new()));
+        self
+    }
+
+    /// Makes `program` exit with `code`, printing `stderr`.
+    pub fn with_failure(mut self, program: &str, code: i32, stderr: &str) -> Self {
+        self.outputs.insert(
+            program.to_string(),
+            (code, String::new(), stderr.to_string()),
+        );
+        self
+    }
+
+    /// Makes `program` hang, so that it times out if it has a timeout,
+    /// and otherwise behaves as configured.
+    pub fn with_hang(mut self, program: &str) -> Self {
+        self.hanging.insert(program.to_string());
+        self
+    }
+
+    /// All invocations so far, in the order of execution.
+    pub fn invocations(&self) -> Vec<Invocation> {
+        self.recorded().clone()
+    }
+
+    /// Names of all programs invoked so far, in the order of execution.
+    pub fn programs(&self) -> Vec<String> {
+        self.recorded()
+            .iter()
+            .map(|invocation| invocation.program.clone())
+            .collect()
+    }
+
+    /// The invocations stay consistent even if another thread panicked while holding
+    /// the lock, since they are only modified by single insertions.
+    fn recorded(&self) -> MutexGuard<'_, Vec<Invocation>> {
+        self.invocations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Fails like a killed process if `command` hangs and has a `timeout`.
+    fn hang(&self, command: &Command, timeout: Option<Duration>) -> io::Result<()> {
+        match timeout {
+            Some(timeout) if self.hanging.contains(&Invocation::from(command).program) => {
+                Err(timed_out(command, timeout))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn record(&self, command: &Command) -> Output {
+        let invocation = Invocation::from(command);
+        let (code, stdout, stderr) = self
+            .outputs
+            .get(&invocation.program)
+            .cloned()
+            .unwrap_or_default();
+        self.recorded().push(invocation);
+        Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: stdout.into_bytes(),
+            stderr: stderr.into_bytes(),
+        }
+    }
+}
+
+impl ExecBackend for FakeBackend {
+    fn spawn(&self, command: &mut Command) -> io::Result<()> {
+        self.record(command);
+        Ok(())
+    }
+
+    fn output(&self, command: &mut Command) -> io::Result<Output> {
+        Ok(self.record(command))
+    }
+
+    fn status(&self, command: &mut Command) -> io::Result<ExitStatus> {
+        Ok(self.record(command).status)
+    }
+
+    fn output_with_usage(
+        &self,
+        command: &mut Command,
+        timeout: Option<Duration>,
+    ) -> io::Result<(Output, Option<ResourceUsage>)> {
+        let output = self.record(command);
+        self.hang(command, timeout)?;
+        Ok((output, None))
+    }
+
+    fn status_with_usage(
+        &self,
+        command: &mut Command,
+        timeout: Option<Duration>,
+    ) -> io::Result<(ExitStatus, Option<ResourceUsage>)> {
+        let output = self.record(command);
+        self.hang(command, timeout)?;
+        Ok((output.status, None))
+    }
+}
 
 /// Executes PISA tools.
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Executor {
     /// The path where the tools are, or None if the system path should be used.
     path: Option<PathBuf>,
+    backend: Arc<dyn ExecBackend>,
+    priorities: Priorities,
+    timeouts: Timeouts,
+    schedule: Schedule,
+    /// Shared by all clones, so that commands executed concurrently are accounted together.
+    stage_times: Arc<Mutex<StageTimes>>,
+    /// The path to `trec_eval`, or None if the system path should be used.
+    trec_eval: Option<PathBuf>,
+    evaluator: Evaluator,
+    /// Whether commands run to completion inherit standard output and error.
+    show_tool_output: bool,
+    /// Options appended to `queries` and `evaluate_queries` commands.
+    query_options: QueryOptions,
+    /// Environment variables set for all executed commands.
+    env: BTreeMap<String, String>,
+    pinning: Pinning,
+    /// Paths of individual tools used instead of those in `path`.
+    tool_overrides: BTreeMap<String, PathBuf>,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Executors are equal if they resolve programs in the same way, regardless of their backends.
+impl PartialEq for Executor {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+    }
 }
 
 impl Executor {
     /// Creates an executor with the system path.
     pub fn new() -> Self {
-        Self { path: None }
+        Self {
+            path: None,
+            backend: Arc::new(ProcessBackend),
+            priorities: Priorities::default(),
+            timeouts: Timeouts::default(),
+            schedule: Schedule::default(),
+            stage_times: Arc::default(),
+            trec_eval: None,
+            evaluator: Evaluator::default(),
+            show_tool_output: true,
+            query_options: QueryOptions::default(),
+            env: BTreeMap::new(),
+            pinning: Pinning::default(),
+            tool_overrides: BTreeMap::new(),
+        }
     }
 
     /// Creates an executor with a custom path.
     pub fn from(path: PathBuf) -> Result<Self, Error> {
         if path.is_dir() {
-            Ok(Self { path: Some(path) })
+            Ok(Self::unchecked(path))
         } else {
             Err(Error::from(format!(
                 "Failed to construct executor: not a directory: {}",
@@ -32,27 +810,311 @@ impl Executor {
         }
     }
 
+    /// Creates an executor with a custom path, which may not exist yet,
+    /// e.g., before the tools are compiled.
+    pub fn unchecked(path: PathBuf) -> Self {
+        Self {
+            path: Some(path),
+            ..Self::new()
+        }
+    }
+
+    /// Resolves the path of `program` if necessary, preferring its override if any.
+    pub(crate) fn program(&self, program: &str) -> String {
+        if let Some(path) = self.tool_overrides.get(program) {
+            return path.to_str().unwrap().to_string();
+        }
+        self.path
+            .as_ref()
+            .unwrap_or(&PathBuf::new())
+            .join(program)
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
     /// Creates a command for `program`, resolving the absolute path if necessary.
     pub fn command(&self, program: &str) -> Command {
+        Command::new(self.program(program))
+    }
+
+    /// Verifies that each of `tools` exists and is runnable, i.e., that `<tool> --help` succeeds,
+    /// before any of them is used, reporting all missing or broken tools at once.
+    pub fn check_tools(&self, tools: &[&str]) -> Result<(), Error> {
+        let missing: Vec<_> = tools
+            .iter()
+            .filter(|tool| {
+                let mut command = self.command(tool);
+                command.arg("--help");
+                !matches!(self.backend.output(&mut command), Ok(output) if output.status.success())
+            })
+            .map(|tool| self.program(tool))
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::from(format!(
+                "Missing or broken tools: {}",
+                missing.join(", ")
+            )))
+        }
+    }
+
+    /// Version reported by `<tool> --version`, or `None` if the tool reports none,
+    /// e.g., if it does not support the option.
+    pub fn tool_version(&self, tool: &str) -> Option<PisaVersion> {
+        let mut command = self.command(tool);
+        command.arg("--version");
+        let output = self.backend.output(&mut command).ok()?;
+        if output.status.success() {
+            PisaVersion::find(&String::from_utf8_lossy(&output.stdout))
+        } else {
+            None
+        }
+    }
+
+    /// Checks that each of `programs`, which are not PISA tools, runs with `--version`,
+    /// and fails with an error of kind `MissingProgram` otherwise.
+    pub fn check_programs(&self, programs: &[&str]) -> Result<(), Error> {
+        for program in programs {
+            let mut command = Command::new(program);
+            command.arg("--version");
+            if !matches!(self.backend.output(&mut command), Ok(output) if output.status.success()) {
+                return Err(Error::from(ErrorKind::MissingProgram {
+                    program: (*program).to_string(),
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the backend executing the commands.
+    pub fn with_backend(self, backend: Arc<dyn ExecBackend>) -> Self {
+        Self { backend, ..self }
+    }
+
+    /// Sets the path to `trec_eval`.
+    pub fn with_trec_eval(self, trec_eval: PathBuf) -> Self {
+        Self {
+            trec_eval: Some(trec_eval),
+            ..self
+        }
+    }
+
+    /// Sets the implementation evaluating run results.
+    pub fn with_evaluator(self, evaluator: Evaluator) -> Self {
+        Self { evaluator, ..self }
+    }
+
+    /// Implementation evaluating run results.
+    pub fn evaluator(&self) -> Evaluator {
+        self.evaluator
+    }
+
+    /// Sets whether the output of commands run with [`status`](#method.status)
+    /// is shown (by default) or discarded.
+    pub fn with_tool_output(self, show_tool_output: bool) -> Self {
+        Self {
+            show_tool_output,
+            ..self
+        }
+    }
+
+    /// Sets the options of query processing commands, e.g., of a particular run.
+    pub fn with_query_options(self, query_options: QueryOptions) -> Self {
+        Self {
+            query_options,
+            ..self
+        }
+    }
+
+    /// Sets environment variables of executed commands, overriding those previously set
+    /// with the same names, e.g., global variables with those of a particular run.
+    pub fn with_env(mut self, env: &BTreeMap<String, String>) -> Self {
+        self.env
+            .extend(env.iter().map(|(key, value)| (key.clone(), value.clone())));
+        self
+    }
+
+    /// Sets the CPUs and NUMA node to which benchmark commands are bound.
+    pub fn with_pinning(self, pinning: Pinning) -> Self {
+        Self { pinning, ..self }
+    }
+
+    /// Sets the paths of individual tools used instead of those in the executor path,
+    /// e.g., a locally patched `create_wand_data`.
+    pub fn with_tool_overrides(self, tool_overrides: BTreeMap<String, PathBuf>) -> Self {
+        Self {
+            tool_overrides,
+            ..self
+        }
+    }
+
+    /// Creates a `queries` command, prefixed with the pinning wrapper if any.
+    fn pinned_queries(&self) -> Command {
+        let prefix = self.pinning.prefix();
+        if let Some((wrapper, args)) = prefix.split_first() {
+            let mut command = Command::new(wrapper);
+            command.args(args).arg(self.program("queries"));
+            command
+        } else {
+            self.command("queries")
+        }
+    }
+
+    /// Creates a `trec_eval` command.
+    pub fn trec_eval(&self) -> Command {
         Command::new(
-            self.path
-                .as_ref()
-                .unwrap_or(&PathBuf::new())
-                .join(program)
-                .to_str()
-                .unwrap()
-                .to_string(),
+            self.trec_eval
+                .as_deref()
+                .unwrap_or_else(|| Path::new("trec_eval")),
         )
     }
 
-    /// Runs `invert` command.
-    pub fn invert<P1, P2>(
+    /// Sets priorities of build and run processes.
+    pub fn with_priorities(self, priorities: Priorities) -> Self {
+        Self { priorities, ..self }
+    }
+
+    /// Sets the priority of `command`, to be executed in `stage`.
+    pub fn prioritize<'a>(&self, command: &'a mut Command, stage: Stage) -> &'a mut Command {
+        set_priority(command, self.priorities.for_stage(stage))
+    }
+
+    /// Sets time limits of tool processes, after which they are killed.
+    pub fn with_timeouts(self, timeouts: Timeouts) -> Self {
+        Self { timeouts, ..self }
+    }
+
+    /// Overrides the time limit of run commands in seconds, if set,
+    /// e.g., with the timeout of a particular run.
+    pub fn with_run_timeout(self, seconds: Option<u64>) -> Self {
+        Self {
+            timeouts: Timeouts {
+                run: seconds.or(self.timeouts.run),
+                ..self.timeouts
+            },
+            ..self
+        }
+    }
+
+    /// Sets time windows in which build and run processes may execute.
+    pub fn with_schedule(self, schedule: Schedule) -> Self {
+        Self { schedule, ..self }
+    }
+
+    /// Blocks until the time window of `stage` is open, if it is restricted.
+    ///
+    /// The clock is checked at least every minute, so that changes of the system
+    /// time are taken into account.
+    pub fn wait_for_window(&self, stage: Stage) {
+        if let Some(window) = self.schedule.for_stage(stage) {
+            let mut wait = window.wait_seconds(local_seconds());
+            if wait > 0 {
+                info!("Stage {} paused until window {} opens", stage, window);
+            }
+            while wait > 0 && !interrupt::interrupted() {
+                thread::sleep(Duration::from_secs(u64::from(wait.min(60))));
+                wait = window.wait_seconds(local_seconds());
+            }
+        }
+    }
+
+    /// Waits for the time window of the stage of the PISA tool `command` executes,
+    /// and sets its priority accordingly. Returns the stage.
+    fn prepare(&self, command: &mut Command) -> Stage {
+        let stage = Invocation::stage_of(command, &self.tool_overrides);
+        self.prepare_in(command, stage);
+        stage
+    }
+
+    /// Sets the environment of `command`, waits for the time window of `stage`,
+    /// and sets the priority of `command` accordingly.
+    fn prepare_in(&self, command: &mut Command, stage: Stage) {
+        command.envs(&self.env);
+        self.wait_for_window(stage);
+        self.prioritize(command, stage).log();
+    }
+
+    /// Accounts a command executed in `stage` since `start`.
+    fn account(&self, stage: Stage, start: Instant, usage: Option<ResourceUsage>) {
+        self.stage_times
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(stage, start.elapsed().as_secs_f64(), usage);
+    }
+
+    /// Time and resources used so far by commands executed to completion in each stage.
+    ///
+    /// Commands that are only started, such as those piping their output into
+    /// another command, are not accounted.
+    pub fn stage_times(&self) -> StageTimes {
+        self.stage_times
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Logs and starts `command` without waiting for it to finish.
+    pub fn spawn(&self, command: &mut Command) -> io::Result<()> {
+        self.prepare(command);
+        self.backend.spawn(command)
+    }
+
+    /// Logs and runs `command`, collecting its output.
+    ///
+    /// Fails with an error of kind `TimedOut` if the command is killed after exceeding
+    /// the timeout of its stage.
+    pub fn output(&self, command: &mut Command) -> io::Result<Output> {
+        let stage = Invocation::stage_of(command, &self.tool_overrides);
+        self.output_in(command, stage)
+    }
+
+    /// Logs and runs `command` like [`output`](#method.output), in `stage` regardless
+    /// of the program it executes, e.g., `trec_eval` evaluating a run.
+    pub fn output_in(&self, command: &mut Command, stage: Stage) -> io::Result<Output> {
+        self.prepare_in(command, stage);
+        let start = Instant::now();
+        let (output, usage) = self
+            .backend
+            .output_with_usage(command, self.timeouts.for_stage(stage))?;
+        self.account(stage, start, usage);
+        Ok(output)
+    }
+
+    /// Logs and runs `command`, returning its exit status.
+    ///
+    /// Its standard output and error are discarded unless the tool output is shown.
+    /// It is killed after the timeout of its stage, like with [`output`](#method.output).
+    pub fn status(&self, command: &mut Command) -> io::Result<ExitStatus> {
+        let stage = Invocation::stage_of(command, &self.tool_overrides);
+        self.status_in(command, stage)
+    }
+
+    /// Logs and runs `command` like [`status`](#method.status), in `stage` regardless
+    /// of the program it executes, e.g., a shell command of an external builder.
+    pub fn status_in(&self, command: &mut Command, stage: Stage) -> io::Result<ExitStatus> {
+        if !self.show_tool_output {
+            command.stdout(Stdio::null()).stderr(Stdio::null());
+        }
+        self.prepare_in(command, stage);
+        let start = Instant::now();
+        let (status, usage) = self
+            .backend
+            .status_with_usage(command, self.timeouts.for_stage(stage))?;
+        self.account(stage, start, usage);
+        Ok(status)
+    }
+
+    /// Creates `invert` command; `term_count` may be a placeholder when planning.
+    pub fn invert_command<P1, P2>(
         &self,
         fwd_index: P1,
         inv_index: P2,
-        term_count: usize,
+        term_count: &str,
         batch_size: usize,
-    ) -> Result<(), Error>
+        threads: Option<usize>,
+    ) -> Command
     where
         P1: AsRef<Path>,
         P2: AsRef<Path>,
@@ -63,51 +1125,142 @@ impl Executor {
             .arg(fwd_index.as_ref())
             .arg("-o")
             .arg(inv_index.as_ref())
-            .args(&["--term-count", &term_count.to_string()])
-            .args(&["--batch-size", &batch_size.to_string()])
-            .log()
-            .status()
-            .context("Failed to execute: invert")?
-            .success()
-            .ok_or("Failed to invert index")?;
+            .args(&["--term-count", term_count])
+            .args(&["--batch-size", &batch_size.to_string()]);
+        threads_arg(&mut invert, threads);
+        invert
+    }
+
+    /// Runs `invert` command.
+    pub fn invert<P1, P2>(
+        &self,
+        fwd_index: P1,
+        inv_index: P2,
+        term_count: usize,
+        batch_size: usize,
+        threads: Option<usize>,
+    ) -> Result<(), Error>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let mut invert = self.invert_command(
+            fwd_index,
+            inv_index,
+            &term_count.to_string(),
+            batch_size,
+            threads,
+        );
+        let status = self
+            .status(&mut invert)
+            .context("Failed to execute: invert")?;
+        check_status("invert", status, "Failed to invert index")?;
         Ok(())
     }
 
-    /// Runs `create_freq_index` command.
-    pub fn compress<P1, P2>(
+    /// Creates `create_freq_index` command, passing the parameters of `encoding`.
+    pub fn compress_command<P1, P2>(
         &self,
         inv_index: P1,
         enc_index: P2,
         encoding: &Encoding,
-    ) -> Result<(), Error>
+        threads: Option<usize>,
+    ) -> Command
     where
         P1: AsRef<Path>,
         P2: AsRef<Path>,
     {
-        let Encoding(encoding) = encoding;
         let mut compress = self.command("create_freq_index");
         compress
-            .args(&["-t", encoding])
+            .args(&["-t", &encoding.name])
             .arg("-c")
             .arg(inv_index.as_ref())
             .arg("-o")
             .arg(enc_index.as_ref())
             .arg("--check")
-            .log()
-            .status()
-            .context("Failed to execute: create_freq_index")?
-            .success()
-            .ok_or("Failed to compress index")?;
-        Ok(())
+            .args(&encoding.params);
+        threads_arg(&mut compress, threads);
+        compress
     }
 
     /// Runs `create_freq_index` command.
-    pub fn create_wand_data<P1, P2>(
+    pub fn compress<P1, P2>(
+        &self,
+        inv_index: P1,
+        enc_index: P2,
+        encoding: &Encoding,
+        threads: Option<usize>,
+    ) -> Result<(), Error>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let mut compress = self.compress_command(inv_index, enc_index, encoding, threads);
+        let status = self
+            .status(&mut compress)
+            .context("Failed to execute: create_freq_index")?;
+        check_status("create_freq_index", status, "Failed to compress index")?;
+        Ok(())
+    }
+
+    /// Creates `create_freq_index` command compressing the index with `encoding`
+    /// and the scores of `scorer`, quantized as in `wand_data`.
+    pub fn quantized_compress_command<P1, P2, P3>(
+        &self,
+        inv_index: P1,
+        enc_index: P2,
+        encoding: &Encoding,
+        wand_data: P3,
+        scorer: &Scorer,
+        threads: Option<usize>,
+    ) -> Command
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+        P3: AsRef<Path>,
+    {
+        let mut compress = self.compress_command(inv_index, enc_index, encoding, threads);
+        compress
+            .arg("-w")
+            .arg(wand_data.as_ref())
+            .args(&["--scorer", scorer.as_ref()])
+            .arg("--quantize");
+        compress
+    }
+
+    /// Runs `create_freq_index` command with quantized scores.
+    pub fn quantized_compress<P1, P2, P3>(
+        &self,
+        inv_index: P1,
+        enc_index: P2,
+        encoding: &Encoding,
+        wand_data: P3,
+        scorer: &Scorer,
+        threads: Option<usize>,
+    ) -> Result<(), Error>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+        P3: AsRef<Path>,
+    {
+        let mut compress = self
+            .quantized_compress_command(inv_index, enc_index, encoding, wand_data, scorer, threads);
+        let status = self
+            .status(&mut compress)
+            .context("Failed to execute: create_freq_index")?;
+        check_status("create_freq_index", status, "Failed to compress index")?;
+        Ok(())
+    }
+
+    /// Creates `create_wand_data` command, with scores quantized if `quantize` is `true`.
+    pub fn create_wand_data_command<P1, P2>(
         &self,
         inv_index: P1,
         wand_data: P2,
         scorer: Option<&Scorer>,
-    ) -> Result<(), Error>
+        quantize: bool,
+        threads: Option<usize>,
+    ) -> Command
     where
         P1: AsRef<Path>,
         P2: AsRef<Path>,
@@ -121,61 +1274,101 @@ impl Executor {
         if let Some(scorer) = scorer {
             command.args(&["--scorer", scorer.as_ref()]);
         }
+        if quantize {
+            command.arg("--quantize");
+        }
+        threads_arg(&mut command, threads);
         command
-            .log()
-            .status()
-            .context("Failed to execute create_wand_data")?
-            .success()
-            .ok_or("Failed to create WAND data")?;
+    }
+
+    /// Runs `create_wand_data` command.
+    pub fn create_wand_data<P1, P2>(
+        &self,
+        inv_index: P1,
+        wand_data: P2,
+        scorer: Option<&Scorer>,
+        quantize: bool,
+        threads: Option<usize>,
+    ) -> Result<(), Error>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let mut command =
+            self.create_wand_data_command(inv_index, wand_data, scorer, quantize, threads);
+        let status = self
+            .status(&mut command)
+            .context("Failed to execute create_wand_data")?;
+        check_status("create_wand_data", status, "Failed to create WAND data")?;
         Ok(())
     }
 
-    /// Runs `lexicon build` command.
-    pub fn build_lexicon<P1, P2>(&self, input: P1, output: P2) -> Result<(), Error>
+    /// Creates `lexicon build` command.
+    pub fn build_lexicon_command<P1, P2>(&self, input: P1, output: P2) -> Command
     where
         P1: AsRef<Path>,
         P2: AsRef<Path>,
     {
-        self.command("lexicon")
+        let mut lexicon = self.command("lexicon");
+        lexicon
             .arg("build")
             .arg(input.as_ref())
-            .arg(output.as_ref())
-            .log()
-            .status()
-            .context("Failed to execute lexicon build")?
-            .success()
-            .ok_or("Failed to build lexicon")?;
+            .arg(output.as_ref());
+        lexicon
+    }
+
+    /// Runs `lexicon build` command.
+    pub fn build_lexicon<P1, P2>(&self, input: P1, output: P2) -> Result<(), Error>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let mut lexicon = self.build_lexicon_command(input, output);
+        let status = self
+            .status(&mut lexicon)
+            .context("Failed to execute lexicon build")?;
+        check_status("lexicon", status, "Failed to build lexicon")?;
         Ok(())
     }
 
-    /// Runs `extract_topics` command.
-    pub fn extract_topics<P1, P2>(&self, input: P1, output: P2) -> Result<(), Error>
+    /// Creates `extract_topics` command.
+    pub fn extract_topics_command<P1, P2>(&self, input: P1, output: P2) -> Command
     where
         P1: AsRef<Path>,
         P2: AsRef<Path>,
     {
-        self.command("extract_topics")
+        let mut extract = self.command("extract_topics");
+        extract
             .arg("-i")
             .arg(input.as_ref())
             .arg("-o")
-            .arg(output.as_ref())
-            .log()
-            .status()
-            .context("Failed to execute extract_topics")?
-            .success()
-            .ok_or("Failed to extract topics")?;
+            .arg(output.as_ref());
+        extract
+    }
+
+    /// Runs `extract_topics` command.
+    pub fn extract_topics<P1, P2>(&self, input: P1, output: P2) -> Result<(), Error>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let mut extract = self.extract_topics_command(input, output);
+        let status = self
+            .status(&mut extract)
+            .context("Failed to execute extract_topics")?;
+        check_status("extract_topics", status, "Failed to extract topics")?;
         Ok(())
     }
 
-    /// Runs `evaluate_queries` command.
-    pub fn evaluate_queries<S>(
+    /// Creates `evaluate_queries` command.
+    pub fn evaluate_queries_command<S>(
         &self,
         collection: &Collection,
         encoding: &Encoding,
         algorithm: &Algorithm,
         queries: S,
         scorer: Option<&Scorer>,
-    ) -> Result<String, Error>
+    ) -> Command
     where
         S: AsRef<str>,
     {
@@ -183,24 +1376,49 @@ impl Executor {
         command
             .args(&["-t", encoding.as_ref()])
             .arg("-i")
-            .arg(collection.enc_index(encoding))
+            .arg(collection.scored_index(encoding, scorer))
             .arg("-w")
-            .arg(collection.wand())
+            .arg(collection.wand(scorer))
             .args(&["-a", algorithm.as_ref()])
             .args(&["-q", queries.as_ref()])
             .arg("--terms")
-            .arg(collection.term_lexicon())
+            .arg(collection.query_term_lexicon())
             .arg("--documents")
-            .arg(collection.document_lexicon())
-            .args(&["--stemmer", "porter2"])
-            .args(&["-k", "1000"]);
+            .arg(collection.query_document_lexicon());
+        if let Some(stemmer) = collection.stemmer() {
+            command.args(&["--stemmer", stemmer]);
+        }
+        command.args(&["-k", &RESULTS_K.to_string()]);
         if let Some(scorer) = scorer {
             command.args(&["--scorer", scorer.as_ref()]);
+            if collection.quantize {
+                command.arg("--quantized");
+            }
         }
-        let output = command
-            .log()
-            .output()
+        command.args(self.query_options.args());
+        command
+    }
+
+    /// Runs `evaluate_queries` command.
+    pub fn evaluate_queries<S>(
+        &self,
+        collection: &Collection,
+        encoding: &Encoding,
+        algorithm: &Algorithm,
+        queries: S,
+        scorer: Option<&Scorer>,
+    ) -> Result<String, Error>
+    where
+        S: AsRef<str>,
+    {
+        let mut command =
+            self.evaluate_queries_command(collection, encoding, algorithm, queries, scorer);
+        let output = self
+            .output(&mut command)
             .context("Failed to run evaluate_queries")?;
+        if let Some(err) = killed("evaluate_queries", output.status) {
+            return Err(err);
+        }
         if output.status.success() {
             Ok(String::from_utf8(output.stdout).unwrap())
         } else {
@@ -208,54 +1426,201 @@ impl Executor {
         }
     }
 
-    /// Runs `queries` command.
-    pub fn benchmark<S>(
+    fn queries_command<S>(
         &self,
+        mut command: Command,
         collection: &Collection,
         encoding: &Encoding,
         algorithm: &Algorithm,
         queries: S,
         scorer: Option<&Scorer>,
-    ) -> Result<String, Error>
+    ) -> Command
     where
         S: AsRef<str>,
     {
-        let mut command = self.command("queries");
         command
             .args(&["-t", encoding.as_ref()])
             .arg("-i")
-            .arg(collection.enc_index(encoding))
+            .arg(collection.scored_index(encoding, scorer))
             .arg("-w")
-            .arg(collection.wand())
+            .arg(collection.wand(scorer))
             .args(&["-a", &algorithm.to_string()])
             .args(&["-q", queries.as_ref()])
             .arg("--terms")
-            .arg(collection.term_lexicon())
-            .args(&["--stemmer", "porter2"])
-            .args(&["-k", "1000"]);
+            .arg(collection.query_term_lexicon());
+        if let Some(stemmer) = collection.stemmer() {
+            command.args(&["--stemmer", stemmer]);
+        }
+        command.args(&["-k", &RESULTS_K.to_string()]);
         if let Some(scorer) = scorer {
             command.args(&["--scorer", scorer.as_ref()]);
+            if collection.quantize {
+                command.arg("--quantized");
+            }
+        }
+        command.args(self.query_options.args());
+        command
+    }
+
+    /// Creates `queries` command, prefixed with `taskset` or `numactl`
+    /// if [pinned](#method.with_pinning).
+    pub fn benchmark_command<S>(
+        &self,
+        collection: &Collection,
+        encoding: &Encoding,
+        algorithm: &Algorithm,
+        queries: S,
+        scorer: Option<&Scorer>,
+    ) -> Command
+    where
+        S: AsRef<str>,
+    {
+        self.queries_command(
+            self.pinned_queries(),
+            collection,
+            encoding,
+            algorithm,
+            queries,
+            scorer,
+        )
+    }
+
+    /// Runs `queries` command.
+    pub fn benchmark<S>(
+        &self,
+        collection: &Collection,
+        encoding: &Encoding,
+        algorithm: &Algorithm,
+        queries: S,
+        scorer: Option<&Scorer>,
+    ) -> Result<String, Error>
+    where
+        S: AsRef<str>,
+    {
+        self.queries_output(
+            self.benchmark_command(collection, encoding, algorithm, queries, scorer),
+        )
+    }
+
+    /// Creates `queries` command with `--extract`, which reports the time of each query
+    /// instead of aggregate statistics.
+    pub fn query_times_command<S>(
+        &self,
+        collection: &Collection,
+        encoding: &Encoding,
+        algorithm: &Algorithm,
+        queries: S,
+        scorer: Option<&Scorer>,
+    ) -> Command
+    where
+        S: AsRef<str>,
+    {
+        let mut command = self.benchmark_command(collection, encoding, algorithm, queries, scorer);
+        command.arg("--extract");
+        command
+    }
+
+    /// Runs `queries` command with `--extract`, which reports the time of each query
+    /// instead of aggregate statistics.
+    pub fn query_times<S>(
+        &self,
+        collection: &Collection,
+        encoding: &Encoding,
+        algorithm: &Algorithm,
+        queries: S,
+        scorer: Option<&Scorer>,
+    ) -> Result<String, Error>
+    where
+        S: AsRef<str>,
+    {
+        self.queries_output(
+            self.query_times_command(collection, encoding, algorithm, queries, scorer),
+        )
+    }
+
+    fn queries_output(&self, mut command: Command) -> Result<String, Error> {
+        let output = self.output(&mut command).context("Failed to run queries")?;
+        if let Some(err) = killed("queries", output.status) {
+            return Err(err);
         }
-        let output = command.log().output().context("Failed to run queries")?;
         if output.status.success() {
             Ok(String::from_utf8(output.stdout).unwrap())
         } else {
             Err(Error::from(String::from_utf8(output.stderr).unwrap()))
         }
     }
+
+    /// Creates `queries` command executed through `perf stat`.
+    pub fn benchmark_with_counters_command<S>(
+        &self,
+        collection: &Collection,
+        encoding: &Encoding,
+        algorithm: &Algorithm,
+        queries: S,
+        scorer: Option<&Scorer>,
+    ) -> Command
+    where
+        S: AsRef<str>,
+    {
+        let mut perf = Command::new("perf");
+        perf.args(&["stat", "-j", "-e", PERF_EVENTS, "--"])
+            .args(self.pinning.prefix())
+            .arg(self.program("queries"));
+        self.queries_command(perf, collection, encoding, algorithm, queries, scorer)
+    }
+
+    /// Runs `queries` command through `perf stat`, collecting hardware counters.
+    ///
+    /// Returns the standard output of `queries` and the standard error,
+    /// which contains the JSON-formatted counters.
+    pub fn benchmark_with_counters<S>(
+        &self,
+        collection: &Collection,
+        encoding: &Encoding,
+        algorithm: &Algorithm,
+        queries: S,
+        scorer: Option<&Scorer>,
+    ) -> Result<(String, String), Error>
+    where
+        S: AsRef<str>,
+    {
+        let mut command =
+            self.benchmark_with_counters_command(collection, encoding, algorithm, queries, scorer);
+        let output = self
+            .output(&mut command)
+            .context("Failed to run perf stat")?;
+        if let Some(err) = killed("queries", output.status) {
+            return Err(err);
+        }
+        if output.status.success() {
+            Ok((
+                String::from_utf8(output.stdout).unwrap(),
+                String::from_utf8_lossy(&output.stderr).to_string(),
+            ))
+        } else {
+            Err(Error::from(String::from_utf8(output.stderr).unwrap()))
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use super::killed;
+    use crate::config::{
+        required_programs, required_tools, CollectionKind, IoPriority, Pinning, Priorities,
+        Priority, Schedule, TimeWindow, Timeouts, TrecEval, TOOL_TARGETS,
+    };
     use crate::run::process_run;
     use crate::tests::{mock_set_up, MockSetup};
-    use crate::{Config, Error, Executor, Stage};
+    use crate::{CommandDebug, Config, Error, ErrorKind, Executor, FakeBackend, Invocation, Stage};
     use crate::{Encoding, RawConfig, ResolvedPathsConfig, Scorer, Source};
+    use std::collections::BTreeMap;
     use std::fs::create_dir_all;
     use std::fs::Permissions;
     use std::os::unix::fs::PermissionsExt;
     use std::path::{Path, PathBuf};
     use std::process::Command;
+    use std::sync::Arc;
     use tempdir::TempDir;
 
     fn test_exec<F>(prog: &str, err: &'static str, exec: F)
@@ -283,7 +1648,362 @@ mod test {
 
     #[test]
     fn test_new_executor() {
-        assert_eq!(Executor::new(), Executor { path: None });
+        assert_eq!(Executor::new(), Executor::default());
+        assert_eq!(Executor::new().path, None);
+    }
+
+    #[test]
+    fn test_priorities() {
+        let executor = Executor::new().with_priorities(Priorities {
+            build: Priority {
+                nice: Some(19),
+                ionice: Some(IoPriority::Idle),
+            },
+            run: Priority::default(),
+        });
+        let output = |stage: Stage| {
+            let mut command = Command::new("sh");
+            command.args(&["-c", "nice; ionice"]);
+            let output = executor.prioritize(&mut command, stage).output().unwrap();
+            String::from_utf8(output.stdout).unwrap()
+        };
+        assert_eq!(output(Stage::Compress), "19\nidle\n");
+        assert!(output(Stage::Run).starts_with("0\n"));
+    }
+
+    #[test]
+    fn test_wait_for_window() {
+        let now = super::local_seconds() / 60;
+        let open = TimeWindow {
+            start: now,
+            end: (now + 2) % (24 * 60),
+        };
+        let executor = Executor::new().with_schedule(Schedule {
+            build: None,
+            run: Some(open),
+        });
+        let start = std::time::Instant::now();
+        executor.wait_for_window(Stage::Run);
+        executor.wait_for_window(Stage::Compress);
+        assert!(start.elapsed().as_secs() < 1);
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let executor = Executor::new().with_timeouts(Timeouts {
+            default: Some(1),
+            ..Timeouts::default()
+        });
+        let start = std::time::Instant::now();
+        // The background `sleep` keeps the output open unless the whole group is killed.
+        let err = executor
+            .output(Command::new("sh").args(&["-c", "sleep 30 & sleep 30"]))
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert_eq!(
+            Error::from(err).kind(),
+            &ErrorKind::Timeout {
+                program: String::from("sh"),
+                seconds: 1
+            }
+        );
+        let err = executor
+            .status(Command::new("sleep").arg("30"))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "sleep timed out after 1 s");
+        assert!(start.elapsed().as_secs() < 10);
+        let output = executor
+            .output(Command::new("sh").args(&["-c", "echo out"]))
+            .unwrap();
+        assert_eq!(output.stdout, b"out\n");
+
+        let backend = Arc::new(FakeBackend::new().with_hang("queries").with_hang("invert"));
+        let executor = Executor::new()
+            .with_backend(backend.clone())
+            .with_timeouts(Timeouts {
+                run: Some(60),
+                ..Timeouts::default()
+            })
+            .with_run_timeout(Some(5));
+        let err = executor
+            .output(&mut executor.command("queries"))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "queries timed out after 5 s");
+        assert!(executor.output(&mut executor.command("invert")).is_ok());
+        assert!(Executor::new()
+            .with_backend(backend.clone())
+            .output(&mut Command::new("queries"))
+            .is_ok());
+        assert_eq!(backend.programs(), vec!["queries", "invert", "queries"]);
+    }
+
+    #[test]
+    fn test_invocation_stage() {
+        let stage = |program: &str, args: &[&str]| {
+            let mut command = Command::new(program);
+            command.args(args);
+            Invocation::from(&command).stage()
+        };
+        assert_eq!(stage("cmake", &[]), Stage::Compile);
+        assert_eq!(stage("curl", &["-o", "f"]), Stage::Fetch);
+        assert_eq!(stage("parse_collection", &[]), Stage::Parse);
+        assert_eq!(stage("parse_collection", &["merge"]), Stage::Join);
+        assert_eq!(stage("lexicon", &["build"]), Stage::Parse);
+        assert_eq!(stage("jq", &[]), Stage::Parse);
+        assert_eq!(stage("zcat", &[]), Stage::Parse);
+        assert_eq!(stage("gzip", &[]), Stage::Parse);
+        assert_eq!(stage("sh", &["-c", "build"]), Stage::External);
+        assert_eq!(stage("trec_eval", &["-q"]), Stage::Run);
+        assert_eq!(stage("numactl", &["queries"]), Stage::Run);
+        assert_eq!(stage("unknown", &[]), Stage::BuildIndex);
+    }
+
+    #[test]
+    fn test_stage_times() {
+        let executor = Executor::new();
+        let output = executor
+            .output(Command::new("sh").args(&["-c", "echo out; echo err >&2"]))
+            .unwrap();
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+        let status = executor
+            .clone()
+            .status(Command::new("sh").args(&["-c", "exit 3"]))
+            .unwrap();
+        assert_eq!(status.code(), Some(3));
+        let times = executor.stage_times();
+        let external = &times.0[&Stage::External];
+        assert_eq!(external.commands, 2);
+        assert!(external.max_rss_kb.unwrap() > 0);
+
+        let backend = Arc::new(FakeBackend::new());
+        let executor = Executor::new().with_backend(backend);
+        executor.invert("fwd", "inv", 10, 100, None).unwrap();
+        executor.spawn(&mut Command::new("cat")).unwrap();
+        let times = executor.stage_times();
+        assert_eq!(times.0.len(), 1);
+        assert_eq!(times.0[&Stage::Invert].commands, 1);
+        assert_eq!(times.0[&Stage::Invert].max_rss_kb, None);
+    }
+
+    #[test]
+    fn test_env() -> Result<(), Error> {
+        let tmp = TempDir::new("executor").unwrap();
+        let MockSetup { mut config, .. } = mock_set_up(&tmp);
+        let global: BTreeMap<_, _> = vec![
+            (String::from("OMP_NUM_THREADS"), String::from("1")),
+            (String::from("TBB_NUM_THREADS"), String::from("1")),
+        ]
+        .into_iter()
+        .collect();
+        config.0.runs[2]
+            .env
+            .insert(String::from("OMP_NUM_THREADS"), String::from("4"));
+        let backend = Arc::new(FakeBackend::new());
+        let executor = Executor::new()
+            .with_backend(backend.clone())
+            .with_env(&global);
+        executor.invert("fwd", "inv", 10, 100, None)?;
+        process_run(
+            &executor,
+            &config.run(2),
+            &config.collection(0),
+            true,
+            tmp.path(),
+        )?;
+        let invocations = backend.invocations();
+        assert_eq!(invocations[0].env, global);
+        let queries = invocations
+            .iter()
+            .find(|invocation| invocation.program == "queries")
+            .unwrap();
+        assert_eq!(queries.env["OMP_NUM_THREADS"], "4");
+        assert_eq!(queries.env["TBB_NUM_THREADS"], "1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_pinning() -> Result<(), Error> {
+        let tmp = TempDir::new("executor").unwrap();
+        let MockSetup { config, .. } = mock_set_up(&tmp);
+        let collection = config.collection(0);
+        let backend = Arc::new(FakeBackend::new());
+        let executor = Executor::unchecked(PathBuf::from("bin"))
+            .with_backend(backend.clone())
+            .with_pinning(Pinning {
+                cpus: Some(String::from("0-3")),
+                numa_node: None,
+            });
+        let encoding = Encoding::from("block_simdbp");
+        executor.benchmark(collection, &encoding, &"wand".into(), "topics", None)?;
+        executor.evaluate_queries(collection, &encoding, &"wand".into(), "topics", None)?;
+        let invocations = backend.invocations();
+        assert_eq!(invocations[0].program, "taskset");
+        assert_eq!(
+            &invocations[0].args[..4],
+            &["-c", "0-3", "bin/queries", "-t"]
+        );
+        assert_eq!(invocations[0].stage(), Stage::Run);
+        assert_eq!(invocations[1].program, "evaluate_queries");
+        let command = executor
+            .with_pinning(Pinning {
+                cpus: None,
+                numa_node: Some(1),
+            })
+            .benchmark_with_counters_command(collection, &encoding, &"wand".into(), "q", None);
+        let args: Vec<_> = command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect();
+        assert_eq!(
+            &args[5..9],
+            &["numactl", "--cpunodebind=1", "--membind=1", "bin/queries"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tool_overrides() -> Result<(), Error> {
+        let backend = Arc::new(FakeBackend::new());
+        let mut tool_overrides = BTreeMap::new();
+        tool_overrides.insert(
+            String::from("create_wand_data"),
+            PathBuf::from("patched/create_wand_data"),
+        );
+        let executor = Executor::unchecked(PathBuf::from("bin"))
+            .with_backend(backend.clone())
+            .with_tool_overrides(tool_overrides);
+        assert_eq!(
+            executor.command("create_wand_data").get_program(),
+            "patched/create_wand_data"
+        );
+        assert_eq!(executor.command("invert").get_program(), "bin/invert");
+        executor.create_wand_data("inv", "inv.wand", None, false, None)?;
+        let invocations = backend.invocations();
+        assert_eq!(invocations[0].program, "create_wand_data");
+        assert_eq!(invocations[0].stage(), Stage::Wand);
+
+        let mut tool_overrides = BTreeMap::new();
+        tool_overrides.insert(String::from("invert"), PathBuf::from("patched/my_invert"));
+        let executor = Executor::unchecked(PathBuf::from("bin"))
+            .with_backend(backend.clone())
+            .with_tool_overrides(tool_overrides.clone());
+        assert_eq!(
+            Invocation::stage_of(&executor.command("invert"), &tool_overrides),
+            Stage::Invert
+        );
+        executor.invert("fwd", "inv", 10, 100, None)?;
+        assert_eq!(backend.programs().last().unwrap(), "my_invert");
+        assert!(executor.stage_times().0.contains_key(&Stage::Invert));
+        assert!(!executor.stage_times().0.contains_key(&Stage::Parse));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fake_backend() {
+        let tmp = TempDir::new("executor").unwrap();
+        let MockSetup { config, .. } = mock_set_up(&tmp);
+        let backend = Arc::new(
+            FakeBackend::new()
+                .with_output("evaluate_queries", "1 Q0 d1 0 2.5 R\n")
+                .with_failure("queries", 1, "unknown algorithm"),
+        );
+        let executor = Executor::from(tmp.path().to_path_buf())
+            .unwrap()
+            .with_backend(backend.clone());
+        let collection = config.collection(0);
+        let encoding = Encoding::from("block_simdbp");
+        executor
+            .compress(&collection.inv_index, "enc", &encoding, None)
+            .unwrap();
+        assert_eq!(
+            executor
+                .evaluate_queries(collection, &encoding, &"wand".into(), "topics", None)
+                .unwrap(),
+            "1 Q0 d1 0 2.5 R\n"
+        );
+        assert_eq!(
+            executor.benchmark(collection, &encoding, &"wand".into(), "topics", None),
+            Err(Error::from("unknown algorithm"))
+        );
+        assert_eq!(
+            backend.programs(),
+            vec!["create_freq_index", "evaluate_queries", "queries"]
+        );
+        assert_eq!(
+            backend.invocations()[0],
+            Invocation {
+                program: String::from("create_freq_index"),
+                args: vec![
+                    "-t",
+                    "block_simdbp",
+                    "-c",
+                    collection.inv_index.to_str().unwrap(),
+                    "-o",
+                    "enc",
+                    "--check"
+                ]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+                env: BTreeMap::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_check_tools() {
+        let tmp = TempDir::new("executor").unwrap();
+        let MockSetup { mut config, .. } = mock_set_up(&tmp);
+        assert_eq!(required_tools(&config, None), TOOL_TARGETS);
+        assert!(required_tools(&config, Some("other")).is_empty());
+        config.0.stages.insert(Stage::BuildIndex, false);
+        assert_eq!(
+            required_tools(&config, None),
+            vec!["queries", "evaluate_queries", "extract_topics"]
+        );
+
+        let backend = Arc::new(
+            FakeBackend::new()
+                .with_failure("invert", 127, "")
+                .with_failure("lexicon", 1, "unknown option"),
+        );
+        let executor = Executor::unchecked(PathBuf::from("bin")).with_backend(backend.clone());
+        assert!(executor.check_tools(&["queries"]).is_ok());
+        assert_eq!(
+            executor.check_tools(TOOL_TARGETS),
+            Err(Error::from(
+                "Missing or broken tools: bin/invert, bin/lexicon"
+            ))
+        );
+        assert!(backend
+            .invocations()
+            .iter()
+            .all(|invocation| invocation.args == ["--help"]));
+        assert_eq!(backend.programs().len(), TOOL_TARGETS.len() + 1);
+    }
+
+    #[test]
+    fn test_check_programs() {
+        let tmp = TempDir::new("executor").unwrap();
+        let MockSetup { mut config, .. } = mock_set_up(&tmp);
+        assert!(required_programs(&config).is_empty());
+        config.0.collections[0].kind = CollectionKind::Jsonl {
+            id_field: String::from("id"),
+            content_field: String::from("contents"),
+        };
+        assert_eq!(required_programs(&config), vec!["jq"]);
+        config.0.stages.insert(Stage::Parse, false);
+        assert!(required_programs(&config).is_empty());
+
+        let executor = Executor::unchecked(PathBuf::from("bin"))
+            .with_backend(Arc::new(FakeBackend::new().with_failure("jq", 127, "")));
+        let err = executor.check_programs(&["jq"]).unwrap_err();
+        assert_eq!(err.to_string(), "Missing or broken program: jq");
+        assert!(err.hint().unwrap().contains("jq"));
+        let executor =
+            Executor::unchecked(PathBuf::from("bin")).with_backend(Arc::new(FakeBackend::new()));
+        assert!(executor.check_programs(&["jq"]).is_ok());
     }
 
     #[test]
@@ -295,6 +2015,7 @@ mod test {
                 &setup.config.collection(0).inv_index,
                 setup.term_count,
                 1000,
+                None,
             )
         });
     }
@@ -313,11 +2034,75 @@ mod test {
                         .collection(0)
                         .enc_index(&Encoding::from("block_simdbp")),
                     &Encoding::from("block_simdbp"),
+                    None,
                 )
             },
         );
     }
 
+    #[test]
+    fn test_compress_params() {
+        let encoding = Encoding {
+            name: String::from("block_simdbp"),
+            params: vec![String::from("--block-size"), String::from("256")],
+        };
+        let command = Executor::new().compress_command("inv", "inv.block_simdbp", &encoding, None);
+        assert_eq!(
+            command.to_string(),
+            "create_freq_index -t block_simdbp -c inv -o inv.block_simdbp --check --block-size 256"
+        );
+        let command = Executor::new().compress_command("inv", "inv.ef", &"ef".into(), Some(4));
+        assert_eq!(
+            command.to_string(),
+            "create_freq_index -t ef -c inv -o inv.ef --check --threads 4"
+        );
+        let bm25 = Scorer::from("bm25");
+        let executor = Executor::new();
+        let command = executor.quantized_compress_command(
+            "inv",
+            "inv.block_simdbp.bm25",
+            &encoding,
+            "inv.wand.bm25",
+            &bm25,
+            None,
+        );
+        assert_eq!(
+            command.to_string(),
+            "create_freq_index -t block_simdbp -c inv -o inv.block_simdbp.bm25 --check \
+             --block-size 256 -w inv.wand.bm25 --scorer bm25 --quantize"
+        );
+        let command =
+            executor.create_wand_data_command("inv", "inv.wand.bm25", Some(&bm25), true, Some(2));
+        assert_eq!(
+            command.to_string(),
+            "create_wand_data -c inv -o inv.wand.bm25 --scorer bm25 --quantize --threads 2"
+        );
+    }
+
+    #[test]
+    fn test_quantized_queries() {
+        let tmp = TempDir::new("executor").unwrap();
+        let MockSetup { config, .. } = mock_set_up(&tmp);
+        let mut collection = config.collection(0).clone();
+        collection.quantize = true;
+        let command = Executor::new().benchmark_command(
+            &collection,
+            &Encoding::from("block_simdbp"),
+            &"wand".into(),
+            "topics",
+            Some(&Scorer::from("bm25")),
+        );
+        assert_eq!(
+            command.to_string(),
+            format!(
+                "queries -t block_simdbp -i {0}.block_simdbp.bm25 -w {0}.wand.bm25 -a wand \
+                 -q topics --terms {1}.termlex --stemmer porter2 -k 1000 --scorer bm25 --quantized",
+                tmp.path().join("inv").display(),
+                tmp.path().join("fwd").display(),
+            )
+        );
+    }
+
     #[test]
     #[cfg_attr(target_family, unix)]
     fn test_create_wand_data() {
@@ -327,8 +2112,10 @@ mod test {
             |setup: &MockSetup| {
                 setup.executor.create_wand_data(
                     &setup.config.collection(0).inv_index,
-                    &setup.config.collection(0).wand(),
+                    &setup.config.collection(0).wand(None),
                     Some(&Scorer::from("bm25")),
+                    false,
+                    None,
                 )
             },
         );
@@ -356,6 +2143,44 @@ mod test {
         assert_eq!(std::str::from_utf8(&output.stdout).unwrap(), "ok\n");
     }
 
+    #[test]
+    fn test_trec_eval_location() {
+        let tmp = TempDir::new("tmp").unwrap();
+        let executor = |trec_eval: TrecEval| {
+            RawConfig {
+                workdir: PathBuf::from("workdir"),
+                source: Source::Path(tmp.path().to_path_buf()),
+                trec_eval,
+                ..RawConfig::default()
+            }
+            .executor()
+            .unwrap()
+        };
+        assert_eq!(
+            executor(TrecEval::System).trec_eval().get_program(),
+            "trec_eval"
+        );
+        assert_eq!(
+            executor(TrecEval::Executor).trec_eval().get_program(),
+            tmp.path().join("trec_eval")
+        );
+        assert_eq!(
+            executor(TrecEval::Path(PathBuf::from("bin/trec_eval")))
+                .trec_eval()
+                .get_program(),
+            "workdir/bin/trec_eval"
+        );
+        let trec_eval: TrecEval = serde_yaml::from_str("path: /opt/trec_eval").unwrap();
+        assert_eq!(trec_eval, TrecEval::Path(PathBuf::from("/opt/trec_eval")));
+        let trec_eval: TrecEval = serde_yaml::from_str("git: {}").unwrap();
+        assert_eq!(
+            trec_eval,
+            TrecEval::Git {
+                url: String::from("https://github.com/usnistgov/trec_eval.git")
+            }
+        );
+    }
+
     #[test]
     fn test_git_executor_wrong_bin() {
         assert_eq!(
@@ -383,6 +2208,11 @@ mod test {
                 cmake_vars: vec![],
                 local_path: "pisa".into(),
                 compile_threads: 1,
+                use_ccache: false,
+                compiler_launcher: None,
+                generator: None,
+                build_tool_args: vec![],
+                targets: vec![],
             },
             ..RawConfig::default()
         })
@@ -455,6 +2285,11 @@ mod test {
                     cmake_vars: vec![],
                     local_path: "pisa".into(),
                     compile_threads: 1,
+                    use_ccache: false,
+                    compiler_launcher: None,
+                    generator: None,
+                    build_tool_args: vec![],
+                    targets: vec![],
                 },
                 ..RawConfig::default()
             })
@@ -463,9 +2298,7 @@ mod test {
         let conf = make_conf("master");
         assert_eq!(
             conf.executor(),
-            Ok(Executor {
-                path: Some(workdir.join("pisa").join("build").join("bin"))
-            })
+            Executor::from(workdir.join("pisa").join("build").join("bin"))
         );
         assert!(workdir.join("pisa").join("README").exists());
 
@@ -473,9 +2306,7 @@ mod test {
         std::fs::remove_file(workdir.join("pisa").join("CMakeLists.txt")).unwrap();
         assert_eq!(
             conf.executor(),
-            Ok(Executor {
-                path: Some(workdir.join("pisa").join("build").join("bin"))
-            })
+            Executor::from(workdir.join("pisa").join("build").join("bin"))
         );
 
         // Reset changes and checkout a commit
@@ -483,32 +2314,35 @@ mod test {
         let conf = make_conf(&commit);
         assert_eq!(
             conf.executor(),
-            Ok(Executor {
-                path: Some(workdir.join("pisa").join("build").join("bin"))
-            })
+            Executor::from(workdir.join("pisa").join("build").join("bin"))
         );
         assert!(!workdir.join("pisa").join("README").exists());
         assert!(workdir.join("pisa").join("CMakeLists.txt").exists());
+        assert_eq!(
+            std::fs::read_link(workdir.join("pisa").join("build")).unwrap(),
+            std::fs::canonicalize(workdir.join("builds").join(&commit)).unwrap()
+        );
 
         // Reset changes and checkout a commit
         std::fs::remove_file(workdir.join("pisa").join("CMakeLists.txt")).unwrap();
         let conf = make_conf("Tag");
         assert_eq!(
             conf.executor(),
-            Ok(Executor {
-                path: Some(workdir.join("pisa").join("build").join("bin"))
-            })
+            Executor::from(workdir.join("pisa").join("build").join("bin"))
         );
         assert!(!workdir.join("pisa").join("README").exists());
         assert!(workdir.join("pisa").join("CMakeLists.txt").exists());
+        // The build of the tagged commit is reused.
+        assert_eq!(
+            std::fs::read_dir(workdir.join("builds")).unwrap().count(),
+            2
+        );
 
         add_branch_to_origin(&origin_dir, "new_branch");
         let conf = make_conf("new_branch");
         assert_eq!(
             conf.executor(),
-            Ok(Executor {
-                path: Some(workdir.join("pisa").join("build").join("bin"))
-            })
+            Executor::from(workdir.join("pisa").join("build").join("bin"))
         );
     }
 
@@ -523,6 +2357,11 @@ mod test {
                 cmake_vars: vec![],
                 local_path: "pisa".into(),
                 compile_threads: 1,
+                use_ccache: false,
+                compiler_launcher: None,
+                generator: None,
+                build_tool_args: vec![],
+                targets: vec![],
             },
             ..RawConfig::default()
         })
@@ -554,7 +2393,7 @@ mod test {
         } = mock_set_up(&tmp);
         let run = &config.run(0);
         let collection = &config.collection(0);
-        process_run(&executor, run, collection, true).unwrap();
+        process_run(&executor, run, collection, true, tmp.path()).unwrap();
         let topics_path = if let crate::config::Topics::Trec {
             path: topics_path, ..
         } = &run.topics[0]
@@ -610,7 +2449,46 @@ mod test {
             "#!/bin/bash\nexit 1",
         )
         .unwrap();
-        assert!(process_run(&executor, run, collection, true).is_err());
+        assert!(process_run(&executor, run, collection, true, tmp.path()).is_err());
+    }
+
+    #[test]
+    fn test_killed_by_signal() {
+        let tmp = TempDir::new("executor").unwrap();
+        let MockSetup {
+            executor, programs, ..
+        } = mock_set_up(&tmp);
+        std::fs::write(programs.get("invert").unwrap(), "#!/bin/bash\nkill -9 $$").unwrap();
+        let err = executor
+            .invert(tmp.path().join("fwd"), tmp.path().join("inv"), 3, 10, None)
+            .err()
+            .unwrap();
+        match err.kind() {
+            ErrorKind::Killed {
+                program,
+                signal,
+                max_rss_kb,
+            } => {
+                assert_eq!(program, "invert");
+                assert_eq!(*signal, 9);
+                assert!(max_rss_kb.unwrap() > 0);
+            }
+            _ => panic!("Unexpected error kind: {:?}", err.kind()),
+        }
+        assert!(err.hint().unwrap().contains("batch_sizes"));
+
+        // Usage is only known for processes reaped by the executor, not any earlier child.
+        let status = Command::new("sh")
+            .args(&["-c", "kill -9 $$"])
+            .status()
+            .unwrap();
+        assert!(matches!(
+            killed("sh", status).unwrap().kind(),
+            ErrorKind::Killed {
+                max_rss_kb: None,
+                ..
+            }
+        ));
     }
 
     #[test]
@@ -625,6 +2503,12 @@ mod test {
         let run = &config.run(2);
         let collection = &config.collection(0);
         std::fs::write(programs.get("queries").unwrap(), "#!/bin/bash\nexit 1").unwrap();
-        assert!(process_run(&executor, run, collection, true).is_err());
+        let err = process_run(&executor, run, collection, true, tmp.path())
+            .err()
+            .unwrap();
+        match err.kind() {
+            ErrorKind::Run { algorithm, .. } => assert_eq!(algorithm, &run.algorithms[0]),
+            _ => panic!("Unexpected error kind: {:?}", err.kind()),
+        }
     }
 }
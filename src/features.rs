@@ -0,0 +1,178 @@
+//! Query features used for building query performance prediction models.
+
+use crate::config::Collection;
+use crate::error::Error;
+use failure::ResultExt;
+use rust_stemmers::{Algorithm, Stemmer};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Features of a single query.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueryFeatures {
+    /// Query ID.
+    pub id: String,
+    /// Number of query terms.
+    pub term_count: usize,
+    /// Number of query terms found in the lexicon.
+    pub known_terms: usize,
+    /// Sum of posting list lengths of all known terms.
+    pub sum_postings: u64,
+    /// Length of the longest posting list of all known terms.
+    pub max_postings: u64,
+}
+
+/// Columns of the CSV produced by [`QueryFeatures::csv_row`](struct.QueryFeatures.html).
+pub const CSV_HEADER: &str = "qid,term_count,known_terms,sum_postings,max_postings,latency";
+
+impl QueryFeatures {
+    /// Formats the features together with the measured latency as a CSV row.
+    pub fn csv_row(&self, latency: f32) -> String {
+        format!(
+            "{},{},{},{},{},{}",
+            self.id,
+            self.term_count,
+            self.known_terms,
+            self.sum_postings,
+            self.max_postings,
+            latency
+        )
+    }
+}
+
+/// Reads posting list lengths from a binary collection file (e.g., `inv.docs`).
+///
+/// Each sequence in the file is a 32-bit length followed by that many 32-bit values.
+/// The first sequence in a document file holds the number of documents, and is skipped.
+pub fn posting_lengths<P: AsRef<Path>>(docs: P) -> Result<Vec<u64>, Error> {
+    let mut reader = BufReader::new(
+        File::open(docs.as_ref()).with_context(|_| docs.as_ref().display().to_string())?,
+    );
+    let mut lengths = Vec::new();
+    let mut buf = [0_u8; 4];
+    loop {
+        match reader.read_exact(&mut buf) {
+            Ok(()) => {}
+            Err(ref err) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.into()),
+        }
+        let len = u32::from_le_bytes(buf);
+        lengths.push(u64::from(len));
+        reader.seek(SeekFrom::Current(4 * i64::from(len)))?;
+    }
+    if lengths.is_empty() {
+        return Err(Error::from(format!(
+            "Empty collection file: {}",
+            docs.as_ref().display()
+        )));
+    }
+    lengths.remove(0);
+    Ok(lengths)
+}
+
+/// Computes query features using the lexicon and posting lengths of a collection.
+pub struct FeatureExtractor {
+    terms: HashMap<String, usize>,
+    lengths: Vec<u64>,
+    stemmer: Stemmer,
+}
+
+impl FeatureExtractor {
+    /// Constructs an extractor from a terms lexicon (one term per line)
+    /// and a list of posting list lengths indexed by term ID.
+    pub fn new<R: BufRead>(terms: R, lengths: Vec<u64>) -> Result<Self, Error> {
+        let terms: Result<HashMap<_, _>, std::io::Error> = terms
+            .lines()
+            .enumerate()
+            .map(|(id, term)| term.map(|t| (t, id)))
+            .collect();
+        Ok(Self {
+            terms: terms?,
+            lengths,
+            stemmer: Stemmer::create(Algorithm::English),
+        })
+    }
+
+    /// Loads the lexicon and posting lengths of an already built collection.
+    pub fn from_collection(collection: &Collection) -> Result<Self, Error> {
+        let terms = File::open(collection.terms()).context("Failed to open terms")?;
+        let mut docs = collection.inv_index.as_os_str().to_os_string();
+        docs.push(".docs");
+        Self::new(BufReader::new(terms), posting_lengths(docs)?)
+    }
+
+    /// Computes features of a query in the format `qid:query` (the ID is optional).
+    pub fn features(&self, line: &str) -> QueryFeatures {
+        let (id, query) = line
+            .find(':')
+            .map_or(("", line), |pos| (&line[..pos], &line[pos + 1..]));
+        let mut features = QueryFeatures {
+            id: id.to_string(),
+            ..QueryFeatures::default()
+        };
+        for term in query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|t| !t.is_empty())
+        {
+            features.term_count += 1;
+            let term = self.stemmer.stem(&term.to_lowercase()).to_string();
+            if let Some(&id) = self.terms.get(&term) {
+                let len = self.lengths.get(id).copied().unwrap_or(0);
+                features.known_terms += 1;
+                features.sum_postings += len;
+                features.max_postings = features.max_postings.max(len);
+            }
+        }
+        features
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn write_collection(path: &Path, sequences: &[&[u32]]) {
+        let bytes: Vec<u8> = sequences
+            .iter()
+            .flat_map(|seq| {
+                std::iter::once(seq.len() as u32)
+                    .chain(seq.iter().cloned())
+                    .flat_map(|v| v.to_le_bytes().to_vec())
+            })
+            .collect();
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_posting_lengths() {
+        let tmp = TempDir::new("features").unwrap();
+        let docs = tmp.path().join("inv.docs");
+        write_collection(&docs, &[&[10], &[0, 1, 2], &[5], &[1, 9]]);
+        assert_eq!(posting_lengths(&docs), Ok(vec![3, 1, 2]));
+        std::fs::write(&docs, "").unwrap();
+        assert!(posting_lengths(&docs).is_err());
+    }
+
+    #[test]
+    fn test_features() -> Result<(), Error> {
+        let extractor = FeatureExtractor::new("appl\nbanana\nrun\n".as_bytes(), vec![3, 10, 7])?;
+        assert_eq!(
+            extractor.features("101:Running apples, unknown"),
+            QueryFeatures {
+                id: String::from("101"),
+                term_count: 3,
+                known_terms: 2,
+                sum_postings: 10,
+                max_postings: 7,
+            }
+        );
+        assert_eq!(
+            extractor.features("banana").csv_row(1.5),
+            String::from(",1,1,10,10,1.5")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+//! Removal of index artifacts no longer referenced by any recent session.
+
+use crate::artifacts::Artifacts;
+use crate::checkpoint::STATE_DIR;
+use crate::config::Config;
+use crate::ensure_parent_exists;
+use crate::error::Error;
+use crate::share;
+use failure::ResultExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Index basenames used by a single session.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Session {
+    /// Start of the session, in seconds since the Unix epoch.
+    pub started: u64,
+    /// Basenames of forward and inverted indexes of all collections.
+    pub indexes: BTreeSet<PathBuf>,
+}
+
+/// Sessions run in a work directory, from the oldest.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct History(pub Vec<Session>);
+
+/// Basenames of forward and inverted indexes of all collections in `config`.
+pub fn index_basenames<C: Config>(config: &C) -> BTreeSet<PathBuf> {
+    config
+        .collections()
+        .iter()
+        .flat_map(|collection| vec![collection.fwd_index.clone(), collection.inv_index.clone()])
+        .collect()
+}
+
+impl History {
+    /// Path to the session history within `workdir`.
+    pub fn path(workdir: &Path) -> PathBuf {
+        workdir.join(STATE_DIR).join("history.json")
+    }
+
+    /// Loads the history of `workdir`, which is empty if no session has been recorded.
+    pub fn load(workdir: &Path) -> Result<Self, Error> {
+        let path = Self::path(workdir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let file = File::open(&path).with_context(|_| path.display().to_string())?;
+        Ok(serde_json::from_reader(file).context("Failed to parse session history")?)
+    }
+
+    /// Appends a session of `config` to the history of its work directory.
+    pub fn record<C: Config>(config: &C) -> Result<(), Error> {
+        let mut history = Self::load(config.workdir())?;
+        history.0.push(Session {
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            indexes: index_basenames(config),
+        });
+        let path = Self::path(config.workdir());
+        ensure_parent_exists(&path)?;
+        let json = serde_json::to_string_pretty(&history)
+            .context("Failed to serialize session history")?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+}
+
+/// Returns `true` if `path` is the index at `basename` or one of its derived files,
+/// e.g., `inv/wapo.block_simdbp` for `inv/wapo`.
+pub(crate) fn derived_from(path: &Path, basename: &Path) -> bool {
+    match (path.file_name(), basename.file_name()) {
+        (Some(name), Some(base)) => {
+            path.parent() == basename.parent()
+                && name
+                    .to_string_lossy()
+                    .strip_prefix(base.to_string_lossy().as_ref())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        }
+        _ => false,
+    }
+}
+
+/// Finds index artifacts not referenced by `config` nor by the last `keep_last` sessions
+/// in its work directory.
+///
+/// Artifacts are all files in the default `fwd` and `inv` directories of the work directory,
+/// the files derived from any index of a recorded session, as well as the index files
+/// in the [inventory of artifacts](../artifacts/index.html). Files in the
+/// [store of shared artifacts](../share/index.html) are included once all their
+/// other links are.
+pub fn garbage<C: Config>(config: &C, keep_last: usize) -> Result<Vec<PathBuf>, Error> {
+    let workdir = config.workdir();
+    let history = History::load(workdir)?;
+    let mut referenced = index_basenames(config);
+    let recorded: BTreeSet<PathBuf> = history
+        .0
+        .iter()
+        .flat_map(|session| session.indexes.iter().cloned())
+        .collect();
+    let artifacts = Artifacts::open(workdir)?;
+    let inventoried: BTreeSet<PathBuf> = artifacts
+        .list()
+        .iter()
+        .filter(|artifact| artifact.run.is_none())
+        .map(|artifact| artifacts.resolve(artifact))
+        .collect();
+    let default_dirs = [workdir.join("fwd"), workdir.join("inv")];
+    for session in history.0.iter().rev().take(keep_last) {
+        referenced.extend(session.indexes.iter().cloned());
+    }
+    let dirs: BTreeSet<PathBuf> = recorded
+        .iter()
+        .chain(&referenced)
+        .chain(&inventoried)
+        .filter_map(|basename| basename.parent().map(Path::to_path_buf))
+        .chain(default_dirs.iter().cloned())
+        .filter(|dir| dir.is_dir())
+        .collect();
+    let mut garbage = Vec::new();
+    for dir in dirs {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let artifact = default_dirs.contains(&dir)
+                || inventoried.contains(&path)
+                || recorded
+                    .iter()
+                    .any(|basename| derived_from(&path, basename));
+            if path.is_file()
+                && artifact
+                && !referenced
+                    .iter()
+                    .any(|basename| derived_from(&path, basename))
+            {
+                garbage.push(path);
+            }
+        }
+    }
+    let unreferenced = share::unreferenced(workdir, &garbage)?;
+    garbage.extend(unreferenced);
+    garbage.sort();
+    Ok(garbage)
+}
+
+/// Space freed by removing all files in `garbage`: a file hard-linked elsewhere
+/// frees nothing, and a file linked several times in `garbage` is counted once.
+pub fn freed_size(garbage: &[PathBuf]) -> Result<u64, Error> {
+    let mut inodes: HashMap<(u64, u64), (u64, u64, u64)> = HashMap::new();
+    for path in garbage {
+        let metadata = fs::metadata(path)?;
+        let (_, _, links) = inodes.entry((metadata.dev(), metadata.ino())).or_insert((
+            metadata.nlink(),
+            metadata.len(),
+            0,
+        ));
+        *links += 1;
+    }
+    Ok(inodes
+        .values()
+        .filter(|(nlink, _, links)| links >= nlink)
+        .map(|(_, len, _)| len)
+        .sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::mkfiles;
+    use crate::RawConfig;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_garbage() -> Result<(), Error> {
+        let tmp = TempDir::new("gc").unwrap();
+        let workdir = tmp.path().join("work");
+        fs::create_dir(&workdir)?;
+        mkfiles(
+            tmp.path(),
+            &[
+                "work/fwd/",
+                "work/inv/",
+                "work/fwd/wapo",
+                "work/fwd/wapo.terms",
+                "work/fwd/wapo2.terms",
+                "work/inv/wapo.block_simdbp",
+                "work/inv/robust.wand",
+                "work/inv/gov2.ef",
+                "work/inv/gov2.ef.tmp/",
+                "external/",
+                "external/cw09b.ef",
+                "external/cw12b.ef",
+                "external/notes.txt",
+            ],
+        )?;
+        let config = |collections: &[(&str, PathBuf)]| -> Result<RawConfig, Error> {
+            Ok(RawConfig {
+                workdir: workdir.clone(),
+                collections: collections
+                    .iter()
+                    .map(|(name, inv)| -> Result<_, Error> {
+                        Ok(serde_yaml::from_str(&format!(
+                            "name: {0}\nkind: robust\nfwd_index: {1}\ninv_index: {2}",
+                            name,
+                            workdir.join("fwd").join(name).display(),
+                            inv.display()
+                        ))
+                        .context("Invalid collection")?)
+                    })
+                    .collect::<Result<_, _>>()?,
+                ..RawConfig::default()
+            })
+        };
+        let inv = |name: &str| workdir.join("inv").join(name);
+        History::record(&config(&[("cw09b", tmp.path().join("external/cw09b"))])?)?;
+        History::record(&config(&[("cw12b", tmp.path().join("external/cw12b"))])?)?;
+        History::record(&config(&[("gov2", inv("gov2"))])?)?;
+        assert_eq!(History::load(&workdir)?.0.len(), 3);
+
+        let current = config(&[("wapo", inv("wapo"))])?;
+        let path = |name: &str| tmp.path().join(name);
+        assert_eq!(
+            garbage(&current, 2)?,
+            vec![
+                path("external/cw09b.ef"),
+                path("work/fwd/wapo2.terms"),
+                path("work/inv/robust.wand"),
+            ]
+        );
+        assert_eq!(
+            garbage(&current, 0)?,
+            vec![
+                path("external/cw09b.ef"),
+                path("external/cw12b.ef"),
+                path("work/fwd/wapo2.terms"),
+                path("work/inv/gov2.ef"),
+                path("work/inv/robust.wand"),
+            ]
+        );
+
+        fs::write(path("work/inv/robust.wand"), "wand")?;
+        let stored = workdir.join(share::SHARED_DIR).join("stored");
+        fs::create_dir_all(stored.parent().unwrap())?;
+        fs::hard_link(path("work/inv/robust.wand"), &stored)?;
+        let collected = garbage(&current, 2)?;
+        assert_eq!(collected.last(), Some(&stored));
+        assert_eq!(freed_size(&collected)?, 4);
+        assert_eq!(freed_size(&[path("work/inv/robust.wand")])?, 0);
+
+        mkfiles(tmp.path(), &["external/old.ef"])?;
+        assert!(!garbage(&current, 2)?.contains(&path("external/old.ef")));
+        let old = config(&[("old", tmp.path().join("external/old"))])?;
+        Artifacts::open(&workdir)?.record_collection(&old.collections[0])?;
+        assert!(garbage(&current, 2)?.contains(&path("external/old.ef")));
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+//! Host-wide lock keeping benchmark measurements isolated from other work.
+//!
+//! All tasks of all sessions on a host lock the same file: parallelizable tasks,
+//! such as index builds or evaluation runs, share the lock, while measurement runs
+//! hold it exclusively, so they never execute concurrently with anything else.
+
+use crate::config::{Run, RunKind};
+use crate::error::Error;
+use failure::ResultExt;
+use log::info;
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// Name of the default lock file in the system temporary directory.
+pub const DEFAULT_LOCK_FILE: &str = "stdbench.lock";
+
+/// Classification of a task with respect to measurement isolation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Isolation {
+    /// May execute concurrently with other parallelizable tasks.
+    Parallelizable,
+    /// May not execute concurrently with any other task.
+    Exclusive,
+}
+
+impl Isolation {
+    /// Classifies a run: benchmarks and external runs measure time, and are exclusive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::guard::Isolation;
+    /// # use stdbench::RunKind;
+    /// # use std::path::PathBuf;
+    /// assert_eq!(Isolation::for_kind(&RunKind::Benchmark), Isolation::Exclusive);
+    /// let evaluate = RunKind::Evaluate { qrels: PathBuf::from("qrels"), min_relevance: None };
+    /// assert_eq!(Isolation::for_kind(&evaluate), Isolation::Parallelizable);
+    /// ```
+    pub fn for_kind(kind: &RunKind) -> Self {
+        match kind {
+            RunKind::Benchmark | RunKind::External { .. } => Self::Exclusive,
+            RunKind::Evaluate { .. } => Self::Parallelizable,
+        }
+    }
+
+    /// Classifies a run by its kind.
+    pub fn for_run(run: &Run) -> Self {
+        Self::for_kind(&run.kind)
+    }
+
+    fn operation(self) -> libc::c_int {
+        match self {
+            Self::Parallelizable => libc::LOCK_SH,
+            Self::Exclusive => libc::LOCK_EX,
+        }
+    }
+}
+
+/// Lock held until dropped.
+#[derive(Debug)]
+pub struct HostLock {
+    _file: File,
+}
+
+impl HostLock {
+    fn open(path: &Path) -> Result<File, Error> {
+        Ok(OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(path)
+            .with_context(|_| format!("Failed to open lock file: {}", path.display()))?)
+    }
+
+    /// Acquires the lock at `path` if available without waiting.
+    pub fn try_acquire(path: &Path, isolation: Isolation) -> Result<Option<Self>, Error> {
+        let file = Self::open(path)?;
+        let locked =
+            unsafe { libc::flock(file.as_raw_fd(), isolation.operation() | libc::LOCK_NB) } == 0;
+        Ok(if locked {
+            Some(Self { _file: file })
+        } else {
+            None
+        })
+    }
+
+    /// Acquires the lock at `path`, waiting for conflicting tasks to finish.
+    pub fn acquire(path: &Path, isolation: Isolation) -> Result<Self, Error> {
+        if let Some(lock) = Self::try_acquire(path, isolation)? {
+            return Ok(lock);
+        }
+        info!(
+            "Waiting for other tasks on this host to finish ({:?}): {}",
+            isolation,
+            path.display()
+        );
+        let file = Self::open(path)?;
+        if unsafe { libc::flock(file.as_raw_fd(), isolation.operation()) } != 0 {
+            return Err(Error::from(format!(
+                "Failed to lock {}: {}",
+                path.display(),
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(Self { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_exclusive_lock() -> Result<(), Error> {
+        let tmp = TempDir::new("guard").unwrap();
+        let path = tmp.path().join(DEFAULT_LOCK_FILE);
+        let shared = HostLock::acquire(&path, Isolation::Parallelizable)?;
+        assert!(HostLock::try_acquire(&path, Isolation::Parallelizable)?.is_some());
+        assert!(HostLock::try_acquire(&path, Isolation::Exclusive)?.is_none());
+        drop(shared);
+        let exclusive = HostLock::acquire(&path, Isolation::Exclusive)?;
+        assert!(HostLock::try_acquire(&path, Isolation::Parallelizable)?.is_none());
+        drop(exclusive);
+        assert!(HostLock::try_acquire(&path, Isolation::Exclusive)?.is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,263 @@
+//! Information about the machine executing the benchmark.
+//!
+//! Besides the hardware, CPU settings that routinely explain latency differences
+//! across machines are detected from sysfs: simultaneous multithreading (SMT),
+//! frequency governors, and turbo boost
+//! (see [`environment_issues`](struct.HostInfo.html#method.environment_issues)).
+
+use crate::error::Error;
+use failure::ResultExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// Name of the file storing host information in the work directory.
+pub const HOST_FILE: &str = "host.json";
+
+/// Directory of CPU settings in sysfs.
+const CPU_SYSFS: &str = "/sys/devices/system/cpu";
+
+/// Host name and hardware description.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct HostInfo {
+    /// Host name.
+    pub hostname: String,
+    /// CPU model name.
+    #[serde(default)]
+    pub cpu: Option<String>,
+    /// Number of logical CPUs.
+    #[serde(default)]
+    pub cpus: Option<usize>,
+    /// Total memory in kB.
+    #[serde(default)]
+    pub memory_kb: Option<u64>,
+    /// Whether simultaneous multithreading (hyper-threading) is active.
+    #[serde(default)]
+    pub smt: Option<bool>,
+    /// Distinct frequency governors of the CPUs, e.g., `performance`.
+    #[serde(default)]
+    pub governors: Vec<String>,
+    /// Whether turbo boost is enabled.
+    #[serde(default)]
+    pub turbo: Option<bool>,
+}
+
+fn parse_cpuinfo(cpuinfo: &str) -> (Option<String>, Option<usize>) {
+    let model = cpuinfo
+        .lines()
+        .find(|line| line.starts_with("model name"))
+        .and_then(|line| line.split_once(':'))
+        .map(|(_, model)| model.trim().to_string());
+    let count = cpuinfo
+        .lines()
+        .filter(|line| line.starts_with("processor"))
+        .count();
+    (model, if count > 0 { Some(count) } else { None })
+}
+
+fn parse_meminfo(meminfo: &str) -> Option<u64> {
+    meminfo
+        .lines()
+        .find(|line| line.starts_with("MemTotal:"))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse().ok())
+}
+
+/// Reads a sysfs flag, either `0`/`1` or `off`/`on`.
+fn read_flag(path: &Path) -> Option<bool> {
+    match fs::read_to_string(path).ok()?.trim() {
+        "1" | "on" => Some(true),
+        "0" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Detects SMT, the frequency governors, and turbo boost from the CPU directory of sysfs.
+fn detect_cpu_settings(root: &Path) -> (Option<bool>, Vec<String>, Option<bool>) {
+    let smt = read_flag(&root.join("smt").join("active"));
+    let mut governors = BTreeSet::new();
+    if let Ok(entries) = fs::read_dir(root) {
+        for entry in entries.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let is_cpu = name
+                .strip_prefix("cpu")
+                .is_some_and(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()));
+            if is_cpu {
+                let path = entry.path().join("cpufreq").join("scaling_governor");
+                if let Ok(governor) = fs::read_to_string(path) {
+                    governors.insert(governor.trim().to_string());
+                }
+            }
+        }
+    }
+    let turbo = read_flag(&root.join("intel_pstate").join("no_turbo"))
+        .map(|no_turbo| !no_turbo)
+        .or_else(|| read_flag(&root.join("cpufreq").join("boost")));
+    (smt, governors.into_iter().collect(), turbo)
+}
+
+impl HostInfo {
+    /// Detects the information about the current host.
+    ///
+    /// Hardware details are read from `/proc`, and are missing if not available.
+    pub fn detect() -> Self {
+        let hostname = fs::read_to_string("/proc/sys/kernel/hostname")
+            .map_or_else(|_| String::from("unknown"), |h| h.trim().to_string());
+        let (cpu, cpus) =
+            fs::read_to_string("/proc/cpuinfo").map_or((None, None), |info| parse_cpuinfo(&info));
+        let memory_kb = fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|info| parse_meminfo(&info));
+        let (smt, governors, turbo) = detect_cpu_settings(Path::new(CPU_SYSFS));
+        Self {
+            hostname,
+            cpu,
+            cpus,
+            memory_kb,
+            smt,
+            governors,
+            turbo,
+        }
+    }
+
+    /// Describes the CPU settings that make latencies unstable or incomparable
+    /// across machines: active SMT, governors other than `performance`
+    /// (such as `powersave`), and enabled turbo boost. Undetected settings are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::host::HostInfo;
+    /// let host = HostInfo {
+    ///     smt: Some(true),
+    ///     governors: vec![String::from("performance"), String::from("powersave")],
+    ///     turbo: Some(false),
+    ///     ..HostInfo::default()
+    /// };
+    /// assert_eq!(
+    ///     host.environment_issues(),
+    ///     vec![
+    ///         "SMT (hyper-threading) is active",
+    ///         "CPU frequency governor is not performance: powersave",
+    ///     ]
+    /// );
+    /// assert!(HostInfo::default().environment_issues().is_empty());
+    /// ```
+    pub fn environment_issues(&self) -> Vec<String> {
+        let mut issues = Vec::new();
+        if self.smt == Some(true) {
+            issues.push(String::from("SMT (hyper-threading) is active"));
+        }
+        let governors: Vec<_> = self
+            .governors
+            .iter()
+            .filter(|governor| *governor != "performance")
+            .map(String::as_str)
+            .collect();
+        if !governors.is_empty() {
+            issues.push(format!(
+                "CPU frequency governor is not performance: {}",
+                governors.join(", ")
+            ));
+        }
+        if self.turbo == Some(true) {
+            issues.push(String::from("Turbo boost is enabled"));
+        }
+        issues
+    }
+
+    /// Short description of the hardware, used as a report dimension.
+    pub fn hardware(&self) -> String {
+        let cpu = self.cpu.as_ref().map_or("unknown", String::as_str);
+        match self.cpus {
+            Some(cpus) => format!("{} x{}", cpu, cpus),
+            None => cpu.to_string(),
+        }
+    }
+
+    /// Writes the information to `dir/host.json`.
+    pub fn write(&self, dir: &Path) -> Result<(), Error> {
+        fs::create_dir_all(dir)?;
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize host info")?;
+        fs::write(dir.join(HOST_FILE), json)?;
+        Ok(())
+    }
+
+    /// Reads the information from `dir/host.json`.
+    pub fn read(dir: &Path) -> Result<Self, Error> {
+        let path = dir.join(HOST_FILE);
+        let file = fs::File::open(&path).with_context(|_| path.display().to_string())?;
+        Ok(serde_json::from_reader(file).context("Failed to parse host info")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_parse_proc() {
+        let cpuinfo = "processor\t: 0\nmodel name\t: Intel(R) Xeon(R) CPU\n\n\
+                       processor\t: 1\nmodel name\t: Intel(R) Xeon(R) CPU\n";
+        assert_eq!(
+            parse_cpuinfo(cpuinfo),
+            (Some(String::from("Intel(R) Xeon(R) CPU")), Some(2))
+        );
+        assert_eq!(parse_cpuinfo(""), (None, None));
+        assert_eq!(
+            parse_meminfo("MemTotal:       16314260 kB\nMemFree: 1 kB"),
+            Some(16_314_260)
+        );
+    }
+
+    #[test]
+    fn test_detect_cpu_settings() -> Result<(), Error> {
+        let tmp = TempDir::new("host").unwrap();
+        let root = tmp.path();
+        assert_eq!(detect_cpu_settings(root), (None, vec![], None));
+        for (cpu, governor) in &[("cpu0", "powersave"), ("cpu1", "performance")] {
+            fs::create_dir_all(root.join(cpu).join("cpufreq"))?;
+            fs::write(
+                root.join(cpu).join("cpufreq").join("scaling_governor"),
+                format!("{}\n", governor),
+            )?;
+        }
+        fs::create_dir_all(root.join("cpufreq"))?;
+        fs::write(root.join("cpufreq").join("scaling_governor"), "ignored\n")?;
+        fs::write(root.join("cpufreq").join("boost"), "1\n")?;
+        fs::create_dir_all(root.join("smt"))?;
+        fs::write(root.join("smt").join("active"), "0\n")?;
+        assert_eq!(
+            detect_cpu_settings(root),
+            (
+                Some(false),
+                vec![String::from("performance"), String::from("powersave")],
+                Some(true)
+            )
+        );
+        fs::create_dir_all(root.join("intel_pstate"))?;
+        fs::write(root.join("intel_pstate").join("no_turbo"), "1\n")?;
+        assert_eq!(detect_cpu_settings(root).2, Some(false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_read() -> Result<(), Error> {
+        let tmp = TempDir::new("host").unwrap();
+        let info = HostInfo {
+            hostname: String::from("bench1"),
+            cpu: Some(String::from("Xeon")),
+            cpus: Some(8),
+            memory_kb: None,
+            smt: Some(false),
+            governors: vec![String::from("performance")],
+            turbo: None,
+        };
+        info.write(tmp.path())?;
+        assert_eq!(HostInfo::read(tmp.path())?, info);
+        assert_eq!(info.hardware(), "Xeon x8");
+        Ok(())
+    }
+}
@@ -0,0 +1,314 @@
+//! Stable identities of measured configurations, independent of their position
+//! in a config and of output file names, used to join results across sessions and hosts.
+
+use crate::config::{Algorithm, Encoding, Run, Scorer, Topics};
+use crate::error::Error;
+use crate::{ensure_parent_exists, RegressionMargin};
+use failure::ResultExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Number of results retrieved per query by all runs.
+pub const RESULTS_K: usize = 1000;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Computes the 64-bit FNV-1a hash of `bytes`.
+///
+/// Unlike `std::collections::hash_map::DefaultHasher`, its result is guaranteed
+/// not to change between Rust versions, so it can be stored.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::identity::fnv1a;
+/// assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+/// assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+/// ```
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    fnv1a_extend(FNV_OFFSET, bytes)
+}
+
+/// Continues the FNV-1a `hash` of preceding bytes with `bytes`, e.g., to hash
+/// large files in chunks.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::identity::{fnv1a, fnv1a_extend};
+/// assert_eq!(fnv1a_extend(fnv1a(b"ab"), b"cd"), fnv1a(b"abcd"));
+/// ```
+pub fn fnv1a_extend(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Hashes the content of topics, so that the same queries are identified
+/// regardless of where their files are stored.
+///
+/// Standard topics and topics given by URL must be resolved first.
+pub fn topics_hash(topics: &Topics) -> Result<String, Error> {
+    let read = |path: &std::path::Path| {
+        fs::read(path).with_context(|_| format!("Failed to read topics: {}", path.display()))
+    };
+    let key = match topics {
+        Topics::Simple { path, .. } => format!("simple:{:016x}", fnv1a(&read(path)?)),
+        Topics::Trec { path, field, .. } => format!("trec:{}:{:016x}", field, fnv1a(&read(path)?)),
+        Topics::Combined { sources } => {
+            let mut key = String::from("combined");
+            for source in sources {
+                key.push_str(&format!(
+                    ":{}*{}",
+                    topics_hash(&source.topics)?,
+                    source.weight
+                ));
+            }
+            key
+        }
+        Topics::Standard { standard } => {
+            return Err(Error::from(format!(
+                "Unresolved standard topics: {}",
+                standard
+            )))
+        }
+        Topics::Url { url, .. } => {
+            return Err(Error::from(format!("Unresolved topics URL: {}", url)))
+        }
+    };
+    Ok(format!("{:016x}", fnv1a(key.as_bytes())))
+}
+
+/// Hashes the content of a qrels file.
+pub fn qrels_hash(qrels: &Path) -> Result<String, Error> {
+    let content =
+        fs::read(qrels).with_context(|_| format!("Failed to read qrels: {}", qrels.display()))?;
+    Ok(format!("{:016x}", fnv1a(&content)))
+}
+
+/// Metadata of the results of a run, written next to them so that when they serve
+/// as a baseline, comparisons with results of different topics or qrels are refused.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BaselineMeta {
+    /// Hashes of the topic sets (see [`topics_hash`](fn.topics_hash.html)), in order.
+    pub topics: Vec<String>,
+    /// Hashes of the qrels of each topic set, if the run is evaluated.
+    pub qrels: Vec<Option<String>>,
+    /// Commit of the PISA source code, if known.
+    #[serde(default)]
+    pub commit: Option<String>,
+    /// Version of this program.
+    pub stdbench_version: String,
+    /// Performance regression margin.
+    pub margin: f32,
+    /// Regression margins of query classes, overriding the global margin.
+    #[serde(default)]
+    pub class_margins: BTreeMap<String, f32>,
+}
+
+impl BaselineMeta {
+    /// Path to the metadata of results with the output prefix `output`.
+    pub fn path(output: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.baseline.meta.json", output.display()))
+    }
+
+    /// Collects the metadata of `run`.
+    pub fn new(run: &Run, commit: Option<&str>, margin: RegressionMargin) -> Result<Self, Error> {
+        Ok(Self {
+            topics: run
+                .topics
+                .iter()
+                .map(topics_hash)
+                .collect::<Result<_, _>>()?,
+            qrels: (0..run.topics.len())
+                .map(|tid| run.qrels(tid).map(qrels_hash).transpose())
+                .collect::<Result<_, _>>()?,
+            commit: commit.map(String::from),
+            stdbench_version: String::from(env!("CARGO_PKG_VERSION")),
+            margin: margin.0,
+            class_margins: run
+                .class_margins
+                .iter()
+                .map(|(class, margin)| (class.to_string(), margin.0))
+                .collect(),
+        })
+    }
+
+    /// Loads metadata from a JSON file.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path).with_context(|_| path.display().to_string())?;
+        Ok(serde_json::from_reader(file).context("Failed to parse baseline metadata")?)
+    }
+
+    /// Writes the metadata of results with the output prefix `output`.
+    pub fn write(&self, output: &Path) -> Result<(), Error> {
+        let path = Self::path(output);
+        ensure_parent_exists(&path)?;
+        let json =
+            serde_json::to_string_pretty(self).context("Failed to serialize baseline metadata")?;
+        fs::write(&path, json)?;
+        Ok(())
+    }
+
+    /// Lists the differences of topics and qrels that make results incomparable
+    /// with those of `baseline`. Other fields are informative only.
+    pub fn mismatches(&self, baseline: &Self) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        if self.topics.len() != baseline.topics.len() {
+            mismatches.push(format!(
+                "{} topic sets, but {} in baseline",
+                self.topics.len(),
+                baseline.topics.len()
+            ));
+        }
+        for (tid, (topics, base)) in self.topics.iter().zip(&baseline.topics).enumerate() {
+            if topics != base {
+                mismatches.push(format!("topics {} differ from baseline", tid));
+            }
+        }
+        for (tid, (qrels, base)) in self.qrels.iter().zip(&baseline.qrels).enumerate() {
+            if qrels != base {
+                mismatches.push(format!("qrels of topics {} differ from baseline", tid));
+            }
+        }
+        mismatches
+    }
+}
+
+/// Everything that determines the results of a single algorithm and encoding
+/// on a single topic set.
+#[derive(Debug, PartialEq)]
+pub struct RunIdentity<'a> {
+    /// Collection name.
+    pub collection: &'a str,
+    /// Posting list encoding.
+    pub encoding: &'a Encoding,
+    /// Query processing algorithm.
+    pub algorithm: &'a Algorithm,
+    /// Hash of the topics (see [`topics_hash`](fn.topics_hash.html)).
+    pub topics_hash: &'a str,
+    /// Number of results retrieved per query.
+    pub k: usize,
+    /// Scoring function, if passed to PISA tools.
+    pub scorer: Option<&'a Scorer>,
+    /// Commit of the PISA source code, if known.
+    pub commit: Option<&'a str>,
+}
+
+impl RunIdentity<'_> {
+    /// Deterministic ID: a hexadecimal hash of all the fields.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::identity::{RunIdentity, RESULTS_K};
+    /// let identity = RunIdentity {
+    ///     collection: "wapo",
+    ///     encoding: &"block_simdbp".into(),
+    ///     algorithm: &"wand".into(),
+    ///     topics_hash: "0123456789abcdef",
+    ///     k: RESULTS_K,
+    ///     scorer: Some(&"bm25".into()),
+    ///     commit: None,
+    /// };
+    /// assert_eq!(identity.id().len(), 16);
+    /// assert_eq!(identity.id(), identity.id());
+    /// assert_ne!(identity.id(), RunIdentity { k: 10, ..identity }.id());
+    /// ```
+    pub fn id(&self) -> String {
+        let key = format!(
+            "{}\0{}\0{}\0{}\0{}\0{}\0{}",
+            self.collection,
+            self.encoding,
+            self.algorithm,
+            self.topics_hash,
+            self.k,
+            self.scorer.map_or("", AsRef::as_ref),
+            self.commit.unwrap_or("")
+        );
+        format!("{:016x}", fnv1a(key.as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{TopicField, TopicSource};
+    use crate::tests::{mock_set_up, MockSetup};
+    use crate::Config;
+    use std::path::Path;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_topics_hash() -> Result<(), Error> {
+        let tmp = TempDir::new("identity").unwrap();
+        let first = tmp.path().join("first");
+        let second = tmp.path().join("second");
+        fs::write(&first, "1:hello world\n")?;
+        fs::write(&second, "1:hello world\n")?;
+        let simple = |path: &Path| Topics::Simple {
+            path: path.to_path_buf(),
+            qrels: None,
+        };
+        assert_eq!(
+            topics_hash(&simple(&first))?,
+            topics_hash(&simple(&second))?
+        );
+        assert_ne!(
+            topics_hash(&simple(&first))?,
+            topics_hash(&Topics::Trec {
+                path: first.clone(),
+                field: TopicField::Title,
+                qrels: None
+            })?
+        );
+        let combined = |weight: f64| Topics::Combined {
+            sources: vec![TopicSource {
+                topics: simple(&first),
+                weight,
+            }],
+        };
+        assert_ne!(topics_hash(&combined(1.0))?, topics_hash(&combined(0.5))?);
+        fs::write(&second, "1:hello\n")?;
+        assert_ne!(
+            topics_hash(&simple(&first))?,
+            topics_hash(&simple(&second))?
+        );
+        assert!(topics_hash(&Topics::Standard {
+            standard: String::from("robust04")
+        })
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_baseline_meta() -> Result<(), Error> {
+        let tmp = TempDir::new("identity").unwrap();
+        let MockSetup { mut config, .. } = mock_set_up(&tmp);
+        let run = &config.run(0);
+        let meta = BaselineMeta::new(run, Some("abc"), RegressionMargin(0.1))?;
+        assert_eq!(meta.topics.len(), run.topics.len());
+        assert!(meta.qrels.iter().all(Option::is_some));
+        meta.write(&run.output)?;
+        let baseline = BaselineMeta::load(&BaselineMeta::path(&run.output))?;
+        assert_eq!(baseline, meta);
+        assert!(meta.mismatches(&baseline).is_empty());
+        let qrels = run.qrels(0).unwrap().to_path_buf();
+        fs::write(&qrels, "1 0 d1 1\n")?;
+        let changed = BaselineMeta::new(&config.run(0), None, RegressionMargin(0.2))?;
+        assert_eq!(
+            changed.mismatches(&baseline),
+            vec![String::from("qrels of topics 0 differ from baseline")]
+        );
+        config.0.runs[0].topics.clear();
+        let changed = BaselineMeta::new(&config.run(0), None, RegressionMargin(0.1))?;
+        assert_eq!(
+            changed.mismatches(&baseline),
+            vec![String::from("0 topic sets, but 1 in baseline")]
+        );
+        Ok(())
+    }
+}
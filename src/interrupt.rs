@@ -0,0 +1,145 @@
+//! Graceful handling of `SIGINT`, e.g., of Ctrl-C pressed during a session.
+//!
+//! On the first interrupt, all running tool processes are killed, together with the processes
+//! they started, and no new ones are started, so that the session fails at its current step
+//! instead of leaving orphaned processes behind. The session then saves its partial results
+//! and checkpoints, and exits with [`EXIT_CODE`](constant.EXIT_CODE.html).
+//! A second interrupt exits immediately.
+//!
+//! The signal handler only wakes up a thread through a pipe, which kills the processes
+//! registered by the executor, since little else is safe to do within a signal handler.
+
+use crate::error::Error;
+use lazy_static::lazy_static;
+use log::warn;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::FromRawFd;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+/// Exit status of an interrupted session, as that of a shell command interrupted by `SIGINT`.
+pub const EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Write end of the pipe waking up the killing thread, or -1 if no handler is installed.
+static WAKE_FD: AtomicI32 = AtomicI32::new(-1);
+
+lazy_static! {
+    /// Running child processes by process ID, with the IDs passed to `kill`:
+    /// negated for processes leading their own process group, so that the group is killed.
+    static ref CHILDREN: Mutex<HashMap<u32, libc::pid_t>> = Mutex::new(HashMap::new());
+}
+
+/// The registry stays consistent even if another thread panicked while holding the lock,
+/// since it is only modified by single insertions and removals.
+fn children() -> MutexGuard<'static, HashMap<u32, libc::pid_t>> {
+    CHILDREN.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+extern "C" fn handle_interrupt(_signal: libc::c_int) {
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        unsafe { libc::_exit(EXIT_CODE) };
+    }
+    let fd = WAKE_FD.load(Ordering::SeqCst);
+    if fd >= 0 {
+        let byte = 1_u8;
+        unsafe { libc::write(fd, std::ptr::addr_of!(byte).cast(), 1) };
+    }
+}
+
+fn kill_children() {
+    let children = children();
+    warn!(
+        "Interrupted; killing {} running processes and stopping the session",
+        children.len()
+    );
+    for &target in children.values() {
+        unsafe { libc::kill(target, libc::SIGKILL) };
+    }
+}
+
+/// Installs the `SIGINT` handler, which should be done before any tool is executed.
+pub fn install() -> Result<(), Error> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } == -1 {
+        return Err(Error::from(io::Error::last_os_error()));
+    }
+    let mut wake = unsafe { File::from_raw_fd(fds[0]) };
+    WAKE_FD.store(fds[1], Ordering::SeqCst);
+    thread::spawn(move || {
+        let mut byte = [0_u8];
+        if wake.read_exact(&mut byte).is_ok() {
+            kill_children();
+        }
+    });
+    let handler = handle_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    if unsafe { libc::signal(libc::SIGINT, handler) } == libc::SIG_ERR {
+        return Err(Error::from(io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+/// Returns `true` if the session has been interrupted.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Fails with an error of kind `Interrupted` if the session has been interrupted,
+/// so that no new processes are started.
+pub(crate) fn check() -> io::Result<()> {
+    if interrupted() {
+        Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "Session interrupted",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Registers the running child process `pid` to be killed on interrupt, with its process
+/// group if it leads one. If the session has already been interrupted, it is killed now.
+pub(crate) fn register(pid: u32, group_leader: bool) {
+    let target = match libc::pid_t::try_from(pid) {
+        Ok(pid) if group_leader => -pid,
+        Ok(pid) => pid,
+        Err(_) => return,
+    };
+    let mut children = children();
+    children.insert(pid, target);
+    if interrupted() {
+        unsafe { libc::kill(target, libc::SIGKILL) };
+    }
+}
+
+/// Unregisters the child process `pid`, which must be done before it is reaped,
+/// so that its ID is never killed once reused by another process.
+pub(crate) fn unregister(pid: u32) {
+    children().remove(&pid);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_register() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        register(child.id(), false);
+        assert_eq!(
+            children().get(&child.id()),
+            Some(&libc::pid_t::try_from(child.id()).unwrap())
+        );
+        assert!(check().is_ok());
+        child.kill().unwrap();
+        unregister(child.id());
+        assert!(!children().contains_key(&child.id()));
+        assert!(!child.wait().unwrap().success());
+    }
+}
@@ -0,0 +1,152 @@
+//! Heuristic detection of the language of short queries.
+//!
+//! Queries in non-Latin scripts are classified by their script, e.g., Cyrillic as `ru`
+//! and Hangul as `ko`. Queries in the Latin script are classified by common function words
+//! and characters specific to a language, such as `ß` or `ñ`, which works well for
+//! natural-language questions, but not for bare keyword queries: these, as well as
+//! any ambiguous queries, are [undetermined](constant.UNDETERMINED.html).
+
+use std::collections::BTreeMap;
+
+/// Language code of queries whose language cannot be determined (as in ISO 639-2).
+pub const UNDETERMINED: &str = "und";
+
+/// All detected language codes, including [`UNDETERMINED`](constant.UNDETERMINED.html).
+pub const LANGUAGES: &[&str] = &[
+    "ar", "de", "el", "en", "es", "fr", "he", "hi", "it", "ja", "ko", "ru", "th", "zh", "und",
+];
+
+/// Unicode ranges of non-Latin scripts, and the languages they identify.
+const SCRIPTS: &[(char, char, &str)] = &[
+    ('\u{0370}', '\u{03ff}', "el"),
+    ('\u{0400}', '\u{04ff}', "ru"),
+    ('\u{0590}', '\u{05ff}', "he"),
+    ('\u{0600}', '\u{06ff}', "ar"),
+    ('\u{0900}', '\u{097f}', "hi"),
+    ('\u{0e00}', '\u{0e7f}', "th"),
+    ('\u{3040}', '\u{30ff}', "ja"),
+    ('\u{ac00}', '\u{d7af}', "ko"),
+    ('\u{4e00}', '\u{9fff}', "zh"),
+];
+
+/// Function words of languages written in the Latin script; words shared
+/// by several of these languages, such as `de` or `la`, are left out.
+const FUNCTION_WORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "of", "and", "to", "is", "for", "what", "how", "who", "are", "with", "does",
+            "which", "why",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "mit", "von", "wie", "was", "für", "ein",
+            "eine", "warum",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "les", "des", "et", "est", "du", "une", "pour", "comment", "quel", "quelle",
+            "pourquoi", "avec", "sont",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "los", "las", "del", "y", "es", "por", "para", "cómo", "qué", "una", "con",
+            "cuál", "son",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "gli", "della", "di", "che", "è", "per", "come", "sono", "perché", "quale",
+            "dei", "nel", "delle",
+        ],
+    ),
+];
+
+/// Characters specific to one of the languages written in the Latin script.
+const SPECIFIC_CHARS: &[(&str, &str)] = &[("de", "äöüß"), ("fr", "çâêîôûœë"), ("es", "ñ¿¡")];
+
+/// Detects the language of `query`, returning its ISO 639-1 code,
+/// or [`UNDETERMINED`](constant.UNDETERMINED.html).
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::lang::detect;
+/// assert_eq!(detect("what is the capital of france"), "en");
+/// assert_eq!(detect("wie groß ist der Eiffelturm"), "de");
+/// assert_eq!(detect("столица франции"), "ru");
+/// assert_eq!(detect("東京の天気"), "ja");
+/// assert_eq!(detect("eiffel tower height"), "und");
+/// ```
+pub fn detect(query: &str) -> &'static str {
+    let mut scores: BTreeMap<&'static str, usize> = BTreeMap::new();
+    for ch in query.chars() {
+        if let Some(&(_, _, lang)) = SCRIPTS
+            .iter()
+            .find(|&&(first, last, _)| first <= ch && ch <= last)
+        {
+            *scores.entry(lang).or_default() += 1;
+        }
+    }
+    if scores.contains_key("ja") {
+        // Japanese mixes kana with Chinese characters.
+        return "ja";
+    }
+    if scores.is_empty() {
+        let query = query.to_lowercase();
+        for word in query.split(|ch: char| !ch.is_alphanumeric()) {
+            for &(lang, words) in FUNCTION_WORDS {
+                if words.contains(&word) {
+                    *scores.entry(lang).or_default() += 1;
+                }
+            }
+        }
+        for &(lang, chars) in SPECIFIC_CHARS {
+            let count = query.chars().filter(|&ch| chars.contains(ch)).count();
+            if count > 0 {
+                *scores.entry(lang).or_default() += count;
+            }
+        }
+    }
+    let best = scores.values().copied().max().unwrap_or(0);
+    let mut leaders = scores.into_iter().filter(|&(_, score)| score == best);
+    match (leaders.next(), leaders.next()) {
+        (Some((lang, _)), None) => lang,
+        _ => UNDETERMINED,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect("how to cook pasta"), "en");
+        assert_eq!(detect("comment faire des crêpes"), "fr");
+        assert_eq!(detect("cómo hacer una tortilla"), "es");
+        assert_eq!(detect("mañana"), "es");
+        assert_eq!(detect("come fare il pane"), "it");
+        assert_eq!(detect("北京天气"), "zh");
+        assert_eq!(detect("서울 날씨"), "ko");
+        assert_eq!(detect("ιστορία της ελλάδας"), "el");
+        assert_eq!(detect(""), UNDETERMINED);
+        assert_eq!(detect("2019"), UNDETERMINED);
+        // Tied between English and Spanish.
+        assert_eq!(detect("the el"), UNDETERMINED);
+        for code in LANGUAGES {
+            assert!(
+                *code == UNDETERMINED
+                    || SCRIPTS.iter().any(|(_, _, lang)| lang == code)
+                    || FUNCTION_WORDS.iter().any(|(lang, _)| lang == code)
+            );
+        }
+    }
+}
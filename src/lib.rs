@@ -12,29 +12,98 @@
 //! on a collection of a significant size.
 
 use lazy_static::lazy_static;
-use log::debug;
+use log::info;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::os::unix::fs::MetadataExt;
 use std::path::Path;
 use std::process::Command;
 use std::{fmt, fs};
 
 pub mod config;
 pub use config::{
-    Algorithm, CMakeVar, Collection, Config, Encoding, RawConfig, Resolved, ResolvedPathsConfig,
-    Run, RunKind, Scorer, Source, Stage,
+    Algorithm, ByteSize, CMakeVar, Collection, Config, ConfigVar, Encoding, RawConfig, Resolved,
+    ResolvedPathsConfig, Run, RunKind, Scorer, Source, Stage,
 };
 
 mod executor;
-pub use executor::Executor;
+pub use executor::{
+    ExecBackend, Executor, FakeBackend, Invocation, ProcessBackend, ResourceUsage, StageTimes,
+    StageUsage, TimedOut,
+};
+
+pub mod aa;
+
+pub mod artifacts;
+
+pub mod bisect;
 
 pub mod build;
 
+pub mod checkpoint;
+
+pub mod database;
+
+pub mod describe;
+
+pub mod diff;
+
+pub mod eval;
+
+pub mod features;
+
+pub mod gc;
+
+pub mod guard;
+
+pub mod host;
+
+pub mod identity;
+
+pub mod interrupt;
+
+pub mod lang;
+
+pub mod lint;
+
+pub mod merge;
+
+pub mod metrics;
+
+pub mod plan;
+
+pub mod prometheus;
+
+pub mod reaggregate;
+
+pub mod registry;
+
+pub mod remote;
+
+pub mod report;
+
 mod error;
-pub use error::Error;
+pub use error::{Error, ErrorKind};
 
 pub mod run;
 
+pub mod sample;
+
+pub mod selective;
+
+pub mod share;
+
+pub mod status;
+
+pub mod summary;
+
+pub mod temp;
+
+pub mod transcript;
+
+pub mod version;
+
 /// If the parent directory of `path` does not exist, create it.
 ///
 /// # Examples
@@ -64,11 +133,34 @@ pub fn ensure_parent_exists(path: &Path) -> Result<(), Error> {
     Ok(())
 }
 
+/// Computes the aggregate size in bytes of all files under `path`.
+///
+/// Symbolic links are not followed, and files hard-linked several times are counted once.
+pub fn dir_size(path: &Path) -> Result<u64, Error> {
+    fn size(path: &Path, seen: &mut HashSet<(u64, u64)>) -> Result<u64, Error> {
+        let metadata = fs::symlink_metadata(path)?;
+        if !metadata.is_dir() {
+            let first = metadata.nlink() == 1 || seen.insert((metadata.dev(), metadata.ino()));
+            return Ok(if first { metadata.len() } else { 0 });
+        }
+        let mut total = 0;
+        for entry in fs::read_dir(path)? {
+            total += size(&entry?.path(), seen)?;
+        }
+        Ok(total)
+    }
+    size(path, &mut HashSet::new())
+}
+
+/// Log target of executed commands, which are shown independently of the verbosity
+/// of other logs (see `--show-commands`).
+pub const COMMANDS_LOG_TARGET: &str = "stdbench::commands";
+
 /// Extension trait for `std::process::Command` that allows to format and log the command.
 pub trait CommandDebug: fmt::Debug {
-    /// Log the command as DEBUG.
+    /// Log the command as INFO with the [commands target](constant.COMMANDS_LOG_TARGET.html).
     fn log(&mut self) -> &mut Self {
-        debug!("[EXEC] {}", self.to_string());
+        info!(target: COMMANDS_LOG_TARGET, "[EXEC] {}", self.to_string());
         self
     }
 
@@ -85,10 +177,40 @@ pub trait CommandDebug: fmt::Debug {
     }
 }
 
-impl CommandDebug for Command {}
+/// Commands are formatted from their program and arguments, quoting any argument
+/// that would otherwise be split or interpreted by a shell.
+impl CommandDebug for Command {
+    fn to_string(&self) -> String {
+        std::iter::once(self.get_program())
+            .chain(self.get_args())
+            .map(|arg| shell_quote(&arg.to_string_lossy()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Quotes `arg` for a POSIX shell if necessary.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::shell_quote;
+/// assert_eq!(shell_quote("-q"), "-q");
+/// assert_eq!(shell_quote("a b"), "'a b'");
+/// assert_eq!(shell_quote("it's"), r#"'it'\''s'"#);
+/// assert_eq!(shell_quote(""), "''");
+/// ```
+pub fn shell_quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
 
 /// Defines the performance regression error allowed.
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
 pub struct RegressionMargin(pub f32);
 
 impl Default for RegressionMargin {
@@ -155,6 +277,18 @@ mod tests {
                 inv_index: tmp.path().join("inv"),
                 encodings: vec!["block_simdbp".into(), "block_qmx".into()],
                 scorers: default_scorers(),
+                batch_sizes: BatchSizesOverride::default(),
+                threads: Threads::default(),
+                input_glob: None,
+                preset: None,
+                builder: Builder::Pisa,
+                stages: Default::default(),
+                stemmer: None,
+                sample_documents: None,
+                shards: 1,
+                shard: None,
+                quantize: false,
+                download: vec![],
             },
             Collection {
                 name: "gov2".to_string(),
@@ -164,6 +298,18 @@ mod tests {
                 inv_index: tmp.path().join("gov2/inv"),
                 encodings: vec!["block_simdbp".into(), "block_qmx".into()],
                 scorers: default_scorers(),
+                batch_sizes: BatchSizesOverride::default(),
+                threads: Threads::default(),
+                input_glob: None,
+                preset: None,
+                builder: Builder::Pisa,
+                stages: Default::default(),
+                stemmer: None,
+                sample_documents: None,
+                shards: 1,
+                shard: None,
+                quantize: false,
+                download: vec![],
             },
             Collection {
                 name: "cw09b".to_string(),
@@ -173,6 +319,18 @@ mod tests {
                 inv_index: tmp.path().join("cw09b/inv"),
                 encodings: vec!["block_simdbp".into(), "block_qmx".into()],
                 scorers: default_scorers(),
+                batch_sizes: BatchSizesOverride::default(),
+                threads: Threads::default(),
+                input_glob: None,
+                preset: None,
+                builder: Builder::Pisa,
+                stages: Default::default(),
+                stemmer: None,
+                sample_documents: None,
+                shards: 1,
+                shard: None,
+                quantize: false,
+                download: vec![],
             },
         ];
         let runs = vec![
@@ -180,30 +338,78 @@ mod tests {
                 collection: "wapo".into(),
                 kind: RunKind::Evaluate {
                     qrels: tmp.path().join("qrels"),
+                    min_relevance: None,
                 },
                 encodings: vec!["block_simdbp".into(), "block_qmx".into()],
                 algorithms: vec!["wand".into(), "maxscore".into()],
                 topics: vec![Topics::Trec {
                     path: tmp.path().join("topics"),
                     field: TopicField::Title,
+                    qrels: None,
                 }],
                 output: tmp.path().join("output.trec"),
                 scorer: default_scorer(),
                 compare_with: None,
+                normalize: QueryNormalization::default(),
+                query_options: QueryOptions::default(),
+                per_class: false,
+                class_margins: HashMap::new(),
+                per_language: false,
+                export_features: false,
+                perf_counters: false,
+                cold_and_warm: false,
+                load_time: false,
+                query_times: false,
+                min_measures: Default::default(),
+                max_avg_ms: None,
+                max_p95_ms: None,
+                env: Default::default(),
+                warmup: 0,
+                repeats: 1,
+                qps_margin: None,
+                source: None,
+                keep_raw_output: false,
+                timeout: None,
+                selective_search: None,
+                shard: None,
             },
             Run {
                 collection: "wapo".into(),
                 kind: RunKind::Evaluate {
                     qrels: tmp.path().join("qrels"),
+                    min_relevance: None,
                 },
                 encodings: vec!["block_simdbp".into()],
                 algorithms: vec!["wand".into(), "maxscore".into()],
                 topics: vec![Topics::Simple {
                     path: tmp.path().join("topics"),
+                    qrels: None,
                 }],
                 output: tmp.path().join("output.trec"),
                 scorer: default_scorer(),
                 compare_with: None,
+                normalize: QueryNormalization::default(),
+                query_options: QueryOptions::default(),
+                per_class: false,
+                class_margins: HashMap::new(),
+                per_language: false,
+                export_features: false,
+                perf_counters: false,
+                cold_and_warm: false,
+                load_time: false,
+                query_times: false,
+                min_measures: Default::default(),
+                max_avg_ms: None,
+                max_p95_ms: None,
+                env: Default::default(),
+                warmup: 0,
+                repeats: 1,
+                qps_margin: None,
+                source: None,
+                keep_raw_output: false,
+                timeout: None,
+                selective_search: None,
+                shard: None,
             },
             Run {
                 collection: "wapo".into(),
@@ -213,10 +419,33 @@ mod tests {
                 topics: vec![Topics::Trec {
                     path: tmp.path().join("topics"),
                     field: TopicField::Title,
+                    qrels: None,
                 }],
                 output: tmp.path().join("bench.json"),
                 scorer: default_scorer(),
                 compare_with: None,
+                normalize: QueryNormalization::default(),
+                query_options: QueryOptions::default(),
+                per_class: false,
+                class_margins: HashMap::new(),
+                per_language: false,
+                export_features: false,
+                perf_counters: false,
+                cold_and_warm: false,
+                load_time: false,
+                query_times: false,
+                min_measures: Default::default(),
+                max_avg_ms: None,
+                max_p95_ms: None,
+                env: Default::default(),
+                warmup: 0,
+                repeats: 1,
+                qps_margin: None,
+                source: None,
+                keep_raw_output: false,
+                timeout: None,
+                selective_search: None,
+                shard: None,
             },
         ];
 
@@ -284,6 +513,7 @@ mod tests {
             ),
         );
         std::fs::write(tmp.path().join("fwd.terms"), "term1\nterm2\nterm3\n").unwrap();
+        mkfiles(tmp.path(), &["fwd.termlex", "fwd.doclex"]).unwrap();
 
         mock_setup
     }
@@ -343,6 +573,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_dir_size() -> Result<(), Error> {
+        let tmp = TempDir::new("dir_size").unwrap();
+        fs::create_dir_all(tmp.path().join("a/b"))?;
+        fs::write(tmp.path().join("a/x"), "12345")?;
+        fs::write(tmp.path().join("a/b/y"), "123")?;
+        assert_eq!(dir_size(&tmp.path().join("a/x"))?, 5);
+        assert_eq!(dir_size(tmp.path())?, 8);
+        Ok(())
+    }
+
     #[test]
     fn test_make_echo() {
         let tmp = TempDir::new("echo").expect("Failed to create echo dir");
@@ -0,0 +1,172 @@
+//! Best-practice checks of a config, reporting questionable setups
+//! that are valid but likely to produce misleading results.
+
+use crate::config::{Config, Run, RunKind, Topics};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::PathBuf;
+
+/// Questionable setup found in a config.
+#[derive(Debug, PartialEq)]
+pub struct Lint {
+    /// What was found.
+    pub message: String,
+    /// Why it is questionable and how to fix it.
+    pub explanation: &'static str,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n  {}", self.message, self.explanation)
+    }
+}
+
+const ZERO_MARGIN: &str = "Any measurement noise will be reported as a regression; \
+                           use a small positive margin, e.g., 0.02.";
+const MISSING_BASELINE: &str = "Results are not compared with any baseline, \
+                                so regressions go unnoticed; define compare_with.";
+const MIXED_FIELDS: &str = "Efficiency is then measured on different queries than \
+                            effectiveness; use the same TREC field in both runs.";
+
+/// TREC topic files of a run with their fields, including sources of combined topics.
+fn trec_topics(run: &Run) -> Vec<(&PathBuf, String)> {
+    let mut topics = Vec::new();
+    for entry in &run.topics {
+        match entry {
+            Topics::Trec { path, field, .. } => topics.push((path, field.to_string())),
+            Topics::Combined { sources } => {
+                for source in sources {
+                    if let Topics::Trec { path, field, .. } = &source.topics {
+                        topics.push((path, field.to_string()));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    topics
+}
+
+/// Checks `config` against best practices, returning all findings.
+pub fn lint_config<C: Config>(config: &C) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    if config.margin().0 <= 0.0 {
+        lints.push(Lint {
+            message: format!("Regression margin is {}", config.margin().0),
+            explanation: ZERO_MARGIN,
+        });
+    }
+    let mut evaluate_fields: BTreeMap<&PathBuf, BTreeSet<String>> = BTreeMap::new();
+    let mut benchmark_fields: BTreeMap<&PathBuf, BTreeSet<String>> = BTreeMap::new();
+    for run in config.runs() {
+        let mut class_margins: Vec<_> = run
+            .class_margins
+            .iter()
+            .filter(|(_, margin)| margin.0 <= 0.0)
+            .map(|(class, _)| class.to_string())
+            .collect();
+        class_margins.sort();
+        for class in class_margins {
+            lints.push(Lint {
+                message: format!(
+                    "Regression margin of class {} is 0 in run: {}",
+                    class,
+                    run.output.display()
+                ),
+                explanation: ZERO_MARGIN,
+            });
+        }
+        if run.compare_with.is_none() {
+            lints.push(Lint {
+                message: format!("Missing compare_with in run: {}", run.output.display()),
+                explanation: MISSING_BASELINE,
+            });
+        }
+        let fields = match run.kind {
+            RunKind::Evaluate { .. } | RunKind::External { .. } => &mut evaluate_fields,
+            RunKind::Benchmark => &mut benchmark_fields,
+        };
+        for (path, field) in trec_topics(run) {
+            fields.entry(path).or_default().insert(field);
+        }
+    }
+    for (path, evaluate) in &evaluate_fields {
+        if let Some(benchmark) = benchmark_fields.get(path) {
+            if evaluate != benchmark {
+                lints.push(Lint {
+                    message: format!(
+                        "Topics {} are evaluated with field {} but benchmarked with field {}",
+                        path.display(),
+                        evaluate.iter().cloned().collect::<Vec<_>>().join(", "),
+                        benchmark.iter().cloned().collect::<Vec<_>>().join(", ")
+                    ),
+                    explanation: MIXED_FIELDS,
+                });
+            }
+        }
+    }
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{QueryClass, TopicField};
+    use crate::{RawConfig, RegressionMargin};
+
+    #[test]
+    fn test_lint_config() {
+        let mut config: RawConfig = serde_yaml::from_str(
+            "
+workdir: /workdir
+source: system
+margin: 0.0
+collections: []
+runs:
+    - collection: wapo
+      kind:
+          evaluate:
+              qrels: /qrels
+      output: eval
+      compare_with: /baseline/eval
+      topics:
+          - kind: trec
+            path: /topics
+            field: title
+    - collection: wapo
+      kind: benchmark
+      output: bench
+      topics:
+          - kind: trec
+            path: /topics
+            field: desc",
+        )
+        .unwrap();
+        config.runs[1]
+            .class_margins
+            .insert(QueryClass::One, RegressionMargin(0.0));
+        let messages: Vec<_> = lint_config(&config)
+            .into_iter()
+            .map(|lint| lint.message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Regression margin is 0",
+                "Regression margin of class 1 is 0 in run: bench",
+                "Missing compare_with in run: bench",
+                "Topics /topics are evaluated with field title but benchmarked with field desc",
+            ]
+        );
+
+        config.margin = RegressionMargin(0.02);
+        config.runs[1].class_margins.clear();
+        config.runs[1].compare_with = Some(PathBuf::from("/baseline/bench").into());
+        config.runs[1].topics = vec![Topics::Trec {
+            path: PathBuf::from("/topics"),
+            field: TopicField::Title,
+            qrels: None,
+        }];
+        assert!(lint_config(&config).is_empty());
+    }
+}
@@ -1,18 +1,165 @@
 use failure::ResultExt;
-use log::{error, info};
+use log::{error, info, warn};
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use std::{env, fs, mem, process};
-use stdbench::run::{compare_with_baseline, process_run, RunStatus};
+use stdbench::aa::aa_test;
+use stdbench::artifacts::Artifacts;
+use stdbench::bisect::bisect;
+use stdbench::build::BuildTimes;
+use stdbench::checkpoint::Checkpoints;
+use stdbench::config::{required_programs, required_tools, FailureInjection};
+use stdbench::database;
+use stdbench::describe::{describe, DescriptionFormat};
+use stdbench::gc::{self, History};
+use stdbench::guard::{HostLock, Isolation};
+use stdbench::host::HostInfo;
+use stdbench::identity::BaselineMeta;
+use stdbench::interrupt;
+use stdbench::lint::lint_config;
+use stdbench::merge::{collect_results, render_config_header, render_markdown};
+use stdbench::prometheus;
+use stdbench::reaggregate;
+use stdbench::remote;
+use stdbench::report::{color_enabled, render_summary_table, write_report};
+use stdbench::run::{
+    check_max_latency, check_min_measures, compare_with_baseline, process_run, verify_baseline,
+    write_run_ids, RunStatus,
+};
+use stdbench::status::{self, SharedProgress};
+use stdbench::summary::{
+    Badge, ConfigInfo, RunOutcome, RunSummary, Summary, WarningKind, BADGE_FILE,
+};
+use stdbench::temp::{run_id, RunTempDir};
+use stdbench::transcript::{replay, TranscriptBackend};
+use stdbench::version;
 use stdbench::{
-    CMakeVar, Collection, Config, Encoding, Error, RawConfig, ResolvedPathsConfig, Source, Stage,
+    ensure_parent_exists, ByteSize, CMakeVar, Collection, Config, ConfigVar, Encoding, Error,
+    ErrorKind, Executor, ProcessBackend, RawConfig, ResolvedPathsConfig, Run, RunKind, Source,
+    Stage, StageTimes,
 };
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 use strum::IntoEnumIterator;
 
 #[derive(StructOpt, Debug)]
-#[structopt(name = "PISA Regression Benchmark Suite")]
+enum Command {
+    /// Merges results collected on multiple hosts into a single report
+    #[structopt(name = "merge-results")]
+    MergeResults {
+        /// Work directories copied from the benchmarked hosts
+        #[structopt(parse(from_os_str), required = true)]
+        dirs: Vec<PathBuf>,
+
+        /// Additionally store merged results in a JSON file
+        #[structopt(long, parse(from_os_str))]
+        json: Option<PathBuf>,
+    },
+    /// Verifies that all baselines referenced by `compare_with` are complete,
+    /// without running anything
+    #[structopt(name = "verify-baselines")]
+    VerifyBaselines {
+        /// Configuration file path
+        #[structopt(long, parse(from_os_str))]
+        config: PathBuf,
+    },
+    /// Describes the experiment design of a config, e.g., for reproducibility appendices
+    #[structopt(name = "describe")]
+    Describe {
+        /// Configuration file path
+        #[structopt(long, parse(from_os_str))]
+        config: PathBuf,
+
+        /// Output format: `md` or `json`
+        #[structopt(long, default_value = "md")]
+        format: DescriptionFormat,
+    },
+    /// Reports questionable setups in a config, without running anything
+    #[structopt(name = "lint-config")]
+    LintConfig {
+        /// Configuration file path
+        #[structopt(long, parse(from_os_str))]
+        config: PathBuf,
+    },
+    /// Removes index artifacts not referenced by the config nor by recent sessions
+    #[structopt(name = "gc")]
+    Gc {
+        /// Configuration file path
+        #[structopt(long, parse(from_os_str))]
+        config: PathBuf,
+
+        /// Number of most recent sessions whose indexes are kept
+        #[structopt(long, default_value = "1")]
+        keep_last: usize,
+
+        /// Only list the artifacts that would be removed
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Executes every run twice and compares the results with each other,
+    /// reporting the rate of false-positive regressions (indexes must be built)
+    #[structopt(name = "aa-test")]
+    AaTest {
+        /// Configuration file path
+        #[structopt(long, parse(from_os_str))]
+        config: PathBuf,
+
+        /// Number of compared pairs of executions of each run
+        #[structopt(long, default_value = "1")]
+        pairs: usize,
+    },
+    /// Finds the first commit of a git source between a good and a bad one at which a run
+    /// regresses compared to its baseline (indexes must be built)
+    #[structopt(name = "bisect")]
+    Bisect {
+        /// Configuration file path
+        #[structopt(long, parse(from_os_str))]
+        config: PathBuf,
+
+        /// Index of the run in the config, counted from 0
+        #[structopt(long, default_value = "0")]
+        run: usize,
+
+        /// Last known commit without the regression
+        #[structopt(long)]
+        good: String,
+
+        /// Commit with the regression
+        #[structopt(long)]
+        bad: String,
+    },
+    /// Regenerates results, summary, and report of the last session in a work dir
+    /// from stored raw tool outputs, without executing PISA
+    #[structopt(name = "reaggregate")]
+    Reaggregate {
+        /// Work directory of the session
+        #[structopt(long, parse(from_os_str))]
+        workdir: PathBuf,
+    },
+    /// Re-executes the commands of a single stage recorded in a session transcript
+    #[structopt(name = "replay")]
+    Replay {
+        /// Transcript path, e.g., `workdir/.stdbench/transcript.json`
+        #[structopt(parse(from_os_str))]
+        transcript: PathBuf,
+
+        /// Stage to re-execute
+        #[structopt(long)]
+        stage: Stage,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "PISA Regression Benchmark Suite",
+    setting = AppSettings::SubcommandsNegateReqs
+)]
 struct Opt {
+    #[structopt(subcommand)]
+    command: Option<Command>,
+
     /// Prints all available stages
     #[structopt(long)]
     print_stages: bool,
@@ -21,10 +168,18 @@ struct Opt {
     #[structopt(long, parse(from_os_str), required_unless = "print-stages")]
     config_file: Option<PathBuf>,
 
-    /// Verbose mode (-v, -vv, -vvv, etc.)
+    /// Verbose mode of stdbench logs (-v, -vv, -vvv, etc.)
     #[structopt(short, long, parse(from_occurrences))]
     verbose: u8,
 
+    /// Log every executed command
+    #[structopt(long)]
+    show_commands: bool,
+
+    /// Show the output of PISA tools, such as `invert` or `compress`
+    #[structopt(long)]
+    show_tool_output: bool,
+
     /// Store logs in a file (PISA output excluded)
     #[structopt(long)]
     log: bool,
@@ -45,7 +200,48 @@ struct Opt {
     #[structopt(long)]
     clean: bool,
 
-    /// No --scorer in runs (for backwards compatibility)
+    /// Retain temporary files of failed runs for debugging
+    #[structopt(long)]
+    keep_temp: bool,
+
+    /// Skip build steps and runs completed in the previous (interrupted) session
+    #[structopt(long)]
+    resume: bool,
+
+    /// Rebuild the tools of a git source even if a build of the same commit is cached
+    #[structopt(long)]
+    force_rebuild: bool,
+
+    /// Print every command that would be executed, without running anything
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Write an HTML report of all results to this path
+    #[structopt(long, parse(from_os_str))]
+    report: Option<PathBuf>,
+
+    /// Write a JSON summary of the session to this path
+    #[structopt(long, parse(from_os_str))]
+    summary: Option<PathBuf>,
+
+    /// Push results as Prometheus metrics to the Pushgateway at this URL
+    #[structopt(long)]
+    push_metrics: Option<String>,
+
+    /// Write a shields.io badge of the session status to this path
+    #[structopt(long, parse(from_os_str))]
+    badge: Option<PathBuf>,
+
+    /// Replace absolute paths in reports and summaries with placeholders
+    #[structopt(long)]
+    redact_paths: bool,
+
+    /// Serve the execution state as JSON on this local port
+    #[structopt(long)]
+    status_port: Option<u16>,
+
+    /// No --scorer in runs (for backwards compatibility);
+    /// detected automatically when the PISA version is known
     #[structopt(long)]
     no_scorer: bool,
 
@@ -53,6 +249,22 @@ struct Opt {
     /// Only for git source.
     #[structopt(long = "cmake-vars")]
     cmake_vars: Vec<CMakeVar>,
+
+    /// Fail if the config contains unknown keys
+    #[structopt(long)]
+    strict_config: bool,
+
+    /// Overrides a config variable, e.g., `branch=dev`.
+    #[structopt(long = "set")]
+    vars: Vec<ConfigVar>,
+
+    /// Builds indexes on this host even if `build_host` is defined (used by the build host)
+    #[structopt(long, hidden = true)]
+    local_build: bool,
+
+    /// Forces a stage to fail, e.g., `stage=compress,collection=gov2` (for testing)
+    #[structopt(long = "inject-failure", hidden = true)]
+    injected_failures: Vec<FailureInjection>,
 }
 
 fn filter_collections(mut config: &mut RawConfig, collections: Vec<String>) {
@@ -101,18 +313,133 @@ fn filter_encodings(config: &mut RawConfig, encodings: Vec<Encoding>) {
     }
 }
 
+fn read_config(
+    config_file: &Path,
+    vars: &[ConfigVar],
+    strict_config: bool,
+) -> Result<RawConfig, Error> {
+    let yaml = fs::read_to_string(config_file)
+        .with_context(|_| format!("Failed to read config: {}", config_file.display()))
+        .map_err(|err| Error::from(err).wrap(ErrorKind::Config))?;
+    if strict_config {
+        RawConfig::parse_strict(&yaml, vars)
+    } else {
+        RawConfig::parse(&yaml, vars)
+    }
+}
+
+fn verify_baselines(config: &ResolvedPathsConfig) -> Result<(), Error> {
+    let mut broken = 0;
+    for run in config.runs() {
+        let problems = verify_baseline(run);
+        if !problems.is_empty() {
+            broken += 1;
+            error!("Broken baseline for run: {}", run.output.display());
+            for problem in problems {
+                error!("{}", problem);
+            }
+        }
+    }
+    if broken > 0 {
+        return Err(Error::from(format!("Found {} broken baselines", broken)));
+    }
+    info!("All baselines verified");
+    Ok(())
+}
+
+fn collect_garbage(
+    config: &ResolvedPathsConfig,
+    keep_last: usize,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let garbage = gc::garbage(config, keep_last)?;
+    let size = gc::freed_size(&garbage)?;
+    for path in &garbage {
+        if dry_run {
+            info!("Would remove: {}", path.display());
+        } else {
+            fs::remove_file(path)?;
+            info!("Removed: {}", path.display());
+        }
+    }
+    if !dry_run {
+        Artifacts::open(config.workdir())?.prune()?;
+    }
+    info!(
+        "Found {} unreferenced artifacts of total size {}",
+        garbage.len(),
+        ByteSize(size)
+    );
+    Ok(())
+}
+
+fn reaggregate_session(workdir: &Path) -> Result<(), Error> {
+    let config = reaggregate::load_config(workdir)?;
+    let regenerated = reaggregate::reaggregate(&config.0.system_executor()?, &config)?;
+    for path in &regenerated {
+        info!("Regenerated: {}", path.display());
+    }
+    let mut summary = Summary::read(config.workdir()).unwrap_or_default();
+    reaggregate::resummarize(&config, &mut summary)?;
+    if let Some(report) = config.report() {
+        write_report(&config, report)?;
+        info!("Report written to: {}", report.display());
+    }
+    let redacted;
+    let written = if config.redact_paths() {
+        redacted = summary.redacted(config.workdir());
+        &redacted
+    } else {
+        &summary
+    };
+    written.write(config.workdir())?;
+    if let Some(path) = config.summary() {
+        written.write_file(path)?;
+        info!("Summary written to: {}", path.display());
+    }
+    let badge = Badge::from_summary(&summary);
+    badge.write(&config.workdir().join(BADGE_FILE))?;
+    if let Some(path) = config.badge() {
+        badge.write(path)?;
+    }
+    info!(
+        "Regenerated {} result files with {} regressions",
+        regenerated.len(),
+        summary.regressions
+    );
+    print!("{}", render_summary_table(&config, color_enabled())?);
+    Ok(())
+}
+
 fn parse_config(args: Vec<String>, init_log: bool) -> Result<Option<ResolvedPathsConfig>, Error> {
     let Opt {
         config_file,
         verbose,
+        show_commands,
+        show_tool_output,
         log,
         print_stages,
         suppress,
         collections,
         encodings,
         clean,
+        keep_temp,
+        resume,
+        force_rebuild,
+        dry_run,
+        report,
+        summary,
+        push_metrics,
+        badge,
+        redact_paths,
+        status_port,
         no_scorer,
         cmake_vars,
+        vars,
+        strict_config,
+        local_build,
+        injected_failures,
+        command,
     } = Opt::from_iter_safe(&args).unwrap_or_else(|err| err.exit());
     if init_log {
         let log_level = match verbose {
@@ -120,7 +447,15 @@ fn parse_config(args: Vec<String>, init_log: bool) -> Result<Option<ResolvedPath
             1 => "debug",
             _ => "trace",
         };
-        let logger = flexi_logger::Logger::with_env_or_str(log_level);
+        // Executed commands are logged with their own target, shown only on request.
+        let commands_level = if show_commands { "info" } else { "off" };
+        let spec = format!(
+            "{}, {}={}",
+            log_level,
+            stdbench::COMMANDS_LOG_TARGET,
+            commands_level
+        );
+        let logger = flexi_logger::Logger::with_env_or_str(&spec);
         if log {
             logger
                 .log_to_file()
@@ -137,9 +472,77 @@ fn parse_config(args: Vec<String>, init_log: bool) -> Result<Option<ResolvedPath
         }
         return Ok(None);
     }
+    match command {
+        Some(Command::MergeResults { dirs, json }) => {
+            let records = collect_results(&dirs)?;
+            print!("{}", render_config_header(&dirs, redact_paths));
+            print!("{}", render_markdown(&records));
+            if let Some(json) = json {
+                let output =
+                    serde_json::to_string_pretty(&records).context("Failed to serialize")?;
+                fs::write(json, output)?;
+            }
+            return Ok(None);
+        }
+        Some(Command::VerifyBaselines { config }) => {
+            let config = read_config(&config, &vars, strict_config)?;
+            verify_baselines(&ResolvedPathsConfig::from(config)?)?;
+            return Ok(None);
+        }
+        Some(Command::Describe { config, format }) => {
+            let config = ResolvedPathsConfig::from(read_config(&config, &vars, strict_config)?)?;
+            let redact = redact_paths || config.redact_paths();
+            print!("{}", describe(&config, format, redact)?);
+            return Ok(None);
+        }
+        Some(Command::LintConfig { config }) => {
+            let lints = lint_config(&read_config(&config, &vars, strict_config)?);
+            for lint in &lints {
+                warn!("{}", lint);
+            }
+            info!("Found {} problems", lints.len());
+            return Ok(None);
+        }
+        Some(Command::Gc {
+            config,
+            keep_last,
+            dry_run,
+        }) => {
+            let config = ResolvedPathsConfig::from(read_config(&config, &vars, strict_config)?)?;
+            collect_garbage(&config, keep_last, dry_run)?;
+            return Ok(None);
+        }
+        Some(Command::AaTest { config, pairs }) => {
+            let config = ResolvedPathsConfig::from(read_config(&config, &vars, strict_config)?)?;
+            let report = aa_test(&config.executor()?, &config, pairs)?;
+            print!("{}", report.render_markdown());
+            return Ok(None);
+        }
+        Some(Command::Bisect {
+            config,
+            run,
+            good,
+            bad,
+        }) => {
+            let config = ResolvedPathsConfig::from(read_config(&config, &vars, strict_config)?)?;
+            match bisect(&config, run, &good, &bad)? {
+                Some(commit) => println!("First bad commit: {}", commit),
+                None => println!("No regression at: {}", bad),
+            }
+            return Ok(None);
+        }
+        Some(Command::Reaggregate { workdir }) => {
+            reaggregate_session(&workdir)?;
+            return Ok(None);
+        }
+        Some(Command::Replay { transcript, stage }) => {
+            replay(&transcript, stage, &Executor::new())?;
+            return Ok(None);
+        }
+        None => {}
+    }
     info!("Parsing config");
-    let mut config: RawConfig = serde_yaml::from_reader(fs::File::open(config_file.unwrap())?)
-        .context("Failed to parse config")?;
+    let mut config = read_config(&config_file.unwrap(), &vars, strict_config)?;
     for stage in suppress {
         config.disable(stage);
     }
@@ -160,8 +563,48 @@ fn parse_config(args: Vec<String>, init_log: bool) -> Result<Option<ResolvedPath
     if clean {
         config.clean = true;
     }
+    if keep_temp {
+        config.keep_temp = true;
+    }
+    if resume {
+        config.resume = true;
+    }
+    if force_rebuild {
+        config.force_rebuild = true;
+    }
+    config.injected_failures = injected_failures;
+    if report.is_some() {
+        config.report = report;
+    }
+    if summary.is_some() {
+        config.summary = summary;
+    }
+    if push_metrics.is_some() {
+        config.push_metrics = push_metrics;
+    }
+    if badge.is_some() {
+        config.badge = badge;
+    }
+    if redact_paths {
+        config.redact_paths = true;
+    }
+    if show_tool_output {
+        config.show_tool_output = true;
+    }
+    if status_port.is_some() {
+        config.status_port = status_port;
+    }
+    if local_build {
+        config.build_host = None;
+    }
     let mut config = ResolvedPathsConfig::from(config)?;
     filter_encodings(&mut config.0, encodings);
+    if dry_run {
+        for command in stdbench::plan::session(&config)? {
+            println!("{}", command);
+        }
+        return Ok(None);
+    }
     Ok(Some(config))
 }
 
@@ -170,7 +613,29 @@ enum FinalStatus {
     FailedRuns {
         undefined_collections: Vec<String>,
         regressions: Vec<usize>,
+        budget_exceeded: bool,
     },
+    Interrupted,
+}
+
+/// Checks the work directory size against its budget, if defined,
+/// and records a warning when exceeded.
+fn budget_exceeded(config: &ResolvedPathsConfig, summary: &mut Summary) -> Result<bool, Error> {
+    if let Some(budget) = config.max_workdir_size() {
+        let size = stdbench::dir_size(config.workdir())?;
+        if size > budget.0 {
+            summary.warn(
+                WarningKind::WorkdirBudget,
+                format!(
+                    "Work directory size {} exceeds budget {}; no new builds or runs scheduled",
+                    ByteSize(size),
+                    budget
+                ),
+            );
+            return Ok(true);
+        }
+    }
+    Ok(false)
 }
 
 #[cfg_attr(tarpaulin, skip)]
@@ -179,32 +644,259 @@ fn run() -> Result<FinalStatus, Error> {
     if config.is_none() {
         return Ok(FinalStatus::Success);
     }
-    let config = config.unwrap();
+    let mut config = config.unwrap();
     info!("Config: {:?}", &config);
+    interrupt::install()?;
 
     if config.clean() {
         std::fs::remove_dir_all(&config.workdir())?;
     }
+    let host = HostInfo::detect();
+    host.write(config.workdir())?;
+    let checkpoints = Checkpoints::open(config.workdir(), config.resume())?;
+    History::record(&config)?;
+    reaggregate::save_config(&config)?;
+    let mut summary = Summary::default();
+    let progress = SharedProgress::default();
+    if let Some(port) = config.status_port() {
+        status::serve(port, progress.clone())?;
+    }
+    match session(&mut config, &host, &checkpoints, &mut summary, &progress) {
+        Err(err) if interrupt::interrupted() => {
+            info!("Session stopped: {}", err);
+            save_interrupted(&config, &checkpoints, &mut summary, &progress)?;
+            Ok(FinalStatus::Interrupted)
+        }
+        status => status,
+    }
+}
 
-    let executor = config.executor()?;
-    info!("Executor ready");
+/// Writes the summary of the session to the work dir and to the `--summary` path, if any.
+fn write_summary(config: &ResolvedPathsConfig, summary: &Summary) -> Result<(), Error> {
+    let redacted;
+    let written = if config.redact_paths() {
+        redacted = summary.redacted(config.workdir());
+        &redacted
+    } else {
+        summary
+    };
+    written.write(config.workdir())?;
+    if let Some(path) = config.summary() {
+        written.write_file(path)?;
+        info!("Summary written to: {}", path.display());
+    }
+    Ok(())
+}
 
+/// Saves the partial results of an interrupted session: the summary of the runs
+/// completed so far, and the checkpoints from which the session can be resumed.
+fn save_interrupted(
+    config: &ResolvedPathsConfig,
+    checkpoints: &Checkpoints,
+    summary: &mut Summary,
+    progress: &SharedProgress,
+) -> Result<(), Error> {
+    summary.interrupted = true;
+    if summary.runs.is_empty() {
+        summary.runs = config.runs().iter().map(RunSummary::new).collect();
+    }
+    if let Some(run) = progress
+        .get()
+        .current_run
+        .and_then(|idx| summary.runs.get_mut(idx))
+    {
+        run.outcome = RunOutcome::Interrupted;
+    }
+    checkpoints.flush()?;
+    write_summary(config, summary)?;
+    progress.update(|progress| progress.runs = summary.runs.clone());
+    Ok(())
+}
+
+/// Compiles the tools, builds the collections, and executes and compares the runs
+/// of a session, recording its outcome in `summary`.
+#[cfg_attr(tarpaulin, skip)]
+fn session(
+    config: &mut ResolvedPathsConfig,
+    host: &HostInfo,
+    checkpoints: &Checkpoints,
+    summary: &mut Summary,
+    progress: &SharedProgress,
+) -> Result<FinalStatus, Error> {
+    let publish = |stage: Option<Stage>, current_run: Option<usize>, summary: &Summary| {
+        progress.update(|progress| {
+            progress.stage = stage;
+            progress.current_run = current_run;
+            progress.runs = summary.runs.clone();
+            progress.warnings = summary.warnings.len();
+        });
+    };
+    for key in &config.0.unknown_keys {
+        summary.warn(
+            WarningKind::UnknownConfigKey,
+            format!("Unknown config key ignored: {}", key),
+        );
+    }
+    if config.enabled(Stage::Run)
+        && config
+            .runs()
+            .iter()
+            .any(|run| !matches!(run.kind, RunKind::Evaluate { .. }))
+    {
+        let issues = host.environment_issues();
+        if config.strict_environment() && !issues.is_empty() {
+            return Err(Error::from(format!(
+                "Unstable measurement environment: {}",
+                issues.join("; ")
+            )));
+        }
+        for issue in issues {
+            summary.warn(WarningKind::MeasurementEnvironment, issue);
+        }
+    }
+    for stage in Stage::iter().filter(|&stage| !config.enabled(stage)) {
+        summary.warn(
+            WarningKind::SuppressedStage,
+            format!("Stage suppressed: {}", stage),
+        );
+    }
     for collection in config.collections() {
-        stdbench::build::collection(&executor, collection, &config)?;
+        for stage in Stage::iter()
+            .filter(|&stage| config.enabled(stage) && !config.enabled_for(collection, stage))
+        {
+            summary.warn(
+                WarningKind::SuppressedStage,
+                format!("Stage suppressed for {}: {}", collection.name, stage),
+            );
+        }
+    }
+
+    publish(Some(Stage::Compile), None, summary);
+    config.check_injected_failure(Stage::Compile, None)?;
+    let transcript =
+        TranscriptBackend::open(config.workdir(), config.resume(), Arc::new(ProcessBackend))?
+            .with_tool_overrides(config.0.tool_overrides.clone());
+    let backend = Arc::new(transcript);
+    let executor = config.executor()?;
+    executor.check_tools(&required_tools(config, None))?;
+    executor.check_programs(&required_programs(config))?;
+    let pisa_version = version::detect(&executor, config.source(), config.workdir());
+    if let Some(pisa_version) = pisa_version {
+        info!("PISA version: {}", pisa_version);
     }
+    for warning in version::check_compatibility(&mut config.0, pisa_version)? {
+        summary.warn(WarningKind::PisaVersion, warning);
+    }
+    let config: &ResolvedPathsConfig = config;
+    let executor = executor.with_backend(backend.clone());
+    let mut source_executors: HashMap<&str, Executor> = HashMap::new();
+    for name in config.0.sources.keys() {
+        info!("Compiling source: {}", name);
+        let source_executor = config.source_executor(name)?;
+        source_executor.check_tools(&required_tools(config, Some(name.as_str())))?;
+        source_executors.insert(name, source_executor.with_backend(backend.clone()));
+    }
+    info!("Executor ready");
+    summary.config = Some(ConfigInfo::from_config(config));
+    let commit = summary.config.as_ref().and_then(|info| info.commit.clone());
+
+    publish(Some(Stage::BuildIndex), None, summary);
+    let mut exceeded = if let Some(build_host) = config.build_host() {
+        let args: Vec<String> = env::args().skip(1).collect();
+        remote::build(build_host, &args, config)?;
+        budget_exceeded(config, summary)?
+    } else {
+        let summary = Mutex::new(&mut *summary);
+        stdbench::build::collections(&executor, config, checkpoints, || {
+            budget_exceeded(config, &mut summary.lock().unwrap())
+        })?
+    };
     let collections: HashMap<String, &Collection> = config
         .collections()
         .iter()
         .map(|c| (c.name.to_string(), c))
         .collect();
+    let artifacts = Artifacts::open(config.workdir())?;
+    let mut skipped_runs: HashSet<usize> = HashSet::new();
+    summary.runs = config.runs().iter().map(RunSummary::new).collect();
     let undefined_collections = {
         let mut undefined_collections: Vec<String> = Vec::new();
         if config.enabled(Stage::Run) {
-            for run in config.runs() {
-                if let Some(collection) = &collections.get(&run.collection) {
+            for (idx, run) in config.runs().iter().enumerate() {
+                if exceeded {
+                    skipped_runs.insert(idx);
+                } else if let Some(collection) = &collections.get(&run.collection) {
+                    let run_id = run_id(idx, run);
+                    if checkpoints.run_done(&run_id) {
+                        info!("Run completed in previous session: {}", run_id);
+                        summary.runs[idx].outcome = RunOutcome::Resumed;
+                        continue;
+                    }
                     info!("Processing run: {:?}", run);
-                    process_run(&executor, run, collection, config.use_scorer())?;
+                    publish(Some(Stage::Run), Some(idx), summary);
+                    let temp_dir = RunTempDir::create(config.workdir(), &run_id)?;
+                    let _lock = HostLock::acquire(&config.host_lock(), Isolation::for_run(run))?;
+                    let start = Instant::now();
+                    let run_executor = |run: &Run| {
+                        run.source
+                            .as_ref()
+                            .map_or(&executor, |source| &source_executors[source.as_str()])
+                    };
+                    let warnings = config
+                        .check_injected_failure(Stage::Run, Some(&run.collection))
+                        .and_then(|()| {
+                            process_run(
+                                run_executor(run),
+                                run,
+                                collection,
+                                config.use_scorer(),
+                                temp_dir.path(),
+                            )
+                        })
+                        .and_then(|mut warnings| {
+                            if let Some(baseline_run) = run.source_baseline() {
+                                info!("Producing baseline: {}", baseline_run.output.display());
+                                ensure_parent_exists(&baseline_run.output)?;
+                                warnings.extend(process_run(
+                                    run_executor(&baseline_run),
+                                    &baseline_run,
+                                    collection,
+                                    config.use_scorer(),
+                                    temp_dir.path(),
+                                )?);
+                                BaselineMeta::new(&baseline_run, None, config.margin())?
+                                    .write(&baseline_run.output)?;
+                            }
+                            Ok(warnings)
+                        });
+                    temp_dir.finish(warnings.is_err(), config.keep_temp())?;
+                    for warning in warnings? {
+                        summary.push(warning);
+                    }
+                    summary.runs[idx].outcome = RunOutcome::Completed;
+                    summary.runs[idx].seconds = Some(start.elapsed().as_secs_f64());
+                    write_run_ids(run, config.use_scorer(), commit.as_deref())?;
+                    BaselineMeta::new(run, commit.as_deref(), config.margin())?
+                        .write(&run.output)?;
+                    artifacts.record_run(&run_id, run)?;
+                    if let Some(baseline_run) = run.source_baseline() {
+                        artifacts.record_run(
+                            &stdbench::temp::run_id(idx, &baseline_run),
+                            &baseline_run,
+                        )?;
+                    }
+                    summary.runs[idx].files = artifacts.run_files(&run_id);
+                    publish(Some(Stage::Run), None, summary);
+                    if let Some(db) = config.results_db() {
+                        database::record(db, run, commit.as_deref())?;
+                    }
+                    checkpoints.complete_run(&run_id)?;
+                    exceeded = budget_exceeded(config, summary)?;
                 } else {
+                    summary.warn(
+                        WarningKind::SkippedCollection,
+                        format!("Run skipped, undefined collection: {}", run.collection),
+                    );
                     undefined_collections.push(run.collection.clone())
                 }
             }
@@ -214,25 +906,136 @@ fn run() -> Result<FinalStatus, Error> {
     let regressions = {
         let mut regressions: Vec<usize> = Vec::new();
         if config.enabled(Stage::Compare) {
-            for run in config.runs() {
-                if let Some(compare_with) = &run.compare_with {
-                    match compare_with_baseline(&executor, run, compare_with, config.margin())? {
+            publish(Some(Stage::Compare), None, summary);
+            if let Some(baseline_dir) = config.compare_build_times_with() {
+                for collection in config.collections() {
+                    let path = BuildTimes::path(config.workdir(), &collection.name);
+                    let baseline_path = BuildTimes::path(baseline_dir, &collection.name);
+                    if !path.exists() || !baseline_path.exists() {
+                        summary.warn(
+                            WarningKind::IgnoredComparison,
+                            format!("Build times not available for: {}", collection.name),
+                        );
+                        continue;
+                    }
+                    let times = BuildTimes::load(&path)?;
+                    let baseline = BuildTimes::load(&baseline_path)?;
+                    let slower = times.regressions(&baseline, config.margin());
+                    if !slower.is_empty() {
+                        eprintln!("Detected build time regression!");
+                        eprintln!("file: {}", path.display());
+                        eprintln!("base: {}", baseline_path.display());
+                        for (step, base, time) in &slower {
+                            eprintln!("{}: {} --> {}", step, base, time);
+                        }
+                        summary.build_time_regressions += slower.len();
+                        regressions.push(slower.len());
+                    }
+                }
+            }
+            for (idx, run) in config.runs().iter().enumerate() {
+                if skipped_runs.contains(&idx) && run.compare_with.is_some() {
+                    summary.warn(
+                        WarningKind::IgnoredComparison,
+                        format!("Comparison skipped for run: {}", run.output.display()),
+                    );
+                } else if let Some(baseline) = &run.compare_with {
+                    config.check_injected_failure(Stage::Compare, Some(&run.collection))?;
+                    match compare_with_baseline(run, &baseline.path, config.margin())? {
                         RunStatus::Success => {}
                         RunStatus::Regression(count) => {
+                            summary.runs[idx].regressions += count;
                             regressions.push(count);
                         }
                     }
                 }
+                if !run.min_measures.is_empty()
+                    && !skipped_runs.contains(&idx)
+                    && collections.contains_key(&run.collection)
+                {
+                    if let RunStatus::Regression(count) = check_min_measures(run)? {
+                        summary.runs[idx].regressions += count;
+                        regressions.push(count);
+                    }
+                }
+                if (run.max_avg_ms.is_some() || run.max_p95_ms.is_some())
+                    && !skipped_runs.contains(&idx)
+                    && collections.contains_key(&run.collection)
+                {
+                    if let RunStatus::Regression(count) = check_max_latency(run)? {
+                        summary.runs[idx].regressions += count;
+                        regressions.push(count);
+                    }
+                }
+            }
+        } else {
+            for run in config
+                .runs()
+                .iter()
+                .filter(|run| run.compare_with.is_some())
+            {
+                summary.warn(
+                    WarningKind::IgnoredComparison,
+                    format!("Comparison suppressed for run: {}", run.output.display()),
+                );
             }
         }
         regressions
     };
-    if undefined_collections.is_empty() && regressions.is_empty() {
+    if let Some(report) = config.report() {
+        write_report(config, report)?;
+        info!("Report written to: {}", report.display());
+    }
+    if let Some(url) = config.push_metrics() {
+        if let Err(err) = prometheus::push_metrics(config, url, commit.as_deref()) {
+            warn!("Failed to push metrics to {}: {}", url, err);
+        }
+    }
+    let stage_times = executor.stage_times();
+    for stage in Stage::iter() {
+        if let Some(usage) = stage_times.0.get(&stage) {
+            info!(
+                "Stage {}: {} commands in {:.1}s",
+                stage, usage.commands, usage.seconds
+            );
+        }
+    }
+    stage_times.write(&StageTimes::path(config.workdir()))?;
+    for collection in config.collections() {
+        let path = BuildTimes::path(config.workdir(), &collection.name);
+        if path.exists() {
+            summary
+                .build_times
+                .insert(collection.name.clone(), BuildTimes::load(&path)?.0);
+        }
+    }
+    for (idx, (run_summary, run)) in summary.runs.iter_mut().zip(config.runs()).enumerate() {
+        run_summary.files = artifacts.run_files(&run_id(idx, run));
+    }
+    summary.regressions = regressions.iter().sum();
+    write_summary(config, summary)?;
+    let badge = Badge::from_summary(summary);
+    badge.write(&config.workdir().join(BADGE_FILE))?;
+    if let Some(path) = config.badge() {
+        badge.write(path)?;
+        info!("Badge written to: {}", path.display());
+    }
+    if !summary.warnings.is_empty() {
+        warn!("Finished with {} warnings:", summary.warnings.len());
+        for warning in &summary.warnings {
+            warn!("{}", warning);
+        }
+    }
+    print!("{}", render_summary_table(config, color_enabled())?);
+    publish(None, None, summary);
+    progress.update(|progress| progress.finished = true);
+    if undefined_collections.is_empty() && regressions.is_empty() && !exceeded {
         Ok(FinalStatus::Success)
     } else {
         Ok(FinalStatus::FailedRuns {
             undefined_collections,
             regressions,
+            budget_exceeded: exceeded,
         })
     }
 }
@@ -242,6 +1045,9 @@ fn main() {
     match run() {
         Err(err) => {
             error!("{}", err);
+            if let Some(hint) = err.hint() {
+                error!("Hint: {}", hint);
+            }
             process::exit(1);
         }
         Ok(FinalStatus::Success) => {
@@ -250,7 +1056,11 @@ fn main() {
         Ok(FinalStatus::FailedRuns {
             undefined_collections,
             regressions,
+            budget_exceeded,
         }) => {
+            if budget_exceeded {
+                error!("Stopped early: work directory size budget exceeded");
+            }
             for name in undefined_collections {
                 error!("Undefined collection: {}", name)
             }
@@ -263,6 +1073,10 @@ fn main() {
             }
             process::exit(1);
         }
+        Ok(FinalStatus::Interrupted) => {
+            error!("Interrupted; pass --resume to continue the session");
+            process::exit(interrupt::EXIT_CODE);
+        }
     }
 }
 
@@ -272,7 +1086,10 @@ mod test {
 
     use super::*;
     use std::fs;
-    use stdbench::config::{CollectionKind, Scorer};
+    use stdbench::config::{
+        BatchSizesOverride, Builder, CollectionKind, QueryNormalization, QueryOptions, Scorer,
+        Threads,
+    };
     use stdbench::{Run, RunKind};
     use tempdir::TempDir;
 
@@ -324,6 +1141,8 @@ collections:
                 config_file.to_str().unwrap(),
                 "--suppress",
                 "compile",
+                "--inject-failure",
+                "stage=wand,collection=wapo2",
             ]
             .into_iter()
             .map(|&s| String::from(s))
@@ -333,6 +1152,16 @@ collections:
         .unwrap();
         assert!(!conf.enabled(Stage::Compile));
         assert!(conf.use_scorer());
+        assert!(conf
+            .check_injected_failure(Stage::Wand, Some("wapo"))
+            .is_ok());
+        assert_eq!(
+            conf.check_injected_failure(Stage::Wand, Some("wapo2"))
+                .err()
+                .unwrap()
+                .to_string(),
+            "Injected failure of wand for wapo2"
+        );
 
         let conf = parse_config(
             [
@@ -342,6 +1171,7 @@ collections:
                 "--collections",
                 "wapo2",
                 "--no-scorer",
+                "--show-tool-output",
             ]
             .into_iter()
             .map(|&s| String::from(s))
@@ -352,6 +1182,7 @@ collections:
         let colnames: Vec<_> = conf.collections().iter().map(|c| c.name.clone()).collect();
         assert_eq!(colnames, vec!["wapo2".to_string()]);
         assert_eq!(conf.use_scorer(), false);
+        assert!(conf.0.show_tool_output);
 
         assert!(parse_config(
             ["exe", "--print-stages"]
@@ -361,6 +1192,51 @@ collections:
             false
         )?
         .is_none());
+
+        let json = tmp.path().join("merged.json");
+        assert!(parse_config(
+            [
+                "exe",
+                "merge-results",
+                tmp.path().to_str().unwrap(),
+                "--json",
+                json.to_str().unwrap()
+            ]
+            .iter()
+            .map(|&s| String::from(s))
+            .collect(),
+            false
+        )?
+        .is_none());
+        assert_eq!(fs::read_to_string(json)?, "[]");
+
+        assert!(parse_config(
+            [
+                "exe",
+                "lint-config",
+                "--config",
+                config_file.to_str().unwrap()
+            ]
+            .iter()
+            .map(|&s| String::from(s))
+            .collect(),
+            false
+        )?
+        .is_none());
+
+        assert!(parse_config(
+            [
+                "exe",
+                "verify-baselines",
+                "--config",
+                config_file.to_str().unwrap()
+            ]
+            .iter()
+            .map(|&s| String::from(s))
+            .collect(),
+            false
+        )?
+        .is_none());
         Ok(())
     }
 
@@ -381,6 +1257,18 @@ collections:
                     Encoding::from("pefopt"),
                 ],
                 scorers: vec![Scorer::from("bm25")],
+                batch_sizes: BatchSizesOverride::default(),
+                threads: Threads::default(),
+                input_glob: None,
+                preset: None,
+                builder: Builder::Pisa,
+                stages: Default::default(),
+                stemmer: None,
+                sample_documents: None,
+                shards: 1,
+                shard: None,
+                quantize: false,
+                download: vec![],
             }],
             runs: vec![Run {
                 collection: "Col01".to_string(),
@@ -395,6 +1283,28 @@ collections:
                 topics: vec![],
                 scorer: Scorer::from("bm25"),
                 compare_with: None,
+                normalize: QueryNormalization::default(),
+                query_options: QueryOptions::default(),
+                per_class: false,
+                class_margins: HashMap::new(),
+                per_language: false,
+                export_features: false,
+                perf_counters: false,
+                cold_and_warm: false,
+                load_time: false,
+                query_times: false,
+                min_measures: Default::default(),
+                max_avg_ms: None,
+                max_p95_ms: None,
+                env: Default::default(),
+                warmup: 0,
+                repeats: 1,
+                qps_margin: None,
+                source: None,
+                keep_raw_output: false,
+                timeout: None,
+                selective_search: None,
+                shard: None,
             }],
             ..RawConfig::default()
         };
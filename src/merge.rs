@@ -0,0 +1,275 @@
+//! Merging benchmark results produced on multiple hosts into a single report.
+
+use crate::artifacts::{ArtifactKind, Artifacts};
+use crate::error::Error;
+use crate::host::HostInfo;
+use crate::run::load_benchmark_results;
+use crate::summary::{redact_paths, Summary};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Single benchmark result tagged with the host that produced it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HostRecord {
+    /// Host name.
+    pub host: String,
+    /// Hardware description.
+    pub hardware: String,
+    /// Result file path relative to the result directory.
+    pub file: String,
+    /// Identity of the measured configuration, if recorded
+    /// (see [`RunIdentity`](../identity/struct.RunIdentity.html)).
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Posting list encoding.
+    pub encoding: String,
+    /// Query processing algorithm.
+    pub algorithm: String,
+    /// Average query time.
+    pub avg: f32,
+    /// 50th percentile of query times, unless not measured, as in external runs.
+    #[serde(default)]
+    pub q50: Option<f32>,
+    /// 90th percentile of query times, unless not measured.
+    #[serde(default)]
+    pub q90: Option<f32>,
+    /// 95th percentile of query times, unless not measured.
+    #[serde(default)]
+    pub q95: Option<f32>,
+}
+
+fn dir_name(dir: &Path) -> String {
+    dir.file_name().map_or_else(
+        || dir.display().to_string(),
+        |n| n.to_string_lossy().to_string(),
+    )
+}
+
+/// Lists the benchmark results in `dir`: those in its
+/// [inventory of artifacts](../artifacts/index.html) if it has one,
+/// or otherwise all files with a `bench` suffix.
+fn bench_files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    if Artifacts::path(dir).exists() {
+        let artifacts = Artifacts::open(dir)?;
+        let mut files: Vec<_> = artifacts
+            .list()
+            .iter()
+            .filter(|artifact| artifact.kind == ArtifactKind::BenchmarkResults)
+            .map(|artifact| artifacts.resolve(artifact))
+            .filter(|path| path.is_file())
+            .collect();
+        files.sort();
+        files.dedup();
+        return Ok(files);
+    }
+    let pattern = dir.join("**").join("*.bench*");
+    let mut files: Vec<_> = glob::glob(pattern.to_str().unwrap())
+        .unwrap()
+        .filter_map(Result::ok)
+        .filter(|p| p.is_file())
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Collects benchmark results from directories produced on different hosts.
+///
+/// Each directory is expected to contain `host.json` written at the beginning
+/// of a benchmark session; if missing, the directory name is used as the host name.
+/// Directories with an [inventory of artifacts](../artifacts/index.html) contribute
+/// the benchmark results it lists.
+///
+/// Records are joined by their identities, falling back on file names for results
+/// without one, so that the same configurations are adjacent even if their files differ.
+pub fn collect_results(dirs: &[PathBuf]) -> Result<Vec<HostRecord>, Error> {
+    let mut records = Vec::new();
+    for dir in dirs {
+        let host = HostInfo::read(dir).unwrap_or_else(|_| {
+            warn!("Missing host info in {}", dir.display());
+            HostInfo {
+                hostname: dir_name(dir),
+                ..HostInfo::default()
+            }
+        });
+        for file in bench_files(dir)? {
+            let results = load_benchmark_results(&file)?;
+            records.push(HostRecord {
+                host: host.hostname.clone(),
+                hardware: host.hardware(),
+                file: file
+                    .strip_prefix(dir)
+                    .unwrap_or(&file)
+                    .display()
+                    .to_string(),
+                id: results.id,
+                encoding: results.kind.to_string(),
+                algorithm: results.algorithm.to_string(),
+                avg: results.avg_time,
+                q50: results.quantile_50,
+                q90: results.quantile_90,
+                q95: results.quantile_95,
+            });
+        }
+    }
+    records.sort_by_cached_key(|r| {
+        (
+            r.id.clone().unwrap_or_else(|| r.file.clone()),
+            r.host.clone(),
+        )
+    });
+    Ok(records)
+}
+
+/// Renders the configurations of the merged sessions, stored in their `summary.json`,
+/// as a Markdown report header. Directories without the configuration are skipped.
+///
+/// If `redact` is `true`, absolute paths are replaced with placeholders.
+pub fn render_config_header(dirs: &[PathBuf], redact: bool) -> String {
+    let mut header = String::new();
+    for dir in dirs {
+        if let Some(config) = Summary::read(dir).ok().and_then(|summary| summary.config) {
+            let host = HostInfo::read(dir).map_or_else(|_| dir_name(dir), |h| h.hostname);
+            header.push_str(&format!("### {}\n\n", host));
+            let config = config.render_markdown();
+            if redact {
+                header.push_str(&redact_paths(&config, dir));
+            } else {
+                header.push_str(&config);
+            }
+            header.push('\n');
+        }
+    }
+    header
+}
+
+/// Renders the merged results as a Markdown table with hardware as a dimension.
+pub fn render_markdown(records: &[HostRecord]) -> String {
+    let mut table = vec![
+        String::from("| file | encoding | algorithm | host | hardware | avg | q50 | q90 | q95 |"),
+        String::from("|---|---|---|---|---|---|---|---|---|"),
+    ];
+    let quantile = |q: Option<f32>| q.map_or_else(|| String::from("-"), |q| q.to_string());
+    for r in records {
+        table.push(format!(
+            "| {} | {} | {} | {} | {} | {} | {} | {} | {} |",
+            r.file,
+            r.encoding,
+            r.algorithm,
+            r.host,
+            r.hardware,
+            r.avg,
+            quantile(r.q50),
+            quantile(r.q90),
+            quantile(r.q95)
+        ));
+    }
+    table.push(String::new());
+    table.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summary::ConfigInfo;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_merge_results() -> Result<(), Error> {
+        let tmp = TempDir::new("merge").unwrap();
+        let host1 = tmp.path().join("host1");
+        let host2 = tmp.path().join("host2");
+        fs::create_dir_all(host1.join("runs"))?;
+        fs::create_dir_all(&host2)?;
+        HostInfo {
+            hostname: String::from("bench1"),
+            cpu: Some(String::from("Xeon")),
+            cpus: Some(4),
+            memory_kb: None,
+            ..HostInfo::default()
+        }
+        .write(&host1)?;
+        let bench = |avg: f32| {
+            format!(
+                r#"{{"type":"ef","query":"wand","avg":{0},"q50":{0},"q90":{0},"q95":{0}}}"#,
+                avg
+            )
+        };
+        fs::write(host1.join("runs/out.wand.ef.0.bench"), bench(1.0))?;
+        fs::write(host2.join("out.wand.ef.0.bench"), bench(2.0))?;
+        let records = collect_results(&[host1.clone(), host2.clone()])?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].host, "host2");
+        assert_eq!(records[0].hardware, "unknown");
+        assert_eq!(records[0].file, "out.wand.ef.0.bench");
+        assert_eq!(records[1].host, "bench1");
+        assert_eq!(records[1].hardware, "Xeon x4");
+        assert_eq!(records[1].file, "runs/out.wand.ef.0.bench");
+        assert_eq!(
+            render_config_header(&[host1.clone(), host2.clone()], false),
+            ""
+        );
+        Summary {
+            config: Some(ConfigInfo {
+                source: String::from("path /home/user/pisa/bin"),
+                algorithms: vec![String::from("wand")],
+                margin: 0.1,
+                ..ConfigInfo::default()
+            }),
+            ..Summary::default()
+        }
+        .write(&host2)?;
+        assert_eq!(
+            render_config_header(&[host1.clone(), host2.clone()], true),
+            "### host2\n\n- source: path <redacted>/bin\n- algorithms: wand\n- margin: 0.1\n\n"
+        );
+        assert_eq!(
+            render_markdown(&records[..1]),
+            "| file | encoding | algorithm | host | hardware | avg | q50 | q90 | q95 |\n\
+             |---|---|---|---|---|---|---|---|---|\n\
+             | out.wand.ef.0.bench | ef | wand | host2 | unknown | 2 | 2 | 2 | 2 |\n"
+        );
+
+        fs::write(host1.join("old.wand.ef.0.bench"), bench(3.0))?;
+        assert_eq!(collect_results(&[host1.clone()])?.len(), 2);
+        fs::write(
+            Artifacts::path(&host1),
+            r#"[{"kind":"benchmark_results","path":"runs/out.wand.ef.0.bench",
+                "collection":"wapo","run":"0-out","stage":"run","size":0,"hash":"0"}]"#,
+        )?;
+        let records = collect_results(&[host1])?;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].file, "runs/out.wand.ef.0.bench");
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_by_id() -> Result<(), Error> {
+        let tmp = TempDir::new("merge").unwrap();
+        let host1 = tmp.path().join("host1");
+        let host2 = tmp.path().join("host2");
+        fs::create_dir_all(&host1)?;
+        fs::create_dir_all(&host2)?;
+        let bench = |id: &str| {
+            format!(
+                r#"{{"type":"ef","query":"wand","avg":1,"q50":1,"q90":1,"q95":1,"id":"{}"}}"#,
+                id
+            )
+        };
+        fs::write(host1.join("a.wand.ef.0.bench"), bench("2"))?;
+        fs::write(host1.join("b.wand.ef.0.bench"), bench("1"))?;
+        fs::write(host2.join("c.wand.ef.1.bench"), bench("1"))?;
+        let records = collect_results(&[host1, host2])?;
+        let files: Vec<_> = records.iter().map(|r| r.file.as_str()).collect();
+        assert_eq!(
+            files,
+            vec![
+                "b.wand.ef.0.bench",
+                "c.wand.ef.1.bench",
+                "a.wand.ef.0.bench"
+            ]
+        );
+        Ok(())
+    }
+}
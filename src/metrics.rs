@@ -0,0 +1,136 @@
+//! Metrics of built indexes, such as the space used by each encoding.
+//!
+//! After the compress and WAND stages of a collection, the on-disk sizes of the
+//! compressed indexes and the WAND data are stored in the work dir, both as JSON
+//! and as CSV, one file per collection.
+
+use crate::config::Collection;
+use crate::ensure_parent_exists;
+use crate::error::Error;
+use failure::ResultExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Directory within the work dir where index sizes are stored.
+pub const INDEX_SIZES_DIR: &str = "index_sizes";
+
+/// Header of the CSV file with index sizes.
+pub const INDEX_SIZES_CSV_HEADER: &str = "file,bytes";
+
+/// Sizes (in bytes) of the files of a built collection, such as `inv.block_simdbp`
+/// or `wand.bm25`. Files that have not been built are omitted.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct IndexSizes(pub BTreeMap<String, u64>);
+
+impl IndexSizes {
+    /// Path to the index sizes of `collection` within `workdir`,
+    /// with the extension `json` or `csv`.
+    pub fn path(workdir: &Path, collection: &str, extension: &str) -> PathBuf {
+        workdir
+            .join(INDEX_SIZES_DIR)
+            .join(format!("{}.{}", collection, extension))
+    }
+
+    /// Measures the sizes of the compressed indexes and the WAND data of `collection`.
+    pub fn measure(collection: &Collection) -> Self {
+        let indexes = collection
+            .compressed_indexes()
+            .into_iter()
+            .map(|(encoding, scorer)| {
+                let name = match scorer {
+                    Some(scorer) => format!("inv.{}.{}", encoding, scorer),
+                    None => format!("inv.{}", encoding),
+                };
+                (name, collection.scored_index(encoding, scorer))
+            });
+        let wands = collection
+            .scorers
+            .iter()
+            .map(|scorer| (format!("wand.{}", scorer), collection.wand(Some(scorer))))
+            .chain(std::iter::once((
+                String::from("wand"),
+                collection.wand(None),
+            )));
+        let files = indexes.chain(wands);
+        Self(
+            files
+                .filter_map(|(name, path)| fs::metadata(path).ok().map(|meta| (name, meta.len())))
+                .collect(),
+        )
+    }
+
+    /// Loads index sizes from a JSON file.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path).with_context(|_| path.display().to_string())?;
+        Ok(serde_json::from_reader(file).context("Failed to parse index sizes")?)
+    }
+
+    /// Formats the sizes as CSV, with the [header](constant.INDEX_SIZES_CSV_HEADER.html).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::metrics::IndexSizes;
+    /// let sizes = IndexSizes(vec![(String::from("inv.block_simdbp"), 1024)].into_iter().collect());
+    /// assert_eq!(sizes.to_csv(), "file,bytes\ninv.block_simdbp,1024\n");
+    /// ```
+    pub fn to_csv(&self) -> String {
+        std::iter::once(String::from(INDEX_SIZES_CSV_HEADER))
+            .chain(
+                self.0
+                    .iter()
+                    .map(|(file, size)| format!("{},{}", file, size)),
+            )
+            .map(|line| line + "\n")
+            .collect()
+    }
+
+    /// Writes the sizes of `collection` to a JSON and a CSV file within `workdir`.
+    pub fn write(&self, workdir: &Path, collection: &str) -> Result<(), Error> {
+        let json_path = Self::path(workdir, collection, "json");
+        ensure_parent_exists(&json_path)?;
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize index sizes")?;
+        fs::write(&json_path, json)?;
+        fs::write(Self::path(workdir, collection, "csv"), self.to_csv())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{mock_set_up, MockSetup};
+    use crate::Config;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_index_sizes() -> Result<(), Error> {
+        let tmp = TempDir::new("metrics").unwrap();
+        let MockSetup { config, .. } = mock_set_up(&tmp);
+        let collection = config.collection(0);
+        fs::write(collection.enc_index(&"block_simdbp".into()), "index")?;
+        fs::write(collection.wand(Some(&"bm25".into())), "wand data")?;
+        let sizes = IndexSizes::measure(collection);
+        assert_eq!(
+            sizes,
+            IndexSizes(
+                vec![
+                    (String::from("inv.block_simdbp"), 5),
+                    (String::from("wand.bm25"), 9)
+                ]
+                .into_iter()
+                .collect()
+            )
+        );
+        sizes.write(config.workdir(), &collection.name)?;
+        let json_path = IndexSizes::path(config.workdir(), &collection.name, "json");
+        assert_eq!(IndexSizes::load(&json_path)?, sizes);
+        assert_eq!(
+            fs::read_to_string(IndexSizes::path(config.workdir(), "wapo", "csv"))?,
+            "file,bytes\ninv.block_simdbp,5\nwand.bm25,9\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+//! Commands a session would execute, listed without executing anything (dry run).
+
+use crate::checkpoint::Checkpoints;
+use crate::config::{Config, Resolved, Stage};
+use crate::error::Error;
+use crate::temp::{run_id, RunTempDir};
+use crate::{build, remote, run, CommandDebug};
+use std::fmt;
+use std::process::Command;
+
+/// Command planned to be executed in a stage.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedCommand {
+    /// Name of the collection the command belongs to, or `pisa` for compilation.
+    pub label: String,
+    /// Stage executing the command.
+    pub stage: Stage,
+    /// Command line, with commands connected by pipes separated by `|`.
+    pub command: String,
+}
+
+impl PlannedCommand {
+    /// Plans a single command.
+    pub fn new(label: &str, stage: Stage, command: &Command) -> Self {
+        Self::pipeline(label, stage, &[command])
+    }
+
+    /// Plans commands connected by pipes, each reading the output of the previous one.
+    pub fn pipeline(label: &str, stage: Stage, commands: &[&Command]) -> Self {
+        Self {
+            label: label.to_string(),
+            stage,
+            command: commands
+                .iter()
+                .map(|command| command.to_string())
+                .collect::<Vec<_>>()
+                .join(" | "),
+        }
+    }
+}
+
+impl fmt::Display for PlannedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] [{}] {}", self.label, self.stage, self.command)
+    }
+}
+
+/// Lists all commands of a session of `config`, in the order of execution:
+/// compilation, builds of all collections (or the remote build, if there is a build host),
+/// and runs.
+///
+/// Nothing is compiled, built, or written. When resuming, steps and runs
+/// completed in the previous session are omitted.
+pub fn session<C: Config + Resolved>(config: &C) -> Result<Vec<PlannedCommand>, Error> {
+    let executor = config.planned_executor();
+    let checkpoints = if config.resume() {
+        Some(Checkpoints::open(config.workdir(), true)?)
+    } else {
+        None
+    };
+    let mut plan: Vec<PlannedCommand> = Vec::new();
+    if config.enabled(Stage::Compile) {
+        for command in config.source().compile_commands(config.workdir()) {
+            plan.push(PlannedCommand::new("pisa", Stage::Compile, &command));
+        }
+    }
+    if let Some(build_host) = config.build_host() {
+        plan.extend(remote::plan(build_host, config));
+    } else {
+        for collection in config.collections() {
+            plan.extend(build::plan(
+                &executor,
+                collection,
+                config,
+                checkpoints.as_ref(),
+            )?);
+        }
+    }
+    if config.enabled(Stage::Run) {
+        for (idx, run) in config.runs().iter().enumerate() {
+            let run_id = run_id(idx, run);
+            let done = checkpoints
+                .as_ref()
+                .is_some_and(|checkpoints| checkpoints.run_done(&run_id));
+            let collection = config
+                .collections()
+                .iter()
+                .find(|c| c.name == run.collection);
+            if let (Some(collection), false) = (collection, done) {
+                plan.extend(run::plan(
+                    &executor,
+                    run,
+                    collection,
+                    config.use_scorer(),
+                    &RunTempDir::planned_path(config.workdir(), &run_id),
+                )?);
+            }
+        }
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{mock_set_up, MockSetup};
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_session() {
+        let tmp = TempDir::new("plan").unwrap();
+        let MockSetup { config, .. } = mock_set_up(&tmp);
+        let plan = session(&config).unwrap();
+        let count = |label: &str, stage: Stage| {
+            plan.iter()
+                .filter(|command| command.label == label && command.stage == stage)
+                .count()
+        };
+        for collection in &["wapo", "gov2", "cw09b"] {
+            assert_eq!(count(collection, Stage::Parse), 3);
+            assert_eq!(count(collection, Stage::Invert), 1);
+            assert_eq!(count(collection, Stage::Compress), 2);
+            assert_eq!(count(collection, Stage::Wand), 1);
+        }
+        assert_eq!(count("wapo", Stage::Run), 16);
+        let run_commands: Vec<_> = plan
+            .iter()
+            .filter(|command| command.stage == Stage::Run)
+            .map(|command| command.command.split(' ').next().unwrap().to_string())
+            .collect();
+        let bin = |name: &str| tmp.path().join("bin").join(name).display().to_string();
+        assert_eq!(run_commands[0], bin("extract_topics"));
+        assert_eq!(run_commands[1], bin("evaluate_queries"));
+        assert_eq!(run_commands[2], "trec_eval");
+        assert_eq!(run_commands[15], bin("queries"));
+        assert!(!tmp.path().join("tmp").exists());
+        assert!(!tmp
+            .path()
+            .join("output.trec.wand.block_simdbp.0.results")
+            .exists());
+    }
+}
@@ -0,0 +1,162 @@
+//! Export of benchmark latencies and evaluation measures as Prometheus metrics,
+//! pushed to a [Pushgateway](https://github.com/prometheus/pushgateway)
+//! to build dashboards of PISA performance over time.
+//!
+//! All metrics are gauges labeled with the collection, run, encoding, algorithm,
+//! topics index, and the PISA commit if known:
+//!
+//! - `stdbench_query_time_microseconds`, with `statistic` being `avg`, `q50`, `q90`, or `q95`;
+//! - `stdbench_queries_per_second`, the throughput of benchmarks;
+//! - `stdbench_eval_measure`, with `measure` being an overall `trec_eval` measure, e.g., `map`.
+
+use crate::config::{Config, RunKind};
+use crate::database::run_results;
+use crate::error::Error;
+use crate::executor::check_status;
+use crate::CommandDebug;
+use failure::ResultExt;
+use log::info;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// File within the work dir where the pushed metrics are written.
+pub const METRICS_FILE: &str = "metrics.prom";
+
+/// Name of the Pushgateway job grouping the metrics.
+pub const JOB: &str = "stdbench";
+
+const TIME_METRIC: &str = "stdbench_query_time_microseconds";
+const QPS_METRIC: &str = "stdbench_queries_per_second";
+const EVAL_METRIC: &str = "stdbench_eval_measure";
+
+/// Escapes a label value of the Prometheus text format.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::prometheus::escape_label;
+/// assert_eq!(escape_label(r#"a "b"\c"#), r#"a \"b\"\\c"#);
+/// assert_eq!(escape_label("a\nb"), r"a\nb");
+/// ```
+pub fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let labels: Vec<_> = pairs
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label(value)))
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+/// Renders the results of all runs of `config` in the Prometheus text format.
+pub fn render_metrics<C: Config>(config: &C, commit: Option<&str>) -> Result<String, Error> {
+    let mut times = vec![format!("# TYPE {} gauge", TIME_METRIC)];
+    let mut throughputs = vec![format!("# TYPE {} gauge", QPS_METRIC)];
+    let mut measures = vec![format!("# TYPE {} gauge", EVAL_METRIC)];
+    for run in config.runs() {
+        let output = run.output.file_name().map_or_else(
+            || run.output.display().to_string(),
+            |name| name.to_string_lossy().to_string(),
+        );
+        for row in run_results(run)? {
+            let topics = row.topics.to_string();
+            let mut common = vec![
+                ("collection", run.collection.as_str()),
+                ("run", output.as_str()),
+                ("encoding", row.encoding.as_str()),
+                ("algorithm", row.algorithm.as_str()),
+                ("topics", topics.as_str()),
+            ];
+            if let Some(commit) = commit {
+                common.push(("commit", commit));
+            }
+            match run.kind {
+                RunKind::Benchmark if row.measure == "qps" => {
+                    throughputs.push(format!("{}{} {}", QPS_METRIC, labels(&common), row.value));
+                }
+                RunKind::Benchmark => {
+                    common.push(("statistic", row.measure.as_str()));
+                    times.push(format!("{}{} {}", TIME_METRIC, labels(&common), row.value));
+                }
+                RunKind::Evaluate { .. } | RunKind::External { .. } => {
+                    common.push(("measure", row.measure.as_str()));
+                    measures.push(format!("{}{} {}", EVAL_METRIC, labels(&common), row.value));
+                }
+            }
+        }
+    }
+    times.extend(throughputs);
+    times.extend(measures);
+    times.push(String::new());
+    Ok(times.join("\n"))
+}
+
+/// Creates a `curl` command pushing the metrics file at `path` to the Pushgateway at `url`.
+pub fn push_command(url: &str, path: &Path) -> Command {
+    let mut curl = Command::new("curl");
+    curl.args(&["-fsS", "--data-binary"])
+        .arg(format!("@{}", path.display()))
+        .arg(format!("{}/metrics/job/{}", url.trim_end_matches('/'), JOB));
+    curl
+}
+
+/// Writes the metrics of `config` to the work dir, and pushes them to the Pushgateway at `url`.
+#[cfg_attr(tarpaulin, skip)]
+pub fn push_metrics<C: Config>(config: &C, url: &str, commit: Option<&str>) -> Result<(), Error> {
+    let path = config.workdir().join(METRICS_FILE);
+    fs::write(&path, render_metrics(config, commit)?)?;
+    let status = push_command(url, &path)
+        .log()
+        .status()
+        .context("Failed to run curl")?;
+    check_status("curl", status, "Failed to push metrics")?;
+    info!("Metrics pushed to: {}", url);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::format_output_path;
+    use crate::tests::{mock_set_up, MockSetup};
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_render_metrics() -> Result<(), Error> {
+        let tmp = TempDir::new("prometheus").unwrap();
+        let MockSetup { config, .. } = mock_set_up(&tmp);
+        let benchmark = &config.0.runs[2];
+        let path = format_output_path(
+            &benchmark.output,
+            &benchmark.algorithms[0],
+            &benchmark.encodings[0],
+            0,
+            "bench",
+        );
+        fs::write(
+            path,
+            r#"{"type":"block_simdbp","query":"wand","avg":1.5,"q50":1,"q90":2,"q95":3}"#,
+        )?;
+        let metrics = render_metrics(&config, Some("abc"))?;
+        let lines: Vec<_> = metrics.lines().collect();
+        assert_eq!(lines[0], "# TYPE stdbench_query_time_microseconds gauge");
+        assert!(lines[1].starts_with("stdbench_query_time_microseconds{collection=\"wapo\","));
+        assert!(lines[1].ends_with(",commit=\"abc\",statistic=\"avg\"} 1.5"));
+        assert_eq!(lines[5], "# TYPE stdbench_queries_per_second gauge");
+        assert_eq!(lines[6], "# TYPE stdbench_eval_measure gauge");
+        assert_eq!(lines.len(), 7);
+
+        let curl = push_command("http://localhost:9091/", Path::new("metrics.prom"));
+        assert_eq!(
+            curl.to_string(),
+            "curl -fsS --data-binary @metrics.prom http://localhost:9091/metrics/job/stdbench"
+        );
+        Ok(())
+    }
+}
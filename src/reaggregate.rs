@@ -0,0 +1,306 @@
+//! Regeneration of results from raw tool outputs stored with `keep_raw_output`,
+//! e.g., after the parsing or aggregation of results changes, without executing PISA again.
+//!
+//! Each session stores its resolved config in the work dir, so that the runs,
+//! and thus their output files, can be found from the work dir alone.
+//! Benchmark results are aggregated again from the raw outputs of their measured executions,
+//! keeping the fields that cannot be recomputed, such as the throughput, from the previous
+//! results. Raw results of evaluation runs are sorted and evaluated again, which executes
+//! `trec_eval` unless the internal evaluator is configured.
+
+use crate::artifacts::Artifacts;
+use crate::checkpoint::STATE_DIR;
+use crate::config::{format_output_path, Config, RawConfig, ResolvedPathsConfig, Run, RunKind};
+use crate::ensure_parent_exists;
+use crate::error::Error;
+use crate::executor::Executor;
+use crate::run::{
+    aggregate_repeats, check_max_latency, check_min_measures, compare_with_baseline,
+    prepare_run_qrels, raw_suffix, topic_weights, with_counters, write_evaluation,
+    HardwareCounters, RunStatus, RAW_RESULTS,
+};
+use crate::summary::{RunSummary, Summary};
+use crate::temp::{run_id, RunTempDir};
+use failure::ResultExt;
+use itertools::iproduct;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Path to the resolved config of the last session within `workdir`.
+pub fn config_path(workdir: &Path) -> PathBuf {
+    workdir.join(STATE_DIR).join("config.yml")
+}
+
+/// Stores the resolved config of a session in its work dir.
+pub fn save_config(config: &ResolvedPathsConfig) -> Result<(), Error> {
+    let path = config_path(config.workdir());
+    ensure_parent_exists(&path)?;
+    let yaml = serde_yaml::to_string(&config.0).context("Failed to serialize config")?;
+    fs::write(&path, yaml)?;
+    Ok(())
+}
+
+/// Loads the config stored by the last session in `workdir`.
+///
+/// The work dir of the config is replaced with `workdir`, e.g., if it was moved,
+/// while the output files of the runs stay where they were recorded.
+pub fn load_config(workdir: &Path) -> Result<ResolvedPathsConfig, Error> {
+    let path = config_path(workdir);
+    let yaml = fs::read_to_string(&path)
+        .with_context(|_| format!("No session config found: {}", path.display()))?;
+    let mut config: RawConfig = serde_yaml::from_str(&yaml).context("Failed to parse config")?;
+    config.workdir = workdir.to_path_buf();
+    ResolvedPathsConfig::from(config)
+}
+
+/// Parses the raw outputs of the measured executions of a benchmark, stored at `raw_path`,
+/// into results aggregated the same way as when measured, or `None` if there are none.
+fn raw_benchmark_results<F>(raw_path: F) -> Result<Option<String>, Error>
+where
+    F: Fn(usize) -> PathBuf,
+{
+    let mut results = Vec::new();
+    while raw_path(results.len()).exists() {
+        let path = raw_path(results.len());
+        let output = fs::read_to_string(&path).with_context(|_| path.display().to_string())?;
+        let perf = PathBuf::from(format!("{}.perf", path.display()));
+        results.push(if perf.exists() {
+            let perf_output =
+                fs::read_to_string(&perf).with_context(|_| perf.display().to_string())?;
+            with_counters(&output, HardwareCounters::parse(&perf_output))?
+        } else {
+            output
+        });
+    }
+    match results.len() {
+        0 => Ok(None),
+        1 => Ok(results.pop()),
+        _ => aggregate_repeats(&results).map(Some),
+    }
+}
+
+/// Adds the fields of the previous benchmark results at `path` missing from the regenerated
+/// `results`, such as the throughput or the identity, which raw outputs do not contain.
+fn carry_over(results: &str, path: &Path) -> Result<String, Error> {
+    let previous = fs::read_to_string(path)
+        .ok()
+        .and_then(|previous| serde_json::from_str(&previous).ok());
+    let previous = match previous {
+        Some(serde_json::Value::Object(fields)) => fields,
+        _ => return Ok(results.to_string()),
+    };
+    let mut results: serde_json::Value =
+        serde_json::from_str(results).context("Unable to parse benchmark results")?;
+    let fields = results
+        .as_object_mut()
+        .ok_or("Benchmark results are not a JSON object")?;
+    for (key, value) in previous {
+        fields.entry(key).or_insert(value);
+    }
+    Ok(results.to_string())
+}
+
+/// Regenerates the benchmark results of `run` from its raw outputs,
+/// returning the paths to the regenerated files.
+fn reaggregate_benchmark(run: &Run) -> Result<Vec<PathBuf>, Error> {
+    let mut regenerated = Vec::new();
+    for (algorithm, encoding, tid) in
+        iproduct!(&run.algorithms, &run.encodings, 0..run.topics.len())
+    {
+        let raw_path = |repeat: usize| {
+            format_output_path(&run.output, algorithm, encoding, tid, &raw_suffix(repeat))
+        };
+        if let Some(results) = raw_benchmark_results(raw_path)? {
+            let path = format_output_path(&run.output, algorithm, encoding, tid, "bench");
+            fs::write(&path, carry_over(&results, &path)?)?;
+            regenerated.push(path);
+        }
+    }
+    Ok(regenerated)
+}
+
+/// Evaluates the raw results of the evaluation `run` again, writing qrels conversions
+/// to `temp_dir`, and returns the paths to the regenerated files.
+fn reevaluate(executor: &Executor, run: &Run, temp_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let min_relevance = match run.kind {
+        RunKind::Evaluate { min_relevance, .. } => min_relevance,
+        _ => return Ok(vec![]),
+    };
+    let qrels = prepare_run_qrels(run, temp_dir)?;
+    let mut regenerated = Vec::new();
+    for (algorithm, encoding, tid) in
+        iproduct!(&run.algorithms, &run.encodings, 0..run.topics.len())
+    {
+        let raw_path = format_output_path(&run.output, algorithm, encoding, tid, RAW_RESULTS);
+        if !raw_path.exists() {
+            continue;
+        }
+        let results =
+            fs::read_to_string(&raw_path).with_context(|_| raw_path.display().to_string())?;
+        let trec_eval_path = format_output_path(&run.output, algorithm, encoding, tid, "trec_eval");
+        write_evaluation(
+            executor,
+            &results,
+            &format_output_path(&run.output, algorithm, encoding, tid, "results"),
+            &trec_eval_path,
+            &qrels[tid],
+            min_relevance,
+            topic_weights(&run.topics[tid])?.as_ref(),
+        )?;
+        regenerated.push(trec_eval_path);
+    }
+    Ok(regenerated)
+}
+
+fn has_raw_results(run: &Run) -> bool {
+    iproduct!(&run.algorithms, &run.encodings, 0..run.topics.len()).any(
+        |(algorithm, encoding, tid)| {
+            format_output_path(&run.output, algorithm, encoding, tid, RAW_RESULTS).exists()
+        },
+    )
+}
+
+/// Regenerates the results of all runs of `config` that have raw outputs,
+/// returning the paths to the regenerated files. Runs without them are left as they are.
+///
+/// The `executor` is only used for evaluating results, e.g., with `trec_eval`.
+/// The output files of regenerated runs are recorded again in the
+/// [inventory of artifacts](../artifacts/index.html).
+pub fn reaggregate<C: Config>(executor: &Executor, config: &C) -> Result<Vec<PathBuf>, Error> {
+    let artifacts = Artifacts::open(config.workdir())?;
+    let mut regenerated = Vec::new();
+    for (idx, run) in config.runs().iter().enumerate() {
+        let files = match run.kind {
+            RunKind::Benchmark => reaggregate_benchmark(run)?,
+            RunKind::Evaluate { .. } if has_raw_results(run) => {
+                let temp_dir = RunTempDir::create(config.workdir(), &run_id(idx, run))?;
+                let evaluated = reevaluate(executor, run, temp_dir.path());
+                temp_dir.finish(evaluated.is_err(), config.keep_temp())?;
+                evaluated?
+            }
+            RunKind::Evaluate { .. } | RunKind::External { .. } => vec![],
+        };
+        if !files.is_empty() {
+            artifacts.record_run(&run_id(idx, run), run)?;
+        }
+        regenerated.extend(files);
+    }
+    Ok(regenerated)
+}
+
+/// Counts the regressions of `run` against its baseline and thresholds, as in a session.
+fn count_regressions<C: Config>(config: &C, run: &Run) -> Result<usize, Error> {
+    let mut statuses = Vec::new();
+    if let Some(baseline) = &run.compare_with {
+        statuses.push(compare_with_baseline(run, &baseline.path, config.margin())?);
+    }
+    if !run.min_measures.is_empty() {
+        statuses.push(check_min_measures(run)?);
+    }
+    if run.max_avg_ms.is_some() || run.max_p95_ms.is_some() {
+        statuses.push(check_max_latency(run)?);
+    }
+    Ok(statuses
+        .into_iter()
+        .map(|status| match status {
+            RunStatus::Success => 0,
+            RunStatus::Regression(count) => count,
+        })
+        .sum())
+}
+
+/// Updates the output files and regressions of the runs in the `summary` of a session
+/// from the inventory of artifacts and their current results.
+/// Runs without output files are not compared.
+pub fn resummarize<C: Config>(config: &C, summary: &mut Summary) -> Result<(), Error> {
+    if summary.runs.len() != config.runs().len() {
+        summary.runs = config.runs().iter().map(RunSummary::new).collect();
+    }
+    let artifacts = Artifacts::open(config.workdir())?;
+    for (idx, (run_summary, run)) in summary.runs.iter_mut().zip(config.runs()).enumerate() {
+        run_summary.files = artifacts.run_files(&run_id(idx, run));
+        if !run_summary.files.is_empty() {
+            run_summary.regressions = count_regressions(config, run)?;
+        }
+    }
+    summary.regressions = summary.build_time_regressions
+        + summary
+            .runs
+            .iter()
+            .map(|run| run.regressions)
+            .sum::<usize>();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Evaluator;
+    use crate::tests::{mock_set_up, MockSetup};
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_reaggregate() -> Result<(), Error> {
+        let tmp = TempDir::new("reaggregate").unwrap();
+        let MockSetup { config, .. } = mock_set_up(&tmp);
+        save_config(&config)?;
+        let config = load_config(config.workdir())?;
+        let benchmark = &config.0.runs[2];
+        let path = |suffix: &str| {
+            format_output_path(
+                &benchmark.output,
+                &benchmark.algorithms[0],
+                &benchmark.encodings[0],
+                0,
+                suffix,
+            )
+        };
+        fs::write(
+            path(&raw_suffix(0)),
+            r#"{"type":"block_simdbp","query":"wand","avg":1,"q50":1,"q90":2,"q95":3}"#,
+        )?;
+        fs::write(
+            path(&raw_suffix(1)),
+            r#"{"type":"block_simdbp","query":"wand","avg":3,"q50":1,"q90":2,"q95":3}"#,
+        )?;
+        fs::write(
+            path("bench"),
+            r#"{"type":"block_simdbp","query":"wand","avg":9,"q50":9,"q90":9,"q95":9,"qps":100}"#,
+        )?;
+        let evaluate = &config.0.runs[0];
+        let raw_results = format_output_path(
+            &evaluate.output,
+            &evaluate.algorithms[0],
+            &evaluate.encodings[0],
+            0,
+            RAW_RESULTS,
+        );
+        fs::write(&raw_results, "1 Q0 d1 1 1.0 R0\n1 Q0 d2 0 2.0 R0\n")?;
+        fs::write(tmp.path().join("qrels"), "1 0 d2 1\n")?;
+
+        let executor = Executor::new().with_evaluator(Evaluator::Internal);
+        // Both evaluation runs of the mock config share their output files.
+        let regenerated = reaggregate(&executor, &config)?;
+        assert_eq!(regenerated.len(), 3);
+        let results: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(path("bench"))?).unwrap();
+        assert!((results["avg"].as_f64().unwrap() - 2.0).abs() < 1e-9);
+        assert_eq!(results["repeats"], 2);
+        assert_eq!(results["qps"], 100);
+        let sorted = fs::read_to_string(format_output_path(
+            &evaluate.output,
+            &evaluate.algorithms[0],
+            &evaluate.encodings[0],
+            0,
+            "results",
+        ))?;
+        assert!(sorted.lines().next().unwrap().contains("d2"));
+        assert!(fs::read_to_string(&regenerated[0])?.contains("map"));
+
+        let mut summary = Summary::default();
+        resummarize(&config, &mut summary)?;
+        assert_eq!(summary.runs.len(), config.0.runs.len());
+        assert!(summary.runs[2].files.contains(&path("bench")));
+        Ok(())
+    }
+}
@@ -0,0 +1,402 @@
+//! Built-in registry of standard TREC topic sets and their relevance judgments,
+//! and of presets of standard collections.
+
+use crate::config::{CollectionKind, TopicField, Topics};
+use crate::executor::check_status;
+use crate::identity::fnv1a;
+use crate::{CommandDebug, Error};
+use failure::ResultExt;
+use log::info;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Name of the directory in the work directory storing downloaded standard topics.
+pub const STANDARD_DIR: &str = "standard";
+
+/// Name of the directory in the work directory storing topics and qrels downloaded by URL.
+pub const DOWNLOADS_DIR: &str = "downloads";
+
+/// Format of a standard topics file, as published.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TopicsFormat {
+    /// TREC format, queried by the title field.
+    Trec,
+    /// Tab-separated query ID and query, converted to the simple format.
+    Tsv,
+}
+
+/// Standard topic set paired with its relevance judgments.
+#[derive(Debug, PartialEq)]
+pub struct StandardTopics {
+    /// Name by which the topics are referenced in configs.
+    pub name: &'static str,
+    /// Download URL of the topics; gzipped if ending with `.gz`.
+    pub topics_url: &'static str,
+    /// Format of the topics.
+    pub format: TopicsFormat,
+    /// Download URL of the relevance judgments.
+    pub qrels_url: &'static str,
+}
+
+/// All registered standard topic sets.
+pub const STANDARD_TOPICS: &[StandardTopics] = &[
+    StandardTopics {
+        name: "robust04",
+        topics_url: "https://trec.nist.gov/data/robust/04.testset.gz",
+        format: TopicsFormat::Trec,
+        qrels_url: "https://trec.nist.gov/data/robust/qrels.robust2004.txt",
+    },
+    StandardTopics {
+        name: "trec-dl-2019",
+        topics_url:
+            "https://msmarco.blob.core.windows.net/msmarcoranking/msmarco-test2019-queries.tsv.gz",
+        format: TopicsFormat::Tsv,
+        qrels_url: "https://trec.nist.gov/data/deep/2019qrels-pass.txt",
+    },
+    StandardTopics {
+        name: "trec-dl-2020",
+        topics_url:
+            "https://msmarco.blob.core.windows.net/msmarcoranking/msmarco-test2020-queries.tsv.gz",
+        format: TopicsFormat::Tsv,
+        qrels_url: "https://trec.nist.gov/data/deep/2020qrels-pass.txt",
+    },
+    StandardTopics {
+        name: "terabyte-2004",
+        topics_url: "https://trec.nist.gov/data/terabyte/04/04topics.701-750.txt",
+        format: TopicsFormat::Trec,
+        qrels_url: "https://trec.nist.gov/data/terabyte/04/04.qrels.12-Nov-04",
+    },
+    StandardTopics {
+        name: "terabyte-2005",
+        topics_url: "https://trec.nist.gov/data/terabyte/05/05.topics.751-800.txt",
+        format: TopicsFormat::Trec,
+        qrels_url: "https://trec.nist.gov/data/terabyte/05/05.adhoc_qrels",
+    },
+    StandardTopics {
+        name: "terabyte-2006",
+        topics_url: "https://trec.nist.gov/data/terabyte/06/06.topics.801-850.txt",
+        format: TopicsFormat::Trec,
+        qrels_url: "https://trec.nist.gov/data/terabyte/06/qrels.tb06.top50",
+    },
+];
+
+/// Looks up standard topics by name.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::registry::standard_topics;
+/// assert_eq!(standard_topics("trec-dl-2019").unwrap().name, "trec-dl-2019");
+/// assert!(standard_topics("trec-dl-2099").is_err());
+/// ```
+pub fn standard_topics(name: &str) -> Result<&'static StandardTopics, Error> {
+    STANDARD_TOPICS
+        .iter()
+        .find(|topics| topics.name == name)
+        .ok_or_else(|| {
+            let names: Vec<_> = STANDARD_TOPICS.iter().map(|topics| topics.name).collect();
+            Error::from(format!(
+                "Unknown standard topics: {} (available: {})",
+                name,
+                names.join(", ")
+            ))
+        })
+}
+
+/// Converts tab-separated queries to the simple (colon-delimited) format.
+fn tsv_to_simple(queries: &str) -> String {
+    let mut output = String::new();
+    for line in queries.lines() {
+        if let Some((qid, query)) = line.split_once('\t') {
+            output.push_str(&format!("{}:{}\n", qid.trim(), query.trim()));
+        }
+    }
+    output
+}
+
+/// Fails unless the SHA-256 checksum of the file at `path` is `expected`.
+pub(crate) fn verify_checksum(path: &Path, expected: &str) -> Result<(), Error> {
+    let output = Command::new("sha256sum")
+        .arg(path)
+        .log()
+        .output()
+        .context("Failed to run sha256sum")?;
+    check_status("sha256sum", output.status, "Failed to compute checksum")?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let actual = stdout.split_whitespace().next().unwrap_or_default();
+    if actual.eq_ignore_ascii_case(expected) {
+        Ok(())
+    } else {
+        Err(Error::from(format!(
+            "Checksum mismatch of {}: expected {}, found {}",
+            path.display(),
+            expected,
+            actual
+        )))
+    }
+}
+
+/// Downloads `url` to `output`, decompressing it if gzipped.
+///
+/// If `sha256` is defined, the checksum of the downloaded (compressed) file is verified.
+#[cfg_attr(tarpaulin, skip)]
+fn download(url: &str, sha256: Option<&str>, output: &Path) -> Result<(), Error> {
+    info!("Downloading {}", url);
+    let download = output.with_extension("download");
+    let status = Command::new("curl")
+        .args(&["-fsSL", "-o"])
+        .arg(&download)
+        .arg(url)
+        .log()
+        .status()
+        .context("Failed to run curl")?;
+    check_status("curl", status, "Failed to download file")?;
+    if let Some(sha256) = sha256 {
+        if let Err(err) = verify_checksum(&download, sha256) {
+            fs::remove_file(&download)?;
+            return Err(err);
+        }
+    }
+    let gzipped = Path::new(url)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gz"));
+    if gzipped {
+        let status = Command::new("gunzip")
+            .arg("-c")
+            .arg(&download)
+            .stdout(File::create(output)?)
+            .log()
+            .status()
+            .context("Failed to run gunzip")?;
+        check_status("gunzip", status, "Failed to decompress file")?;
+        fs::remove_file(&download)?;
+    } else {
+        fs::rename(&download, output)?;
+    }
+    Ok(())
+}
+
+impl StandardTopics {
+    fn dir(&self, workdir: &Path) -> PathBuf {
+        workdir.join(STANDARD_DIR).join(self.name)
+    }
+
+    /// Path to the topics file within the work directory.
+    pub fn topics_path(&self, workdir: &Path) -> PathBuf {
+        self.dir(workdir).join("topics")
+    }
+
+    /// Path to the qrels file within the work directory.
+    pub fn qrels_path(&self, workdir: &Path) -> PathBuf {
+        self.dir(workdir).join("qrels")
+    }
+
+    /// Topics pointing to the (downloaded) files in the work directory,
+    /// paired with their qrels.
+    pub fn topics(&self, workdir: &Path) -> Topics {
+        let path = self.topics_path(workdir);
+        let qrels = Some(self.qrels_path(workdir));
+        match self.format {
+            TopicsFormat::Trec => Topics::Trec {
+                path,
+                field: TopicField::Title,
+                qrels,
+            },
+            TopicsFormat::Tsv => Topics::Simple { path, qrels },
+        }
+    }
+
+    /// Downloads the topics and qrels to the work directory, unless already there.
+    pub fn fetch(&self, workdir: &Path) -> Result<(), Error> {
+        fs::create_dir_all(self.dir(workdir))?;
+        let topics_path = self.topics_path(workdir);
+        if !topics_path.exists() {
+            let raw = topics_path.with_extension("raw");
+            download(self.topics_url, None, &raw)?;
+            match self.format {
+                TopicsFormat::Trec => fs::rename(&raw, &topics_path)?,
+                TopicsFormat::Tsv => {
+                    fs::write(&topics_path, tsv_to_simple(&fs::read_to_string(&raw)?))?;
+                    fs::remove_file(&raw)?;
+                }
+            }
+        }
+        let qrels_path = self.qrels_path(workdir);
+        if !qrels_path.exists() {
+            download(self.qrels_url, None, &qrels_path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Path within `workdir` to which [`fetch_url`](fn.fetch_url.html) downloads `url`.
+///
+/// Files are stored in a directory named by the hash of the URL, so that files
+/// of different URLs with the same name do not collide.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::registry::downloaded_path;
+/// # use std::path::Path;
+/// let path = downloaded_path(Path::new("/work"), "https://example.com/topics.301-450.gz");
+/// assert!(path.starts_with("/work/downloads"));
+/// assert!(path.ends_with("topics.301-450"));
+/// ```
+pub fn downloaded_path(workdir: &Path, url: &str) -> PathBuf {
+    let name = url.trim_end_matches('/').rsplit('/').next().unwrap_or(url);
+    workdir
+        .join(DOWNLOADS_DIR)
+        .join(format!("{:016x}", fnv1a(url.as_bytes())))
+        .join(name.strip_suffix(".gz").unwrap_or(name))
+}
+
+/// Downloads `url` to the work directory, unless already there, verifying
+/// its SHA-256 checksum if defined; returns the path of the downloaded file.
+pub fn fetch_url(workdir: &Path, url: &str, sha256: Option<&str>) -> Result<PathBuf, Error> {
+    let path = downloaded_path(workdir, url);
+    if !path.exists() {
+        fs::create_dir_all(path.parent().expect("Download path without parent"))?;
+        download(url, sha256, &path)?;
+    }
+    Ok(path)
+}
+
+/// Defaults of a standard collection.
+#[derive(Debug, PartialEq)]
+pub struct CollectionPreset {
+    /// Name by which the preset is referenced in configs, and the default collection name.
+    pub name: &'static str,
+    /// Type of collection format.
+    pub kind: CollectionKind,
+    /// Pattern of input files relative to the input directory,
+    /// if different from the default of `kind`.
+    pub input_glob: Option<&'static str>,
+    /// Recommended encodings.
+    pub encodings: &'static [&'static str],
+}
+
+/// All registered collection presets.
+pub const COLLECTION_PRESETS: &[CollectionPreset] = &[
+    CollectionPreset {
+        name: "gov2",
+        kind: CollectionKind::TrecWeb,
+        input_glob: Some("GX*/*.gz"),
+        encodings: &["block_simdbp", "block_optpfor", "ef"],
+    },
+    CollectionPreset {
+        name: "cw09b",
+        kind: CollectionKind::Warc,
+        input_glob: Some("ClueWeb09_English_1/*/*.warc.gz"),
+        encodings: &["block_simdbp", "block_optpfor", "ef"],
+    },
+    CollectionPreset {
+        name: "cw12b",
+        kind: CollectionKind::Warc,
+        input_glob: Some("ClueWeb12_*/*/*.warc.gz"),
+        encodings: &["block_simdbp", "block_optpfor", "ef"],
+    },
+    CollectionPreset {
+        name: "wapo",
+        kind: CollectionKind::WashingtonPost,
+        input_glob: None,
+        encodings: &["block_simdbp", "block_qmx"],
+    },
+    CollectionPreset {
+        name: "robust04",
+        kind: CollectionKind::Robust,
+        input_glob: None,
+        encodings: &["block_simdbp", "block_qmx"],
+    },
+    CollectionPreset {
+        name: "msmarco-passage",
+        kind: CollectionKind::MsMarco,
+        input_glob: Some("collection.tsv"),
+        encodings: &["block_simdbp", "block_qmx"],
+    },
+    CollectionPreset {
+        name: "msmarco-document",
+        kind: CollectionKind::MsMarco,
+        input_glob: Some("msmarco-docs.tsv*"),
+        encodings: &["block_simdbp", "block_qmx"],
+    },
+];
+
+/// Looks up a collection preset by name.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::registry::collection_preset;
+/// assert_eq!(collection_preset("gov2").unwrap().name, "gov2");
+/// assert!(collection_preset("gov3").is_err());
+/// ```
+pub fn collection_preset(name: &str) -> Result<&'static CollectionPreset, Error> {
+    COLLECTION_PRESETS
+        .iter()
+        .find(|preset| preset.name == name)
+        .ok_or_else(|| {
+            let names: Vec<_> = COLLECTION_PRESETS
+                .iter()
+                .map(|preset| preset.name)
+                .collect();
+            Error::from(format!(
+                "Unknown collection preset: {} (available: {})",
+                name,
+                names.join(", ")
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_tsv_to_simple() {
+        assert_eq!(
+            tsv_to_simple("1037798\twho is robert gray\n104861\t cost of interior \n"),
+            "1037798:who is robert gray\n104861:cost of interior\n"
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_family, unix)]
+    fn test_fetch_url() -> Result<(), Error> {
+        let tmp = TempDir::new("registry").unwrap();
+        let source = tmp.path().join("topics.txt");
+        fs::write(&source, "abc")?;
+        let url = format!("file://{}", source.display());
+        let workdir = tmp.path().join("work");
+        assert!(fetch_url(&workdir, &url, Some("0000")).is_err());
+        assert!(!downloaded_path(&workdir, &url).exists());
+        let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let path = fetch_url(&workdir, &url, Some(sha256))?;
+        assert_eq!(path, downloaded_path(&workdir, &url));
+        assert_eq!(fs::read_to_string(&path)?, "abc");
+        fs::remove_file(&source)?;
+        assert_eq!(fetch_url(&workdir, &url, Some(sha256))?, path);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetch_cached() -> Result<(), Error> {
+        let tmp = TempDir::new("registry").unwrap();
+        let topics = standard_topics("robust04")?;
+        fs::create_dir_all(tmp.path().join("standard/robust04"))?;
+        fs::write(topics.topics_path(tmp.path()), "<top></top>")?;
+        fs::write(topics.qrels_path(tmp.path()), "301 0 FBIS3-10082 1")?;
+        topics.fetch(tmp.path())?;
+        assert_eq!(
+            topics.topics(tmp.path()),
+            Topics::Trec {
+                path: tmp.path().join("standard/robust04/topics"),
+                field: TopicField::Title,
+                qrels: Some(tmp.path().join("standard/robust04/qrels"))
+            }
+        );
+        Ok(())
+    }
+}
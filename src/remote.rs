@@ -0,0 +1,181 @@
+//! Building indexes on a dedicated build host, separately from the host
+//! on which runs are measured.
+//!
+//! The build stages are executed over SSH by a `stdbench` session on the build host,
+//! with the same arguments and config path as the local session.
+//! Afterwards, the built indexes, their inventory, build times and sizes are copied with `rsync`
+//! to the same paths on the local host.
+
+use crate::artifacts::Artifacts;
+use crate::build::BUILD_TIMES_DIR;
+use crate::config::{BuildHost, Collection, Config, Stage};
+use crate::error::Error;
+use crate::executor::check_status;
+use crate::metrics::INDEX_SIZES_DIR;
+use crate::plan::PlannedCommand;
+use crate::{ensure_parent_exists, shell_quote, CommandDebug};
+use failure::ResultExt;
+use log::info;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Creates the command executing the build stages on the build host,
+/// where `args` are the arguments of the local session.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::BuildHost;
+/// # use stdbench::remote::build_command;
+/// # use stdbench::CommandDebug;
+/// let build_host = BuildHost { host: String::from("builder"), stdbench: "stdbench".into() };
+/// let ssh = build_command(&build_host, &[String::from("--config-file"), String::from("a b.yml")]);
+/// assert_eq!(
+///     ssh.to_string(),
+///     "ssh builder -- stdbench --config-file ''\\''a b.yml'\\''' \
+///      --local-build --suppress run --suppress compare"
+/// );
+/// ```
+pub fn build_command(build_host: &BuildHost, args: &[String]) -> Command {
+    let mut ssh = Command::new("ssh");
+    ssh.arg(&build_host.host)
+        .arg("--")
+        .arg(shell_quote(&build_host.stdbench.to_string_lossy()));
+    for arg in args {
+        ssh.arg(shell_quote(arg));
+    }
+    ssh.args(&[
+        "--local-build",
+        "--suppress",
+        "run",
+        "--suppress",
+        "compare",
+    ]);
+    ssh
+}
+
+/// Creates a command copying files matching `pattern` on the build host to `destination`.
+fn transfer_command(build_host: &BuildHost, pattern: &str, destination: &Path) -> Command {
+    let mut rsync = Command::new("rsync");
+    rsync
+        .args(&["-a", "--ignore-missing-args"])
+        .arg(format!("{}:{}", build_host.host, pattern))
+        .arg(destination);
+    rsync
+}
+
+/// Creates commands copying the indexes of `collections`, and the inventory of artifacts,
+/// build times, and index sizes in `workdir` from the build host.
+pub fn transfer_commands(
+    build_host: &BuildHost,
+    workdir: &Path,
+    collections: &[Collection],
+) -> Vec<Command> {
+    let mut commands: Vec<_> = collections
+        .iter()
+        .flat_map(|collection| vec![&collection.fwd_index, &collection.inv_index])
+        .map(|prefix| {
+            transfer_command(
+                build_host,
+                &format!("{}*", prefix.display()),
+                prefix.parent().unwrap_or_else(|| Path::new(".")),
+            )
+        })
+        .collect();
+    commands.push(transfer_command(
+        build_host,
+        &Artifacts::path(workdir).display().to_string(),
+        workdir,
+    ));
+    for dir in &[BUILD_TIMES_DIR, INDEX_SIZES_DIR] {
+        let dir = workdir.join(dir);
+        commands.push(transfer_command(
+            build_host,
+            &format!("{}/*", dir.display()),
+            &dir,
+        ));
+    }
+    commands
+}
+
+/// Builds the indexes of all collections on the build host, and copies them
+/// to the local host.
+#[cfg_attr(tarpaulin, skip)]
+pub fn build<C: Config>(build_host: &BuildHost, args: &[String], config: &C) -> Result<(), Error> {
+    info!("[{}] [build] Building indexes remotely", build_host.host);
+    let status = build_command(build_host, args)
+        .log()
+        .status()
+        .context("Failed to run ssh")?;
+    check_status("ssh", status, "Remote build failed")?;
+    for collection in config.collections() {
+        ensure_parent_exists(&collection.fwd_index)?;
+        ensure_parent_exists(&collection.inv_index)?;
+    }
+    fs::create_dir_all(config.workdir().join(BUILD_TIMES_DIR))?;
+    fs::create_dir_all(config.workdir().join(INDEX_SIZES_DIR))?;
+    info!("[{}] [build] Copying indexes", build_host.host);
+    for mut rsync in transfer_commands(build_host, config.workdir(), config.collections()) {
+        let status = rsync.log().status().context("Failed to run rsync")?;
+        check_status("rsync", status, "Failed to copy indexes from build host")?;
+    }
+    Ok(())
+}
+
+/// Lists the commands that [`build`](fn.build.html) would execute,
+/// with the arguments of the local session shown as a placeholder.
+pub fn plan<C: Config>(build_host: &BuildHost, config: &C) -> Vec<PlannedCommand> {
+    let ssh = build_command(build_host, &[String::from("<arguments>")]);
+    std::iter::once(ssh)
+        .chain(transfer_commands(
+            build_host,
+            config.workdir(),
+            config.collections(),
+        ))
+        .map(|command| PlannedCommand::new(&build_host.host, Stage::BuildIndex, &command))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{mock_set_up, MockSetup};
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_transfer_commands() {
+        let tmp = TempDir::new("remote").unwrap();
+        let MockSetup { config, .. } = mock_set_up(&tmp);
+        let build_host = BuildHost {
+            host: String::from("builder"),
+            stdbench: "stdbench".into(),
+        };
+        let commands = transfer_commands(&build_host, config.workdir(), config.collections());
+        assert_eq!(commands.len(), 2 * config.collections().len() + 3);
+        let args = |command: &Command| -> Vec<String> {
+            command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().to_string())
+                .collect()
+        };
+        let fwd_index = &config.collection(0).fwd_index;
+        assert_eq!(
+            args(&commands[0]),
+            vec![
+                String::from("-a"),
+                String::from("--ignore-missing-args"),
+                format!("builder:{}*", fwd_index.display()),
+                fwd_index.parent().unwrap().display().to_string(),
+            ]
+        );
+        let build_times = config.workdir().join("build_times");
+        assert_eq!(
+            args(&commands[commands.len() - 2])[2..],
+            [
+                format!("builder:{}/*", build_times.display()),
+                build_times.display().to_string(),
+            ]
+        );
+    }
+}
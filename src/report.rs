@@ -0,0 +1,587 @@
+//! Single HTML report of all evaluation and benchmark results of a config,
+//! and its condensed counterpart printed to the terminal at the end of a session.
+
+use crate::config::{format_output_path, Config, Run, RunKind};
+use crate::diff::escape;
+use crate::error::Error;
+use crate::run::{
+    eval_regressed, load_benchmark_results, load_eval_results, overall_measure, BenchmarkResults,
+};
+use crate::{ensure_parent_exists, Algorithm, Encoding, RegressionMargin};
+use itertools::iproduct;
+use std::cmp::Ordering;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Measures of evaluation results shown in the report, as named by `trec_eval`.
+pub const REPORT_MEASURES: &[&str] = &["map", "P_10", "ndcg", "recall_1000"];
+
+/// Outcome of comparing a single result with its baseline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    /// Run has no `compare_with`, or the baseline file is missing.
+    NoBaseline,
+    Unchanged,
+    Regression,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Self::NoBaseline => "",
+            Self::Unchanged => "ok",
+            Self::Regression => "regression",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Self::NoBaseline => Color::None,
+            Self::Unchanged => Color::Green,
+            Self::Regression => Color::Red,
+        }
+    }
+}
+
+/// Change of a single value compared to its baseline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Change {
+    /// No baseline value to compare with.
+    Unknown,
+    /// Same as the baseline, or faster.
+    Fine,
+    /// Higher measure than the baseline, or slower within the regression margin.
+    Tolerated,
+    /// Lower measure than the baseline, or slower beyond the regression margin.
+    Regressed,
+}
+
+impl Change {
+    fn of_measure(value: Option<f64>, baseline: Option<f64>) -> Self {
+        match (value, baseline) {
+            (Some(value), Some(baseline)) => match value.partial_cmp(&baseline) {
+                Some(Ordering::Equal) => Self::Fine,
+                Some(Ordering::Greater) => Self::Tolerated,
+                _ => Self::Regressed,
+            },
+            _ => Self::Unknown,
+        }
+    }
+
+    fn of_time(value: Option<f32>, baseline: Option<f32>, margin: RegressionMargin) -> Self {
+        match (value, baseline) {
+            (Some(value), Some(baseline)) if value <= baseline => Self::Fine,
+            (Some(value), Some(baseline)) if value <= baseline * (1.0 + margin.0) => {
+                Self::Tolerated
+            }
+            (Some(_), Some(_)) => Self::Regressed,
+            _ => Self::Unknown,
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Self::Unknown => Color::None,
+            Self::Fine => Color::Green,
+            Self::Tolerated => Color::Yellow,
+            Self::Regressed => Color::Red,
+        }
+    }
+}
+
+/// Color of a cell of the terminal table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Color {
+    None,
+    Green,
+    Yellow,
+    Red,
+}
+
+impl Color {
+    /// Wraps `text` in ANSI escape codes of the color.
+    fn paint(self, text: &str) -> String {
+        let code = match self {
+            Self::None => return text.to_string(),
+            Self::Green => 32,
+            Self::Yellow => 33,
+            Self::Red => 31,
+        };
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    }
+}
+
+/// Single table row: results of one algorithm and encoding on one topic set.
+#[derive(Debug, PartialEq)]
+struct Row {
+    run: String,
+    encoding: String,
+    algorithm: String,
+    topics: usize,
+    values: Vec<String>,
+    /// Changes of `values` compared to the baseline.
+    changes: Vec<Change>,
+    status: Status,
+}
+
+/// Results of all runs of a single collection.
+#[derive(Debug, Default, PartialEq)]
+struct Section {
+    collection: String,
+    evaluation: Vec<Row>,
+    benchmark: Vec<Row>,
+}
+
+/// Baseline counterpart of `path` produced by `format_output_path`, if the run has one.
+fn baseline_path(
+    run: &Run,
+    algorithm: &Algorithm,
+    encoding: &Encoding,
+    tid: usize,
+    suffix: &str,
+) -> Option<std::path::PathBuf> {
+    run.compare_with
+        .as_ref()
+        .map(|base| format_output_path(&base.path, algorithm, encoding, tid, suffix))
+        .filter(|path| path.exists())
+}
+
+fn evaluation_row(
+    run: &Run,
+    algorithm: &Algorithm,
+    encoding: &Encoding,
+    tid: usize,
+) -> Result<Option<Row>, Error> {
+    let path = format_output_path(&run.output, algorithm, encoding, tid, "trec_eval");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let results = load_eval_results(&path)?;
+    let baseline = match baseline_path(run, algorithm, encoding, tid, "trec_eval") {
+        Some(base) => Some(load_eval_results(&base)?),
+        None => None,
+    };
+    let status = match (&baseline, &run.compare_with) {
+        (Some(baseline), Some(base)) if eval_regressed(&results, baseline, &base.tolerances)? => {
+            Status::Regression
+        }
+        (Some(_), _) => Status::Unchanged,
+        _ => Status::NoBaseline,
+    };
+    let values = REPORT_MEASURES
+        .iter()
+        .map(|measure| {
+            overall_measure(&results, measure).map_or_else(|| String::from("-"), |v| v.to_string())
+        })
+        .collect();
+    let changes = REPORT_MEASURES
+        .iter()
+        .map(|measure| {
+            Change::of_measure(
+                overall_measure(&results, measure),
+                baseline
+                    .as_ref()
+                    .and_then(|baseline| overall_measure(baseline, measure)),
+            )
+        })
+        .collect();
+    Ok(Some(Row {
+        run: file_name(&run.output),
+        encoding: encoding.to_string(),
+        algorithm: algorithm.to_string(),
+        topics: tid,
+        values,
+        changes,
+        status,
+    }))
+}
+
+fn benchmark_row(
+    run: &Run,
+    algorithm: &Algorithm,
+    encoding: &Encoding,
+    tid: usize,
+    margin: RegressionMargin,
+) -> Result<Option<Row>, Error> {
+    let path = format_output_path(&run.output, algorithm, encoding, tid, "bench");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let results = load_benchmark_results(&path)?;
+    let baseline = match baseline_path(run, algorithm, encoding, tid, "bench") {
+        Some(base) => Some(load_benchmark_results(&base)?),
+        None => None,
+    };
+    let status = match &baseline {
+        Some(baseline)
+            if results
+                .regression_with_qps_margin(baseline, margin, run.qps_margin.unwrap_or(margin))?
+                .is_some() =>
+        {
+            Status::Regression
+        }
+        Some(_) => Status::Unchanged,
+        None => Status::NoBaseline,
+    };
+    let times = |results: &BenchmarkResults| {
+        [
+            Some(results.avg_time),
+            results.quantile_50,
+            results.quantile_90,
+            results.quantile_95,
+        ]
+    };
+    let values = times(&results)
+        .iter()
+        .map(|time| time.map_or_else(|| String::from("-"), |time| time.to_string()))
+        .collect();
+    let changes = match &baseline {
+        Some(baseline) => times(&results)
+            .iter()
+            .zip(&times(baseline))
+            .map(|(&time, &base)| Change::of_time(time, base, margin))
+            .collect(),
+        None => vec![Change::Unknown; 4],
+    };
+    Ok(Some(Row {
+        run: file_name(&run.output),
+        encoding: encoding.to_string(),
+        algorithm: algorithm.to_string(),
+        topics: tid,
+        values,
+        changes,
+        status,
+    }))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().to_string(),
+    )
+}
+
+/// Collects the results of all runs, grouped by collection, in the order of the config.
+/// Results not (yet) produced are skipped.
+fn collect_sections<C: Config>(config: &C) -> Result<Vec<Section>, Error> {
+    let mut sections: Vec<Section> = Vec::new();
+    for run in config.runs() {
+        if !sections
+            .iter()
+            .any(|section| section.collection == run.collection)
+        {
+            sections.push(Section {
+                collection: run.collection.clone(),
+                ..Section::default()
+            });
+        }
+        let section = sections
+            .iter_mut()
+            .find(|section| section.collection == run.collection)
+            .unwrap();
+        let (evaluate, benchmark) = match run.kind {
+            RunKind::Evaluate { .. } => (true, false),
+            RunKind::External { .. } => (true, true),
+            RunKind::Benchmark => (false, true),
+        };
+        for (algorithm, encoding, tid) in
+            iproduct!(&run.algorithms, &run.encodings, 0..run.topics.len())
+        {
+            if evaluate {
+                section
+                    .evaluation
+                    .extend(evaluation_row(run, algorithm, encoding, tid)?);
+            }
+            if benchmark {
+                section.benchmark.extend(benchmark_row(
+                    run,
+                    algorithm,
+                    encoding,
+                    tid,
+                    config.margin(),
+                )?);
+            }
+        }
+    }
+    Ok(sections)
+}
+
+fn render_table(title: &str, columns: &[&str], rows: &[Row]) -> String {
+    if rows.is_empty() {
+        return String::new();
+    }
+    let mut table = format!(
+        "<h3>{}</h3>\n<table>\n<tr><th>run</th><th>encoding</th><th>algorithm</th><th>topics</th>",
+        title
+    );
+    for column in columns {
+        table.push_str(&format!("<th>{}</th>", escape(column)));
+    }
+    table.push_str("<th>baseline</th></tr>\n");
+    for row in rows {
+        let class = if row.status == Status::Regression {
+            " class=\"regression\""
+        } else {
+            ""
+        };
+        table.push_str(&format!(
+            "<tr{}><td>{}</td><td>{}</td><td>{}</td><td>{}</td>",
+            class,
+            escape(&row.run),
+            escape(&row.encoding),
+            escape(&row.algorithm),
+            row.topics
+        ));
+        for value in &row.values {
+            table.push_str(&format!("<td>{}</td>", escape(value)));
+        }
+        table.push_str(&format!("<td>{}</td></tr>\n", row.status.label()));
+    }
+    table.push_str("</table>\n");
+    table
+}
+
+/// Renders all `trec_eval` and benchmark results of the runs of `config` as an HTML page,
+/// with tables per collection, and a row per encoding, algorithm, and topic set.
+///
+/// Rows that regressed compared to their baseline (by the same criteria as
+/// [`compare_with_baseline`](../run/fn.compare_with_baseline.html)) are highlighted.
+/// Times are in microseconds.
+pub fn render_report<C: Config>(config: &C) -> Result<String, Error> {
+    let mut body = String::from("<h1>Benchmark report</h1>\n");
+    for section in collect_sections(config)? {
+        body.push_str(&format!("<h2>{}</h2>\n", escape(&section.collection)));
+        body.push_str(&render_table(
+            "Effectiveness",
+            REPORT_MEASURES,
+            &section.evaluation,
+        ));
+        body.push_str(&render_table(
+            "Efficiency",
+            &["avg", "q50", "q90", "q95"],
+            &section.benchmark,
+        ));
+    }
+    Ok(format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Benchmark report</title>\n\
+         <style>table {{ border-collapse: collapse; }} td, th {{ padding: 2px 8px; }} \
+         .regression {{ background: #fdd; }}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        body
+    ))
+}
+
+/// Renders a table as aligned plain text, coloring each value by its change,
+/// and the run and baseline status by the status of the row.
+fn render_text_table(title: &str, columns: &[&str], rows: &[Row], color: bool) -> String {
+    if rows.is_empty() {
+        return String::new();
+    }
+    let header: Vec<(String, Color)> = ["run", "encoding", "algorithm", "topics"]
+        .iter()
+        .chain(columns)
+        .chain(&["baseline"])
+        .map(|column| (String::from(*column), Color::None))
+        .collect();
+    let lines: Vec<Vec<(String, Color)>> = std::iter::once(header)
+        .chain(rows.iter().map(|row| {
+            let mut cells = vec![
+                (row.run.clone(), row.status.color()),
+                (row.encoding.clone(), Color::None),
+                (row.algorithm.clone(), Color::None),
+                (row.topics.to_string(), Color::None),
+            ];
+            cells.extend(
+                row.values
+                    .iter()
+                    .cloned()
+                    .zip(row.changes.iter().copied().map(Change::color)),
+            );
+            cells.push((row.status.label().to_string(), row.status.color()));
+            cells
+        }))
+        .collect();
+    let mut widths = vec![0; lines[0].len()];
+    for line in &lines {
+        for (width, (text, _)) in widths.iter_mut().zip(line) {
+            *width = (*width).max(text.chars().count());
+        }
+    }
+    let mut table = format!("{}\n", title);
+    for line in &lines {
+        let cells: Vec<String> = line
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(idx, ((text, cell_color), &width))| {
+                let padded = if idx + 1 < widths.len() {
+                    format!("{:width$}", text, width = width)
+                } else {
+                    text.clone()
+                };
+                if color {
+                    cell_color.paint(&padded)
+                } else {
+                    padded
+                }
+            })
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// Renders the results of the runs of `config`, as in [`render_report`](fn.render_report.html),
+/// as aligned plain-text tables to be printed to the terminal.
+///
+/// If `color` is set, values are colored by their change compared to the baseline:
+/// green if unchanged or faster, yellow if higher or slower within the regression margin,
+/// and red if lower or slower beyond it. Run names are colored by the status of the row.
+pub fn render_summary_table<C: Config>(config: &C, color: bool) -> Result<String, Error> {
+    let mut text = String::new();
+    for section in collect_sections(config)? {
+        for (title, columns, rows) in &[
+            ("Effectiveness", REPORT_MEASURES, &section.evaluation),
+            (
+                "Efficiency",
+                &["avg", "q50", "q90", "q95"][..],
+                &section.benchmark,
+            ),
+        ] {
+            if !rows.is_empty() {
+                let title = format!("{}: {}", section.collection, title);
+                text.push_str(&render_text_table(&title, columns, rows, color));
+                text.push('\n');
+            }
+        }
+    }
+    Ok(text)
+}
+
+/// Whether the summary table printed to standard output should be colored:
+/// only if it is a terminal, and `NO_COLOR` is not set.
+pub fn color_enabled() -> bool {
+    env::var_os("NO_COLOR").is_none() && unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1
+}
+
+/// Writes the report (see [`render_report`](fn.render_report.html)) to `path`.
+pub fn write_report<C: Config>(config: &C, path: &Path) -> Result<(), Error> {
+    ensure_parent_exists(path)?;
+    fs::write(path, render_report(config)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RawConfig;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    /// Config with an unchanged evaluation run and a regressed benchmark run in `dir`.
+    fn report_config(dir: &Path) -> Result<RawConfig, Error> {
+        let mut config: RawConfig = serde_yaml::from_str(
+            "
+workdir: /workdir
+source: system
+margin: 0.1
+collections: []
+runs:
+    - collection: wapo
+      kind:
+          evaluate:
+              qrels: /qrels
+      algorithms: [wand]
+      encodings: [ef]
+      output: eval
+      topics:
+          - kind: simple
+            path: /topics
+    - collection: wapo
+      kind: benchmark
+      algorithms: [wand, maxscore]
+      encodings: [ef]
+      output: bench
+      topics:
+          - kind: simple
+            path: /topics",
+        )
+        .unwrap();
+        let path = |name: &str, algorithm: &str, suffix: &str| {
+            format_output_path(&dir.join(name), &algorithm.into(), &"ef".into(), 0, suffix)
+        };
+        config.runs[0].output = dir.join("eval");
+        config.runs[0].compare_with = Some(dir.join("base_eval").into());
+        config.runs[1].output = dir.join("bench");
+        config.runs[1].compare_with = Some(dir.join("base_bench").into());
+        fs::write(
+            path("eval", "wand", "trec_eval"),
+            "map\tall\t0.25\nP_10\tall\t0.4\n",
+        )?;
+        fs::write(
+            path("base_eval", "wand", "trec_eval"),
+            "map\tall\t0.25\nP_10\tall\t0.4\n",
+        )?;
+        let bench = |avg: f32| {
+            format!(
+                r#"{{"type":"ef","query":"wand","avg":{0},"q50":{0},"q90":{0},"q95":{0}}}"#,
+                avg
+            )
+        };
+        fs::write(path("bench", "wand", "bench"), bench(20.0))?;
+        fs::write(path("base_bench", "wand", "bench"), bench(10.0))?;
+        Ok(config)
+    }
+
+    #[test]
+    fn test_render_report() -> Result<(), Error> {
+        let tmp = TempDir::new("report").unwrap();
+        let mut config = report_config(tmp.path())?;
+        let report = tmp.path().join("report/index.html");
+        write_report(&config, &report)?;
+        let html = fs::read_to_string(&report)?;
+        assert!(html.contains("<h2>wapo</h2>"));
+        assert!(html.contains(
+            "<tr><td>eval</td><td>ef</td><td>wand</td><td>0</td>\
+             <td>0.25</td><td>0.4</td><td>-</td><td>-</td><td>ok</td></tr>"
+        ));
+        assert!(html.contains(
+            "<tr class=\"regression\"><td>bench</td><td>ef</td><td>wand</td><td>0</td>\
+             <td>20</td><td>20</td><td>20</td><td>20</td><td>regression</td></tr>"
+        ));
+        assert!(!html.contains("maxscore"));
+
+        config.runs[1].compare_with = Some(PathBuf::from("/missing").into());
+        let html = render_report(&config)?;
+        assert!(html.contains(
+            "<tr><td>bench</td><td>ef</td><td>wand</td><td>0</td>\
+             <td>20</td><td>20</td><td>20</td><td>20</td><td></td></tr>"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_summary_table() -> Result<(), Error> {
+        let tmp = TempDir::new("report").unwrap();
+        let config = report_config(tmp.path())?;
+        assert_eq!(
+            render_summary_table(&config, false)?,
+            "wapo: Effectiveness\n\
+             run   encoding  algorithm  topics  map   P_10  ndcg  recall_1000  baseline\n\
+             eval  ef        wand       0       0.25  0.4   -     -            ok\n\
+             \n\
+             wapo: Efficiency\n\
+             run    encoding  algorithm  topics  avg  q50  q90  q95  baseline\n\
+             bench  ef        wand       0       20   20   20   20   regression\n\
+             \n"
+        );
+        let colored = render_summary_table(&config, true)?;
+        assert!(colored.contains("\x1b[32meval\x1b[0m  ef  "));
+        assert!(colored.contains("\x1b[32m0.25\x1b[0m  \x1b[32m0.4 \x1b[0m  -     -  "));
+        assert!(colored.contains("\x1b[31m20 \x1b[0m"));
+        assert!(colored.contains("\x1b[31mregression\x1b[0m\n"));
+        Ok(())
+    }
+}
@@ -1,27 +1,679 @@
 //! All things related to experimental runs, including efficiency and precision runs.
 
 use crate::{
-    config::{format_output_path, output_path_formatter, Collection, Run, RunKind, Topics},
-    error::Error,
-    executor::Executor,
-    Algorithm, CommandDebug, Encoding, RegressionMargin,
+    build::BuildTimes,
+    config::{
+        baseline_path, format_output_path, output_path_formatter, Collection, Evaluator,
+        QueryClass, QueryNormalization, Run, RunKind, Stage, Topics,
+    },
+    diff::render_html_diff,
+    error::{Error, ErrorKind},
+    eval,
+    executor::{check_status, evict_from_page_cache, Executor},
+    features::{FeatureExtractor, CSV_HEADER},
+    identity::{topics_hash, BaselineMeta, RunIdentity, RESULTS_K},
+    lang,
+    plan::PlannedCommand,
+    selective,
+    summary::{Warning, WarningKind},
+    Algorithm, CommandDebug, Encoding, RegressionMargin, Scorer,
 };
 use cranky::ResultRecord;
 use failure::ResultExt;
 use itertools::iproduct;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::BufReader;
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 use std::{fmt, fs, process::Command};
+use strum::IntoEnumIterator;
 
+/// Path to TREC topics at `path` extracted into `temp_dir`, without the field suffix.
+fn extracted_topics_path(path: &Path, temp_dir: &Path) -> Result<PathBuf, Error> {
+    Ok(temp_dir.join(
+        path.file_name()
+            .ok_or_else(|| format!("Invalid topics path: {}", path.display()))?,
+    ))
+}
+
+/// Path of the queries of weighted simple topics at `path`, with the weights removed.
+fn unweighted_queries_path(path: &Path, id: &str, temp_dir: &Path) -> PathBuf {
+    temp_dir.join(format!(
+        "{}.unweighted.{}",
+        path.file_name().unwrap_or_default().to_string_lossy(),
+        id
+    ))
+}
+
+/// Identifies the `idx`-th source of the combined topics identified by `id`,
+/// e.g., `0-1` for the second source of the first topics of a run.
+fn source_id(id: &str, idx: usize) -> String {
+    format!("{}-{}", id, idx)
+}
+
+/// Resolves the query file of the topics identified by `id` like `queries_path`,
+/// but adds the commands that would produce it to `commands` instead of executing them.
+fn planned_queries_path(
+    topics: &Topics,
+    id: &str,
+    executor: &Executor,
+    temp_dir: &Path,
+    commands: &mut Vec<Command>,
+) -> Result<String, Error> {
+    match topics {
+        Topics::Trec { path, field, .. } => {
+            let output = extracted_topics_path(path, temp_dir)?;
+            commands.push(executor.extract_topics_command(&path, &output));
+            Ok(format!("{}.{}", &output.display(), field))
+        }
+        Topics::Simple { path, .. } => match topic_weights(topics)? {
+            Some(_) => Ok(unweighted_queries_path(path, id, temp_dir)
+                .display()
+                .to_string()),
+            None => Ok(path.to_str().unwrap().to_string()),
+        },
+        Topics::Combined { sources } => {
+            for (idx, source) in sources.iter().enumerate() {
+                planned_queries_path(
+                    &source.topics,
+                    &source_id(id, idx),
+                    executor,
+                    temp_dir,
+                    commands,
+                )?;
+            }
+            Ok(temp_dir
+                .join(format!("combined.{}", id))
+                .display()
+                .to_string())
+        }
+        Topics::Standard { standard } => Err(Error::from(format!(
+            "Unresolved standard topics: {}",
+            standard
+        ))),
+        Topics::Url { url, .. } => Err(Error::from(format!("Unresolved topics URL: {}", url))),
+    }
+}
+
+/// Resolves the query file of topics, extracting TREC topics
+/// and merging combined topic sets into `temp_dir` if needed.
+///
+/// The files written to `temp_dir` are named after `id`, which identifies the topics
+/// within the run: the index of the topics, followed by those of the sources
+/// of nested combined topics (see [`source_id`](fn.source_id.html)).
 #[cfg_attr(tarpaulin, skip)]
-fn queries_path(topics: &Topics, executor: &Executor) -> Result<String, Error> {
+fn queries_path(
+    topics: &Topics,
+    id: &str,
+    executor: &Executor,
+    temp_dir: &Path,
+) -> Result<String, Error> {
+    match topics {
+        Topics::Trec { path, field, .. } => {
+            let output = extracted_topics_path(path, temp_dir)?;
+            executor.extract_topics(&path, &output)?;
+            Ok(format!("{}.{}", &output.display(), field))
+        }
+        Topics::Simple { path, .. } => {
+            let content = fs::read_to_string(path).with_context(|_| path.display().to_string())?;
+            match QueryWeights::split(&content)? {
+                (queries, Some(_)) => {
+                    let output = unweighted_queries_path(path, id, temp_dir);
+                    fs::write(&output, queries)?;
+                    Ok(output.display().to_string())
+                }
+                (_, None) => Ok(path.to_str().unwrap().to_string()),
+            }
+        }
+        Topics::Combined { sources } => {
+            let mut merged = String::new();
+            for (idx, source) in sources.iter().enumerate() {
+                let path = queries_path(&source.topics, &source_id(id, idx), executor, temp_dir)?;
+                let queries = sample_queries(
+                    &fs::read_to_string(&path).with_context(|_| path.clone())?,
+                    source.weight,
+                );
+                fs::write(source_queries_path(temp_dir, id, idx), &queries)?;
+                merged.push_str(&queries);
+            }
+            let path = temp_dir.join(format!("combined.{}", id));
+            fs::write(&path, merged)?;
+            Ok(path.display().to_string())
+        }
+        Topics::Standard { standard } => Err(Error::from(format!(
+            "Unresolved standard topics: {}",
+            standard
+        ))),
+        Topics::Url { url, .. } => Err(Error::from(format!("Unresolved topics URL: {}", url))),
+    }
+}
+
+/// Samples queries evenly, keeping a `weight` fraction of them (rounded down).
+fn sample_queries(queries: &str, weight: f64) -> String {
+    let mut output = String::new();
+    for (idx, line) in queries.lines().filter(|l| !l.trim().is_empty()).enumerate() {
+        #[allow(clippy::cast_precision_loss)]
+        let idx = idx as f64;
+        if ((idx + 1.0) * weight).floor() > (idx * weight).floor() {
+            output.push_str(line);
+            output.push('\n');
+        }
+    }
+    output
+}
+
+/// Path to the sampled queries of a single source of combined topics.
+fn source_queries_path(temp_dir: &Path, id: &str, source_idx: usize) -> PathBuf {
+    temp_dir.join(format!("combined.{}.source-{}", id, source_idx))
+}
+
+/// Weights of queries by their IDs, given in simple topics as `qid:query\tweight`,
+/// e.g., to reflect the distribution of production traffic.
+///
+/// Queries without an ID are identified by their (0-based) line number,
+/// and queries without a weight have weight 1.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryWeights(pub BTreeMap<String, f64>);
+
+impl QueryWeights {
+    /// Splits weights from queries, returning the queries without weights,
+    /// and the weights if any query has one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::run::QueryWeights;
+    /// let (queries, weights) = QueryWeights::split("1:a b\t2.5\n2:c\n").unwrap();
+    /// assert_eq!(queries, "1:a b\n2:c\n");
+    /// let weights = weights.unwrap();
+    /// assert_eq!(weights.weight("1"), 2.5);
+    /// assert_eq!(weights.weight("2"), 1.0);
+    /// assert_eq!(QueryWeights::split("1:a\n").unwrap(), (String::from("1:a\n"), None));
+    /// assert!(QueryWeights::split("1:a\theavy\n").is_err());
+    /// ```
+    pub fn split(queries: &str) -> Result<(String, Option<Self>), Error> {
+        let mut unweighted = String::new();
+        let mut weights = BTreeMap::new();
+        for (idx, line) in queries.lines().filter(|l| !l.trim().is_empty()).enumerate() {
+            let query = match line.rfind('\t') {
+                Some(pos) => {
+                    let weight = line[pos + 1..]
+                        .trim()
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|weight| *weight >= 0.0)
+                        .ok_or_else(|| format!("Invalid query weight: {}", line))?;
+                    let query = &line[..pos];
+                    let qid = match query.find(':') {
+                        Some(colon) => query[..colon].to_string(),
+                        None => idx.to_string(),
+                    };
+                    weights.insert(qid, weight);
+                    query
+                }
+                None => line,
+            };
+            unweighted.push_str(query);
+            unweighted.push('\n');
+        }
+        let weights = if weights.is_empty() {
+            None
+        } else {
+            Some(Self(weights))
+        };
+        Ok((unweighted, weights))
+    }
+
+    /// Weight of the query `qid`.
+    pub fn weight(&self, qid: &str) -> f64 {
+        self.0.get(qid).copied().unwrap_or(1.0)
+    }
+
+    /// Weighted mean of per-query `values`, or `None` if the total weight is 0.
+    pub fn mean(&self, values: &[(String, f64)]) -> Option<f64> {
+        let total: f64 = values.iter().map(|(qid, _)| self.weight(qid)).sum();
+        if total > 0.0 {
+            let sum: f64 = values
+                .iter()
+                .map(|(qid, value)| self.weight(qid) * value)
+                .sum();
+            Some(sum / total)
+        } else {
+            None
+        }
+    }
+
+    /// Weighted `quantile` of per-query `values`, i.e., the smallest value such that
+    /// the queries with lower or equal values have at least that fraction of the total weight.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::run::QueryWeights;
+    /// let values: Vec<_> = vec![("a", 10.0), ("b", 20.0), ("c", 30.0)]
+    ///     .into_iter()
+    ///     .map(|(qid, value)| (String::from(qid), value))
+    ///     .collect();
+    /// let weights = QueryWeights(vec![(String::from("c"), 8.0)].into_iter().collect());
+    /// assert_eq!(weights.quantile(&values, 0.1), Some(10.0));
+    /// assert_eq!(weights.quantile(&values, 0.5), Some(30.0));
+    /// assert_eq!(weights.mean(&values), Some(28.0));
+    /// ```
+    pub fn quantile(&self, values: &[(String, f64)], quantile: f64) -> Option<f64> {
+        let mut sorted: Vec<_> = values
+            .iter()
+            .map(|(qid, value)| (*value, self.weight(qid)))
+            .collect();
+        sorted.sort_by(|lhs, rhs| lhs.0.partial_cmp(&rhs.0).unwrap_or(Ordering::Equal));
+        let total: f64 = sorted.iter().map(|(_, weight)| weight).sum();
+        let mut cumulative = 0.0;
+        for (value, weight) in sorted {
+            cumulative += weight;
+            if cumulative >= quantile * total && cumulative > 0.0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+/// Reads the query weights of `topics`, if given; only simple topics can have weights.
+pub(crate) fn topic_weights(topics: &Topics) -> Result<Option<QueryWeights>, Error> {
     match topics {
-        Topics::Trec { path, field } => {
-            executor.extract_topics(&path, &path)?;
-            Ok(format!("{}.{}", &path.display(), field))
+        Topics::Simple { path, .. } => {
+            let content = fs::read_to_string(path).with_context(|_| path.display().to_string())?;
+            Ok(QueryWeights::split(&content)?.1)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Inserts latencies aggregated with query `weights` into JSON benchmark results,
+/// under `weighted`.
+fn with_weighted_times(
+    results: &str,
+    weights: &QueryWeights,
+    times: &[QueryTime],
+) -> Result<String, Error> {
+    let values: Vec<_> = times
+        .iter()
+        .map(|time| (time.id.clone(), time.mean()))
+        .collect();
+    let mut weighted = serde_json::Map::new();
+    let aggregates = [
+        ("avg", weights.mean(&values)),
+        ("q50", weights.quantile(&values, 0.5)),
+        ("q90", weights.quantile(&values, 0.9)),
+        ("q95", weights.quantile(&values, 0.95)),
+    ];
+    for (name, value) in &aggregates {
+        if let Some(value) = value {
+            weighted.insert(String::from(*name), serde_json::Value::from(*value));
+        }
+    }
+    with_field(results, "weighted", serde_json::Value::Object(weighted))
+}
+
+/// Writes the means of the per-query measures in `eval_results`, weighted with query
+/// `weights`, in the format of overall `trec_eval` measures.
+fn weighted_eval_results(eval_results: &str, weights: &QueryWeights) -> String {
+    let mut measures: BTreeMap<&str, Vec<(String, f64)>> = BTreeMap::new();
+    for line in eval_results.lines() {
+        if let [measure, qid, value] = line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            if *qid == "all" {
+                continue;
+            }
+            if let Ok(value) = value.parse::<f64>() {
+                measures
+                    .entry(*measure)
+                    .or_default()
+                    .push((qid.to_string(), value));
+            }
+        }
+    }
+    let mut output = String::new();
+    for (measure, values) in measures {
+        if let Some(mean) = weights.mean(&values) {
+            output.push_str(&format!("{}\tall\t{:.4}\n", measure, mean));
         }
-        Topics::Simple { path } => Ok(path.to_str().unwrap().to_string()),
     }
+    output
+}
+
+/// Writes the weighted evaluation results next to `trec_eval_path`, if `weights` are given.
+fn write_weighted_eval(trec_eval_path: &Path, weights: Option<&QueryWeights>) -> Result<(), Error> {
+    if let Some(weights) = weights {
+        let results = load_eval_results(trec_eval_path)?;
+        fs::write(
+            format!("{}.weighted", trec_eval_path.display()),
+            weighted_eval_results(&results, weights),
+        )?;
+    }
+    Ok(())
+}
+
+/// Cache states measured separately with `cold_and_warm` enabled.
+const CACHE_STATES: [&str; 2] = ["cold", "warm"];
+
+fn cache_suffix(state: &str) -> String {
+    format!("bench.{}", state)
+}
+
+fn source_suffix(source_idx: usize) -> String {
+    format!("bench.source-{}", source_idx)
+}
+
+/// Statistics of applying a normalization policy to a query file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NormalizationStats {
+    /// Number of queries whose terms have changed.
+    pub modified: usize,
+    /// Number of queries whose text has changed only in whitespace, e.g., by collapsing spaces,
+    /// which does not affect their terms.
+    pub whitespace_only: usize,
+    /// Number of removed duplicate queries.
+    pub duplicates: usize,
+}
+
+fn normalize_query(query: &str, policy: QueryNormalization) -> String {
+    let query = if policy.strip_punctuation {
+        query
+            .chars()
+            .map(|c| if c.is_ascii_punctuation() { ' ' } else { c })
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    } else {
+        query.to_string()
+    };
+    if policy.lowercase {
+        query.to_lowercase()
+    } else {
+        query
+    }
+}
+
+/// Applies a normalization policy to queries in the format `qid:query`
+/// (one per line; the ID is optional).
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::QueryNormalization;
+/// # use stdbench::run::{normalize_queries, NormalizationStats};
+/// let policy = QueryNormalization {
+///     dedup: true,
+///     lowercase: true,
+///     strip_punctuation: true,
+/// };
+/// let (queries, stats) = normalize_queries("1:Hello, World!\n2:hello world\n3:foo", policy);
+/// assert_eq!(queries, "1:hello world\n3:foo\n");
+/// assert_eq!(
+///     stats,
+///     NormalizationStats {
+///         modified: 1,
+///         whitespace_only: 0,
+///         duplicates: 1
+///     }
+/// );
+/// ```
+pub fn normalize_queries(
+    queries: &str,
+    policy: QueryNormalization,
+) -> (String, NormalizationStats) {
+    let mut stats = NormalizationStats::default();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut output = String::new();
+    for line in queries.lines().filter(|l| !l.trim().is_empty()) {
+        let (id, query) = line
+            .find(':')
+            .map_or(("", line), |pos| (&line[..=pos], &line[pos + 1..]));
+        let normalized = normalize_query(query, policy);
+        if normalized.split_whitespace().ne(query.split_whitespace()) {
+            stats.modified += 1;
+        } else if normalized != query {
+            stats.whitespace_only += 1;
+        }
+        if policy.dedup && !seen.insert(normalized.clone()) {
+            stats.duplicates += 1;
+            continue;
+        }
+        output.push_str(id);
+        output.push_str(&normalized);
+        output.push('\n');
+    }
+    (output, stats)
+}
+
+/// Path to the normalized queries of the file at `path`, within `temp_dir`.
+fn normalized_queries_path(path: &str, temp_dir: &Path) -> PathBuf {
+    temp_dir.join(format!(
+        "{}.normalized",
+        Path::new(path).file_name().map_or_else(
+            || path.to_string(),
+            |name| name.to_string_lossy().to_string()
+        )
+    ))
+}
+
+/// Path to a subset of the queries at `queries`, identified by `suffix`, within `temp_dir`.
+fn split_queries_path(queries: &str, suffix: &str, temp_dir: &Path) -> PathBuf {
+    temp_dir.join(format!(
+        "{}.{}",
+        Path::new(queries).file_name().map_or_else(
+            || queries.to_string(),
+            |name| name.to_string_lossy().to_string()
+        ),
+        suffix
+    ))
+}
+
+/// Resolves query files of a run, and applies normalization if requested.
+///
+/// All intermediate files are written to `temp_dir`.
+fn prepare_queries(run: &Run, executor: &Executor, temp_dir: &Path) -> Result<Vec<String>, Error> {
+    run.topics
+        .iter()
+        .enumerate()
+        .map(|(tid, topics)| {
+            let path = queries_path(topics, &tid.to_string(), executor, temp_dir)?;
+            if run.normalize.is_noop() {
+                return Ok(path);
+            }
+            let (queries, stats) = normalize_queries(
+                &fs::read_to_string(&path).with_context(|_| path.clone())?,
+                run.normalize,
+            );
+            info!(
+                "[{}] Normalized queries in {}: {} modified, {} changed only in whitespace, \
+                 {} duplicates removed",
+                run.collection, path, stats.modified, stats.whitespace_only, stats.duplicates
+            );
+            let normalized_path = normalized_queries_path(&path, temp_dir);
+            fs::write(&normalized_path, queries)?;
+            Ok(normalized_path.display().to_string())
+        })
+        .collect()
+}
+
+/// Fraction of query terms above which an out-of-vocabulary warning is issued.
+pub const OOV_WARNING_THRESHOLD: f64 = 0.1;
+
+/// Computes the fraction of query terms missing from the lexicon of the collection.
+///
+/// Returns `None` if the collection's terms or the queries are not available,
+/// or there are no query terms.
+#[allow(clippy::cast_precision_loss)]
+fn oov_rate(collection: &Collection, queries: &str) -> Result<Option<f64>, Error> {
+    let terms = collection.terms();
+    if !terms.exists() || !Path::new(queries).exists() {
+        return Ok(None);
+    }
+    let extractor = FeatureExtractor::new(
+        BufReader::new(fs::File::open(&terms).with_context(|_| terms.display().to_string())?),
+        Vec::new(),
+    )?;
+    let (total, known) = fs::read_to_string(queries)
+        .with_context(|_| queries.to_string())?
+        .lines()
+        .map(|line| extractor.features(line))
+        .fold((0, 0), |(total, known), features| {
+            (total + features.term_count, known + features.known_terms)
+        });
+    Ok(if total == 0 {
+        None
+    } else {
+        Some(1.0 - known as f64 / total as f64)
+    })
+}
+
+fn oov_warnings(collection: &Collection, queries: &[String]) -> Result<Vec<Warning>, Error> {
+    let mut warnings = Vec::new();
+    for path in queries {
+        if let Some(rate) = oov_rate(collection, path)? {
+            if rate > OOV_WARNING_THRESHOLD {
+                warnings.push(Warning::new(
+                    WarningKind::OutOfVocabulary,
+                    format!(
+                        "{:.1}% of query terms in {} not found in collection {}",
+                        rate * 100.0,
+                        path,
+                        collection.name
+                    ),
+                ));
+            }
+        }
+    }
+    Ok(warnings)
+}
+
+/// Splits queries in the format `qid:query` by their class.
+///
+/// Classes without any queries are omitted.
+pub fn split_by_class(queries: &str) -> Vec<(QueryClass, String)> {
+    let mut classes: Vec<(QueryClass, String)> = Vec::new();
+    for line in queries.lines() {
+        let query = line.find(':').map_or(line, |pos| &line[pos + 1..]);
+        if let Some(class) = QueryClass::of(query.split_whitespace().count()) {
+            let idx = classes
+                .iter()
+                .position(|(c, _)| *c == class)
+                .unwrap_or_else(|| {
+                    classes.push((class, String::new()));
+                    classes.len() - 1
+                });
+            classes[idx].1.push_str(line);
+            classes[idx].1.push('\n');
+        }
+    }
+    classes.sort_by_key(|(class, _)| *class as usize);
+    classes
+}
+
+fn class_suffix(class: QueryClass) -> String {
+    format!("bench.{}", class)
+}
+
+/// Aggregates the per-query `times` of a benchmark with `results` over the queries of each class
+/// (see [`split_by_class`](fn.split_by_class.html)), into results of the same encoding
+/// and algorithm. Queries without an ID are identified by their (0-based) line number.
+/// Classes without any timed queries are omitted.
+fn class_results(
+    results: &str,
+    queries: &str,
+    times: &[QueryTime],
+) -> Result<Vec<(QueryClass, String)>, Error> {
+    let results: BenchmarkResults =
+        serde_json::from_str(results).context("Unable to parse benchmark results")?;
+    let classes: HashMap<String, QueryClass> = queries
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .filter_map(|(idx, line)| {
+            let (qid, query) = match line.find(':') {
+                Some(pos) => (line[..pos].to_string(), &line[pos + 1..]),
+                None => (idx.to_string(), line),
+            };
+            QueryClass::of(query.split_whitespace().count()).map(|class| (qid, class))
+        })
+        .collect();
+    let unweighted = QueryWeights::default();
+    let mut class_results = Vec::new();
+    for class in QueryClass::iter() {
+        let values: Vec<_> = times
+            .iter()
+            .filter(|time| classes.get(&time.id) == Some(&class))
+            .map(|time| (time.id.clone(), time.mean()))
+            .collect();
+        if let Some(avg) = unweighted.mean(&values) {
+            #[allow(clippy::cast_possible_truncation)]
+            let quantile = |q| unweighted.quantile(&values, q).map(|value| value as f32);
+            #[allow(clippy::cast_possible_truncation)]
+            let class_result = BenchmarkResults {
+                kind: results.kind.clone(),
+                algorithm: results.algorithm.clone(),
+                avg_time: avg as f32,
+                quantile_50: quantile(0.5),
+                quantile_90: quantile(0.9),
+                quantile_95: quantile(0.95),
+                counters: None,
+                phases: BTreeMap::new(),
+                qps: None,
+                id: None,
+            };
+            class_results.push((
+                class,
+                serde_json::to_string(&class_result).context("Failed to serialize")?,
+            ));
+        }
+    }
+    Ok(class_results)
+}
+
+/// Splits queries in the format `qid:query` by their detected [language](../lang/fn.detect.html),
+/// ordered by the language code.
+pub fn split_by_language(queries: &str) -> Vec<(&'static str, String)> {
+    let mut languages: BTreeMap<&'static str, String> = BTreeMap::new();
+    for line in queries.lines().filter(|line| !line.trim().is_empty()) {
+        let query = line.find(':').map_or(line, |pos| &line[pos + 1..]);
+        let queries = languages.entry(lang::detect(query)).or_default();
+        queries.push_str(line);
+        queries.push('\n');
+    }
+    languages.into_iter().collect()
+}
+
+fn language_suffix(base: &str, language: &str) -> String {
+    format!("{}.lang.{}", base, language)
+}
+
+/// Aggregates the evaluation results of a run over the queries of each detected language.
+fn evaluate_per_language(
+    run: &Run,
+    algorithm: &Algorithm,
+    encoding: &Encoding,
+    tid: usize,
+    queries: &str,
+) -> Result<(), Error> {
+    let content = fs::read_to_string(queries).with_context(|_| queries.to_string())?;
+    let trec_eval_path = format_output_path(&run.output, algorithm, encoding, tid, "trec_eval");
+    let eval_results = load_eval_results(&trec_eval_path)?;
+    for (language, language_queries) in split_by_language(&content) {
+        let qids: HashSet<&str> = language_queries
+            .lines()
+            .filter_map(|line| line.find(':').map(|pos| &line[..pos]))
+            .collect();
+        let suffix = language_suffix("trec_eval", language);
+        fs::write(
+            format_output_path(&run.output, algorithm, encoding, tid, &suffix),
+            eval::aggregate(&eval_results, |qid| qids.contains(qid)),
+        )?;
+    }
+    Ok(())
 }
 
 /// The result of checking against a gold standard.
@@ -34,25 +686,156 @@ pub enum RunStatus {
     Regression(usize),
 }
 
+/// Hardware counters collected with `perf stat`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct HardwareCounters {
+    /// CPU cycles.
+    pub cycles: Option<f64>,
+    /// Retired instructions.
+    pub instructions: Option<f64>,
+    /// Last level cache load misses.
+    pub llc_misses: Option<f64>,
+}
+
+impl HardwareCounters {
+    /// Parses the JSON lines produced by `perf stat -j`; other lines are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::run::HardwareCounters;
+    /// let output = r#"some log line
+    /// {"counter-value" : "2000.000000", "unit" : "", "event" : "cycles"}
+    /// {"counter-value" : "3000.000000", "unit" : "", "event" : "instructions:u"}
+    /// {"counter-value" : "<not supported>", "unit" : "", "event" : "LLC-load-misses"}"#;
+    /// let counters = HardwareCounters::parse(output);
+    /// assert_eq!(counters.cycles, Some(2000.0));
+    /// assert_eq!(counters.instructions, Some(3000.0));
+    /// assert_eq!(counters.llc_misses, None);
+    /// assert_eq!(counters.ipc(), Some(1.5));
+    /// ```
+    pub fn parse(perf_output: &str) -> Self {
+        let mut counters = Self::default();
+        for value in perf_output
+            .lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        {
+            let event = value["event"].as_str().unwrap_or("");
+            let count = value["counter-value"]
+                .as_str()
+                .and_then(|v| v.parse::<f64>().ok());
+            if event.starts_with("cycles") {
+                counters.cycles = count;
+            } else if event.starts_with("instructions") {
+                counters.instructions = count;
+            } else if event.starts_with("LLC-load-misses") {
+                counters.llc_misses = count;
+            }
+        }
+        counters
+    }
+
+    /// Instructions per cycle.
+    pub fn ipc(&self) -> Option<f64> {
+        match (self.instructions, self.cycles) {
+            (Some(instructions), Some(cycles)) if cycles > 0.0 => Some(instructions / cycles),
+            _ => None,
+        }
+    }
+}
+
+/// Header of the CSV file with per-query latencies.
+pub const QUERY_TIMES_CSV_HEADER: &str = "qid,mean,min,max";
+
+/// Latencies of a single query, as reported by `queries --extract`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryTime {
+    /// Query ID.
+    pub id: String,
+    /// Measured times in microseconds, one for each repetition.
+    pub times: Vec<f64>,
+}
+
+impl QueryTime {
+    /// Parses the tab-separated lines of `queries --extract`, each with a query ID
+    /// followed by one or more times in microseconds; empty lines are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::run::QueryTime;
+    /// let times = QueryTime::parse("1\t120\n\n2\t80\t100\n").unwrap();
+    /// assert_eq!(times[1].id, "2");
+    /// assert_eq!(times[1].times, vec![80.0, 100.0]);
+    /// assert_eq!(times[1].csv_row(), "2,90,80,100");
+    /// assert!(QueryTime::parse("1\tfast").is_err());
+    /// assert!(QueryTime::parse("1").is_err());
+    /// ```
+    pub fn parse(output: &str) -> Result<Vec<Self>, Error> {
+        output
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let mut fields = line.split('\t');
+                let id = fields.next().unwrap_or_default().trim().to_string();
+                let times = fields
+                    .map(|time| time.trim().parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .ok()
+                    .filter(|times| !times.is_empty())
+                    .ok_or_else(|| Error::from(format!("Invalid query time: {}", line)))?;
+                Ok(Self { id, times })
+            })
+            .collect()
+    }
+
+    /// Average time in microseconds.
+    pub fn mean(&self) -> f64 {
+        #[allow(clippy::cast_precision_loss)]
+        let count = self.times.len() as f64;
+        self.times.iter().sum::<f64>() / count
+    }
+
+    /// Formats the ID with the average, minimum, and maximum times as a CSV row.
+    pub fn csv_row(&self) -> String {
+        let min = self.times.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = self.times.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        format!("{},{},{},{}", self.id, self.mean(), min, max)
+    }
+}
+
 /// Benchmark results as obtained from `queries` in JSON format.
 #[derive(Serialize, Deserialize, Debug)]
-struct BenchmarkResults {
+pub(crate) struct BenchmarkResults {
     #[serde(rename = "type")]
-    kind: Encoding,
+    pub(crate) kind: Encoding,
     #[serde(rename = "query")]
-    algorithm: Algorithm,
+    pub(crate) algorithm: Algorithm,
     #[serde(rename = "avg")]
-    avg_time: f32,
-    #[serde(rename = "q50")]
-    quantile_50: f32,
-    #[serde(rename = "q90")]
-    quantile_90: f32,
-    #[serde(rename = "q95")]
-    quantile_95: f32,
+    pub(crate) avg_time: f32,
+    /// Latency quantiles, unless only the total time was measured, as in external runs.
+    #[serde(rename = "q50", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) quantile_50: Option<f32>,
+    #[serde(rename = "q90", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) quantile_90: Option<f32>,
+    #[serde(rename = "q95", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) quantile_95: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) counters: Option<HardwareCounters>,
+    /// Average times of query processing phases, such as parsing, lookup, or scoring,
+    /// if reported by `queries`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) phases: BTreeMap<String, f32>,
+    /// Throughput in queries per second (see [`queries_per_second`](fn.queries_per_second.html)).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) qps: Option<f64>,
+    /// Identity of the measured configuration (see [`RunIdentity`](../identity/struct.RunIdentity.html)).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
-struct PerformanceRegression {
+pub(crate) struct PerformanceRegression {
     #[serde(rename = "avg")]
     avg_time: Option<(f32, f32)>,
     #[serde(rename = "q50")]
@@ -61,6 +844,24 @@ struct PerformanceRegression {
     quantile_90: Option<(f32, f32)>,
     #[serde(rename = "q95")]
     quantile_95: Option<(f32, f32)>,
+    instructions: Option<(f64, f64)>,
+    ipc: Option<(f64, f64)>,
+    qps: Option<(f64, f64)>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    phases: BTreeMap<String, (f32, f32)>,
+}
+
+impl PerformanceRegression {
+    fn is_empty(&self) -> bool {
+        self.avg_time.is_none()
+            && self.quantile_50.is_none()
+            && self.quantile_90.is_none()
+            && self.quantile_95.is_none()
+            && self.instructions.is_none()
+            && self.ipc.is_none()
+            && self.qps.is_none()
+            && self.phases.is_empty()
+    }
 }
 
 impl fmt::Display for PerformanceRegression {
@@ -74,6 +875,24 @@ impl fmt::Display for PerformanceRegression {
                 writeln!(f, "{}: {} --> {}", prop, baseline, time)?;
             }
         }
+        for (prop, regression) in std::iter::once(("instructions", self.instructions))
+            .chain(std::iter::once(("ipc", self.ipc)))
+            .chain(std::iter::once(("qps", self.qps)))
+        {
+            if let Some((value, baseline)) = regression {
+                writeln!(f, "{}: {} --> {}", prop, baseline, value)?;
+            }
+        }
+        for (phase, (time, baseline)) in &self.phases {
+            writeln!(
+                f,
+                "{} time: {} --> {} ({:+.0}%)",
+                phase,
+                baseline,
+                time,
+                (time / baseline - 1.0) * 100.0
+            )?;
+        }
         write!(f, "")
     }
 }
@@ -86,10 +905,31 @@ impl BenchmarkResults {
             None
         }
     }
-    fn regression(
+
+    /// Same as [`calc_diff`](#method.calc_diff), but only if both quantiles were measured.
+    fn calc_quantile_diff(
+        value: Option<f32>,
+        gold: Option<f32>,
+        margin: RegressionMargin,
+    ) -> Option<(f32, f32)> {
+        Self::calc_diff(value?, gold?, margin)
+    }
+
+    pub(crate) fn regression(
+        &self,
+        gold: &Self,
+        margin: RegressionMargin,
+    ) -> Result<Option<PerformanceRegression>, Error> {
+        self.regression_with_qps_margin(gold, margin, margin)
+    }
+
+    /// Same as [`regression`](#method.regression), but with a separate margin
+    /// of the throughput drop.
+    pub(crate) fn regression_with_qps_margin(
         &self,
         gold: &Self,
         margin: RegressionMargin,
+        qps_margin: RegressionMargin,
     ) -> Result<Option<PerformanceRegression>, Error> {
         if self.kind != gold.kind {
             return Err(Error::from("Encodings do not match"));
@@ -98,272 +938,3010 @@ impl BenchmarkResults {
             return Err(Error::from("Algorithms do not match"));
         }
         let avg = Self::calc_diff(self.avg_time, gold.avg_time, margin);
-        let q50 = Self::calc_diff(self.quantile_50, gold.quantile_50, margin);
-        let q90 = Self::calc_diff(self.quantile_90, gold.quantile_90, margin);
-        let q95 = Self::calc_diff(self.quantile_95, gold.quantile_95, margin);
-        Ok(match (avg, q50, q90, q95) {
-            (None, None, None, None) => None,
-            (avg_time, quantile_50, quantile_90, quantile_95) => Some(PerformanceRegression {
-                avg_time,
-                quantile_50,
-                quantile_90,
-                quantile_95,
-            }),
+        let q50 = Self::calc_quantile_diff(self.quantile_50, gold.quantile_50, margin);
+        let q90 = Self::calc_quantile_diff(self.quantile_90, gold.quantile_90, margin);
+        let q95 = Self::calc_quantile_diff(self.quantile_95, gold.quantile_95, margin);
+        let (instructions, ipc) = match (self.counters, gold.counters) {
+            (Some(counters), Some(gold_counters)) => {
+                Self::counters_diff(&counters, &gold_counters, f64::from(margin.0))
+            }
+            _ => (None, None),
+        };
+        let qps = match (self.qps, gold.qps) {
+            (Some(qps), Some(gold)) if gold * (1.0 - f64::from(qps_margin.0)) - qps > 0.0 => {
+                Some((qps, gold))
+            }
+            _ => None,
+        };
+        let phases = self
+            .phases
+            .iter()
+            .filter_map(|(phase, &time)| {
+                let gold = *gold.phases.get(phase)?;
+                Some((phase.clone(), Self::calc_diff(time, gold, margin)?))
+            })
+            .collect();
+        let regression = PerformanceRegression {
+            avg_time: avg,
+            quantile_50: q50,
+            quantile_90: q90,
+            quantile_95: q95,
+            instructions,
+            ipc,
+            qps,
+            phases,
+        };
+        Ok(if regression.is_empty() {
+            None
+        } else {
+            Some(regression)
         })
     }
+
+    /// Instruction count increase and IPC decrease are frequency-independent,
+    /// and therefore compared separately from timing.
+    #[allow(clippy::type_complexity)]
+    fn counters_diff(
+        counters: &HardwareCounters,
+        gold: &HardwareCounters,
+        margin: f64,
+    ) -> (Option<(f64, f64)>, Option<(f64, f64)>) {
+        let instructions = match (counters.instructions, gold.instructions) {
+            (Some(value), Some(gold)) if value - gold * (1.0 + margin) > 0.0 => Some((value, gold)),
+            _ => None,
+        };
+        let ipc = match (counters.ipc(), gold.ipc()) {
+            (Some(value), Some(gold)) if gold * (1.0 - margin) - value > 0.0 => Some((value, gold)),
+            _ => None,
+        };
+        (instructions, ipc)
+    }
 }
 
-/// Two paths to files that are supposed to be equal but are not.
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Diff(pub PathBuf, pub PathBuf);
+/// Inserts a `value` under `key` into JSON benchmark results.
+fn with_field(results: &str, key: &str, value: serde_json::Value) -> Result<String, Error> {
+    let mut results: serde_json::Value =
+        serde_json::from_str(results).context("Unable to parse benchmark results")?;
+    results
+        .as_object_mut()
+        .ok_or("Benchmark results are not a JSON object")?
+        .insert(String::from(key), value);
+    Ok(results.to_string())
+}
 
-/// Process a run (e.g., single precision evaluation or benchmark).
-pub fn process_run(
-    executor: &Executor,
-    run: &Run,
-    collection: &Collection,
-    use_scorer: bool,
-) -> Result<(), Error> {
-    let scorer = if use_scorer { Some(&run.scorer) } else { None };
-    let queries: Result<Vec<_>, Error> = run
-        .topics
-        .iter()
-        .map(|t| queries_path(t, executor))
-        .collect();
-    match &run.kind {
-        RunKind::Evaluate { qrels } => {
-            for (algorithm, encoding, (tid, queries)) in
-                iproduct!(&run.algorithms, &run.encodings, queries?.iter().enumerate())
-            {
-                let results =
-                    executor.evaluate_queries(&collection, encoding, algorithm, queries, scorer)?;
-                let results_path =
-                    format_output_path(&run.output, algorithm, encoding, tid, "results");
-                let trec_eval_path =
-                    format_output_path(&run.output, algorithm, encoding, tid, "trec_eval");
-                let mut results: Vec<ResultRecord> =
-                    cranky::read_records(std::io::Cursor::new(results))?;
-                results.sort_by(|lhs, rhs| {
-                    (&lhs.run, &lhs.iter, &lhs.qid, &-lhs.score.0, &lhs.docid)
-                        .partial_cmp(&(&rhs.run, &rhs.iter, &rhs.qid, &-rhs.score.0, &rhs.docid))
-                        .unwrap()
-                });
-                let results: String = results
-                    .into_iter()
-                    .map(|r| r.to_string())
-                    .collect::<Vec<_>>()
-                    .join("\n");
-                fs::write(&results_path, &results)?;
-                let output = Command::new("trec_eval")
-                    .arg("-q")
-                    .arg("-a")
-                    .arg(qrels.to_str().unwrap())
-                    .arg(results_path)
-                    .log()
-                    .output()?;
-                let eval_result = String::from_utf8(output.stdout)
-                    .context("unable to parse result of trec_eval")?;
-                fs::write(&trec_eval_path, &eval_result)?;
-            }
-        }
-        RunKind::Benchmark => {
-            for (algorithm, encoding, (tid, queries)) in
-                iproduct!(&run.algorithms, &run.encodings, queries?.iter().enumerate())
-            {
-                let results =
-                    executor.benchmark(&collection, encoding, algorithm, &queries, scorer)?;
-                let path = format_output_path(&run.output, algorithm, encoding, tid, "bench");
-                fs::write(&path, &results)?;
-            }
-        }
+/// Throughput in queries per second of `count` queries processed in `seconds`,
+/// or `None` if nothing was measured.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::run::queries_per_second;
+/// assert_eq!(queries_per_second(500, 0.25), Some(2000.0));
+/// assert_eq!(queries_per_second(500, 0.0), None);
+/// assert_eq!(queries_per_second(0, 1.0), None);
+/// ```
+pub fn queries_per_second(count: usize, seconds: f64) -> Option<f64> {
+    #[allow(clippy::cast_precision_loss)]
+    let count = count as f64;
+    if count > 0.0 && seconds > 0.0 {
+        Some(count / seconds)
+    } else {
+        None
     }
-    Ok(())
 }
 
-fn load_benchmark_results(path: &Path) -> Result<BenchmarkResults, Error> {
-    let results: BenchmarkResults = serde_json::from_reader(
-        fs::File::open(path).with_context(|_| path.to_string_lossy().to_string())?,
-    )
-    .context("Unable to parse benchmark results")?;
-    Ok(results)
+/// Counts the non-empty lines of the queries file at `path`.
+fn count_queries(path: &str) -> Result<usize, Error> {
+    Ok(fs::read_to_string(path)
+        .with_context(|_| path.to_string())?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .count())
 }
 
-fn load_eval_results(path: &Path) -> Result<String, Error> {
-    Ok(fs::read_to_string(path).with_context(|_| path.to_string_lossy().to_string())?)
+/// Throughput of the query loop of `queries`, from the average query time in its `results`,
+/// which excludes loading the index and any other overhead of the process,
+/// or `None` if the results have no such time.
+fn query_loop_qps(results: &str) -> Option<f64> {
+    let avg = serde_json::from_str::<serde_json::Value>(results).ok()?["avg"].as_f64()?;
+    queries_per_second(1, avg / 1_000_000.0)
 }
 
-/// Compares the results of the runs with a given baseline.
-pub fn compare_with_baseline(
-    executor: &Executor,
-    run: &Run,
-    compare_with: &Path,
-    margin: RegressionMargin,
-) -> Result<RunStatus, Error> {
-    let queries: Result<Vec<_>, Error> = run
-        .topics
+/// Inserts the throughput into JSON benchmark results, unless it is `None`
+/// or the results are not a JSON object, which are left to fail their validation.
+fn with_qps(results: &str, qps: Option<f64>) -> Result<String, Error> {
+    let is_object =
+        serde_json::from_str::<serde_json::Value>(results).is_ok_and(|results| results.is_object());
+    match qps {
+        Some(qps) if is_object => with_field(results, "qps", serde_json::Value::from(qps)),
+        _ => Ok(results.to_string()),
+    }
+}
+
+/// Inserts hardware counters into JSON benchmark results.
+pub(crate) fn with_counters(results: &str, counters: HardwareCounters) -> Result<String, Error> {
+    with_field(
+        results,
+        "counters",
+        serde_json::to_value(counters).context("Unable to serialize counters")?,
+    )
+}
+
+/// Aggregates JSON benchmark results of repeated executions of the same measurement.
+///
+/// Numeric fields, such as `avg` or `q95`, are averaged, and their standard deviations
+/// are stored under `stddev`, along with the number of `repeats`. Other fields,
+/// such as hardware counters, are those of the first results.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::run::aggregate_repeats;
+/// let results = vec![
+///     String::from(r#"{"type":"ef","query":"wand","avg":1.0,"q95":3.0}"#),
+///     String::from(r#"{"type":"ef","query":"wand","avg":3.0,"q95":3.0}"#),
+/// ];
+/// let aggregated: serde_json::Value =
+///     serde_json::from_str(&aggregate_repeats(&results).unwrap()).unwrap();
+/// assert_eq!(aggregated["avg"], 2.0);
+/// assert_eq!(aggregated["stddev"]["avg"], 1.0);
+/// assert_eq!(aggregated["stddev"]["q95"], 0.0);
+/// assert_eq!(aggregated["repeats"], 2);
+/// assert_eq!(aggregated["query"], "wand");
+/// ```
+#[allow(clippy::cast_precision_loss)]
+pub fn aggregate_repeats(results: &[String]) -> Result<String, Error> {
+    let parsed = results
         .iter()
-        .map(|t| queries_path(t, executor))
-        .collect();
-    match &run.kind {
-        RunKind::Evaluate { .. } => {
-            let mut regression_count = 0;
-            for (algorithm, encoding, tid) in
-                iproduct!(&run.algorithms, &run.encodings, 0..queries?.len())
-            {
-                let format_path = output_path_formatter(algorithm, encoding, tid, "trec_eval");
-                let result_path = format_path(&run.output);
-                let base_result_path = format_path(compare_with);
-                let results = load_eval_results(&result_path)?;
-                let baseline = load_eval_results(&base_result_path)?;
-                if results != baseline {
-                    eprintln!("Detected correctness regression!");
-                    eprintln!("file: {}", result_path.display());
-                    eprintln!("base: {}", base_result_path.display());
-                    regression_count += 1;
-                }
-            }
-            if regression_count > 0 {
-                return Ok(RunStatus::Regression(regression_count));
-            }
-        }
-        RunKind::Benchmark => {
-            let mut regression_count = 0;
-            for (algorithm, encoding, tid) in
-                iproduct!(&run.algorithms, &run.encodings, 0..queries?.len())
-            {
-                let format_path = output_path_formatter(algorithm, encoding, tid, "bench");
-                let result_path = format_path(&run.output);
-                let base_result_path = format_path(compare_with);
-                let results = load_benchmark_results(&result_path)?;
-                let baseline = load_benchmark_results(&base_result_path)?;
-                if let Some(regression) = results.regression(&baseline, margin)? {
-                    eprintln!("Detected performance regression!");
-                    eprintln!("file: {}", result_path.display());
-                    eprintln!("base: {}", base_result_path.display());
-                    eprintln!("{}", regression);
-                    regression_count += 1;
-                }
-            }
-            if regression_count > 0 {
-                return Ok(RunStatus::Regression(regression_count));
+        .map(|results| -> Result<_, Error> {
+            match serde_json::from_str(results).context("Unable to parse benchmark results")? {
+                serde_json::Value::Object(fields) => Ok(fields),
+                _ => Err(Error::from("Benchmark results are not a JSON object")),
             }
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let mut aggregated = parsed
+        .first()
+        .cloned()
+        .ok_or("No benchmark results to aggregate")?;
+    let mut stddev = serde_json::Map::new();
+    for (key, value) in &mut aggregated {
+        if !value.is_number() {
+            continue;
         }
+        let values: Vec<f64> = parsed
+            .iter()
+            .filter_map(|fields| fields.get(key).and_then(serde_json::Value::as_f64))
+            .collect();
+        let count = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / count;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / count;
+        *value = serde_json::Value::from(mean);
+        stddev.insert(key.clone(), serde_json::Value::from(variance.sqrt()));
     }
-    Ok(RunStatus::Success)
+    aggregated.insert(String::from("stddev"), serde_json::Value::Object(stddev));
+    aggregated.insert(
+        String::from("repeats"),
+        serde_json::Value::from(results.len()),
+    );
+    Ok(serde_json::Value::Object(aggregated).to_string())
+}
+
+/// Suffix of the raw standard output of the `repeat`-th measured execution of `queries`
+/// in a benchmark run with `keep_raw_output`; the output of `perf stat`, if any,
+/// is stored with an additional `.perf` suffix.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::run::raw_suffix;
+/// assert_eq!(raw_suffix(0), "bench.raw.0");
+/// ```
+pub fn raw_suffix(repeat: usize) -> String {
+    format!("bench.raw.{}", repeat)
+}
+
+/// Removes raw outputs of measured executions from `repeats` on, left by a previous session
+/// with more repeats, so that the stored ones all belong to the same measurement.
+fn remove_raw_outputs<F>(raw_path: F, repeats: usize) -> Result<(), Error>
+where
+    F: Fn(usize) -> PathBuf,
+{
+    let mut repeat = repeats;
+    while raw_path(repeat).exists() {
+        fs::remove_file(raw_path(repeat))?;
+        let perf = PathBuf::from(format!("{}.perf", raw_path(repeat).display()));
+        if perf.exists() {
+            fs::remove_file(perf)?;
+        }
+        repeat += 1;
+    }
+    Ok(())
+}
+
+/// Executes `measure` as many times as the warm-up and repeats of `run` require,
+/// discarding the warm-up results and aggregating the repeated ones.
+fn measure_repeated<F>(run: &Run, mut measure: F) -> Result<String, Error>
+where
+    F: FnMut() -> Result<String, Error>,
+{
+    for _ in 0..run.warmup {
+        measure()?;
+    }
+    if run.repeats <= 1 {
+        return measure();
+    }
+    let results = (0..run.repeats)
+        .map(|_| measure())
+        .collect::<Result<Vec<_>, _>>()?;
+    aggregate_repeats(&results)
+}
+
+/// Stores the identity (see [`RunIdentity`](../identity/struct.RunIdentity.html))
+/// of each algorithm, encoding, and topic set of a processed run in its benchmark results,
+/// so that they can be joined across sessions regardless of file names.
+/// Runs without benchmark results are left untouched.
+pub fn write_run_ids(run: &Run, use_scorer: bool, commit: Option<&str>) -> Result<(), Error> {
+    if let RunKind::Evaluate { .. } = run.kind {
+        return Ok(());
+    }
+    for (tid, topics) in run.topics.iter().enumerate() {
+        let topics_hash = topics_hash(topics)?;
+        for (algorithm, encoding) in iproduct!(&run.algorithms, &run.encodings) {
+            let path = format_output_path(&run.output, algorithm, encoding, tid, "bench");
+            let identity = RunIdentity {
+                collection: &run.collection,
+                encoding,
+                algorithm,
+                topics_hash: &topics_hash,
+                k: RESULTS_K,
+                scorer: if use_scorer { Some(&run.scorer) } else { None },
+                commit,
+            };
+            let results = fs::read_to_string(&path).with_context(|_| path.display().to_string())?;
+            fs::write(
+                &path,
+                with_field(&results, "id", serde_json::Value::from(identity.id()))?,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Measures each query separately, and writes its features and latency as CSV.
+///
+/// Each query is written in turn to a file within `temp_dir`.
+#[allow(clippy::too_many_arguments)]
+fn export_features(
+    executor: &Executor,
+    collection: &Collection,
+    encoding: &Encoding,
+    algorithm: &Algorithm,
+    queries: &str,
+    scorer: Option<&Scorer>,
+    output: &Path,
+    temp_dir: &Path,
+) -> Result<(), Error> {
+    let extractor = FeatureExtractor::from_collection(collection)?;
+    let single_query_path = split_queries_path(queries, "single", temp_dir);
+    let mut csv = vec![CSV_HEADER.to_string()];
+    for line in fs::read_to_string(queries)
+        .with_context(|_| queries.to_string())?
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+    {
+        fs::write(&single_query_path, line)?;
+        let results = executor.benchmark(
+            collection,
+            encoding,
+            algorithm,
+            &single_query_path.display().to_string(),
+            scorer,
+        )?;
+        let results: BenchmarkResults =
+            serde_json::from_str(&results).context("Unable to parse benchmark results")?;
+        csv.push(extractor.features(line).csv_row(results.avg_time));
+    }
+    fs::remove_file(&single_query_path)?;
+    csv.push(String::new());
+    fs::write(output, csv.join("\n"))?;
+    Ok(())
+}
+
+/// Measures the time of each query with `queries --extract` and writes them
+/// to a CSV file at `output`.
+fn export_query_times(
+    executor: &Executor,
+    collection: &Collection,
+    encoding: &Encoding,
+    algorithm: &Algorithm,
+    queries: &str,
+    scorer: Option<&Scorer>,
+    output: &Path,
+) -> Result<Vec<QueryTime>, Error> {
+    let extracted = executor.query_times(collection, encoding, algorithm, queries, scorer)?;
+    let times = QueryTime::parse(&extracted)?;
+    let mut csv = vec![QUERY_TIMES_CSV_HEADER.to_string()];
+    csv.extend(times.iter().map(QueryTime::csv_row));
+    csv.push(String::new());
+    fs::write(output, csv.join("\n"))?;
+    Ok(times)
+}
+
+/// Two paths to files that are supposed to be equal but are not.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diff(pub PathBuf, pub PathBuf);
+
+fn run_failed<'a>(run: &'a Run, algorithm: &'a Algorithm) -> impl Fn(Error) -> Error + 'a {
+    move |err| {
+        err.wrap(ErrorKind::Run {
+            run: run.output.display().to_string(),
+            algorithm: algorithm.clone(),
+        })
+    }
+}
+
+/// Converts qrels to the TREC format, `<qid> 0 <docid> <relevance>`.
+///
+/// Lines with four columns, as in TREC qrels and MS MARCO four-column TSV files,
+/// are already in that format. Lines of MS MARCO two-column TSV files, `<qid>\t<docid>`,
+/// are converted, judging the document relevant with level 1.
+/// Returns `None` if no line needs to be converted.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::run::trec_qrels;
+/// assert_eq!(trec_qrels("1 0 d1 2\n1\t0\td2\t1\n").unwrap(), None);
+/// assert_eq!(
+///     trec_qrels("1\td1\n2\td2\n").unwrap(),
+///     Some(String::from("1 0 d1 1\n2 0 d2 1\n"))
+/// );
+/// assert!(trec_qrels("1 d1 1\n").is_err());
+/// ```
+pub fn trec_qrels(qrels: &str) -> Result<Option<String>, Error> {
+    let mut converted = String::new();
+    let mut changed = false;
+    for line in qrels.lines().filter(|line| !line.trim().is_empty()) {
+        match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [qid, docid] => {
+                changed = true;
+                converted.push_str(&format!("{} 0 {} 1\n", qid, docid));
+            }
+            [qid, iter, docid, relevance] => {
+                converted.push_str(&format!("{} {} {} {}\n", qid, iter, docid, relevance));
+            }
+            _ => return Err(Error::from(format!("Invalid qrels line: {}", line))),
+        }
+    }
+    Ok(if changed { Some(converted) } else { None })
+}
+
+/// Returns the path to qrels in the TREC format: `qrels` itself, or its conversion
+/// written to `converted_path` if it is in another format.
+fn prepare_qrels(qrels: &Path, converted_path: &Path) -> Result<PathBuf, Error> {
+    let content = fs::read_to_string(qrels).with_context(|_| qrels.display().to_string())?;
+    match trec_qrels(&content)? {
+        Some(converted) => {
+            fs::write(converted_path, converted)?;
+            Ok(converted_path.to_path_buf())
+        }
+        None => Ok(qrels.to_path_buf()),
+    }
+}
+
+/// Prepares the qrels of each topics entry of `run`, indexed by topics ID,
+/// writing conversions to `temp_dir`.
+pub(crate) fn prepare_run_qrels(run: &Run, temp_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    (0..run.topics.len())
+        .map(|tid| {
+            let qrels = run.qrels(tid).ok_or("Missing qrels")?;
+            prepare_qrels(qrels, &temp_dir.join(format!("qrels.{}.trec", tid)))
+        })
+        .collect()
+}
+
+fn trec_eval_command(
+    executor: &Executor,
+    qrels: &Path,
+    min_relevance: Option<u32>,
+    results_path: &Path,
+) -> Command {
+    let mut trec_eval = executor.trec_eval();
+    trec_eval.arg("-q").arg("-a");
+    if let Some(level) = min_relevance {
+        trec_eval.arg(format!("-l{}", level));
+    }
+    trec_eval.arg(qrels.to_str().unwrap()).arg(results_path);
+    trec_eval
+}
+
+/// Evaluates TREC results with `trec_eval`, or the internal [evaluator](../eval/index.html)
+/// if configured, writing the measures to `trec_eval_path`.
+///
+/// If `min_relevance` is defined, it is passed to `trec_eval` and recorded in the output.
+fn trec_eval(
+    executor: &Executor,
+    qrels: &Path,
+    min_relevance: Option<u32>,
+    results_path: &Path,
+    trec_eval_path: &Path,
+) -> Result<(), Error> {
+    let mut eval_result = match executor.evaluator() {
+        Evaluator::TrecEval => {
+            let output = executor
+                .output_in(
+                    &mut trec_eval_command(executor, qrels, min_relevance, results_path),
+                    Stage::Run,
+                )
+                .context("Failed to execute: trec_eval")?;
+            check_status("trec_eval", output.status, "Failed to evaluate results")?;
+            String::from_utf8(output.stdout).context("unable to parse result of trec_eval")?
+        }
+        Evaluator::Internal => eval::evaluate(
+            &fs::read_to_string(qrels).with_context(|_| qrels.display().to_string())?,
+            &fs::read_to_string(results_path)
+                .with_context(|_| results_path.display().to_string())?,
+            min_relevance,
+        )?,
+    };
+    if let Some(level) = min_relevance {
+        eval_result.insert_str(0, &format!("{}\tall\t{}\n", MIN_RELEVANCE, level));
+    }
+    fs::write(&trec_eval_path, &eval_result)?;
+    Ok(())
+}
+
+/// Suffix of the raw results of an evaluation run with `keep_raw_output`.
+pub const RAW_RESULTS: &str = "results.raw";
+
+/// Sorts the raw TREC `results` of an evaluation and writes them to `results_path`,
+/// then evaluates them against `qrels`, writing the measures to `trec_eval_path`,
+/// followed by the weighted ones if the queries have `weights`.
+pub(crate) fn write_evaluation(
+    executor: &Executor,
+    results: &str,
+    results_path: &Path,
+    trec_eval_path: &Path,
+    qrels: &Path,
+    min_relevance: Option<u32>,
+    weights: Option<&QueryWeights>,
+) -> Result<(), Error> {
+    let mut results: Vec<ResultRecord> = cranky::read_records(std::io::Cursor::new(results))?;
+    results.sort_by(|lhs, rhs| {
+        (&lhs.run, &lhs.iter, &lhs.qid, &-lhs.score.0, &lhs.docid)
+            .partial_cmp(&(&rhs.run, &rhs.iter, &rhs.qid, &-rhs.score.0, &rhs.docid))
+            .unwrap()
+    });
+    let results: String = results
+        .into_iter()
+        .map(|r| r.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    fs::write(results_path, &results)?;
+    trec_eval(executor, qrels, min_relevance, results_path, trec_eval_path)?;
+    write_weighted_eval(trec_eval_path, weights)
+}
+
+/// Runs the command of an external run, writing its TREC results, and its processing time
+/// averaged over the queries and its throughput in the benchmark format. Latency quantiles
+/// are left out, since only the total time is measured. Returns the path to the results.
+fn run_external(
+    executor: &Executor,
+    run: &Run,
+    collection: &Collection,
+    algorithm: &Algorithm,
+    encoding: &Encoding,
+    tid: usize,
+    queries: &str,
+) -> Result<PathBuf, Error> {
+    let results_path = format_output_path(&run.output, algorithm, encoding, tid, "results");
+    let command = run
+        .external_command(collection, algorithm, encoding, queries, &results_path)?
+        .ok_or("Not an external run")?;
+    let query_count = count_queries(queries)?;
+    let start = Instant::now();
+    let status = executor
+        .status_in(Command::new("sh").args(&["-c", &command]), Stage::Run)
+        .context("Failed to run external command")?;
+    check_status("external command", status, "External command failed")?;
+    let seconds = start.elapsed().as_secs_f64();
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    let avg_time = (seconds * 1_000_000.0 / query_count.max(1) as f64) as f32;
+    let results = BenchmarkResults {
+        kind: encoding.clone(),
+        algorithm: algorithm.clone(),
+        avg_time,
+        quantile_50: None,
+        quantile_90: None,
+        quantile_95: None,
+        counters: None,
+        phases: BTreeMap::new(),
+        qps: queries_per_second(query_count, seconds),
+        id: None,
+    };
+    fs::write(
+        format_output_path(&run.output, algorithm, encoding, tid, "bench"),
+        serde_json::to_string(&results).context("Failed to serialize")?,
+    )?;
+    Ok(results_path)
+}
+
+/// Process a run (e.g., single precision evaluation or benchmark).
+///
+/// Errors are of kind [`ErrorKind::Run`](../enum.ErrorKind.html), identifying the failed algorithm.
+/// On success, returns non-fatal warnings, such as high out-of-vocabulary rates of the queries.
+/// Intermediate files, such as extracted topics or queries split by language,
+/// are written to `temp_dir`, and never next to the topics.
+#[allow(clippy::too_many_lines)]
+pub fn process_run(
+    executor: &Executor,
+    run: &Run,
+    collection: &Collection,
+    use_scorer: bool,
+    temp_dir: &Path,
+) -> Result<Vec<Warning>, Error> {
+    let scorer = if use_scorer { Some(&run.scorer) } else { None };
+    let executor = &executor
+        .clone()
+        .with_query_options(run.query_options.clone())
+        .with_env(&run.env)
+        .with_run_timeout(run.timeout);
+    let queries = prepare_queries(run, executor, temp_dir)?;
+    let warnings = oov_warnings(collection, &queries)?;
+    let weights = run
+        .topics
+        .iter()
+        .map(topic_weights)
+        .collect::<Result<Vec<_>, _>>()?;
+    match &run.kind {
+        RunKind::Evaluate { min_relevance, .. } => {
+            let qrels = prepare_run_qrels(run, temp_dir)?;
+            let evaluate = |algorithm: &Algorithm,
+                            encoding: &Encoding,
+                            tid: usize,
+                            queries: &str|
+             -> Result<(), Error> {
+                let results =
+                    executor.evaluate_queries(&collection, encoding, algorithm, queries, scorer)?;
+                if run.keep_raw_output {
+                    let path =
+                        format_output_path(&run.output, algorithm, encoding, tid, RAW_RESULTS);
+                    fs::write(path, &results)?;
+                }
+                write_evaluation(
+                    executor,
+                    &results,
+                    &format_output_path(&run.output, algorithm, encoding, tid, "results"),
+                    &format_output_path(&run.output, algorithm, encoding, tid, "trec_eval"),
+                    &qrels[tid],
+                    *min_relevance,
+                    weights[tid].as_ref(),
+                )?;
+                if run.per_language {
+                    evaluate_per_language(run, algorithm, encoding, tid, queries)?;
+                }
+                Ok(())
+            };
+            for (algorithm, encoding, (tid, queries)) in
+                iproduct!(&run.algorithms, &run.encodings, queries.iter().enumerate())
+            {
+                evaluate(algorithm, encoding, tid, queries).map_err(run_failed(run, algorithm))?;
+            }
+        }
+        RunKind::External { min_relevance, .. } => {
+            let qrels = prepare_run_qrels(run, temp_dir)?;
+            for (algorithm, encoding, (tid, queries)) in
+                iproduct!(&run.algorithms, &run.encodings, queries.iter().enumerate())
+            {
+                run_external(executor, run, collection, algorithm, encoding, tid, queries)
+                    .and_then(|results_path| {
+                        let trec_eval_path =
+                            format_output_path(&run.output, algorithm, encoding, tid, "trec_eval");
+                        trec_eval(
+                            executor,
+                            &qrels[tid],
+                            *min_relevance,
+                            &results_path,
+                            &trec_eval_path,
+                        )?;
+                        write_weighted_eval(&trec_eval_path, weights[tid].as_ref())
+                    })
+                    .and_then(|()| {
+                        if run.per_language {
+                            evaluate_per_language(run, algorithm, encoding, tid, queries)
+                        } else {
+                            Ok(())
+                        }
+                    })
+                    .map_err(run_failed(run, algorithm))?;
+            }
+        }
+        RunKind::Benchmark => {
+            let benchmark = |algorithm: &Algorithm,
+                             encoding: &Encoding,
+                             tid: usize,
+                             queries: &str|
+             -> Result<(), Error> {
+                let raw_path = |repeat: usize| {
+                    format_output_path(&run.output, algorithm, encoding, tid, &raw_suffix(repeat))
+                };
+                if run.keep_raw_output {
+                    remove_raw_outputs(&raw_path, run.repeats)?;
+                }
+                let mut executions = 0;
+                let mut results = measure_repeated(run, || {
+                    let (output, perf_output) = if run.perf_counters {
+                        let (output, perf_output) = executor.benchmark_with_counters(
+                            collection, encoding, algorithm, &queries, scorer,
+                        )?;
+                        (output, Some(perf_output))
+                    } else {
+                        let output = executor.benchmark(
+                            &collection,
+                            encoding,
+                            algorithm,
+                            &queries,
+                            scorer,
+                        )?;
+                        (output, None)
+                    };
+                    if run.keep_raw_output && executions >= run.warmup {
+                        let path = raw_path(executions - run.warmup);
+                        fs::write(&path, &output)?;
+                        if let Some(perf_output) = &perf_output {
+                            fs::write(format!("{}.perf", path.display()), perf_output)?;
+                        }
+                    }
+                    executions += 1;
+                    let results = match perf_output {
+                        Some(perf_output) => {
+                            with_counters(&output, HardwareCounters::parse(&perf_output))?
+                        }
+                        None => output,
+                    };
+                    let qps = query_loop_qps(&results);
+                    with_qps(&results, qps)
+                })?;
+                if run.export_features {
+                    export_features(
+                        executor,
+                        collection,
+                        encoding,
+                        algorithm,
+                        queries,
+                        scorer,
+                        &format_output_path(&run.output, algorithm, encoding, tid, "features.csv"),
+                        temp_dir,
+                    )?;
+                }
+                let times = if run.query_times {
+                    let times = export_query_times(
+                        executor,
+                        collection,
+                        encoding,
+                        algorithm,
+                        queries,
+                        scorer,
+                        &format_output_path(
+                            &run.output,
+                            algorithm,
+                            encoding,
+                            tid,
+                            "query_times.csv",
+                        ),
+                    )?;
+                    let seconds = times.iter().map(QueryTime::mean).sum::<f64>() / 1_000_000.0;
+                    if let Some(qps) = queries_per_second(times.len(), seconds) {
+                        results = with_qps(&results, Some(qps))?;
+                    }
+                    Some(times)
+                } else if weights[tid].is_some() || run.per_class {
+                    Some(QueryTime::parse(&executor.query_times(
+                        collection, encoding, algorithm, queries, scorer,
+                    )?)?)
+                } else {
+                    None
+                };
+                if let (Some(weights), Some(times)) = (&weights[tid], &times) {
+                    results = with_weighted_times(&results, weights, times)?;
+                }
+                let path = format_output_path(&run.output, algorithm, encoding, tid, "bench");
+                fs::write(&path, &results)?;
+                if let (true, Some(times)) = (run.per_class, &times) {
+                    let content =
+                        fs::read_to_string(&queries).with_context(|_| queries.to_string())?;
+                    for (class, results) in class_results(&results, &content, times)? {
+                        let suffix = class_suffix(class);
+                        let path =
+                            format_output_path(&run.output, algorithm, encoding, tid, &suffix);
+                        info!(
+                            "[{}] [{}] [{}] Class {}: {}",
+                            run.collection,
+                            encoding,
+                            algorithm,
+                            class,
+                            results.trim()
+                        );
+                        fs::write(&path, &results)?;
+                    }
+                }
+                if run.per_language {
+                    let content =
+                        fs::read_to_string(&queries).with_context(|_| queries.to_string())?;
+                    for (language, language_queries) in split_by_language(&content) {
+                        let language_queries_path = split_queries_path(
+                            queries,
+                            &language_suffix("lang", language),
+                            temp_dir,
+                        );
+                        fs::write(&language_queries_path, &language_queries)?;
+                        let results = executor.benchmark(
+                            collection,
+                            encoding,
+                            algorithm,
+                            &language_queries_path.display().to_string(),
+                            scorer,
+                        )?;
+                        let suffix = language_suffix("bench", language);
+                        let path =
+                            format_output_path(&run.output, algorithm, encoding, tid, &suffix);
+                        info!(
+                            "[{}] [{}] [{}] Language {}: {}",
+                            run.collection,
+                            encoding,
+                            algorithm,
+                            language,
+                            results.trim()
+                        );
+                        fs::write(&path, &results)?;
+                    }
+                }
+                if run.cold_and_warm {
+                    evict_from_page_cache(&[
+                        collection.scored_index(encoding, scorer),
+                        collection.wand(scorer),
+                        collection.query_term_lexicon(),
+                    ]);
+                    let cold =
+                        executor.benchmark(collection, encoding, algorithm, queries, scorer)?;
+                    executor.benchmark(collection, encoding, algorithm, queries, scorer)?;
+                    let warm =
+                        executor.benchmark(collection, encoding, algorithm, queries, scorer)?;
+                    for (state, results) in CACHE_STATES.iter().zip(&[cold, warm]) {
+                        info!(
+                            "[{}] [{}] [{}] {} cache: {}",
+                            run.collection,
+                            encoding,
+                            algorithm,
+                            state,
+                            results.trim()
+                        );
+                        let suffix = cache_suffix(state);
+                        let path =
+                            format_output_path(&run.output, algorithm, encoding, tid, &suffix);
+                        fs::write(&path, results)?;
+                    }
+                }
+                if let Topics::Combined { sources } = &run.topics[tid] {
+                    for idx in 0..sources.len() {
+                        let source_queries = source_queries_path(temp_dir, &tid.to_string(), idx);
+                        let results = executor.benchmark(
+                            collection,
+                            encoding,
+                            algorithm,
+                            source_queries.to_string_lossy(),
+                            scorer,
+                        )?;
+                        let path = format_output_path(
+                            &run.output,
+                            algorithm,
+                            encoding,
+                            tid,
+                            &source_suffix(idx),
+                        );
+                        info!(
+                            "[{}] [{}] [{}] Source {}: {}",
+                            run.collection,
+                            encoding,
+                            algorithm,
+                            idx,
+                            results.trim()
+                        );
+                        fs::write(&path, &results)?;
+                    }
+                }
+                Ok(())
+            };
+            for (algorithm, encoding, (tid, queries)) in
+                iproduct!(&run.algorithms, &run.encodings, queries.iter().enumerate())
+            {
+                benchmark(algorithm, encoding, tid, queries).map_err(run_failed(run, algorithm))?;
+            }
+            if run.load_time {
+                measure_load_times(executor, run, collection, scorer, temp_dir)?;
+            }
+        }
+    }
+    if run
+        .shard
+        .is_some_and(|shard| shard.index + 1 == shard.count)
+    {
+        selective::process(executor, run, temp_dir)?;
+    }
+    Ok(warnings)
+}
+
+/// Lists the main commands that [`process_run`](fn.process_run.html) would execute,
+/// without executing anything.
+///
+/// Commands depending on the contents of intermediate files, such as measurements
+/// of query classes, single queries, or index loading, are not listed.
+pub fn plan(
+    executor: &Executor,
+    run: &Run,
+    collection: &Collection,
+    use_scorer: bool,
+    temp_dir: &Path,
+) -> Result<Vec<PlannedCommand>, Error> {
+    let scorer = if use_scorer { Some(&run.scorer) } else { None };
+    let executor = &executor
+        .clone()
+        .with_query_options(run.query_options.clone())
+        .with_env(&run.env);
+    let mut commands = Vec::new();
+    let mut queries = Vec::new();
+    for (tid, topics) in run.topics.iter().enumerate() {
+        let path =
+            planned_queries_path(topics, &tid.to_string(), executor, temp_dir, &mut commands)?;
+        queries.push(if run.normalize.is_noop() {
+            path
+        } else {
+            normalized_queries_path(&path, temp_dir)
+                .display()
+                .to_string()
+        });
+    }
+    for (algorithm, encoding, (tid, queries)) in
+        iproduct!(&run.algorithms, &run.encodings, queries.iter().enumerate())
+    {
+        let results_path = format_output_path(&run.output, algorithm, encoding, tid, "results");
+        match &run.kind {
+            RunKind::Evaluate { min_relevance, .. } => {
+                commands
+                    .push(executor.evaluate_queries_command(
+                        collection, encoding, algorithm, queries, scorer,
+                    ));
+                if executor.evaluator() == Evaluator::TrecEval {
+                    commands.push(trec_eval_command(
+                        executor,
+                        run.qrels(tid).ok_or("Missing qrels")?,
+                        *min_relevance,
+                        &results_path,
+                    ));
+                }
+            }
+            RunKind::External { min_relevance, .. } => {
+                let command = run
+                    .external_command(collection, algorithm, encoding, queries, &results_path)?
+                    .ok_or("Not an external run")?;
+                let mut sh = Command::new("sh");
+                sh.args(&["-c", &command]);
+                commands.push(sh);
+                if executor.evaluator() == Evaluator::TrecEval {
+                    commands.push(trec_eval_command(
+                        executor,
+                        run.qrels(tid).ok_or("Missing qrels")?,
+                        *min_relevance,
+                        &results_path,
+                    ));
+                }
+            }
+            RunKind::Benchmark => {
+                commands.push(if run.perf_counters {
+                    executor.benchmark_with_counters_command(
+                        collection, encoding, algorithm, queries, scorer,
+                    )
+                } else {
+                    executor.benchmark_command(collection, encoding, algorithm, queries, scorer)
+                });
+                if run.query_times || run.per_class {
+                    commands.push(
+                        executor
+                            .query_times_command(collection, encoding, algorithm, queries, scorer),
+                    );
+                }
+                if run.cold_and_warm {
+                    for _ in 0..3 {
+                        commands.push(
+                            executor.benchmark_command(
+                                collection, encoding, algorithm, queries, scorer,
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+    }
+    Ok(commands
+        .iter()
+        .map(|command| PlannedCommand::new(&run.collection, Stage::Run, command))
+        .collect())
+}
+
+/// Path to the index load times of a run with a given output basename.
+///
+/// Load times are stored in the same format as [`BuildTimes`](../build/struct.BuildTimes.html),
+/// keyed by encoding.
+pub fn load_times_path(output: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.load_times.json", output.display()))
+}
+
+/// Measures the index load time of each encoding of a run, as the duration of
+/// running the `queries` tool with no queries.
+fn measure_load_times(
+    executor: &Executor,
+    run: &Run,
+    collection: &Collection,
+    scorer: Option<&Scorer>,
+    temp_dir: &Path,
+) -> Result<(), Error> {
+    let algorithm = run
+        .algorithms
+        .first()
+        .ok_or_else(|| format!("No algorithms defined for run: {}", run.output.display()))?;
+    let empty_queries = temp_dir.join("empty_queries");
+    fs::write(&empty_queries, "")?;
+    let mut times = BuildTimes::default();
+    for encoding in &run.encodings {
+        let start = Instant::now();
+        executor
+            .benchmark(
+                collection,
+                encoding,
+                algorithm,
+                empty_queries.to_string_lossy(),
+                scorer,
+            )
+            .map_err(run_failed(run, algorithm))?;
+        let elapsed = start.elapsed().as_secs_f64();
+        info!(
+            "[{}] [{}] Index load time: {:.3}s",
+            run.collection, encoding, elapsed
+        );
+        times.0.insert(encoding.to_string(), elapsed);
+    }
+    times.write(&load_times_path(&run.output))
+}
+
+pub(crate) fn load_benchmark_results(path: &Path) -> Result<BenchmarkResults, Error> {
+    let results: BenchmarkResults = serde_json::from_reader(
+        fs::File::open(path).with_context(|_| path.to_string_lossy().to_string())?,
+    )
+    .context("Unable to parse benchmark results")?;
+    Ok(results)
+}
+
+pub(crate) fn load_eval_results(path: &Path) -> Result<String, Error> {
+    Ok(fs::read_to_string(path).with_context(|_| path.to_string_lossy().to_string())?)
+}
+
+fn verify_eval_results(path: &Path) -> Result<(), Error> {
+    let results = load_eval_results(path)?;
+    if results.trim().is_empty() {
+        return Err(Error::from(format!("Empty results: {}", path.display())));
+    }
+    match results
+        .lines()
+        .find(|line| !line.trim().is_empty() && line.split_whitespace().count() != 3)
+    {
+        Some(line) => Err(Error::from(format!(
+            "Malformed line in {}: {}",
+            path.display(),
+            line
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Suffixes of the output files a run can produce for each algorithm, encoding, and topics.
+const OUTPUT_SUFFIXES: &[&str] = &[
+    "results",
+    "trec_eval",
+    "trec_eval.weighted",
+    "bench",
+    "features.csv",
+    "query_times.csv",
+    "diff.html",
+];
+
+/// Lists the existing output files of a run, for each combination of algorithm,
+/// encoding, and topics.
+pub fn output_files(run: &Run) -> Vec<PathBuf> {
+    iproduct!(
+        &run.algorithms,
+        &run.encodings,
+        0..run.topics.len(),
+        OUTPUT_SUFFIXES
+    )
+    .map(|(algorithm, encoding, tid, suffix)| {
+        format_output_path(&run.output, algorithm, encoding, tid, suffix)
+    })
+    .filter(|path| path.exists())
+    .collect()
+}
+
+/// Verifies that the baseline of a run, if defined, is complete
+/// without running anything.
+///
+/// For each combination of algorithm, encoding, and topics, the baseline file must
+/// exist and parse correctly. Returns a list of problems found. Baselines produced
+/// in the session by another source are not verified.
+pub fn verify_baseline(run: &Run) -> Vec<String> {
+    let compare_with = match &run.compare_with {
+        Some(baseline) if baseline.source.is_none() => &baseline.path,
+        _ => return vec![],
+    };
+    let suffix = match run.kind {
+        RunKind::Evaluate { .. } | RunKind::External { .. } => "trec_eval",
+        RunKind::Benchmark => "bench",
+    };
+    let mut problems = Vec::new();
+    for (algorithm, encoding, tid) in
+        iproduct!(&run.algorithms, &run.encodings, 0..run.topics.len())
+    {
+        let path = baseline_path(compare_with, algorithm, encoding, tid, suffix);
+        let verified = match run.kind {
+            RunKind::Evaluate { .. } | RunKind::External { .. } => verify_eval_results(&path),
+            RunKind::Benchmark => load_benchmark_results(&path).map(|_| ()),
+        };
+        if let Err(err) = verified {
+            problems.push(format!("{}: {}", path.display(), err));
+        }
+    }
+    match baseline_meta_mismatches(run, compare_with) {
+        Ok(mismatches) => problems.extend(mismatches),
+        Err(err) => problems.push(err.to_string()),
+    }
+    problems
+}
+
+/// Lists the differences of topics and qrels between `run` and its baseline,
+/// as recorded in the [baseline metadata](../identity/struct.BaselineMeta.html).
+/// Baselines without metadata are not verified.
+fn baseline_meta_mismatches(run: &Run, compare_with: &Path) -> Result<Vec<String>, Error> {
+    let path = BaselineMeta::path(compare_with);
+    if !path.exists() {
+        warn!(
+            "Baseline metadata not found, topics and qrels not verified: {}",
+            path.display()
+        );
+        return Ok(vec![]);
+    }
+    let baseline = BaselineMeta::load(&path)?;
+    let meta = BaselineMeta::new(run, None, RegressionMargin::default())?;
+    Ok(meta.mismatches(&baseline))
+}
+
+/// Compares the results of the runs with a given baseline.
+///
+/// Errors are of kind [`ErrorKind::Comparison`](../enum.ErrorKind.html).
+pub fn compare_with_baseline(
+    run: &Run,
+    compare_with: &Path,
+    margin: RegressionMargin,
+) -> Result<RunStatus, Error> {
+    compare(run, compare_with, margin).map_err(|err| {
+        err.wrap(ErrorKind::Comparison {
+            run: run.output.display().to_string(),
+        })
+    })
+}
+
+/// Writes a per-query HTML diff of the ranked results of a run and its baseline
+/// to `<output>.<algorithm>.<encoding>.<topics>.diff.html`.
+///
+/// Returns the path of the diff, or `None` if any of the results is missing
+/// or the ranked lists are identical.
+fn write_result_diff(
+    run: &Run,
+    compare_with: &Path,
+    algorithm: &Algorithm,
+    encoding: &Encoding,
+    tid: usize,
+) -> Result<Option<PathBuf>, Error> {
+    let format_path = output_path_formatter(algorithm, encoding, tid, "results");
+    let (result_path, base_result_path) = (
+        format_path(&run.output),
+        baseline_path(compare_with, algorithm, encoding, tid, "results"),
+    );
+    if !result_path.exists() || !base_result_path.exists() {
+        return Ok(None);
+    }
+    let diff = render_html_diff(
+        &load_eval_results(&result_path)?,
+        &load_eval_results(&base_result_path)?,
+    );
+    if let Some(diff) = diff {
+        let diff_path = format_output_path(&run.output, algorithm, encoding, tid, "diff.html");
+        fs::write(&diff_path, diff)?;
+        Ok(Some(diff_path))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Compares index load times with the baseline, if both are available,
+/// and returns the number of regressions.
+fn load_time_regressions(
+    run: &Run,
+    compare_with: &Path,
+    margin: RegressionMargin,
+) -> Result<usize, Error> {
+    let times_path = load_times_path(&run.output);
+    let base_times_path = load_times_path(compare_with);
+    if !times_path.exists() || !base_times_path.exists() {
+        return Ok(0);
+    }
+    let times = BuildTimes::load(&times_path)?;
+    let baseline = BuildTimes::load(&base_times_path)?;
+    let slower = times.regressions(&baseline, margin);
+    if !slower.is_empty() {
+        eprintln!("Detected index load time regression!");
+        eprintln!("file: {}", times_path.display());
+        eprintln!("base: {}", base_times_path.display());
+        for (encoding, base, time) in &slower {
+            eprintln!("{}: {} --> {}", encoding, base, time);
+        }
+    }
+    Ok(slower.len())
+}
+
+/// Compares a partial result, such as of a single query class, with its baseline.
+fn breakdown_regression(
+    result_path: &Path,
+    base_result_path: &Path,
+    margin: RegressionMargin,
+    label: &str,
+) -> Result<bool, Error> {
+    let results = load_benchmark_results(result_path)?;
+    let baseline = load_benchmark_results(base_result_path)?;
+    if let Some(regression) = results.regression(&baseline, margin)? {
+        eprintln!("Detected performance regression for {}!", label);
+        eprintln!("file: {}", result_path.display());
+        eprintln!("base: {}", base_result_path.display());
+        eprintln!("{}", regression);
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Name of the measure recording the relevance threshold in evaluation results,
+/// written only when `min_relevance` is defined.
+pub const MIN_RELEVANCE: &str = "min_relevance";
+
+/// Returns the relevance threshold recorded in evaluation results, if any.
+fn relevance_threshold(eval_results: &str) -> Option<&str> {
+    eval_results.lines().find_map(|line| {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [MIN_RELEVANCE, _, level] => Some(*level),
+            _ => None,
+        }
+    })
+}
+
+/// Measures with tolerances that dropped by more than their tolerance compared to the baseline,
+/// as `(measure, baseline value, value)`.
+pub(crate) fn measure_drops<'a>(
+    results: &str,
+    baseline: &str,
+    tolerances: &'a BTreeMap<String, f64>,
+) -> Result<Vec<(&'a str, f64, f64)>, Error> {
+    let mut drops = Vec::new();
+    for (measure, tolerance) in tolerances {
+        let value = |results: &str| {
+            overall_measure(results, measure)
+                .ok_or_else(|| Error::from(format!("Measure {} not found", measure)))
+        };
+        let (value, base) = (value(results)?, value(baseline)?);
+        if base - value > *tolerance {
+            drops.push((measure.as_str(), base, value));
+        }
+    }
+    Ok(drops)
+}
+
+/// Whether evaluation `results` regressed compared to `baseline`: without tolerances,
+/// any difference is a regression (see [`Baseline`](../config/struct.Baseline.html)).
+pub(crate) fn eval_regressed(
+    results: &str,
+    baseline: &str,
+    tolerances: &BTreeMap<String, f64>,
+) -> Result<bool, Error> {
+    if tolerances.is_empty() {
+        Ok(results != baseline)
+    } else {
+        Ok(!measure_drops(results, baseline, tolerances)?.is_empty())
+    }
+}
+
+/// Compares the evaluation results of a run with its baseline,
+/// and returns the number of differing results.
+///
+/// Fails if the results were computed with different relevance thresholds.
+fn compare_evaluation(run: &Run, compare_with: &Path) -> Result<usize, Error> {
+    let no_tolerances = BTreeMap::new();
+    let tolerances = run
+        .compare_with
+        .as_ref()
+        .map_or(&no_tolerances, |baseline| &baseline.tolerances);
+    let mut regression_count = 0;
+    for (algorithm, encoding, tid) in
+        iproduct!(&run.algorithms, &run.encodings, 0..run.topics.len())
+    {
+        let result_path = format_output_path(&run.output, algorithm, encoding, tid, "trec_eval");
+        let base_result_path = baseline_path(compare_with, algorithm, encoding, tid, "trec_eval");
+        let results = load_eval_results(&result_path)?;
+        let baseline = load_eval_results(&base_result_path)?;
+        let (threshold, base_threshold) = (
+            relevance_threshold(&results),
+            relevance_threshold(&baseline),
+        );
+        if threshold != base_threshold {
+            return Err(Error::from(format!(
+                "Relevance thresholds differ: {} (run) vs {} (baseline): {}",
+                threshold.unwrap_or("none"),
+                base_threshold.unwrap_or("none"),
+                base_result_path.display()
+            )));
+        }
+        if eval_regressed(&results, &baseline, tolerances)? {
+            eprintln!("Detected correctness regression!");
+            eprintln!("file: {}", result_path.display());
+            eprintln!("base: {}", base_result_path.display());
+            for (measure, base, value) in measure_drops(&results, &baseline, tolerances)? {
+                eprintln!("{}: {} --> {}", measure, base, value);
+            }
+            if let Some(diff_path) = write_result_diff(run, compare_with, algorithm, encoding, tid)?
+            {
+                eprintln!("diff: {}", diff_path.display());
+            }
+            regression_count += 1;
+        }
+    }
+    Ok(regression_count)
+}
+
+/// Returns the value of `measure` over all queries in evaluation results, if any.
+pub(crate) fn overall_measure(eval_results: &str, measure: &str) -> Option<f64> {
+    eval_results.lines().find_map(|line| {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [name, "all", value] if *name == measure => value.parse().ok(),
+            _ => None,
+        }
+    })
+}
+
+/// Checks the evaluation results of a run against its minimum measures,
+/// independently of any baseline.
+///
+/// Errors are of kind [`ErrorKind::Comparison`](../enum.ErrorKind.html).
+pub fn check_min_measures(run: &Run) -> Result<RunStatus, Error> {
+    let check = || -> Result<RunStatus, Error> {
+        let mut violations = 0;
+        for (algorithm, encoding, tid) in
+            iproduct!(&run.algorithms, &run.encodings, 0..run.topics.len())
+        {
+            let path = format_output_path(&run.output, algorithm, encoding, tid, "trec_eval");
+            let results = load_eval_results(&path)?;
+            for (measure, &min) in &run.min_measures {
+                let value = overall_measure(&results, measure).ok_or_else(|| {
+                    format!("Measure {} not found in: {}", measure, path.display())
+                })?;
+                if value < min {
+                    eprintln!("Detected effectiveness below minimum!");
+                    eprintln!("file: {}", path.display());
+                    eprintln!("{}: {} < {}", measure, value, min);
+                    violations += 1;
+                }
+            }
+        }
+        Ok(if violations > 0 {
+            RunStatus::Regression(violations)
+        } else {
+            RunStatus::Success
+        })
+    };
+    check().map_err(|err| {
+        err.wrap(ErrorKind::Comparison {
+            run: run.output.display().to_string(),
+        })
+    })
+}
+
+/// Checks the benchmark results of a run against its latency ceilings,
+/// independently of any baseline.
+///
+/// Errors are of kind [`ErrorKind::Comparison`](../enum.ErrorKind.html).
+pub fn check_max_latency(run: &Run) -> Result<RunStatus, Error> {
+    let check = || -> Result<RunStatus, Error> {
+        let mut violations = 0;
+        for (algorithm, encoding, tid) in
+            iproduct!(&run.algorithms, &run.encodings, 0..run.topics.len())
+        {
+            let path = format_output_path(&run.output, algorithm, encoding, tid, "bench");
+            let results = load_benchmark_results(&path)?;
+            let ceilings = [
+                ("avg", Some(results.avg_time), run.max_avg_ms),
+                ("q95", results.quantile_95, run.max_p95_ms),
+            ];
+            for (name, micros, max) in &ceilings {
+                if let (Some(micros), Some(max)) = (micros, max) {
+                    let millis = micros / 1000.0;
+                    if millis > *max {
+                        eprintln!("Detected latency above maximum!");
+                        eprintln!("file: {}", path.display());
+                        eprintln!("{}: {} ms > {} ms", name, millis, max);
+                        violations += 1;
+                    }
+                }
+            }
+        }
+        Ok(if violations > 0 {
+            RunStatus::Regression(violations)
+        } else {
+            RunStatus::Success
+        })
+    };
+    check().map_err(|err| {
+        err.wrap(ErrorKind::Comparison {
+            run: run.output.display().to_string(),
+        })
+    })
+}
+
+/// Compares both the evaluation and the processing time of an external run.
+fn compare_external(
+    run: &Run,
+    compare_with: &Path,
+    margin: RegressionMargin,
+) -> Result<usize, Error> {
+    let mut regression_count = compare_evaluation(run, compare_with)?;
+    for (algorithm, encoding, tid) in
+        iproduct!(&run.algorithms, &run.encodings, 0..run.topics.len())
+    {
+        let base_result_path = baseline_path(compare_with, algorithm, encoding, tid, "bench");
+        if base_result_path.exists()
+            && breakdown_regression(
+                &format_output_path(&run.output, algorithm, encoding, tid, "bench"),
+                &base_result_path,
+                margin,
+                "external command",
+            )?
+        {
+            regression_count += 1;
+        }
+    }
+    Ok(regression_count)
+}
+
+fn compare(run: &Run, compare_with: &Path, margin: RegressionMargin) -> Result<RunStatus, Error> {
+    let mismatches = baseline_meta_mismatches(run, compare_with)?;
+    if !mismatches.is_empty() {
+        return Err(Error::from(format!(
+            "Results not comparable with baseline {}: {}",
+            compare_with.display(),
+            mismatches.join("; ")
+        )));
+    }
+    match &run.kind {
+        RunKind::Evaluate { .. } => {
+            let regression_count = compare_evaluation(run, compare_with)?;
+            if regression_count > 0 {
+                return Ok(RunStatus::Regression(regression_count));
+            }
+        }
+        RunKind::External { .. } => {
+            let regression_count = compare_external(run, compare_with, margin)?;
+            if regression_count > 0 {
+                return Ok(RunStatus::Regression(regression_count));
+            }
+        }
+        RunKind::Benchmark => {
+            let mut regression_count = 0;
+            for (algorithm, encoding, tid) in
+                iproduct!(&run.algorithms, &run.encodings, 0..run.topics.len())
+            {
+                let result_path =
+                    format_output_path(&run.output, algorithm, encoding, tid, "bench");
+                let base_result_path =
+                    baseline_path(compare_with, algorithm, encoding, tid, "bench");
+                let results = load_benchmark_results(&result_path)?;
+                let baseline = load_benchmark_results(&base_result_path)?;
+                let qps_margin = run.qps_margin.unwrap_or(margin);
+                if let Some(regression) =
+                    results.regression_with_qps_margin(&baseline, margin, qps_margin)?
+                {
+                    eprintln!("Detected performance regression!");
+                    eprintln!("file: {}", result_path.display());
+                    eprintln!("base: {}", base_result_path.display());
+                    eprintln!("{}", regression);
+                    regression_count += 1;
+                }
+                if run.per_class {
+                    for class in QueryClass::iter() {
+                        let suffix = class_suffix(class);
+                        let format_path = output_path_formatter(algorithm, encoding, tid, &suffix);
+                        let result_path = format_path(&run.output);
+                        if !result_path.exists() {
+                            continue;
+                        }
+                        let margin = run.class_margins.get(&class).copied().unwrap_or(margin);
+                        if breakdown_regression(
+                            &result_path,
+                            &format_path(compare_with),
+                            margin,
+                            &format!("class {}", class),
+                        )? {
+                            regression_count += 1;
+                        }
+                    }
+                }
+                if run.per_language {
+                    for language in lang::LANGUAGES {
+                        let suffix = language_suffix("bench", language);
+                        let format_path = output_path_formatter(algorithm, encoding, tid, &suffix);
+                        let result_path = format_path(&run.output);
+                        if !result_path.exists() {
+                            continue;
+                        }
+                        if breakdown_regression(
+                            &result_path,
+                            &format_path(compare_with),
+                            margin,
+                            &format!("language {}", language),
+                        )? {
+                            regression_count += 1;
+                        }
+                    }
+                }
+                for state in &CACHE_STATES {
+                    let suffix = cache_suffix(state);
+                    let format_path = output_path_formatter(algorithm, encoding, tid, &suffix);
+                    let result_path = format_path(&run.output);
+                    if !result_path.exists() {
+                        continue;
+                    }
+                    if breakdown_regression(
+                        &result_path,
+                        &format_path(compare_with),
+                        margin,
+                        &format!("{} cache", state),
+                    )? {
+                        regression_count += 1;
+                    }
+                }
+                if let Topics::Combined { sources } = &run.topics[tid] {
+                    for idx in 0..sources.len() {
+                        let suffix = source_suffix(idx);
+                        let format_path = output_path_formatter(algorithm, encoding, tid, &suffix);
+                        let result_path = format_path(&run.output);
+                        if !result_path.exists() {
+                            continue;
+                        }
+                        if breakdown_regression(
+                            &result_path,
+                            &format_path(compare_with),
+                            margin,
+                            &format!("source {}", idx),
+                        )? {
+                            regression_count += 1;
+                        }
+                    }
+                }
+            }
+            regression_count += load_time_regressions(run, compare_with, margin)?;
+            if regression_count > 0 {
+                return Ok(RunStatus::Regression(regression_count));
+            }
+        }
+    }
+    Ok(RunStatus::Success)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{QueryOptions, TopicSource};
     use crate::tests::{mock_program, mock_set_up, EchoMode, EchoOutput, MockSetup};
     use crate::Config;
     use crate::Error;
+    use crate::FakeBackend;
     use std::path;
+    use std::sync::Arc;
     use tempdir::TempDir;
 
     #[test]
     #[cfg_attr(target_family, unix)]
-    fn test_evaluate() {
-        let tmp = TempDir::new("build").unwrap();
+    fn test_evaluate() {
+        let tmp = TempDir::new("build").unwrap();
+        let MockSetup {
+            config,
+            executor,
+            programs,
+            outputs,
+            ..
+        } = mock_set_up(&tmp);
+        process_run(
+            &executor,
+            &config.run(0),
+            &config.collection(0),
+            true,
+            tmp.path(),
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(outputs.get("evaluate_queries").unwrap()).unwrap(),
+            format!(
+                "{0} -t block_simdbp -i {2}.block_simdbp -w {2}.wand.bm25 -a wand \
+                 -q {3} --terms {1}.termlex --documents {1}.doclex \
+                 --stemmer porter2 -k 1000 --scorer bm25\n\
+                 {0} -t block_qmx -i {2}.block_qmx -w {2}.wand.bm25 -a wand \
+                 -q {3} --terms {1}.termlex --documents {1}.doclex \
+                 --stemmer porter2 -k 1000 --scorer bm25\n\
+                 {0} -t block_simdbp -i {2}.block_simdbp -w {2}.wand.bm25 -a maxscore \
+                 -q {3} --terms {1}.termlex --documents {1}.doclex \
+                 --stemmer porter2 -k 1000 --scorer bm25\n\
+                 {0} -t block_qmx -i {2}.block_qmx -w {2}.wand.bm25 -a maxscore \
+                 -q {3} --terms {1}.termlex --documents {1}.doclex \
+                 --stemmer porter2 -k 1000 --scorer bm25\n",
+                programs.get("evaluate_queries").unwrap().display(),
+                tmp.path().join("fwd").display(),
+                tmp.path().join("inv").display(),
+                tmp.path().join("topics.title").display(),
+            )
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_family, unix)]
+    fn test_evaluate_min_relevance() -> Result<(), Error> {
+        let tmp = TempDir::new("build").unwrap();
+        let mut mock_setup = mock_set_up(&tmp);
+        mock_program(
+            &tmp.path().join("bin"),
+            &mut mock_setup,
+            "trec_eval",
+            EchoMode::Stdout,
+        );
+        let MockSetup {
+            mut config,
+            executor,
+            ..
+        } = mock_setup;
+        config.0.runs[1].kind = RunKind::Evaluate {
+            qrels: tmp.path().join("qrels"),
+            min_relevance: Some(2),
+        };
+        process_run(
+            &executor,
+            &config.run(1),
+            &config.collection(0),
+            true,
+            tmp.path(),
+        )?;
+        let output = &config.run(1).output;
+        let trec_eval = fs::read_to_string(format_output_path(
+            output,
+            &"wand".into(),
+            &"block_simdbp".into(),
+            0,
+            "trec_eval",
+        ))?;
+        assert!(trec_eval.starts_with("min_relevance\tall\t2\n"));
+        assert!(trec_eval.contains(" -q -a -l2 "));
+        assert_eq!(relevance_threshold(&trec_eval), Some("2"));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_family, unix)]
+    fn test_external_run() -> Result<(), Error> {
+        let tmp = TempDir::new("build").unwrap();
+        let mut mock_setup = mock_set_up(&tmp);
+        mock_program(
+            &tmp.path().join("bin"),
+            &mut mock_setup,
+            "trec_eval",
+            EchoMode::Stdout,
+        );
+        let MockSetup {
+            mut config,
+            executor,
+            ..
+        } = mock_setup;
+        config.0.runs[1].kind = RunKind::External {
+            command: String::from("echo \"1 Q0 d1 0 1.0 {collection}.{algorithm}\" > {output}"),
+            qrels: tmp.path().join("qrels"),
+            min_relevance: None,
+        };
+        process_run(
+            &executor,
+            &config.run(1),
+            &config.collection(0),
+            true,
+            tmp.path(),
+        )?;
+        let output = &config.run(1).output;
+        let path = |suffix: &str| {
+            format_output_path(output, &"wand".into(), &"block_simdbp".into(), 0, suffix)
+        };
+        assert_eq!(
+            fs::read_to_string(path("results"))?,
+            "1 Q0 d1 0 1.0 wapo.wand\n"
+        );
+        assert!(fs::read_to_string(path("trec_eval"))?.contains(" -q -a "));
+        let bench = load_benchmark_results(&path("bench"))?;
+        assert_eq!(bench.algorithm, "wand".into());
+        assert!(bench.avg_time > 0.0);
+        assert_eq!(bench.quantile_95, None);
+        assert!(!fs::read_to_string(path("bench"))?.contains("q95"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_msmarco_qrels() -> Result<(), Error> {
+        let tmp = TempDir::new("build").unwrap();
+        let mut mock_setup = mock_set_up(&tmp);
+        mock_program(
+            &tmp.path().join("bin"),
+            &mut mock_setup,
+            "trec_eval",
+            EchoMode::Stdout,
+        );
+        let MockSetup {
+            mut config,
+            executor,
+            ..
+        } = mock_setup;
+        let temp_dir = tmp.path().join("temp");
+        fs::create_dir(&temp_dir)?;
+        fs::write(tmp.path().join("qrels.tsv"), "1\td1\n2\td7\n")?;
+        config.0.runs[1].kind = RunKind::External {
+            command: String::from("echo \"1 Q0 d1 0 1.0 run\" > {output}"),
+            qrels: tmp.path().join("qrels.tsv"),
+            min_relevance: None,
+        };
+        process_run(
+            &executor,
+            &config.run(1),
+            &config.collection(0),
+            true,
+            &temp_dir,
+        )?;
+        let converted = temp_dir.join("qrels.0.trec");
+        assert_eq!(fs::read_to_string(&converted)?, "1 0 d1 1\n2 0 d7 1\n");
+        let trec_eval = format_output_path(
+            &config.run(1).output,
+            &"wand".into(),
+            &"block_simdbp".into(),
+            0,
+            "trec_eval",
+        );
+        assert!(fs::read_to_string(trec_eval)?.contains(&converted.display().to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_trec_eval_failure() {
+        let tmp = TempDir::new("build").unwrap();
+        let executor = Executor::unchecked(tmp.path().to_path_buf()).with_backend(Arc::new(
+            FakeBackend::new().with_failure("trec_eval", 1, ""),
+        ));
+        let trec_eval_path = tmp.path().join("trec_eval");
+        assert_eq!(
+            trec_eval(
+                &executor,
+                &tmp.path().join("qrels"),
+                None,
+                &tmp.path().join("results"),
+                &trec_eval_path,
+            ),
+            Err(Error::from("Failed to evaluate results"))
+        );
+        assert!(!trec_eval_path.exists());
+        assert!(executor.stage_times().0.contains_key(&Stage::Run));
+    }
+
+    #[test]
+    fn test_internal_evaluator() -> Result<(), Error> {
+        let tmp = TempDir::new("build").unwrap();
+        let MockSetup {
+            mut config,
+            executor,
+            ..
+        } = mock_set_up(&tmp);
+        let executor = executor.with_evaluator(Evaluator::Internal);
+        let temp_dir = tmp.path().join("temp");
+        fs::create_dir(&temp_dir)?;
+        fs::write(tmp.path().join("qrels"), "1 0 d1 1\n1 0 d2 1\n")?;
+        config.0.runs[1].kind = RunKind::External {
+            command: String::from("echo \"1 Q0 d1 0 1.0 run\" > {output}"),
+            qrels: tmp.path().join("qrels"),
+            min_relevance: None,
+        };
+        process_run(
+            &executor,
+            &config.run(1),
+            &config.collection(0),
+            true,
+            &temp_dir,
+        )?;
+        let trec_eval = format_output_path(
+            &config.run(1).output,
+            &"wand".into(),
+            &"block_simdbp".into(),
+            0,
+            "trec_eval",
+        );
+        let eval_results = fs::read_to_string(trec_eval)?;
+        assert_eq!(overall_measure(&eval_results, "map"), Some(0.5));
+        assert_eq!(overall_measure(&eval_results, "num_rel_ret"), Some(1.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_min_measures() -> Result<(), Error> {
+        let tmp = TempDir::new("floors").unwrap();
+        let mut run = Run {
+            collection: String::from("wapo"),
+            kind: RunKind::Evaluate {
+                qrels: tmp.path().join("qrels"),
+                min_relevance: None,
+            },
+            encodings: vec!["ef".into()],
+            algorithms: vec!["wand".into()],
+            output: tmp.path().join("out"),
+            topics: vec![Topics::Simple {
+                path: tmp.path().join("topics"),
+                qrels: None,
+            }],
+            scorer: Scorer::from("bm25"),
+            compare_with: None,
+            normalize: QueryNormalization::default(),
+            query_options: QueryOptions::default(),
+            per_class: false,
+            class_margins: std::collections::HashMap::new(),
+            per_language: false,
+            export_features: false,
+            perf_counters: false,
+            cold_and_warm: false,
+            load_time: false,
+            query_times: false,
+            min_measures: vec![(String::from("map"), 0.25), (String::from("P_10"), 0.4)]
+                .into_iter()
+                .collect(),
+            max_avg_ms: None,
+            max_p95_ms: None,
+            env: Default::default(),
+            warmup: 0,
+            repeats: 1,
+            qps_margin: None,
+            source: None,
+            keep_raw_output: false,
+            timeout: None,
+            selective_search: None,
+            shard: None,
+        };
+        fs::write(
+            format_output_path(&run.output, &"wand".into(), &"ef".into(), 0, "trec_eval"),
+            "map\t301\t0.1\nmap\tall\t0.2612\nP_10\tall\t0.3800\n",
+        )?;
+        assert_eq!(check_min_measures(&run)?, RunStatus::Regression(1));
+        run.min_measures.remove("P_10");
+        assert_eq!(check_min_measures(&run)?, RunStatus::Success);
+        run.min_measures.insert(String::from("ndcg"), 0.4);
+        assert_eq!(
+            format!("{}", check_min_measures(&run).err().unwrap()),
+            format!(
+                "Failed to compare {} with baseline: Measure ndcg not found in: {}",
+                run.output.display(),
+                format_output_path(&run.output, &"wand".into(), &"ef".into(), 0, "trec_eval")
+                    .display()
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_max_latency() -> Result<(), Error> {
+        let tmp = TempDir::new("ceilings").unwrap();
+        let mut run = Run {
+            collection: String::from("wapo"),
+            kind: RunKind::Benchmark,
+            encodings: vec!["ef".into()],
+            algorithms: vec!["wand".into()],
+            output: tmp.path().join("out"),
+            topics: vec![Topics::Simple {
+                path: tmp.path().join("topics"),
+                qrels: None,
+            }],
+            scorer: Scorer::from("bm25"),
+            compare_with: None,
+            normalize: QueryNormalization::default(),
+            query_options: QueryOptions::default(),
+            per_class: false,
+            class_margins: std::collections::HashMap::new(),
+            per_language: false,
+            export_features: false,
+            perf_counters: false,
+            cold_and_warm: false,
+            load_time: false,
+            query_times: false,
+            min_measures: Default::default(),
+            max_avg_ms: Some(2.0),
+            max_p95_ms: Some(5.0),
+            env: Default::default(),
+            warmup: 0,
+            repeats: 1,
+            qps_margin: None,
+            source: None,
+            keep_raw_output: false,
+            timeout: None,
+            selective_search: None,
+            shard: None,
+        };
+        fs::write(
+            format_output_path(&run.output, &"wand".into(), &"ef".into(), 0, "bench"),
+            r#"{"type":"ef","query":"wand","avg":1500.0,"q50":1000.0,"q90":4000.0,"q95":6000.0}"#,
+        )?;
+        assert_eq!(check_max_latency(&run)?, RunStatus::Regression(1));
+        run.max_avg_ms = Some(1.0);
+        assert_eq!(check_max_latency(&run)?, RunStatus::Regression(2));
+        run.max_avg_ms = None;
+        run.max_p95_ms = Some(6.0);
+        assert_eq!(check_max_latency(&run)?, RunStatus::Success);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_run_ids() -> Result<(), Error> {
+        let tmp = TempDir::new("ids").unwrap();
+        fs::write(tmp.path().join("topics"), "1:hello world\n")?;
+        let mut run = Run {
+            collection: String::from("wapo"),
+            kind: RunKind::Benchmark,
+            encodings: vec!["ef".into()],
+            algorithms: vec!["wand".into(), "maxscore".into()],
+            output: tmp.path().join("out"),
+            topics: vec![Topics::Simple {
+                path: tmp.path().join("topics"),
+                qrels: None,
+            }],
+            scorer: Scorer::from("bm25"),
+            compare_with: None,
+            normalize: QueryNormalization::default(),
+            query_options: QueryOptions::default(),
+            per_class: false,
+            class_margins: std::collections::HashMap::new(),
+            per_language: false,
+            export_features: false,
+            perf_counters: false,
+            cold_and_warm: false,
+            load_time: false,
+            query_times: false,
+            min_measures: Default::default(),
+            max_avg_ms: None,
+            max_p95_ms: None,
+            env: Default::default(),
+            warmup: 0,
+            repeats: 1,
+            qps_margin: None,
+            source: None,
+            keep_raw_output: false,
+            timeout: None,
+            selective_search: None,
+            shard: None,
+        };
+        let bench = |output: &Path, algorithm: &str| {
+            format_output_path(output, &algorithm.into(), &"ef".into(), 0, "bench")
+        };
+        let out = tmp.path().join("out");
+        let moved = tmp.path().join("moved");
+        for algorithm in &["wand", "maxscore"] {
+            fs::write(
+                bench(&out, algorithm),
+                format!(
+                    r#"{{"type":"ef","query":"{}","avg":1.0,"q50":1.0,"q90":1.0,"q95":1.0}}"#,
+                    algorithm
+                ),
+            )?;
+        }
+        let id = |output: &Path, algorithm: &str| {
+            load_benchmark_results(&bench(output, algorithm))
+                .unwrap()
+                .id
+        };
+        write_run_ids(&run, true, Some("abc"))?;
+        let wand = id(&out, "wand");
+        assert!(wand.is_some());
+        assert_ne!(wand, id(&out, "maxscore"));
+        write_run_ids(&run, true, Some("abc"))?;
+        assert_eq!(id(&out, "wand"), wand);
+        write_run_ids(&run, true, Some("def"))?;
+        assert_ne!(id(&out, "wand"), wand);
+
+        run.output = moved.clone();
+        fs::copy(tmp.path().join("topics"), tmp.path().join("moved_topics"))?;
+        run.topics = vec![Topics::Simple {
+            path: tmp.path().join("moved_topics"),
+            qrels: None,
+        }];
+        for algorithm in &["wand", "maxscore"] {
+            fs::copy(bench(&out, algorithm), bench(&moved, algorithm))?;
+        }
+        write_run_ids(&run, true, Some("abc"))?;
+        assert_eq!(id(&moved, "wand"), wand);
+        Ok(())
+    }
+
+    #[test]
+    fn test_relevance_threshold_mismatch() -> Result<(), Error> {
+        let tmp = TempDir::new("threshold").unwrap();
+        let base = tmp.path().join("base");
+        let output = tmp.path().join("out");
+        let run = Run {
+            collection: String::from("wapo"),
+            kind: RunKind::Evaluate {
+                qrels: tmp.path().join("qrels"),
+                min_relevance: Some(1),
+            },
+            encodings: vec!["ef".into()],
+            algorithms: vec!["wand".into()],
+            output: output.clone(),
+            topics: vec![Topics::Simple {
+                path: tmp.path().join("topics"),
+                qrels: None,
+            }],
+            scorer: Scorer::from("bm25"),
+            compare_with: Some(base.clone().into()),
+            normalize: QueryNormalization::default(),
+            query_options: QueryOptions::default(),
+            per_class: false,
+            class_margins: std::collections::HashMap::new(),
+            per_language: false,
+            export_features: false,
+            perf_counters: false,
+            cold_and_warm: false,
+            load_time: false,
+            query_times: false,
+            min_measures: Default::default(),
+            max_avg_ms: None,
+            max_p95_ms: None,
+            env: Default::default(),
+            warmup: 0,
+            repeats: 1,
+            qps_margin: None,
+            source: None,
+            keep_raw_output: false,
+            timeout: None,
+            selective_search: None,
+            shard: None,
+        };
+        let path = |prefix: &Path| {
+            format_output_path(prefix, &"wand".into(), &"ef".into(), 0, "trec_eval")
+        };
+        fs::write(path(&output), "min_relevance\tall\t1\nmap\tall\t0.2\n")?;
+        fs::write(path(&base), "map\tall\t0.2\n")?;
+        let err = compare_with_baseline(&run, &base, RegressionMargin::default())
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("Relevance thresholds differ: 1 (run) vs none (baseline)"));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_family, unix)]
+    fn test_evaluate_simple_topics() {
+        let tmp = TempDir::new("build").unwrap();
+        let mut mock_setup = mock_set_up(&tmp);
+        mock_program(
+            &tmp.path().join("bin"),
+            &mut mock_setup,
+            "trec_eval",
+            EchoMode::Stdout,
+        );
+        let MockSetup {
+            config,
+            executor,
+            programs,
+            outputs,
+            ..
+        } = mock_setup;
+        process_run(
+            &executor,
+            &config.run(1),
+            &config.collection(0),
+            true,
+            tmp.path(),
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(outputs.get("evaluate_queries").unwrap()).unwrap(),
+            format!(
+                "{0} -t block_simdbp -i {2}.block_simdbp -w {2}.wand.bm25 -a wand \
+                 -q {3} --terms {1}.termlex --documents {1}.doclex \
+                 --stemmer porter2 -k 1000 --scorer bm25\n\
+                 {0} -t block_simdbp -i {2}.block_simdbp -w {2}.wand.bm25 -a maxscore \
+                 -q {3} --terms {1}.termlex --documents {1}.doclex \
+                 --stemmer porter2 -k 1000 --scorer bm25\n",
+                programs.get("evaluate_queries").unwrap().display(),
+                tmp.path().join("fwd").display(),
+                tmp.path().join("inv").display(),
+                tmp.path().join("topics").display(),
+            )
+        );
+        let trec_eval = programs.get("trec_eval").unwrap().to_str().unwrap();
+        let qrels = tmp
+            .path()
+            .join("qrels")
+            .into_os_string()
+            .into_string()
+            .unwrap();
+        let run = config.run(1).output.to_str().unwrap().to_string();
+        assert_eq!(
+            EchoOutput::from(
+                path::PathBuf::from(format!(
+                    "{}.wand.block_simdbp.0.trec_eval",
+                    config.run(1).output.display()
+                ))
+                .as_path()
+            ),
+            EchoOutput::from(format!(
+                "{} -q -a {} {}.wand.block_simdbp.0.results",
+                &trec_eval, &qrels, &run
+            )),
+        );
+        assert_eq!(
+            EchoOutput::from(
+                path::PathBuf::from(format!(
+                    "{}.maxscore.block_simdbp.0.trec_eval",
+                    config.run(1).output.display()
+                ))
+                .as_path()
+            ),
+            EchoOutput::from(format!(
+                "{} -q -a {} {}.maxscore.block_simdbp.0.results",
+                &trec_eval, &qrels, &run
+            )),
+        );
+    }
+
+    #[test]
+    fn test_normalize_queries() {
+        let queries = "1:Hello, World!\n2:hello world\n3:Foo\n\n4:Foo\n5:a  b";
+        let policy = QueryNormalization {
+            dedup: false,
+            lowercase: false,
+            strip_punctuation: true,
+        };
+        assert_eq!(
+            normalize_queries(queries, policy),
+            (
+                String::from("1:Hello World\n2:hello world\n3:Foo\n4:Foo\n5:a b\n"),
+                NormalizationStats {
+                    modified: 1,
+                    whitespace_only: 1,
+                    duplicates: 0
+                }
+            )
+        );
+        let policy = QueryNormalization {
+            dedup: true,
+            lowercase: false,
+            strip_punctuation: false,
+        };
+        assert_eq!(
+            normalize_queries(queries, policy),
+            (
+                String::from("1:Hello, World!\n2:hello world\n3:Foo\n5:a  b\n"),
+                NormalizationStats {
+                    modified: 0,
+                    whitespace_only: 0,
+                    duplicates: 1
+                }
+            )
+        );
+        assert_eq!(
+            normalize_queries("query without id", QueryNormalization::default()).0,
+            "query without id\n"
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_family, unix)]
+    fn test_evaluate_normalized_topics() {
+        let tmp = TempDir::new("build").unwrap();
+        let MockSetup {
+            mut config,
+            executor,
+            outputs,
+            ..
+        } = mock_set_up(&tmp);
+        std::fs::write(tmp.path().join("topics"), "1:Query\n2:query\n").unwrap();
+        config.0.runs[1].normalize = QueryNormalization {
+            dedup: true,
+            lowercase: true,
+            strip_punctuation: false,
+        };
+        process_run(
+            &executor,
+            &config.run(1),
+            &config.collection(0),
+            true,
+            tmp.path(),
+        )
+        .unwrap();
+        let normalized = tmp.path().join("topics.normalized");
+        assert_eq!(std::fs::read_to_string(&normalized).unwrap(), "1:query\n");
+        assert!(
+            std::fs::read_to_string(outputs.get("evaluate_queries").unwrap())
+                .unwrap()
+                .contains(&format!("-q {} ", normalized.display()))
+        );
+    }
+
+    #[test]
+    fn test_split_by_class() {
+        assert_eq!(
+            split_by_class("1:a b c\n2:a\n3:\n4:a b c d e f\n5:b\n6:a b c d"),
+            vec![
+                (QueryClass::One, String::from("2:a\n5:b\n")),
+                (
+                    QueryClass::ThreeToFour,
+                    String::from("1:a b c\n6:a b c d\n")
+                ),
+                (QueryClass::FiveOrMore, String::from("4:a b c d e f\n")),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_family, unix)]
+    fn test_benchmark_per_class() -> Result<(), Error> {
+        let tmp = TempDir::new("run").unwrap();
+        let MockSetup {
+            mut config,
+            executor,
+            programs,
+            ..
+        } = mock_set_up(&tmp);
+        fs::write(
+            programs.get("queries").unwrap(),
+            "#!/bin/bash\n\
+             if [[ \" $* \" == *\" --extract \"* ]]; then printf '1\\t100\\n2\\t300\\n3\\t200\\n'; \
+             else echo '{\"type\":\"block_simdbp\",\"query\":\"wand\",\"avg\":200}'; fi",
+        )?;
+        fs::write(tmp.path().join("topics"), "1:a\n2:a b\n3:b\n")?;
+        config.0.runs[2].topics = vec![Topics::Simple {
+            path: tmp.path().join("topics"),
+            qrels: None,
+        }];
+        config.0.runs[2].per_class = true;
+        let temp_dir = tmp.path().join("temp");
+        fs::create_dir(&temp_dir)?;
+        process_run(
+            &executor,
+            &config.run(2),
+            &config.collection(0),
+            true,
+            &temp_dir,
+        )?;
+        let output = &config.run(2).output;
+        let path = |suffix: &str| {
+            format_output_path(output, &"wand".into(), &"block_simdbp".into(), 0, suffix)
+        };
+        assert!(path("bench").exists());
+        let one = load_benchmark_results(&path("bench.1"))?;
+        assert_eq!(one.algorithm, "wand".into());
+        assert!((one.avg_time - 150.0).abs() < 1e-6);
+        assert_eq!(one.quantile_50, Some(100.0));
+        assert_eq!(one.quantile_95, Some(200.0));
+        assert!((load_benchmark_results(&path("bench.2"))?.avg_time - 300.0).abs() < 1e-6);
+        assert!(!path("bench.3-4").exists());
+        // Classes are aggregated from the timed queries instead of being run separately.
+        assert_eq!(fs::read_dir(&temp_dir)?.count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_family, unix)]
+    fn test_benchmark_keeps_topics_dir_clean() -> Result<(), Error> {
+        let tmp = TempDir::new("run").unwrap();
         let MockSetup {
-            config,
+            mut config,
             executor,
             programs,
-            outputs,
             ..
         } = mock_set_up(&tmp);
-        process_run(&executor, &config.run(0), &config.collection(0), true).unwrap();
+        fs::write(
+            programs.get("queries").unwrap(),
+            "#!/bin/bash\necho '{\"type\":\"block_simdbp\",\"query\":\"wand\",\"avg\":1.5}'",
+        )?;
+        let docs: Vec<u8> = [1_u32, 10, 2, 0, 1, 1, 5, 3, 0, 1, 2]
+            .iter()
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect();
+        fs::write(tmp.path().join("inv.docs"), docs)?;
+        let topics_dir = tmp.path().join("topics");
+        fs::create_dir(&topics_dir)?;
+        fs::write(
+            topics_dir.join("topics"),
+            "1:the a\n2:столица\n3:of the b\n",
+        )?;
+        config.0.runs[2].topics = vec![Topics::Simple {
+            path: topics_dir.join("topics"),
+            qrels: None,
+        }];
+        config.0.runs[2].per_language = true;
+        config.0.runs[2].export_features = true;
+        let temp_dir = tmp.path().join("temp");
+        fs::create_dir(&temp_dir)?;
+        process_run(
+            &executor,
+            &config.run(2),
+            &config.collection(0),
+            true,
+            &temp_dir,
+        )?;
+        let files: Vec<_> = fs::read_dir(&topics_dir)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(files, vec![std::ffi::OsString::from("topics")]);
+        assert!(temp_dir.join("topics.lang.en").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_language() {
         assert_eq!(
-            std::fs::read_to_string(outputs.get("evaluate_queries").unwrap()).unwrap(),
-            format!(
-                "{0} -t block_simdbp -i {2}.block_simdbp -w {2}.wand -a wand \
-                 -q {3} --terms {1}.termlex --documents {1}.doclex \
-                 --stemmer porter2 -k 1000 --scorer bm25\n\
-                 {0} -t block_qmx -i {2}.block_qmx -w {2}.wand -a wand \
-                 -q {3} --terms {1}.termlex --documents {1}.doclex \
-                 --stemmer porter2 -k 1000 --scorer bm25\n\
-                 {0} -t block_simdbp -i {2}.block_simdbp -w {2}.wand -a maxscore \
-                 -q {3} --terms {1}.termlex --documents {1}.doclex \
-                 --stemmer porter2 -k 1000 --scorer bm25\n\
-                 {0} -t block_qmx -i {2}.block_qmx -w {2}.wand -a maxscore \
-                 -q {3} --terms {1}.termlex --documents {1}.doclex \
-                 --stemmer porter2 -k 1000 --scorer bm25\n",
-                programs.get("evaluate_queries").unwrap().display(),
-                tmp.path().join("fwd").display(),
-                tmp.path().join("inv").display(),
-                tmp.path().join("topics.title").display(),
-            )
+            split_by_language("1:what is it\n2:wie ist das\n\n3:eiffel tower\n4:how to\n"),
+            vec![
+                ("de", String::from("2:wie ist das\n")),
+                ("en", String::from("1:what is it\n4:how to\n")),
+                ("und", String::from("3:eiffel tower\n")),
+            ]
         );
     }
 
     #[test]
     #[cfg_attr(target_family, unix)]
-    fn test_evaluate_simple_topics() {
-        let tmp = TempDir::new("build").unwrap();
+    fn test_benchmark_per_language() -> Result<(), Error> {
+        let tmp = TempDir::new("run").unwrap();
         let mut mock_setup = mock_set_up(&tmp);
         mock_program(
             &tmp.path().join("bin"),
             &mut mock_setup,
-            "trec_eval",
+            "queries",
             EchoMode::Stdout,
         );
         let MockSetup {
-            config,
+            mut config,
             executor,
-            programs,
-            outputs,
             ..
         } = mock_setup;
-        process_run(&executor, &config.run(1), &config.collection(0), true).unwrap();
+        fs::write(tmp.path().join("topics"), "1:the a\n2:столица\n3:of b\n")?;
+        config.0.runs[2].topics = vec![Topics::Simple {
+            path: tmp.path().join("topics"),
+            qrels: None,
+        }];
+        config.0.runs[2].per_language = true;
+        let temp_dir = tmp.path().join("temp");
+        fs::create_dir(&temp_dir)?;
+        process_run(
+            &executor,
+            &config.run(2),
+            &config.collection(0),
+            true,
+            &temp_dir,
+        )?;
+        let output = &config.run(2).output;
+        let path = |suffix: &str| {
+            format_output_path(output, &"wand".into(), &"block_simdbp".into(), 0, suffix)
+        };
+        assert!(path("bench").exists());
+        assert!(path("bench.lang.en").exists());
+        assert!(path("bench.lang.ru").exists());
+        assert!(!path("bench.lang.und").exists());
         assert_eq!(
-            std::fs::read_to_string(outputs.get("evaluate_queries").unwrap()).unwrap(),
-            format!(
-                "{0} -t block_simdbp -i {2}.block_simdbp -w {2}.wand -a wand \
-                 -q {3} --terms {1}.termlex --documents {1}.doclex \
-                 --stemmer porter2 -k 1000 --scorer bm25\n\
-                 {0} -t block_simdbp -i {2}.block_simdbp -w {2}.wand -a maxscore \
-                 -q {3} --terms {1}.termlex --documents {1}.doclex \
-                 --stemmer porter2 -k 1000 --scorer bm25\n",
-                programs.get("evaluate_queries").unwrap().display(),
-                tmp.path().join("fwd").display(),
-                tmp.path().join("inv").display(),
-                tmp.path().join("topics").display(),
-            )
+            fs::read_to_string(temp_dir.join("topics.lang.en"))?,
+            "1:the a\n3:of b\n"
         );
-        let trec_eval = programs.get("trec_eval").unwrap().to_str().unwrap();
-        let qrels = tmp
-            .path()
-            .join("qrels")
-            .into_os_string()
-            .into_string()
+        assert!(!tmp.path().join("topics.lang.en").exists());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_family, unix)]
+    fn test_benchmark_cold_and_warm() -> Result<(), Error> {
+        let tmp = TempDir::new("run").unwrap();
+        let mut mock_setup = mock_set_up(&tmp);
+        mock_program(
+            &tmp.path().join("bin"),
+            &mut mock_setup,
+            "queries",
+            EchoMode::Stdout,
+        );
+        let MockSetup {
+            mut config,
+            executor,
+            ..
+        } = mock_setup;
+        config.0.runs[2].cold_and_warm = true;
+        process_run(
+            &executor,
+            &config.run(2),
+            &config.collection(0),
+            true,
+            tmp.path(),
+        )?;
+        let output = &config.run(2).output;
+        let path = |suffix: &str| {
+            format_output_path(output, &"wand".into(), &"block_simdbp".into(), 0, suffix)
+        };
+        assert!(path("bench").exists());
+        assert!(path("bench.cold").exists());
+        assert!(path("bench.warm").exists());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_family, unix)]
+    fn test_benchmark_load_time() -> Result<(), Error> {
+        let tmp = TempDir::new("run").unwrap();
+        let MockSetup {
+            mut config,
+            executor,
+            ..
+        } = mock_set_up(&tmp);
+        config.0.runs[2].load_time = true;
+        process_run(
+            &executor,
+            &config.run(2),
+            &config.collection(0),
+            true,
+            tmp.path(),
+        )?;
+        let times = BuildTimes::load(&load_times_path(&config.run(2).output))?;
+        let encodings: Vec<_> = times.0.keys().map(String::as_str).collect();
+        assert_eq!(encodings, vec!["block_simdbp"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample_queries() {
+        let queries = "1:a\n2:b\n\n3:c\n4:d\n";
+        assert_eq!(sample_queries(queries, 1.0), "1:a\n2:b\n3:c\n4:d\n");
+        assert_eq!(sample_queries(queries, 0.5), "2:b\n4:d\n");
+        assert_eq!(sample_queries(queries, 0.25), "4:d\n");
+    }
+
+    #[test]
+    #[cfg_attr(target_family, unix)]
+    fn test_benchmark_combined_topics() -> Result<(), Error> {
+        let tmp = TempDir::new("run").unwrap();
+        let mut mock_setup = mock_set_up(&tmp);
+        mock_program(
+            &tmp.path().join("bin"),
+            &mut mock_setup,
+            "queries",
+            EchoMode::Stdout,
+        );
+        let MockSetup {
+            mut config,
+            executor,
+            ..
+        } = mock_setup;
+        fs::write(tmp.path().join("short"), "1:a\n2:b\n")?;
+        fs::write(tmp.path().join("long"), "1:a b c\n2:b c d\n")?;
+        let source = |name: &str, weight: f64| TopicSource {
+            topics: Topics::Simple {
+                path: tmp.path().join(name),
+                qrels: None,
+            },
+            weight,
+        };
+        config.0.runs[2].topics = vec![Topics::Combined {
+            sources: vec![source("short", 1.0), source("long", 0.5)],
+        }];
+        let temp_dir = tmp.path().join("temp");
+        fs::create_dir(&temp_dir)?;
+        process_run(
+            &executor,
+            &config.run(2),
+            &config.collection(0),
+            true,
+            &temp_dir,
+        )?;
+        assert_eq!(
+            fs::read_to_string(temp_dir.join("combined.0"))?,
+            "1:a\n2:b\n2:b c d\n"
+        );
+        let output = &config.run(2).output;
+        let path = |suffix: &str| {
+            format_output_path(output, &"wand".into(), &"block_simdbp".into(), 0, suffix)
+        };
+        assert!(path("bench").exists());
+        assert!(path("bench.source-0").exists());
+        assert!(path("bench.source-1").exists());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_family, unix)]
+    fn test_export_features() -> Result<(), Error> {
+        let tmp = TempDir::new("run").unwrap();
+        let MockSetup {
+            mut config,
+            executor,
+            programs,
+            ..
+        } = mock_set_up(&tmp);
+        fs::write(
+            programs.get("queries").unwrap(),
+            "#!/bin/bash\necho '{\"type\":\"block_simdbp\",\"query\":\"wand\",             \"avg\":1.5,\"q50\":1,\"q90\":2,\"q95\":3}'",
+        )?;
+        let docs: Vec<u8> = [1_u32, 10, 2, 0, 1, 1, 5, 3, 0, 1, 2]
+            .iter()
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect();
+        fs::write(tmp.path().join("inv.docs"), docs)?;
+        fs::write(tmp.path().join("topics"), "1:term1 term3\n2:unknown\n")?;
+        config.0.runs[2].topics = vec![Topics::Simple {
+            path: tmp.path().join("topics"),
+            qrels: None,
+        }];
+        config.0.runs[2].export_features = true;
+        let temp_dir = tmp.path().join("temp");
+        fs::create_dir(&temp_dir)?;
+        process_run(
+            &executor,
+            &config.run(2),
+            &config.collection(0),
+            true,
+            &temp_dir,
+        )?;
+        let csv = format_output_path(
+            &config.run(2).output,
+            &"wand".into(),
+            &"block_simdbp".into(),
+            0,
+            "features.csv",
+        );
+        assert_eq!(
+            fs::read_to_string(csv)?,
+            format!("{}\n1,2,2,5,3,1.5\n2,1,0,0,0,1.5\n", CSV_HEADER)
+        );
+        assert!(!tmp.path().join("topics.single").exists());
+        assert!(!temp_dir.join("topics.single").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_query_times() -> Result<(), Error> {
+        let tmp = TempDir::new("run").unwrap();
+        let MockSetup {
+            mut config,
+            executor,
+            programs,
+            ..
+        } = mock_set_up(&tmp);
+        fs::write(
+            programs.get("queries").unwrap(),
+            "#!/bin/bash\n\
+             if [[ \" $* \" == *\" --extract \"* ]]; then printf '1\\t120\\n2\\t80\\t100\\n'; \
+             else echo '{\"type\":\"block_simdbp\",\"query\":\"wand\",\"avg\":1.5}'; fi",
+        )?;
+        config.0.runs[2].query_times = true;
+        process_run(
+            &executor,
+            &config.run(2),
+            &config.collection(0),
+            true,
+            tmp.path(),
+        )?;
+        let csv = format_output_path(
+            &config.run(2).output,
+            &"wand".into(),
+            &"block_simdbp".into(),
+            0,
+            "query_times.csv",
+        );
+        assert_eq!(
+            fs::read_to_string(&csv)?,
+            format!("{}\n1,120,120,120\n2,90,80,100\n", QUERY_TIMES_CSV_HEADER)
+        );
+        assert!(output_files(config.run(2)).contains(&csv));
+        Ok(())
+    }
+
+    #[test]
+    fn test_oov_warnings() -> Result<(), Error> {
+        let tmp = TempDir::new("run").unwrap();
+        let MockSetup { config, .. } = mock_set_up(&tmp);
+        let collection = config.collection(0);
+        let queries = tmp.path().join("topics");
+        fs::write(&queries, "1:term1 term3\n2:unknown\n")?;
+        let queries = vec![queries.display().to_string()];
+        let warnings = oov_warnings(&collection, &queries)?;
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, WarningKind::OutOfVocabulary);
+        assert!(warnings[0].message.starts_with("33.3% of query terms"));
+        fs::write(&queries[0], "1:term1 term3\n")?;
+        assert!(oov_warnings(&collection, &queries)?.is_empty());
+        Ok(())
+    }
+
+    fn bench_results(avg_time: f32, counters: Option<HardwareCounters>) -> BenchmarkResults {
+        BenchmarkResults {
+            kind: Encoding::from("block_simdbp"),
+            algorithm: Algorithm::from("wand"),
+            avg_time,
+            quantile_50: Some(avg_time),
+            quantile_90: Some(avg_time),
+            quantile_95: Some(avg_time),
+            counters,
+            phases: BTreeMap::new(),
+            qps: None,
+            id: None,
+        }
+    }
+
+    #[test]
+    fn test_counters_regression() -> Result<(), Error> {
+        let counters = |cycles: f64, instructions: f64| HardwareCounters {
+            cycles: Some(cycles),
+            instructions: Some(instructions),
+            llc_misses: None,
+        };
+        let margin = RegressionMargin(0.1);
+        let gold = bench_results(10.0, Some(counters(100.0, 200.0)));
+        assert!(bench_results(10.0, Some(counters(100.0, 200.0)))
+            .regression(&gold, margin)?
+            .is_none());
+        assert!(bench_results(10.0, None)
+            .regression(&gold, margin)?
+            .is_none());
+        let regression = bench_results(10.0, Some(counters(100.0, 230.0)))
+            .regression(&gold, margin)?
             .unwrap();
-        let run = config.run(1).output.to_str().unwrap().to_string();
+        assert_eq!(regression.instructions, Some((230.0, 200.0)));
+        assert_eq!(regression.ipc, None);
+        let regression = bench_results(10.0, Some(counters(200.0, 200.0)))
+            .regression(&gold, margin)?
+            .unwrap();
+        assert_eq!(regression.instructions, None);
+        assert_eq!(regression.ipc, Some((1.0, 2.0)));
+        assert_eq!(regression.to_string(), "ipc: 2 --> 1\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_phase_regression() -> Result<(), Error> {
+        let results = |scoring: f32| -> BenchmarkResults {
+            serde_json::from_str(&format!(
+                r#"{{"type":"block_simdbp","query":"wand","avg":10,"q50":10,"q90":10,"q95":10,
+                    "phases":{{"parsing":1,"lookup":2,"scoring":{}}}}}"#,
+                scoring
+            ))
+            .unwrap()
+        };
+        let margin = RegressionMargin(0.1);
+        let gold = results(5.0);
+        assert_eq!(gold.phases.len(), 3);
+        assert!(results(5.5).regression(&gold, margin)?.is_none());
+        assert!(bench_results(10.0, None)
+            .regression(&gold, margin)?
+            .is_none());
+        let regression = results(6.0).regression(&gold, margin)?.unwrap();
+        assert_eq!(regression.avg_time, None);
+        assert_eq!(regression.to_string(), "scoring time: 5 --> 6 (+20%)\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_combined_sources_with_same_file_name() -> Result<(), Error> {
+        let tmp = TempDir::new("run").unwrap();
+        let source = |dir: &str, queries: &str| -> Result<TopicSource, Error> {
+            let path = tmp.path().join(dir).join("topics");
+            fs::create_dir(tmp.path().join(dir))?;
+            fs::write(&path, queries)?;
+            Ok(TopicSource {
+                topics: Topics::Simple { path, qrels: None },
+                weight: 1.0,
+            })
+        };
+        let topics = Topics::Combined {
+            sources: vec![source("a", "1:a\t2\n")?, source("b", "1:b\t3\n")?],
+        };
+        let queries = queries_path(&topics, "0", &Executor::new(), tmp.path())?;
+        assert_eq!(fs::read_to_string(&queries)?, "1:a\n1:b\n");
         assert_eq!(
-            EchoOutput::from(
-                path::PathBuf::from(format!(
-                    "{}.wand.block_simdbp.0.trec_eval",
-                    config.run(1).output.display()
-                ))
-                .as_path()
-            ),
-            EchoOutput::from(format!(
-                "{} -q -a {} {}.wand.block_simdbp.0.results",
-                &trec_eval, &qrels, &run
-            )),
+            fs::read_to_string(tmp.path().join("topics.unweighted.0-0"))?,
+            "1:a\n"
         );
         assert_eq!(
-            EchoOutput::from(
-                path::PathBuf::from(format!(
-                    "{}.maxscore.block_simdbp.0.trec_eval",
-                    config.run(1).output.display()
-                ))
-                .as_path()
+            fs::read_to_string(tmp.path().join("topics.unweighted.0-1"))?,
+            "1:b\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_weighted_topics() -> Result<(), Error> {
+        let tmp = TempDir::new("run").unwrap();
+        let path = tmp.path().join("topics");
+        fs::write(&path, "1:a\t2\n2:b\n")?;
+        let topics = Topics::Simple { path, qrels: None };
+        let queries = queries_path(&topics, "0", &Executor::new(), tmp.path())?;
+        assert_eq!(fs::read_to_string(&queries)?, "1:a\n2:b\n");
+        let weights = topic_weights(&topics)?.unwrap();
+        assert_eq!(
+            weighted_eval_results(
+                "map\t1\t0.5\nmap\t2\t0.2\nmap\tall\t0.35\nrunid\t1\tx\n",
+                &weights
             ),
-            EchoOutput::from(format!(
-                "{} -q -a {} {}.maxscore.block_simdbp.0.results",
-                &trec_eval, &qrels, &run
-            )),
+            "map\tall\t0.4000\n"
+        );
+        let times = QueryTime::parse("1\t10\n2\t40\n")?;
+        let results: serde_json::Value =
+            serde_json::from_str(&with_weighted_times(r#"{"avg":25}"#, &weights, &times)?).unwrap();
+        let weighted = |name: &str| results["weighted"][name].as_f64().unwrap();
+        assert!((weighted("avg") - 20.0).abs() < 1e-9);
+        assert!((weighted("q50") - 10.0).abs() < 1e-9);
+        assert!((weighted("q95") - 40.0).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_qps_regression() -> Result<(), Error> {
+        let results = |qps: f64| BenchmarkResults {
+            qps: Some(qps),
+            ..bench_results(10.0, None)
+        };
+        let margin = RegressionMargin(0.1);
+        let gold = results(1000.0);
+        assert!(results(950.0).regression(&gold, margin)?.is_none());
+        assert!(bench_results(10.0, None)
+            .regression(&gold, margin)?
+            .is_none());
+        let regression = results(800.0).regression(&gold, margin)?.unwrap();
+        assert_eq!(regression.avg_time, None);
+        assert_eq!(regression.to_string(), "qps: 1000 --> 800\n");
+        assert!(results(800.0)
+            .regression_with_qps_margin(&gold, margin, RegressionMargin(0.25))?
+            .is_none());
+        assert_eq!(
+            with_qps(r#"{"avg":1}"#, Some(2.5))?,
+            r#"{"avg":1,"qps":2.5}"#
+        );
+        assert_eq!(with_qps("", Some(2.5))?, "");
+        assert_eq!(query_loop_qps(r#"{"avg":250,"q50":1}"#), Some(4000.0));
+        assert_eq!(query_loop_qps(r#"{"avg":0}"#), None);
+        assert_eq!(query_loop_qps("not json"), None);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_family, unix)]
+    fn test_benchmark_perf_counters() -> Result<(), Error> {
+        let tmp = TempDir::new("run").unwrap();
+        let MockSetup {
+            mut config,
+            executor,
+            ..
+        } = mock_set_up(&tmp);
+        let perf = tmp.path().join("bin").join("perf");
+        fs::write(
+            &perf,
+            "#!/bin/bash\n\
+             echo '{\"counter-value\" : \"100.0\", \"event\" : \"cycles\"}' >&2\n\
+             echo '{\"counter-value\" : \"300.0\", \"event\" : \"instructions\"}' >&2\n\
+             echo '{\"type\":\"block_simdbp\",\"query\":\"wand\",\
+             \"avg\":1.5,\"q50\":1,\"q90\":2,\"q95\":3}'",
+        )?;
+        fs::set_permissions(&perf, std::os::unix::fs::PermissionsExt::from_mode(0o744))?;
+        config.0.runs[2].perf_counters = true;
+        process_run(
+            &executor,
+            &config.run(2),
+            &config.collection(0),
+            true,
+            tmp.path(),
+        )?;
+        let bench = format_output_path(
+            &config.run(2).output,
+            &"wand".into(),
+            &"block_simdbp".into(),
+            0,
+            "bench",
+        );
+        let results = load_benchmark_results(&bench)?;
+        assert_eq!(
+            results.counters,
+            Some(HardwareCounters {
+                cycles: Some(100.0),
+                instructions: Some(300.0),
+                llc_misses: None,
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_correctness_regression_diff() -> Result<(), Error> {
+        let tmp = TempDir::new("diff").unwrap();
+        let base = tmp.path().join("base");
+        let output = tmp.path().join("out");
+        let run = Run {
+            collection: String::from("wapo"),
+            kind: RunKind::Evaluate {
+                qrels: tmp.path().join("qrels"),
+                min_relevance: None,
+            },
+            encodings: vec!["ef".into()],
+            algorithms: vec!["wand".into()],
+            output: output.clone(),
+            topics: vec![Topics::Simple {
+                path: tmp.path().join("topics"),
+                qrels: None,
+            }],
+            scorer: Scorer::from("bm25"),
+            compare_with: Some(base.clone().into()),
+            normalize: QueryNormalization::default(),
+            query_options: QueryOptions::default(),
+            per_class: false,
+            class_margins: std::collections::HashMap::new(),
+            per_language: false,
+            export_features: false,
+            perf_counters: false,
+            cold_and_warm: false,
+            load_time: false,
+            query_times: false,
+            min_measures: Default::default(),
+            max_avg_ms: None,
+            max_p95_ms: None,
+            env: Default::default(),
+            warmup: 0,
+            repeats: 1,
+            qps_margin: None,
+            source: None,
+            keep_raw_output: false,
+            timeout: None,
+            selective_search: None,
+            shard: None,
+        };
+        let path = |prefix: &Path, suffix: &str| {
+            format_output_path(prefix, &"wand".into(), &"ef".into(), 0, suffix)
+        };
+        fs::write(path(&output, "trec_eval"), "map\tall\t0.2\n")?;
+        fs::write(path(&base, "trec_eval"), "map\tall\t0.3\n")?;
+        fs::write(path(&output, "results"), "1 Q0 d1 0 2.0 R\n")?;
+        fs::write(path(&base, "results"), "1 Q0 d2 0 2.0 R\n")?;
+        assert_eq!(
+            compare_with_baseline(&run, &base, RegressionMargin::default())?,
+            RunStatus::Regression(1)
+        );
+        assert!(fs::read_to_string(path(&output, "diff.html"))?.contains("<h2>Query 1</h2>"));
+
+        let mut run = run;
+        let tolerances = |map: f64| crate::config::Baseline {
+            path: base.clone(),
+            tolerances: vec![(String::from("map"), map)].into_iter().collect(),
+            source: None,
+        };
+        run.compare_with = Some(tolerances(0.15));
+        assert_eq!(
+            compare_with_baseline(&run, &base, RegressionMargin::default())?,
+            RunStatus::Success
         );
+        run.compare_with = Some(tolerances(0.05));
+        assert_eq!(
+            compare_with_baseline(&run, &base, RegressionMargin::default())?,
+            RunStatus::Regression(1)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_files() -> Result<(), Error> {
+        let tmp = TempDir::new("outputs").unwrap();
+        let run = Run {
+            collection: String::from("wapo"),
+            kind: RunKind::Benchmark,
+            encodings: vec!["ef".into()],
+            algorithms: vec!["wand".into(), "maxscore".into()],
+            output: tmp.path().join("out"),
+            topics: vec![Topics::Simple {
+                path: tmp.path().join("topics"),
+                qrels: None,
+            }],
+            scorer: Scorer::from("bm25"),
+            compare_with: None,
+            normalize: QueryNormalization::default(),
+            query_options: QueryOptions::default(),
+            per_class: false,
+            class_margins: std::collections::HashMap::new(),
+            per_language: false,
+            export_features: false,
+            perf_counters: false,
+            cold_and_warm: false,
+            load_time: false,
+            query_times: false,
+            min_measures: Default::default(),
+            max_avg_ms: None,
+            max_p95_ms: None,
+            env: Default::default(),
+            warmup: 0,
+            repeats: 1,
+            qps_margin: None,
+            source: None,
+            keep_raw_output: false,
+            timeout: None,
+            selective_search: None,
+            shard: None,
+        };
+        assert!(output_files(&run).is_empty());
+        let path = |algorithm: &str, suffix: &str| {
+            format_output_path(&run.output, &algorithm.into(), &"ef".into(), 0, suffix)
+        };
+        fs::write(path("wand", "bench"), "")?;
+        fs::write(path("maxscore", "bench"), "")?;
+        fs::write(path("maxscore", "results"), "")?;
+        assert_eq!(
+            output_files(&run),
+            vec![
+                path("wand", "bench"),
+                path("maxscore", "results"),
+                path("maxscore", "bench"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_baseline() -> Result<(), Error> {
+        let tmp = TempDir::new("verify").unwrap();
+        let base = tmp.path().join("base");
+        let mut run = Run {
+            collection: String::from("wapo"),
+            kind: RunKind::Benchmark,
+            encodings: vec!["ef".into()],
+            algorithms: vec!["wand".into(), "maxscore".into()],
+            output: tmp.path().join("out"),
+            topics: vec![Topics::Simple {
+                path: tmp.path().join("topics"),
+                qrels: None,
+            }],
+            scorer: Scorer::from("bm25"),
+            compare_with: None,
+            normalize: QueryNormalization::default(),
+            query_options: QueryOptions::default(),
+            per_class: false,
+            class_margins: std::collections::HashMap::new(),
+            per_language: false,
+            export_features: false,
+            perf_counters: false,
+            cold_and_warm: false,
+            load_time: false,
+            query_times: false,
+            min_measures: Default::default(),
+            max_avg_ms: None,
+            max_p95_ms: None,
+            env: Default::default(),
+            warmup: 0,
+            repeats: 1,
+            qps_margin: None,
+            source: None,
+            keep_raw_output: false,
+            timeout: None,
+            selective_search: None,
+            shard: None,
+        };
+        assert!(verify_baseline(&run).is_empty());
+        run.compare_with = Some(base.clone().into());
+        fs::write(
+            format_output_path(&base, &"wand".into(), &"ef".into(), 0, "bench"),
+            r#"{"type":"ef","query":"wand","avg":1,"q50":1,"q90":1,"q95":1}"#,
+        )?;
+        fs::write(
+            format_output_path(&base, &"maxscore".into(), &"ef".into(), 0, "bench"),
+            "{",
+        )?;
+        let problems = verify_baseline(&run);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("base.maxscore.ef.0.bench"));
+
+        run.kind = RunKind::Evaluate {
+            qrels: tmp.path().join("qrels"),
+            min_relevance: None,
+        };
+        fs::write(
+            format_output_path(&base, &"wand".into(), &"ef".into(), 0, "trec_eval"),
+            "map\tall\t0.25\n",
+        )?;
+        let problems = verify_baseline(&run);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("base.maxscore.ef.0.trec_eval"));
+
+        fs::write(tmp.path().join("topics"), "1:hello\n")?;
+        fs::write(tmp.path().join("qrels"), "1 0 d1 1\n")?;
+        BaselineMeta::new(&run, None, RegressionMargin::default())?.write(&base)?;
+        assert_eq!(verify_baseline(&run).len(), 1);
+        fs::write(tmp.path().join("topics"), "1:world\n")?;
+        let problems = verify_baseline(&run);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[1], "topics 0 differ from baseline");
+        let err = compare_with_baseline(&run, &base, RegressionMargin::default())
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("Results not comparable with baseline"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_legacy_baseline() -> Result<(), Error> {
+        let tmp = TempDir::new("verify").unwrap();
+        let base = tmp.path().join("base");
+        let run = Run {
+            collection: String::from("wapo"),
+            kind: RunKind::Benchmark,
+            encodings: vec!["ef".into()],
+            algorithms: vec!["wand".into()],
+            output: tmp.path().join("out"),
+            topics: vec![Topics::Simple {
+                path: tmp.path().join("topics"),
+                qrels: None,
+            }],
+            scorer: Scorer::from("bm25"),
+            compare_with: Some(base.clone().into()),
+            normalize: QueryNormalization::default(),
+            query_options: QueryOptions::default(),
+            per_class: false,
+            class_margins: std::collections::HashMap::new(),
+            per_language: false,
+            export_features: false,
+            perf_counters: false,
+            cold_and_warm: false,
+            load_time: false,
+            query_times: false,
+            min_measures: Default::default(),
+            max_avg_ms: None,
+            max_p95_ms: None,
+            env: Default::default(),
+            warmup: 0,
+            repeats: 1,
+            qps_margin: None,
+            source: None,
+            keep_raw_output: false,
+            timeout: None,
+            selective_search: None,
+            shard: None,
+        };
+        assert_eq!(verify_baseline(&run).len(), 1);
+        fs::write(
+            tmp.path().join("base.wand.ef.bench"),
+            r#"{"type":"ef","query":"wand","avg":1,"q50":1,"q90":1,"q95":1}"#,
+        )?;
+        assert!(verify_baseline(&run).is_empty());
+        Ok(())
     }
 
     #[test]
@@ -377,13 +3955,19 @@ mod tests {
             outputs,
             ..
         } = mock_set_up(&tmp);
-        process_run(&executor, &config.run(2), &config.collection(0), true)?;
+        process_run(
+            &executor,
+            &config.run(2),
+            &config.collection(0),
+            true,
+            tmp.path(),
+        )?;
         let actual = EchoOutput::from(outputs.get("queries").unwrap().as_path());
         let expected = EchoOutput::from(format!(
-            "{0} -t block_simdbp -i {2}.block_simdbp -w {2}.wand -a wand \
+            "{0} -t block_simdbp -i {2}.block_simdbp -w {2}.wand.bm25 -a wand \
              -q {3} --terms {1}.termlex --stemmer porter2 -k 1000 \
              --scorer bm25\n\
-             {0} -t block_simdbp -i {2}.block_simdbp -w {2}.wand -a maxscore \
+             {0} -t block_simdbp -i {2}.block_simdbp -w {2}.wand.bm25 -a maxscore \
              -q {3} --terms {1}.termlex --stemmer porter2 -k 1000 \
              --scorer bm25",
             programs.get("queries").unwrap().display(),
@@ -394,4 +3978,95 @@ mod tests {
         assert_eq!(actual, expected);
         Ok(())
     }
+
+    #[test]
+    fn test_benchmark_query_options() -> Result<(), Error> {
+        let tmp = TempDir::new("run").unwrap();
+        let MockSetup {
+            mut config,
+            executor,
+            programs,
+            outputs,
+            ..
+        } = mock_set_up(&tmp);
+        config.0.runs[2].query_options = QueryOptions {
+            weighted: true,
+            thresholds: Some(tmp.path().join("thresholds")),
+            safe: true,
+            extra_args: vec![String::from("--quantized")],
+        };
+        config.0.runs[2].algorithms.truncate(1);
+        process_run(
+            &executor,
+            &config.run(2),
+            &config.collection(0),
+            true,
+            tmp.path(),
+        )?;
+        let actual = EchoOutput::from(outputs.get("queries").unwrap().as_path());
+        let expected = EchoOutput::from(format!(
+            "{0} -t block_simdbp -i {2}.block_simdbp -w {2}.wand.bm25 -a wand \
+             -q {3} --terms {1}.termlex --stemmer porter2 -k 1000 \
+             --scorer bm25 --weighted --thresholds {4} --safe --quantized",
+            programs.get("queries").unwrap().display(),
+            tmp.path().join("fwd").display(),
+            tmp.path().join("inv").display(),
+            tmp.path().join("topics.title").display(),
+            tmp.path().join("thresholds").display(),
+        ));
+        assert_eq!(actual, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_benchmark_repeats() -> Result<(), Error> {
+        let tmp = TempDir::new("run").unwrap();
+        let MockSetup { mut config, .. } = mock_set_up(&tmp);
+        let backend = Arc::new(FakeBackend::new().with_output(
+            "queries",
+            r#"{"type":"block_simdbp","query":"wand","avg":1,"q50":1,"q90":1,"q95":1}"#,
+        ));
+        let executor = Executor::new().with_backend(backend.clone());
+        config.0.runs[2].algorithms.truncate(1);
+        config.0.runs[2].warmup = 1;
+        config.0.runs[2].repeats = 3;
+        config.0.runs[2].keep_raw_output = true;
+        let path = |suffix: &str| {
+            format_output_path(
+                &config.run(2).output,
+                &"wand".into(),
+                &"block_simdbp".into(),
+                0,
+                suffix,
+            )
+        };
+        fs::write(path(&raw_suffix(3)), "stale")?;
+        process_run(
+            &executor,
+            &config.run(2),
+            &config.collection(0),
+            true,
+            tmp.path(),
+        )?;
+        let queries = backend
+            .programs()
+            .into_iter()
+            .filter(|program| program == "queries")
+            .count();
+        assert_eq!(queries, 4);
+        for repeat in 0..3 {
+            assert_eq!(
+                fs::read_to_string(path(&raw_suffix(repeat)))?,
+                r#"{"type":"block_simdbp","query":"wand","avg":1,"q50":1,"q90":1,"q95":1}"#
+            );
+        }
+        assert!(!path(&raw_suffix(3)).exists());
+        let path = path("bench");
+        let results: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?).unwrap();
+        assert_eq!(results["repeats"], 3);
+        assert_eq!(results["avg"], 1.0);
+        assert_eq!(results["stddev"]["avg"], 0.0);
+        assert!(load_benchmark_results(&path).is_ok());
+        Ok(())
+    }
 }
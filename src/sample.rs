@@ -0,0 +1,216 @@
+//! Deterministic sampling of the documents of a collection, e.g., for studying how
+//! effectiveness or efficiency scale with the size of the collection.
+//!
+//! The input stream of the parser is filtered document by document, keeping those whose
+//! hash, seeded by the configured seed, falls below the sampled fraction. The sample thus
+//! depends only on the content of the documents and the seed, not on their order,
+//! and a sample is contained in any larger sample with the same seed.
+//!
+//! Sharded collections are split the same way, each shard keeping the documents
+//! whose hash modulo the number of shards is its index.
+
+use crate::config::{CollectionKind, DocumentSample};
+use crate::error::Error;
+use crate::identity::{fnv1a, fnv1a_extend};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+
+/// How documents are delimited in the input stream of the parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// Each line is a document.
+    Lines,
+    /// Each document starts with a `<DOC>` line.
+    TrecDocuments,
+    /// Each record starts with a `WARC/<version>` line.
+    Warc,
+}
+
+impl RecordFormat {
+    /// Format of the parser input of collections of `kind`.
+    pub fn of(kind: &CollectionKind) -> Self {
+        match kind {
+            CollectionKind::TrecWeb | CollectionKind::Robust => Self::TrecDocuments,
+            CollectionKind::Warc => Self::Warc,
+            CollectionKind::NewYorkTimes
+            | CollectionKind::WashingtonPost
+            | CollectionKind::Plaintext
+            | CollectionKind::MsMarco
+            | CollectionKind::Jsonl { .. } => Self::Lines,
+        }
+    }
+
+    fn starts_record(self, line: &[u8]) -> bool {
+        match self {
+            Self::Lines => true,
+            Self::TrecDocuments => String::from_utf8_lossy(line)
+                .trim_start()
+                .starts_with("<DOC>"),
+            Self::Warc => line.starts_with(b"WARC/"),
+        }
+    }
+}
+
+/// Returns `true` if `record` belongs to `sample`.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::config::DocumentSample;
+/// # use stdbench::sample::is_sampled;
+/// let half = DocumentSample { fraction: 0.5, seed: 7 };
+/// let all = DocumentSample { fraction: 1.0, seed: 7 };
+/// assert_eq!(is_sampled(b"doc", half), is_sampled(b"doc", half));
+/// assert!(is_sampled(b"doc", all));
+/// ```
+#[allow(clippy::cast_precision_loss)]
+pub fn is_sampled(record: &[u8], sample: DocumentSample) -> bool {
+    let hash = fnv1a_extend(fnv1a(&sample.seed.to_le_bytes()), record);
+    sample.fraction >= 1.0 || (hash as f64) / (u64::MAX as f64) < sample.fraction
+}
+
+/// Partition of the documents of a sharded collection: documents are assigned to shards
+/// by their hash, so that each shard gets a similar share regardless of the input order.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    /// Index of the shard, from 0.
+    pub index: usize,
+    /// Number of shards of the collection.
+    pub count: usize,
+}
+
+impl Shard {
+    /// Returns `true` if `record` belongs to this shard.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::sample::Shard;
+    /// let shards: Vec<_> = (0..4).map(|index| Shard { index, count: 4 }).collect();
+    /// let containing = shards.iter().filter(|shard| shard.contains(b"doc")).count();
+    /// assert_eq!(containing, 1);
+    /// assert!(Shard { index: 0, count: 1 }.contains(b"doc"));
+    /// ```
+    pub fn contains(self, record: &[u8]) -> bool {
+        fnv1a(record) % self.count as u64 == self.index as u64
+    }
+}
+
+/// Copies the documents of `input` that belong to `sample` to `output`,
+/// returning the number of copied documents. Anything preceding the first document
+/// is copied as well.
+pub fn sample_documents<R: BufRead, W: Write>(
+    input: R,
+    output: W,
+    format: RecordFormat,
+    sample: DocumentSample,
+) -> Result<usize, Error> {
+    filter_documents(input, output, format, |record| is_sampled(record, sample))
+}
+
+/// Copies the documents of `input` for which `keep` returns `true` to `output`,
+/// returning the number of copied documents. Anything preceding the first document
+/// is copied as well.
+pub fn filter_documents<R, W, F>(
+    mut input: R,
+    mut output: W,
+    format: RecordFormat,
+    keep: F,
+) -> Result<usize, Error>
+where
+    R: BufRead,
+    W: Write,
+    F: Fn(&[u8]) -> bool,
+{
+    let mut kept = 0;
+    let mut record: Option<Vec<u8>> = None;
+    let mut line = Vec::new();
+    let mut flush = |record: Vec<u8>, output: &mut W| -> Result<(), Error> {
+        if keep(&record) {
+            output.write_all(&record)?;
+            kept += 1;
+        }
+        Ok(())
+    };
+    loop {
+        line.clear();
+        if input.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        if format.starts_record(&line) {
+            if let Some(previous) = record.replace(line.clone()) {
+                flush(previous, &mut output)?;
+            }
+        } else if let Some(record) = &mut record {
+            record.extend_from_slice(&line);
+        } else {
+            output.write_all(&line)?;
+        }
+    }
+    if let Some(record) = record {
+        flush(record, &mut output)?;
+    }
+    output.flush()?;
+    Ok(kept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(input: &str, format: RecordFormat, fraction: f64) -> (String, usize) {
+        let mut output = Vec::new();
+        let kept = sample_documents(
+            input.as_bytes(),
+            &mut output,
+            format,
+            DocumentSample { fraction, seed: 1 },
+        )
+        .unwrap();
+        (String::from_utf8(output).unwrap(), kept)
+    }
+
+    #[test]
+    fn test_sample_documents() {
+        let lines: String = (0..1000).map(|doc| format!("d{} text\n", doc)).collect();
+        let (all, kept) = sample(&lines, RecordFormat::Lines, 1.0);
+        assert_eq!((all.as_str(), kept), (lines.as_str(), 1000));
+        let (tenth, kept) = sample(&lines, RecordFormat::Lines, 0.1);
+        assert!(kept > 50 && kept < 150);
+        assert_eq!(tenth.lines().count(), kept);
+        let (half, _) = sample(&lines, RecordFormat::Lines, 0.5);
+        assert!(tenth
+            .lines()
+            .all(|line| half.lines().any(|kept| kept == line)));
+
+        let docs: String = (0..100)
+            .map(|doc| format!("<DOC>\n<DOCNO>d{}</DOCNO>\ntext\n</DOC>\n", doc))
+            .collect();
+        let (sampled, kept) = sample(&docs, RecordFormat::TrecDocuments, 0.5);
+        assert_eq!(sampled.matches("<DOC>").count(), kept);
+        assert_eq!(sampled.matches("</DOC>").count(), kept);
+
+        let warc = "header\nWARC/1.0\nWARC-Type: warcinfo\n\nWARC/1.0\nWARC-Type: response\n\n";
+        let (sampled, kept) = sample(warc, RecordFormat::Warc, 1.0);
+        assert_eq!((sampled.as_str(), kept), (warc, 2));
+        let (sampled, kept) = sample(warc, RecordFormat::Warc, 1e-9);
+        assert_eq!((sampled.as_str(), kept), ("header\n", 0));
+
+        let mut sizes = Vec::new();
+        let mut total = 0;
+        for index in 0..3 {
+            let shard = Shard { index, count: 3 };
+            let mut output = Vec::new();
+            let kept =
+                filter_documents(lines.as_bytes(), &mut output, RecordFormat::Lines, |doc| {
+                    shard.contains(doc)
+                })
+                .unwrap();
+            assert_eq!(String::from_utf8(output).unwrap().lines().count(), kept);
+            sizes.push(kept);
+            total += kept;
+        }
+        assert_eq!(total, 1000);
+        assert!(sizes.iter().all(|&size| size > 250 && size < 420));
+    }
+}
@@ -0,0 +1,432 @@
+//! Selective search over sharded collections, searching each query only on the shards
+//! chosen for it by a resource-selection algorithm instead of exhaustively on all of them.
+//!
+//! The run on each shard processes all queries, as for exhaustive search. Once the run on
+//! the last shard is done, the results of all shards are merged per query, once over all
+//! shards and once over the shards selected for the query, and both are evaluated with the
+//! qrels of the run. For benchmarks, the latency of a query is aggregated from its times
+//! on the searched shards: summed, as if the shards were searched one after another,
+//! and maximal, as if they were searched in parallel.
+//!
+//! Results are merged by the scores computed within each shard, without normalizing
+//! the statistics of the shards.
+
+use crate::config::{format_output_path, Run, RunKind};
+use crate::error::Error;
+use crate::run::{prepare_run_qrels, write_evaluation};
+use crate::Executor;
+use failure::ResultExt;
+use itertools::iproduct;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// Shards selected for each query, by query ID.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResourceSelection(BTreeMap<String, BTreeSet<usize>>);
+
+impl ResourceSelection {
+    /// Parses the resource selection of a collection with `shards` shards: each line holds
+    /// a query ID followed by the (0-based) indexes of the shards to search for it,
+    /// separated by whitespace. Queries that are not listed are not searched at all.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::selective::ResourceSelection;
+    /// let selection = ResourceSelection::parse("1 0 2\n\n2 1\n", 3).unwrap();
+    /// assert_eq!(selection.shards("1"), vec![0, 2]);
+    /// assert!(selection.selects("2", 1));
+    /// assert!(selection.shards("3").is_empty());
+    /// assert!(ResourceSelection::parse("1 3", 3).is_err());
+    /// assert!(ResourceSelection::parse("1 first", 3).is_err());
+    /// ```
+    pub fn parse(selection: &str, shards: usize) -> Result<Self, Error> {
+        let mut selected: BTreeMap<String, BTreeSet<usize>> = BTreeMap::new();
+        for line in selection.lines().filter(|line| !line.trim().is_empty()) {
+            let mut fields = line.split_whitespace();
+            let qid = fields.next().unwrap_or_default().to_string();
+            let indexes = fields
+                .map(|field| field.parse().ok().filter(|&index| index < shards))
+                .collect::<Option<Vec<usize>>>()
+                .ok_or_else(|| format!("Invalid resource selection line: {}", line))?;
+            selected.entry(qid).or_default().extend(indexes);
+        }
+        Ok(Self(selected))
+    }
+
+    /// Loads the resource selection of a collection with `shards` shards from `path`
+    /// (see [`parse`](#method.parse)).
+    pub fn load(path: &Path, shards: usize) -> Result<Self, Error> {
+        let selection = fs::read_to_string(path).with_context(|_| path.display().to_string())?;
+        Self::parse(&selection, shards)
+    }
+
+    /// Indexes of the shards selected for query `qid`, in increasing order.
+    pub fn shards(&self, qid: &str) -> Vec<usize> {
+        self.0
+            .get(qid)
+            .map_or_else(Vec::new, |shards| shards.iter().copied().collect())
+    }
+
+    /// Returns `true` if `shard` is selected for query `qid`.
+    pub fn selects(&self, qid: &str, shard: usize) -> bool {
+        self.0
+            .get(qid)
+            .is_some_and(|shards| shards.contains(&shard))
+    }
+}
+
+/// Merges the TREC results of the shards, given in the order of their indexes,
+/// keeping for each query the best scored documents of the shards for which `searched`
+/// returns `true`, as many as the most results of a query on any single shard.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::selective::merge_results;
+/// let shards = vec![
+///     String::from("1 Q0 a 1 3.0 R\n1 Q0 b 2 1.0 R\n"),
+///     String::from("1 Q0 c 1 2.0 R\n2 Q0 d 1 1.0 R\n"),
+/// ];
+/// assert_eq!(
+///     merge_results(&shards, |_, _| true).unwrap(),
+///     "1 Q0 a 1 3 R\n1 Q0 c 2 2 R\n2 Q0 d 1 1 R\n"
+/// );
+/// assert_eq!(
+///     merge_results(&shards, |qid, shard| qid == "1" && shard == 0).unwrap(),
+///     "1 Q0 a 1 3 R\n1 Q0 b 2 1 R\n"
+/// );
+/// ```
+pub fn merge_results<F>(shard_results: &[String], searched: F) -> Result<String, Error>
+where
+    F: Fn(&str, usize) -> bool,
+{
+    let mut merged: BTreeMap<String, Vec<(f64, String)>> = BTreeMap::new();
+    let mut depth = 0;
+    let mut tag = String::new();
+    for (shard, results) in shard_results.iter().enumerate() {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for line in results.lines().filter(|line| !line.trim().is_empty()) {
+            match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [qid, _, docid, _, score, run] => {
+                    let count = counts.entry(*qid).or_default();
+                    *count += 1;
+                    depth = depth.max(*count);
+                    if searched(*qid, shard) {
+                        let score = score
+                            .parse()
+                            .map_err(|_| format!("Invalid score in results line: {}", line))?;
+                        merged
+                            .entry((*qid).to_string())
+                            .or_default()
+                            .push((score, (*docid).to_string()));
+                    }
+                    tag = (*run).to_string();
+                }
+                _ => return Err(Error::from(format!("Invalid results line: {}", line))),
+            }
+        }
+    }
+    let mut output = String::new();
+    for (qid, mut documents) in merged {
+        documents.sort_by(|(lscore, ldoc), (rscore, rdoc)| {
+            rscore
+                .partial_cmp(lscore)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| ldoc.cmp(rdoc))
+        });
+        for (rank, (score, docid)) in documents.into_iter().take(depth).enumerate() {
+            output.push_str(&format!(
+                "{} Q0 {} {} {} {}\n",
+                qid,
+                docid,
+                rank + 1,
+                score,
+                tag
+            ));
+        }
+    }
+    Ok(output)
+}
+
+/// Latency of queries searched on one or more shards, in microseconds.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ShardedLatency {
+    /// Average sum of the times of a query on its searched shards,
+    /// as if they were searched one after another.
+    pub avg: f64,
+    /// Average maximum of the times of a query on its searched shards,
+    /// as if they were searched in parallel.
+    pub avg_parallel: f64,
+    /// Average number of searched shards per query.
+    pub avg_shards: f64,
+}
+
+/// Latencies of the same queries searched exhaustively and selectively.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SelectiveLatency {
+    /// Number of shards of the collection.
+    pub shards: usize,
+    /// Number of measured queries.
+    pub queries: usize,
+    /// Searching all shards.
+    pub exhaustive: ShardedLatency,
+    /// Searching the selected shards only.
+    pub selective: ShardedLatency,
+}
+
+fn sharded_latency<F>(
+    shard_times: &[BTreeMap<String, f64>],
+    qids: &BTreeSet<&str>,
+    searched: F,
+) -> ShardedLatency
+where
+    F: Fn(&str, usize) -> bool,
+{
+    let mut total = ShardedLatency::default();
+    for &qid in qids {
+        let times: Vec<f64> = shard_times
+            .iter()
+            .enumerate()
+            .filter(|&(shard, _)| searched(qid, shard))
+            .filter_map(|(_, times)| times.get(qid).copied())
+            .collect();
+        total.avg += times.iter().sum::<f64>();
+        total.avg_parallel += times.iter().copied().fold(0.0, f64::max);
+        #[allow(clippy::cast_precision_loss)]
+        let searched_shards = times.len() as f64;
+        total.avg_shards += searched_shards;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let count = qids.len().max(1) as f64;
+    ShardedLatency {
+        avg: total.avg / count,
+        avg_parallel: total.avg_parallel / count,
+        avg_shards: total.avg_shards / count,
+    }
+}
+
+/// Compares the latencies of exhaustive and selective search, given the average time
+/// of each query on each shard, by query ID, in the order of the shard indexes.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::selective::{latencies, ResourceSelection};
+/// # use std::collections::BTreeMap;
+/// let times = |pairs: &[(&str, f64)]| -> BTreeMap<String, f64> {
+///     pairs.iter().map(|&(qid, time)| (qid.to_string(), time)).collect()
+/// };
+/// let shard_times = vec![times(&[("1", 10.0), ("2", 20.0)]), times(&[("1", 30.0), ("2", 40.0)])];
+/// let selection = ResourceSelection::parse("1 1\n2 0 1\n", 2).unwrap();
+/// let latency = latencies(&shard_times, &selection);
+/// assert_eq!(latency.queries, 2);
+/// assert_eq!(latency.exhaustive.avg, 50.0);
+/// assert_eq!(latency.exhaustive.avg_parallel, 35.0);
+/// assert_eq!(latency.selective.avg, 45.0);
+/// assert_eq!(latency.selective.avg_shards, 1.5);
+/// ```
+pub fn latencies(
+    shard_times: &[BTreeMap<String, f64>],
+    selection: &ResourceSelection,
+) -> SelectiveLatency {
+    let qids: BTreeSet<&str> = shard_times
+        .iter()
+        .flat_map(|times| times.keys().map(String::as_str))
+        .collect();
+    SelectiveLatency {
+        shards: shard_times.len(),
+        queries: qids.len(),
+        exhaustive: sharded_latency(shard_times, &qids, |_, _| true),
+        selective: sharded_latency(shard_times, &qids, |qid, shard| {
+            selection.selects(qid, shard)
+        }),
+    }
+}
+
+/// Average times by query ID from a CSV file written by a run with `query_times: true`.
+fn load_query_times(path: &Path) -> Result<BTreeMap<String, f64>, Error> {
+    let csv = fs::read_to_string(path).with_context(|_| path.display().to_string())?;
+    csv.lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut fields = line.split(',');
+            match (
+                fields.next(),
+                fields.next().and_then(|mean| mean.parse().ok()),
+            ) {
+                (Some(qid), Some(mean)) => Ok((qid.to_string(), mean)),
+                _ => Err(Error::from(format!("Invalid query time: {}", line))),
+            }
+        })
+        .collect()
+}
+
+/// Merges and measures the outputs of the runs on all shards of a collection searched
+/// selectively, given the run on any of its shards, and writes the results next to the
+/// outputs of the shard runs, without the shard suffix:
+/// `exhaustive.results`, `exhaustive.trec_eval`, `selective.results`, and `selective.trec_eval`
+/// for evaluation runs, and `selective.json` for benchmarks.
+///
+/// Does nothing if the run does not search a shard selectively.
+pub(crate) fn process(executor: &Executor, run: &Run, temp_dir: &Path) -> Result<(), Error> {
+    let (selective, count) = match (&run.selective_search, run.shard) {
+        (Some(selective), Some(shard)) => (selective, shard.count),
+        _ => return Ok(()),
+    };
+    let selection = ResourceSelection::load(&selective.resource_selection, count)?;
+    let unsharded = run.unsharded();
+    let shard_runs: Vec<Run> = (0..count)
+        .map(|index| unsharded.shard(index, count))
+        .collect();
+    let qrels = match run.kind {
+        RunKind::Evaluate { .. } => prepare_run_qrels(run, temp_dir)?,
+        _ => vec![],
+    };
+    for (algorithm, encoding, tid) in
+        iproduct!(&run.algorithms, &run.encodings, 0..run.topics.len())
+    {
+        let output = |run: &Run, suffix: &str| {
+            format_output_path(&run.output, algorithm, encoding, tid, suffix)
+        };
+        match &run.kind {
+            RunKind::Evaluate { min_relevance, .. } => {
+                let results = shard_runs
+                    .iter()
+                    .map(|run| {
+                        let path = output(run, "results");
+                        fs::read_to_string(&path)
+                            .with_context(|_| path.display().to_string())
+                            .map_err(Error::from)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let merged = vec![
+                    ("exhaustive", merge_results(&results, |_, _| true)?),
+                    (
+                        "selective",
+                        merge_results(&results, |qid, shard| selection.selects(qid, shard))?,
+                    ),
+                ];
+                for (search, results) in merged {
+                    let trec_eval_path = output(&unsharded, &format!("{}.trec_eval", search));
+                    write_evaluation(
+                        executor,
+                        &results,
+                        &output(&unsharded, &format!("{}.results", search)),
+                        &trec_eval_path,
+                        &qrels[tid],
+                        *min_relevance,
+                        None,
+                    )?;
+                    info!(
+                        "[{}] [{}] [{}] Evaluated {} search: {}",
+                        unsharded.collection,
+                        encoding,
+                        algorithm,
+                        search,
+                        trec_eval_path.display()
+                    );
+                }
+            }
+            RunKind::Benchmark => {
+                let times = shard_runs
+                    .iter()
+                    .map(|run| load_query_times(&output(run, "query_times.csv")))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let latency = latencies(&times, &selection);
+                info!(
+                    "[{}] [{}] [{}] Searched {:.1} of {} shards: {:.1} (exhaustive: {:.1})",
+                    unsharded.collection,
+                    encoding,
+                    algorithm,
+                    latency.selective.avg_shards,
+                    count,
+                    latency.selective.avg,
+                    latency.exhaustive.avg
+                );
+                fs::write(
+                    output(&unsharded, "selective.json"),
+                    serde_json::to_string(&latency).context("Failed to serialize")?,
+                )?;
+            }
+            RunKind::External { .. } => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{SelectiveSearch, Topics};
+    use crate::run::QUERY_TIMES_CSV_HEADER;
+    use crate::tests::{mock_set_up, MockSetup};
+    use crate::Config;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_merge_results_truncates() -> Result<(), Error> {
+        let shards = vec![
+            String::from("1 Q0 a 1 3.5 R\n1 Q0 b 2 1.0 R\n"),
+            String::from("1 Q0 c 1 2.0 R\n1 Q0 d 2 0.5 R\n"),
+        ];
+        assert_eq!(
+            merge_results(&shards, |_, _| true)?,
+            "1 Q0 a 1 3.5 R\n1 Q0 c 2 2 R\n"
+        );
+        assert!(merge_results(&[String::from("1 Q0 a 1 R")], |_, _| true).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_benchmark() -> Result<(), Error> {
+        let tmp = TempDir::new("selective").unwrap();
+        let MockSetup {
+            config, executor, ..
+        } = mock_set_up(&tmp);
+        fs::write(tmp.path().join("selection"), "1 0\n2 0 1\n")?;
+        let mut run = config.run(2).clone();
+        run.query_times = true;
+        run.topics = vec![Topics::Simple {
+            path: tmp.path().join("topics"),
+            qrels: None,
+        }];
+        run.selective_search = Some(SelectiveSearch {
+            resource_selection: tmp.path().join("selection"),
+        });
+        let shards: Vec<Run> = (0..2).map(|index| run.shard(index, 2)).collect();
+        for (shard, times) in shards
+            .iter()
+            .zip(&["1,10,9,11\n2,20,20,20\n", "1,30,30,30\n2,40,40,40\n"])
+        {
+            for (algorithm, encoding) in iproduct!(&run.algorithms, &run.encodings) {
+                fs::write(
+                    format_output_path(&shard.output, algorithm, encoding, 0, "query_times.csv"),
+                    format!("{}\n{}", QUERY_TIMES_CSV_HEADER, times),
+                )?;
+            }
+        }
+        assert_eq!(shards[1].unsharded(), run);
+        process(&executor, &shards[1], tmp.path())?;
+        let latency: SelectiveLatency =
+            serde_json::from_str(&fs::read_to_string(format_output_path(
+                &run.output,
+                &run.algorithms[0],
+                &run.encodings[0],
+                0,
+                "selective.json",
+            ))?)
+            .unwrap();
+        assert_eq!(latency.queries, 2);
+        assert_eq!(latency.exhaustive.avg, 50.0);
+        assert_eq!(latency.selective.avg, 35.0);
+        assert_eq!(latency.selective.avg_parallel, 25.0);
+        assert_eq!(latency.selective.avg_shards, 1.5);
+        Ok(())
+    }
+}
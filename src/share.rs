@@ -0,0 +1,203 @@
+//! Sharing of identical index artifacts through hard links.
+//!
+//! Compressed indexes and WAND data are registered in a content-addressed store
+//! in the work dir, `shared/<hash>-<size>`, which holds a hard link to each distinct
+//! artifact. An artifact identical to one already in the store is replaced with another
+//! link to it, so that it is stored once regardless of how many collections use it.
+//!
+//! A stored file is referenced as long as it has other links than the one in the store,
+//! so removing artifacts, e.g., with `gc`, releases the space of the last reference only.
+//! Before an artifact is rebuilt, its link is removed so that the tools never write
+//! through a shared file (see [`unshare`](fn.unshare.html)).
+
+use crate::config::Collection;
+use crate::error::Error;
+use crate::identity::{fnv1a, fnv1a_extend};
+use failure::ResultExt;
+use log::info;
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Directory within the work dir storing links to shared artifacts.
+pub const SHARED_DIR: &str = "shared";
+
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Hashes the content of the file at `path` in chunks.
+pub(crate) fn file_hash(path: &Path) -> Result<u64, Error> {
+    let mut file = File::open(path).with_context(|_| path.display().to_string())?;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut hash = fnv1a(&[]);
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hash);
+        }
+        hash = fnv1a_extend(hash, &buffer[..read]);
+    }
+}
+
+/// Reads from `reader` until `buffer` is full or the end is reached,
+/// returning the number of bytes read.
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize, Error> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        let read = reader.read(&mut buffer[filled..])?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    Ok(filled)
+}
+
+/// Returns `true` if the files at `first` and `second` have the same content.
+fn same_content(first: &Path, second: &Path) -> Result<bool, Error> {
+    let mut first = File::open(first)?;
+    let mut second = File::open(second)?;
+    let mut first_buffer = vec![0; CHUNK_SIZE];
+    let mut second_buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let first_read = read_chunk(&mut first, &mut first_buffer)?;
+        let second_read = read_chunk(&mut second, &mut second_buffer)?;
+        if first_buffer[..first_read] != second_buffer[..second_read] {
+            return Ok(false);
+        }
+        if first_read == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Returns `true` if `first` and `second` are links to the same file.
+fn same_file(first: &fs::Metadata, second: &fs::Metadata) -> bool {
+    first.dev() == second.dev() && first.ino() == second.ino()
+}
+
+/// Registers the artifact at `path` in the store of `workdir`, replacing it with
+/// a link to an identical stored file if there is one.
+///
+/// Returns the number of bytes saved, i.e., the size of the artifact if it was replaced.
+pub fn share(workdir: &Path, path: &Path) -> Result<u64, Error> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => return Ok(0),
+    };
+    let stored =
+        workdir
+            .join(SHARED_DIR)
+            .join(format!("{:016x}-{}", file_hash(path)?, metadata.len()));
+    match fs::metadata(&stored) {
+        Ok(stored_metadata) => {
+            if same_file(&metadata, &stored_metadata) || !same_content(path, &stored)? {
+                return Ok(0);
+            }
+            fs::remove_file(path)?;
+            fs::hard_link(&stored, path)
+                .with_context(|_| format!("Failed to link {}", path.display()))?;
+            Ok(metadata.len())
+        }
+        Err(_) => {
+            fs::create_dir_all(workdir.join(SHARED_DIR))?;
+            fs::hard_link(path, &stored)
+                .with_context(|_| format!("Failed to link {}", stored.display()))?;
+            Ok(0)
+        }
+    }
+}
+
+/// Shares the compressed indexes and the WAND data of `collection`,
+/// returning the number of bytes saved.
+pub fn share_collection(workdir: &Path, collection: &Collection) -> Result<u64, Error> {
+    let mut saved = 0;
+    for path in collection
+        .compressed_indexes()
+        .into_iter()
+        .map(|(encoding, scorer)| collection.scored_index(encoding, scorer))
+        .chain(collection.wands())
+    {
+        saved += share(workdir, &path)?;
+    }
+    if saved > 0 {
+        info!(
+            "[{}] [build] Shared index artifacts, saving {} bytes",
+            collection.name, saved
+        );
+    }
+    Ok(saved)
+}
+
+/// Removes the artifact at `path` if it is linked elsewhere, e.g., before it is rebuilt,
+/// so that other links keep the previous content.
+pub fn unshare(path: &Path) -> Result<(), Error> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if metadata.is_file() && metadata.nlink() > 1 {
+            fs::remove_file(path).with_context(|_| path.display().to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists the stored files of `workdir` that are not referenced by any artifact
+/// once the artifacts at `removed` are removed.
+pub fn unreferenced(workdir: &Path, removed: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let dir = workdir.join(SHARED_DIR);
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let removed = removed
+        .iter()
+        .filter_map(|path| fs::symlink_metadata(path).ok())
+        .collect::<Vec<_>>();
+    let mut unreferenced = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let metadata = fs::symlink_metadata(&path)?;
+        let removed_links = removed
+            .iter()
+            .filter(|removed| same_file(removed, &metadata))
+            .count();
+        if metadata.is_file() && metadata.nlink() == 1 + removed_links as u64 {
+            unreferenced.push(path);
+        }
+    }
+    unreferenced.sort();
+    Ok(unreferenced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_share() -> Result<(), Error> {
+        let tmp = TempDir::new("share").unwrap();
+        let first = tmp.path().join("wapo.block_simdbp");
+        let second = tmp.path().join("wapo2.block_simdbp");
+        let other = tmp.path().join("wapo2.wand");
+        fs::write(&first, "index")?;
+        fs::write(&second, "index")?;
+        fs::write(&other, "wands")?;
+        assert_eq!(share(tmp.path(), &first)?, 0);
+        assert_eq!(share(tmp.path(), &second)?, 5);
+        assert_eq!(share(tmp.path(), &second)?, 0);
+        assert_eq!(share(tmp.path(), &other)?, 0);
+        assert_eq!(fs::metadata(&first)?.nlink(), 3);
+        assert_eq!(fs::read_to_string(&second)?, "index");
+        assert_eq!(share(tmp.path(), &tmp.path().join("missing"))?, 0);
+        assert!(unreferenced(tmp.path(), &[])?.is_empty());
+        assert_eq!(unreferenced(tmp.path(), &[other.clone()])?.len(), 1);
+
+        unshare(&second)?;
+        assert!(!second.exists());
+        assert_eq!(fs::metadata(&first)?.nlink(), 2);
+        fs::remove_file(&other)?;
+        let unreferenced = unreferenced(tmp.path(), &[])?;
+        assert_eq!(unreferenced.len(), 1);
+        assert_eq!(fs::read_to_string(&unreferenced[0])?, "wands");
+        Ok(())
+    }
+}
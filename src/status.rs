@@ -0,0 +1,122 @@
+//! Execution state of a session, served as JSON over HTTP for dashboards
+//! polling long-running benchmarks.
+//!
+//! The endpoint listens on the loopback interface only, and responds to any request
+//! with the current [`Progress`](struct.Progress.html).
+
+use crate::config::Stage;
+use crate::error::Error;
+use crate::summary::RunSummary;
+use failure::ResultExt;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Current state of a session.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Progress {
+    /// Stage being executed, or `None` before the first and after the last stage.
+    pub stage: Option<Stage>,
+    /// Index of the run being processed, if any.
+    #[serde(default)]
+    pub current_run: Option<usize>,
+    /// Runs in the order of the config, with the outcomes and output files of those
+    /// that have already finished.
+    #[serde(default)]
+    pub runs: Vec<RunSummary>,
+    /// Number of warnings so far.
+    #[serde(default)]
+    pub warnings: usize,
+    /// Whether the session has finished.
+    #[serde(default)]
+    pub finished: bool,
+}
+
+/// Progress shared between the session and the endpoint.
+#[derive(Debug, Clone, Default)]
+pub struct SharedProgress(Arc<Mutex<Progress>>);
+
+impl SharedProgress {
+    /// Modifies the progress with `update`.
+    pub fn update<F: FnOnce(&mut Progress)>(&self, update: F) {
+        update(&mut self.0.lock().unwrap());
+    }
+
+    /// Returns a snapshot of the progress.
+    pub fn get(&self) -> Progress {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Serializes the progress as JSON.
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(&self.get()).context("Failed to serialize progress")?)
+    }
+}
+
+/// Reads (and ignores) the request head, responding with the progress.
+fn respond(mut stream: TcpStream, progress: &SharedProgress) -> Result<(), Error> {
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && !line.trim().is_empty() {
+        line.clear();
+    }
+    let body = progress.to_json()?;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+/// Starts serving `progress` on `localhost:port` in a background thread,
+/// which lives as long as the process. Returns the bound port, which is chosen
+/// by the system if `port` is 0.
+pub fn serve(port: u16, progress: SharedProgress) -> Result<u16, Error> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .with_context(|_| format!("Failed to bind status port {}", port))?;
+    let port = listener.local_addr()?.port();
+    info!("Serving status at: http://localhost:{}/", port);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Err(err) = stream
+                .map_err(Error::from)
+                .and_then(|stream| respond(stream, &progress))
+            {
+                warn!("Failed to serve status: {}", err);
+            }
+        }
+    });
+    Ok(port)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_serve_progress() -> Result<(), Error> {
+        let progress = SharedProgress::default();
+        let port = serve(0, progress.clone())?;
+        progress.update(|progress| {
+            progress.stage = Some(Stage::Run);
+            progress.current_run = Some(1);
+        });
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))?;
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let served: Progress = serde_json::from_str(body).unwrap();
+        assert_eq!(served, progress.get());
+        assert_eq!(served.stage, Some(Stage::Run));
+        Ok(())
+    }
+}
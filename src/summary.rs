@@ -0,0 +1,499 @@
+//! Summary of a benchmark session, written to `summary.json` in the work directory.
+
+use crate::config::{Config, Run};
+use crate::ensure_parent_exists;
+use crate::error::Error;
+use failure::ResultExt;
+use lazy_static::lazy_static;
+use log::warn;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use strum_macros::Display;
+
+/// Name of the summary file in the work directory.
+pub const SUMMARY_FILE: &str = "summary.json";
+
+/// Name of the status badge file in the work directory.
+pub const BADGE_FILE: &str = "badge.json";
+
+/// Placeholder replacing the work directory in redacted reports.
+pub const WORKDIR_PLACEHOLDER: &str = "$WORKDIR";
+
+/// Placeholder replacing the directory of any other absolute path in redacted reports.
+pub const REDACTED_PLACEHOLDER: &str = "<redacted>";
+
+/// Replaces absolute paths in `text` with stable placeholders, so that reports
+/// can be shared without leaking directory structures or user names.
+///
+/// Paths within `workdir` are kept relative to [`WORKDIR_PLACEHOLDER`](constant.WORKDIR_PLACEHOLDER.html),
+/// while only the file name of any other path is kept.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::summary::redact_paths;
+/// # use std::path::Path;
+/// assert_eq!(
+///     redact_paths(
+///         "Topics not found: /home/user/topics.txt (in /bench/work/runs)",
+///         Path::new("/bench/work")
+///     ),
+///     "Topics not found: <redacted>/topics.txt (in $WORKDIR/runs)"
+/// );
+/// assert_eq!(
+///     redact_paths("git https://github.com/pisa-engine/pisa.git (master)", Path::new("/w")),
+///     "git https://github.com/pisa-engine/pisa.git (master)"
+/// );
+/// ```
+pub fn redact_paths(text: &str, workdir: &Path) -> String {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r#"(^|[\s(=])(/[^\s,;()"']*)"#).unwrap();
+    }
+    RE.replace_all(text, |caps: &Captures<'_>| {
+        let path = Path::new(&caps[2]);
+        let redacted = if let Ok(relative) = path.strip_prefix(workdir) {
+            if relative.as_os_str().is_empty() {
+                Path::new(WORKDIR_PLACEHOLDER).to_path_buf()
+            } else {
+                Path::new(WORKDIR_PLACEHOLDER).join(relative)
+            }
+        } else {
+            path.file_name().map_or_else(
+                || Path::new(REDACTED_PLACEHOLDER).to_path_buf(),
+                |name| Path::new(REDACTED_PLACEHOLDER).join(name),
+            )
+        };
+        format!("{}{}", &caps[1], redacted.display())
+    })
+    .to_string()
+}
+
+fn redact_path(path: &Path, workdir: &Path) -> PathBuf {
+    PathBuf::from(redact_paths(&path.display().to_string(), workdir))
+}
+
+/// Category of a non-fatal warning.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[serde(rename_all = "kebab-case")]
+pub enum WarningKind {
+    /// A stage was suppressed.
+    #[strum(serialize = "suppressed-stage")]
+    SuppressedStage,
+    /// A run was skipped because its collection is not defined.
+    #[strum(serialize = "skipped-collection")]
+    SkippedCollection,
+    /// A config key was not recognized and ignored.
+    #[strum(serialize = "unknown-config-key")]
+    UnknownConfigKey,
+    /// Many query terms are missing from the collection lexicon.
+    #[strum(serialize = "out-of-vocabulary")]
+    OutOfVocabulary,
+    /// A comparison with baseline was defined but not executed.
+    #[strum(serialize = "ignored-comparison")]
+    IgnoredComparison,
+    /// The work directory exceeded its size budget.
+    #[strum(serialize = "workdir-budget")]
+    WorkdirBudget,
+    /// CPU settings of the host make latencies unstable.
+    #[strum(serialize = "measurement-environment")]
+    MeasurementEnvironment,
+    /// The config was adjusted to the version of the PISA tools, or it could not be verified.
+    #[strum(serialize = "pisa-version")]
+    PisaVersion,
+}
+
+/// Non-fatal problem that should be brought to the user's attention.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Warning {
+    /// Warning category.
+    pub kind: WarningKind,
+    /// Description of the problem.
+    pub message: String,
+}
+
+impl Warning {
+    /// Constructs a new warning.
+    pub fn new<S: Into<String>>(kind: WarningKind, message: S) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.kind, self.message)
+    }
+}
+
+/// Resolved configuration of a benchmark session, making reports interpretable standalone.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ConfigInfo {
+    /// Source of the PISA tools.
+    pub source: String,
+    /// Commit of the source code, if built from a Git repository.
+    #[serde(default)]
+    pub commit: Option<String>,
+    /// Collection names mapped to their encodings.
+    pub collections: BTreeMap<String, Vec<String>>,
+    /// Query processing algorithms used in runs.
+    pub algorithms: Vec<String>,
+    /// Performance regression margin.
+    pub margin: f32,
+}
+
+impl ConfigInfo {
+    /// Collects the information from a config.
+    ///
+    /// The commit is read from the local repository of a Git source, if it exists.
+    pub fn from_config<C: Config>(config: &C) -> Self {
+        let commit = config
+            .source()
+            .git_dir(config.workdir())
+            .and_then(|dir| git2::Repository::open(dir).ok())
+            .and_then(|repo| {
+                repo.head()
+                    .ok()
+                    .and_then(|head| head.peel_to_commit().ok())
+                    .map(|commit| commit.id().to_string())
+            });
+        let mut algorithms: Vec<String> = Vec::new();
+        for algorithm in config.runs().iter().flat_map(|run| &run.algorithms) {
+            let algorithm = algorithm.to_string();
+            if !algorithms.contains(&algorithm) {
+                algorithms.push(algorithm);
+            }
+        }
+        Self {
+            source: config.source().to_string(),
+            commit,
+            collections: config
+                .collections()
+                .iter()
+                .map(|c| {
+                    let encodings = c.encodings.iter().map(ToString::to_string).collect();
+                    (c.name.clone(), encodings)
+                })
+                .collect(),
+            algorithms,
+            margin: config.margin().0,
+        }
+    }
+
+    /// Renders the configuration as a Markdown list.
+    pub fn render_markdown(&self) -> String {
+        let mut lines = vec![format!("- source: {}", self.source)];
+        if let Some(commit) = &self.commit {
+            lines.push(format!("- commit: {}", commit));
+        }
+        for (name, encodings) in &self.collections {
+            lines.push(format!("- collection {}: {}", name, encodings.join(", ")));
+        }
+        lines.push(format!("- algorithms: {}", self.algorithms.join(", ")));
+        lines.push(format!("- margin: {}", self.margin));
+        lines.push(String::new());
+        lines.join("\n")
+    }
+}
+
+/// What happened to a run in a session.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Display)]
+#[serde(rename_all = "kebab-case")]
+pub enum RunOutcome {
+    /// The run was not executed, e.g., because its stage was suppressed.
+    #[default]
+    #[strum(serialize = "skipped")]
+    Skipped,
+    /// The run was completed in a previous session (see `--resume`).
+    #[strum(serialize = "resumed")]
+    Resumed,
+    /// The run was executed in this session.
+    #[strum(serialize = "completed")]
+    Completed,
+    /// The run was executing when the session was interrupted (see `--resume`).
+    #[strum(serialize = "interrupted")]
+    Interrupted,
+}
+
+/// Outcome of a single run of a session.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RunSummary {
+    /// Collection name.
+    pub collection: String,
+    /// Basename of the output files.
+    pub output: PathBuf,
+    /// What happened to the run.
+    pub outcome: RunOutcome,
+    /// Wall-clock time (in seconds) of executing the run, if completed in this session.
+    #[serde(default)]
+    pub seconds: Option<f64>,
+    /// Existing output files of the run.
+    #[serde(default)]
+    pub files: Vec<PathBuf>,
+    /// Number of regressions found when comparing with the baseline and thresholds.
+    #[serde(default)]
+    pub regressions: usize,
+}
+
+impl RunSummary {
+    /// Creates a summary of a run that has not been executed (yet).
+    pub fn new(run: &Run) -> Self {
+        Self {
+            collection: run.collection.clone(),
+            output: run.output.clone(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Summary of a benchmark session.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Summary {
+    /// Resolved configuration of the session.
+    #[serde(default)]
+    pub config: Option<ConfigInfo>,
+    /// Warnings collected during the session.
+    pub warnings: Vec<Warning>,
+    /// Durations (in seconds) of the build steps of each collection,
+    /// e.g., `parse` or `compress.block_simdbp` (see [`BuildTimes`](../build/struct.BuildTimes.html)).
+    #[serde(default)]
+    pub build_times: BTreeMap<String, BTreeMap<String, f64>>,
+    /// Runs in the order of the config.
+    #[serde(default)]
+    pub runs: Vec<RunSummary>,
+    /// Number of build steps slower than in the baseline.
+    #[serde(default)]
+    pub build_time_regressions: usize,
+    /// Total number of regressions, including those of build times.
+    #[serde(default)]
+    pub regressions: usize,
+    /// The session was interrupted, so that some of its runs did not execute.
+    #[serde(default)]
+    pub interrupted: bool,
+}
+
+impl Summary {
+    /// Records a warning, also logging it immediately.
+    pub fn warn<S: Into<String>>(&mut self, kind: WarningKind, message: S) {
+        self.push(Warning::new(kind, message));
+    }
+
+    /// Records an already constructed warning, also logging it immediately.
+    pub fn push(&mut self, warning: Warning) {
+        warn!("{}", warning);
+        self.warnings.push(warning);
+    }
+
+    /// Writes the summary to `dir/summary.json`.
+    pub fn write(&self, dir: &Path) -> Result<(), Error> {
+        fs::create_dir_all(dir)?;
+        self.write_file(&dir.join(SUMMARY_FILE))
+    }
+
+    /// Writes the summary to `path`, e.g., one passed with `--summary`.
+    pub fn write_file(&self, path: &Path) -> Result<(), Error> {
+        ensure_parent_exists(path)?;
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize summary")?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Returns a copy of the summary with absolute paths redacted (see [`redact_paths`](fn.redact_paths.html)).
+    pub fn redacted(&self, workdir: &Path) -> Self {
+        Self {
+            config: self.config.as_ref().map(|config| ConfigInfo {
+                source: redact_paths(&config.source, workdir),
+                ..config.clone()
+            }),
+            warnings: self
+                .warnings
+                .iter()
+                .map(|w| Warning::new(w.kind, redact_paths(&w.message, workdir)))
+                .collect(),
+            build_times: self.build_times.clone(),
+            runs: self
+                .runs
+                .iter()
+                .map(|run| RunSummary {
+                    output: redact_path(&run.output, workdir),
+                    files: run
+                        .files
+                        .iter()
+                        .map(|file| redact_path(file, workdir))
+                        .collect(),
+                    ..run.clone()
+                })
+                .collect(),
+            ..*self
+        }
+    }
+
+    /// Reads the summary from `dir/summary.json`.
+    pub fn read(dir: &Path) -> Result<Self, Error> {
+        let path = dir.join(SUMMARY_FILE);
+        let file = fs::File::open(&path).with_context(|_| path.display().to_string())?;
+        Ok(serde_json::from_reader(file).context("Failed to parse summary")?)
+    }
+}
+
+/// Status of a session in the [shields.io endpoint](https://shields.io/endpoint) format,
+/// for displaying a badge, e.g., in a repository README.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::summary::{Badge, Summary};
+/// let summary = Summary { regressions: 3, ..Summary::default() };
+/// assert_eq!(
+///     serde_json::to_string(&Badge::from_summary(&summary)).unwrap(),
+///     r#"{"schemaVersion":1,"label":"benchmark","message":"3 regressions","color":"red"}"#
+/// );
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Badge {
+    /// Version of the endpoint schema, always 1.
+    pub schema_version: u8,
+    /// Left-hand side text.
+    pub label: String,
+    /// Right-hand side text: `passing`, or the number of regressions.
+    pub message: String,
+    /// Color of the right-hand side.
+    pub color: String,
+}
+
+impl Badge {
+    /// Creates the badge of a finished session.
+    pub fn from_summary(summary: &Summary) -> Self {
+        let (message, color) = match summary.regressions {
+            0 => (String::from("passing"), "brightgreen"),
+            1 => (String::from("1 regression"), "red"),
+            count => (format!("{} regressions", count), "red"),
+        };
+        Self {
+            schema_version: 1,
+            label: String::from("benchmark"),
+            message,
+            color: String::from(color),
+        }
+    }
+
+    /// Writes the badge to `path`.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        ensure_parent_exists(path)?;
+        let json = serde_json::to_string(self).context("Failed to serialize badge")?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{mock_set_up, MockSetup};
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_write_summary() -> Result<(), Error> {
+        let tmp = TempDir::new("summary").unwrap();
+        let mut summary = Summary::default();
+        summary.warn(WarningKind::SuppressedStage, "Stage suppressed: compile");
+        assert_eq!(
+            summary.warnings[0].to_string(),
+            "[suppressed-stage] Stage suppressed: compile"
+        );
+        summary.write(tmp.path())?;
+        let written: Summary =
+            serde_json::from_str(&fs::read_to_string(tmp.path().join(SUMMARY_FILE))?).unwrap();
+        assert_eq!(written, summary);
+        assert_eq!(Summary::read(tmp.path())?, summary);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_badge() -> Result<(), Error> {
+        let tmp = TempDir::new("summary").unwrap();
+        let path = tmp.path().join(BADGE_FILE);
+        Badge::from_summary(&Summary::default()).write(&path)?;
+        let badge: Badge = serde_json::from_str(&fs::read_to_string(&path)?).unwrap();
+        assert_eq!(badge.message, "passing");
+        assert_eq!(badge.color, "brightgreen");
+        let summary = Summary {
+            regressions: 1,
+            ..Summary::default()
+        };
+        assert_eq!(Badge::from_summary(&summary).message, "1 regression");
+        Ok(())
+    }
+
+    #[test]
+    fn test_redacted_summary() {
+        let workdir = Path::new("/home/user/bench");
+        let mut summary = Summary {
+            config: Some(ConfigInfo {
+                source: String::from("path /home/user/bench/pisa/bin"),
+                ..ConfigInfo::default()
+            }),
+            ..Summary::default()
+        };
+        summary.warn(
+            WarningKind::IgnoredComparison,
+            "Build times not available for: /home/user/bench",
+        );
+        summary.runs.push(RunSummary {
+            output: workdir.join("runs/eval"),
+            files: vec![workdir.join("runs/eval.wand.ef.0.trec_eval")],
+            outcome: RunOutcome::Completed,
+            regressions: 2,
+            ..RunSummary::default()
+        });
+        summary.regressions = 2;
+        let redacted = summary.redacted(workdir);
+        assert_eq!(
+            redacted.runs[0],
+            RunSummary {
+                output: PathBuf::from("$WORKDIR/runs/eval"),
+                files: vec![PathBuf::from("$WORKDIR/runs/eval.wand.ef.0.trec_eval")],
+                ..summary.runs[0].clone()
+            }
+        );
+        assert_eq!(redacted.regressions, 2);
+        assert_eq!(redacted.config.unwrap().source, "path $WORKDIR/pisa/bin");
+        assert_eq!(
+            redacted.warnings[0].message,
+            "Build times not available for: $WORKDIR"
+        );
+    }
+
+    #[test]
+    fn test_config_info() {
+        let tmp = TempDir::new("summary").unwrap();
+        let MockSetup { config, .. } = mock_set_up(&tmp);
+        let info = ConfigInfo::from_config(&config);
+        assert_eq!(
+            info.source,
+            format!("path {}", tmp.path().join("bin").display())
+        );
+        assert_eq!(info.commit, None);
+        assert_eq!(
+            info.collections.get("wapo"),
+            Some(&vec![
+                String::from("block_simdbp"),
+                String::from("block_qmx")
+            ])
+        );
+        assert_eq!(info.algorithms, vec!["wand", "maxscore"]);
+        let markdown = info.render_markdown();
+        assert!(markdown.starts_with(&format!(
+            "- source: path {}\n",
+            tmp.path().join("bin").display()
+        )));
+        assert!(markdown.contains("- collection wapo: block_simdbp, block_qmx\n"));
+        assert!(markdown.ends_with("- algorithms: wand, maxscore\n- margin: 0.02\n"));
+    }
+}
@@ -0,0 +1,124 @@
+//! Management of intermediate files produced during runs.
+
+use crate::config::Run;
+use crate::error::Error;
+use log::info;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the directory in the work directory storing intermediate files.
+pub const TEMP_DIR: &str = "tmp";
+
+/// Directory `workdir/tmp/<run-id>/` storing intermediate files of a single run,
+/// such as extracted topics or normalized queries.
+#[derive(Debug)]
+pub struct RunTempDir {
+    path: PathBuf,
+}
+
+/// Identifier of a run, unique within a config, used to name its temporary directory.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::temp::run_id;
+/// # use stdbench::{Run, RunKind};
+/// let run = Run {
+///     collection: String::from("wapo"),
+///     kind: RunKind::Benchmark,
+///     encodings: vec![],
+///     algorithms: vec![],
+///     output: "runs/wapo.bench".into(),
+///     topics: vec![],
+///     scorer: "bm25".into(),
+///     compare_with: None,
+///     normalize: Default::default(),
+///     query_options: Default::default(),
+///     per_class: false,
+///     class_margins: Default::default(),
+///     per_language: false,
+///     export_features: false,
+///     perf_counters: false,
+///     cold_and_warm: false,
+///     load_time: false,
+///     query_times: false,
+///     min_measures: Default::default(),
+///     max_avg_ms: None,
+///     max_p95_ms: None,
+///     env: Default::default(),
+///     warmup: 0,
+///     repeats: 1,
+///     qps_margin: None,
+///     source: None,
+///     keep_raw_output: false,
+///     timeout: None,
+///     selective_search: None,
+///     shard: None,
+/// };
+/// assert_eq!(run_id(3, &run), "3-wapo.bench");
+/// ```
+pub fn run_id(idx: usize, run: &Run) -> String {
+    match run.output.file_name() {
+        Some(name) => format!("{}-{}", idx, name.to_string_lossy()),
+        None => idx.to_string(),
+    }
+}
+
+impl RunTempDir {
+    /// Creates an empty temporary directory for a run, removing any leftovers
+    /// of a previous session.
+    pub fn create(workdir: &Path, run_id: &str) -> Result<Self, Error> {
+        let path = Self::planned_path(workdir, run_id);
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+        }
+        fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+
+    /// Path to the directory of a run, without creating it.
+    pub fn planned_path(workdir: &Path, run_id: &str) -> PathBuf {
+        workdir.join(TEMP_DIR).join(run_id)
+    }
+
+    /// Path to the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Removes the directory, unless the run failed and `keep` is set,
+    /// in which case it is retained for debugging.
+    pub fn finish(self, failed: bool, keep: bool) -> Result<(), Error> {
+        if failed && keep {
+            info!("Temporary files retained in {}", self.path.display());
+        } else {
+            fs::remove_dir_all(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_run_temp_dir() -> Result<(), Error> {
+        let tmp = TempDir::new("temp").unwrap();
+        let dir = RunTempDir::create(tmp.path(), "0-run")?;
+        assert_eq!(dir.path(), tmp.path().join("tmp/0-run"));
+        fs::write(dir.path().join("topics.title"), "1:query")?;
+        dir.finish(false, true)?;
+        assert!(!tmp.path().join("tmp/0-run").exists());
+
+        let dir = RunTempDir::create(tmp.path(), "0-run")?;
+        dir.finish(true, true)?;
+        assert!(tmp.path().join("tmp/0-run").exists());
+
+        let dir = RunTempDir::create(tmp.path(), "0-run")?;
+        dir.finish(true, false)?;
+        assert!(!tmp.path().join("tmp/0-run").exists());
+        Ok(())
+    }
+}
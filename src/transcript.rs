@@ -0,0 +1,387 @@
+//! Transcripts of all commands executed by PISA tools, allowing a single stage
+//! of a session to be re-executed in isolation, e.g., to reproduce a failure.
+
+use crate::checkpoint::STATE_DIR;
+use crate::config::Stage;
+use crate::ensure_parent_exists;
+use crate::error::Error;
+use crate::executor::{killed, ExecBackend, Executor, Invocation, ResourceUsage};
+use failure::ResultExt;
+use log::info;
+use os_pipe::pipe;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+
+/// Single command recorded in a [`Transcript`](struct.Transcript.html).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Stage to which the command belongs.
+    pub stage: Stage,
+    /// Program, as resolved by the executor.
+    pub program: PathBuf,
+    /// Command line arguments, including all input and output paths.
+    pub args: Vec<String>,
+    /// Working directory, if different from the one of the session.
+    #[serde(default)]
+    pub current_dir: Option<PathBuf>,
+    /// Environment variables set in addition to the ones of the session.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Index of the entry to whose standard input the standard output is piped, if any.
+    #[serde(default)]
+    pub stdout_to: Option<usize>,
+    /// Exit code, or `None` if the command was killed, is still running,
+    /// or its output is piped.
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+}
+
+/// Commands executed in a session, along with the environment in which they were executed.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Transcript {
+    /// Working directory of the session.
+    pub current_dir: PathBuf,
+    /// Environment variables of the session.
+    pub env: BTreeMap<String, String>,
+    /// Commands in the order of execution.
+    pub entries: Vec<Entry>,
+}
+
+fn lossy(value: &OsStr) -> String {
+    value.to_string_lossy().to_string()
+}
+
+impl Transcript {
+    /// Path to the transcript within `workdir`.
+    pub fn path(workdir: &Path) -> PathBuf {
+        workdir.join(STATE_DIR).join("transcript.json")
+    }
+
+    /// Creates an empty transcript of the current process environment.
+    pub fn current() -> Result<Self, Error> {
+        Ok(Self {
+            current_dir: env::current_dir()?,
+            env: env::vars_os()
+                .map(|(key, value)| (lossy(&key), lossy(&value)))
+                .collect(),
+            entries: vec![],
+        })
+    }
+
+    /// Loads a transcript from `path`.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path).with_context(|_| path.display().to_string())?;
+        Ok(serde_json::from_reader(file).context("Failed to parse transcript")?)
+    }
+
+    /// Rebuilds the command of `entry`, in the environment of the transcript only.
+    pub fn command(&self, entry: &Entry) -> Command {
+        let mut command = Command::new(&entry.program);
+        command
+            .args(&entry.args)
+            .env_clear()
+            .envs(&self.env)
+            .envs(&entry.env)
+            .current_dir(entry.current_dir.as_ref().unwrap_or(&self.current_dir));
+        command
+    }
+
+    fn record(&mut self, command: &Command, tool_overrides: &BTreeMap<String, PathBuf>) -> usize {
+        self.entries.push(Entry {
+            stage: Invocation::stage_of(command, tool_overrides),
+            program: PathBuf::from(command.get_program()),
+            args: command.get_args().map(lossy).collect(),
+            current_dir: command.get_current_dir().map(Path::to_path_buf),
+            env: command
+                .get_envs()
+                .filter_map(|(key, value)| value.map(|value| (lossy(key), lossy(value))))
+                .collect(),
+            stdout_to: None,
+            exit_code: None,
+        });
+        self.entries.len() - 1
+    }
+}
+
+/// Backend recording all commands in a transcript file before delegating them to another backend.
+///
+/// The transcript is saved before each command is started, so that it is available
+/// even if the session is killed, and again when the command finishes.
+#[derive(Debug)]
+pub struct TranscriptBackend {
+    path: PathBuf,
+    inner: Arc<dyn ExecBackend>,
+    transcript: Mutex<Transcript>,
+    /// Spawned commands whose output is piped to the next command of the same thread.
+    pipes: Mutex<HashMap<ThreadId, usize>>,
+    /// Tools replaced by other binaries, whose commands are recorded in the stages of the tools.
+    tool_overrides: BTreeMap<String, PathBuf>,
+}
+
+impl TranscriptBackend {
+    /// Opens the transcript in `workdir`. If `resume` is `true`, new commands are appended
+    /// to the transcript of the previous session (if any); otherwise, it is discarded.
+    pub fn open(workdir: &Path, resume: bool, inner: Arc<dyn ExecBackend>) -> Result<Self, Error> {
+        let path = Transcript::path(workdir);
+        let transcript = if resume && path.exists() {
+            Transcript::load(&path)?
+        } else {
+            Transcript::current()?
+        };
+        let backend = Self {
+            path,
+            inner,
+            transcript: Mutex::new(transcript),
+            pipes: Mutex::new(HashMap::new()),
+            tool_overrides: BTreeMap::new(),
+        };
+        backend.save(&backend.transcript())?;
+        Ok(backend)
+    }
+
+    /// Sets the tools replaced by other binaries, as in the executor, so that their commands
+    /// are recorded in the stages of the tools they replace.
+    pub fn with_tool_overrides(self, tool_overrides: BTreeMap<String, PathBuf>) -> Self {
+        Self {
+            tool_overrides,
+            ..self
+        }
+    }
+
+    /// The transcript stays consistent even if another thread panicked while holding the lock,
+    /// since it is only modified by single insertions and updates.
+    fn transcript(&self) -> MutexGuard<'_, Transcript> {
+        self.transcript
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn save(&self, transcript: &Transcript) -> Result<(), Error> {
+        ensure_parent_exists(&self.path)?;
+        let json =
+            serde_json::to_string_pretty(transcript).context("Failed to serialize transcript")?;
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    fn start(&self, command: &Command, piped: bool) -> io::Result<usize> {
+        let mut transcript = self.transcript();
+        let idx = transcript.record(command, &self.tool_overrides);
+        let mut sources = self.pipes.lock().unwrap_or_else(PoisonError::into_inner);
+        if piped {
+            sources.insert(thread::current().id(), idx);
+        } else if let Some(source) = sources.remove(&thread::current().id()) {
+            transcript.entries[source].stdout_to = Some(idx);
+        }
+        self.save(&transcript).map_err(|err| to_io(&err))?;
+        Ok(idx)
+    }
+
+    fn finish(&self, idx: usize, status: ExitStatus) -> io::Result<()> {
+        let mut transcript = self.transcript();
+        transcript.entries[idx].exit_code = status.code();
+        self.save(&transcript).map_err(|err| to_io(&err))
+    }
+}
+
+fn to_io(err: &Error) -> io::Error {
+    io::Error::other(err.to_string())
+}
+
+impl ExecBackend for TranscriptBackend {
+    fn spawn(&self, command: &mut Command) -> io::Result<()> {
+        self.start(command, true)?;
+        self.inner.spawn(command)
+    }
+
+    fn output(&self, command: &mut Command) -> io::Result<Output> {
+        let idx = self.start(command, false)?;
+        let output = self.inner.output(command)?;
+        self.finish(idx, output.status)?;
+        Ok(output)
+    }
+
+    fn status(&self, command: &mut Command) -> io::Result<ExitStatus> {
+        let idx = self.start(command, false)?;
+        let status = self.inner.status(command)?;
+        self.finish(idx, status)?;
+        Ok(status)
+    }
+
+    fn output_with_usage(
+        &self,
+        command: &mut Command,
+        timeout: Option<Duration>,
+    ) -> io::Result<(Output, Option<ResourceUsage>)> {
+        let idx = self.start(command, false)?;
+        let (output, usage) = self.inner.output_with_usage(command, timeout)?;
+        self.finish(idx, output.status)?;
+        Ok((output, usage))
+    }
+
+    fn status_with_usage(
+        &self,
+        command: &mut Command,
+        timeout: Option<Duration>,
+    ) -> io::Result<(ExitStatus, Option<ResourceUsage>)> {
+        let idx = self.start(command, false)?;
+        let (status, usage) = self.inner.status_with_usage(command, timeout)?;
+        self.finish(idx, status)?;
+        Ok((status, usage))
+    }
+}
+
+/// Re-executes all commands of `stage` recorded in the transcript at `path`, in their
+/// original order and environment, piping the output of commands that were piped before.
+pub fn replay(path: &Path, stage: Stage, executor: &Executor) -> Result<(), Error> {
+    let transcript = Transcript::load(path)?;
+    let entries: Vec<(usize, &Entry)> = transcript
+        .entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.stage == stage)
+        .collect();
+    if entries.is_empty() {
+        return Err(Error::from(format!(
+            "No commands of stage {} in transcript: {}",
+            stage,
+            path.display()
+        )));
+    }
+    let mut readers = HashMap::new();
+    for (idx, entry) in entries {
+        let program = entry.program.display().to_string();
+        let mut command = transcript.command(entry);
+        if let Some(reader) = readers.remove(&idx) {
+            command.stdin(reader);
+        }
+        if let Some(target) = entry.stdout_to {
+            let (reader, writer) = pipe()?;
+            executor.spawn(command.stdout(writer))?;
+            readers.insert(target, reader);
+            continue;
+        }
+        let status = executor
+            .status(&mut command)
+            .with_context(|_| format!("Failed to execute: {}", program))?;
+        if let Some(err) = killed(&program, status) {
+            return Err(err);
+        }
+        if !status.success() {
+            return Err(Error::from(format!(
+                "Replayed command failed: {} (exit code: {}, recorded: {})",
+                program,
+                status.code().unwrap_or_default(),
+                entry
+                    .exit_code
+                    .map_or_else(|| String::from("none"), |code| code.to_string())
+            )));
+        }
+        info!("Replayed: {}", program);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::FakeBackend;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_transcript() -> Result<(), Error> {
+        let tmp = TempDir::new("transcript").unwrap();
+        let recorded = Arc::new(FakeBackend::new().with_failure("create_wand_data", 2, ""));
+        let backend = Arc::new(TranscriptBackend::open(
+            tmp.path(),
+            false,
+            recorded.clone(),
+        )?);
+        let executor = Executor::new().with_backend(backend);
+        executor.spawn(Command::new("cat").arg("input"))?;
+        executor.status(executor.command("parse_collection").args(&["-o", "fwd"]))?;
+        executor.invert("fwd", "inv", 10, 100, None)?;
+        executor.compress("inv", "inv.block_simdbp", &"block_simdbp".into(), None)?;
+        assert!(executor
+            .create_wand_data("inv", "inv.wand", None, false, None)
+            .is_err());
+
+        let transcript = Transcript::load(&Transcript::path(tmp.path()))?;
+        assert_eq!(transcript.current_dir, env::current_dir()?);
+        assert_eq!(
+            transcript
+                .entries
+                .iter()
+                .map(|entry| (entry.stage, entry.stdout_to, entry.exit_code))
+                .collect::<Vec<_>>(),
+            vec![
+                (Stage::Parse, Some(1), None),
+                (Stage::Parse, None, Some(0)),
+                (Stage::Invert, None, Some(0)),
+                (Stage::Compress, None, Some(0)),
+                (Stage::Wand, None, Some(2)),
+            ]
+        );
+
+        let replayed = Arc::new(FakeBackend::new());
+        let executor = Executor::new().with_backend(replayed.clone());
+        replay(&Transcript::path(tmp.path()), Stage::Parse, &executor)?;
+        replay(&Transcript::path(tmp.path()), Stage::Compress, &executor)?;
+        assert_eq!(
+            replayed.invocations(),
+            recorded.invocations()[..2]
+                .iter()
+                .chain(&recorded.invocations()[3..4])
+                .cloned()
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            replay(&Transcript::path(tmp.path()), Stage::Join, &executor),
+            Err(Error::from(format!(
+                "No commands of stage join in transcript: {}",
+                Transcript::path(tmp.path()).display()
+            )))
+        );
+        let executor = Executor::new()
+            .with_backend(Arc::new(FakeBackend::new().with_failure("invert", 1, "")));
+        assert_eq!(
+            replay(&Transcript::path(tmp.path()), Stage::Invert, &executor),
+            Err(Error::from(
+                "Replayed command failed: invert (exit code: 1, recorded: 0)"
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resume_transcript() -> Result<(), Error> {
+        let tmp = TempDir::new("transcript").unwrap();
+        let open = |resume: bool| -> Result<Executor, Error> {
+            Ok(
+                Executor::new().with_backend(Arc::new(TranscriptBackend::open(
+                    tmp.path(),
+                    resume,
+                    Arc::new(FakeBackend::new()),
+                )?)),
+            )
+        };
+        open(false)?.invert("fwd", "inv", 10, 100, None)?;
+        open(true)?.compress("inv", "inv.ef", &"ef".into(), None)?;
+        let path = Transcript::path(tmp.path());
+        assert_eq!(Transcript::load(&path)?.entries.len(), 2);
+        open(false)?;
+        assert!(Transcript::load(&path)?.entries.is_empty());
+        Ok(())
+    }
+}
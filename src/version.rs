@@ -0,0 +1,239 @@
+//! Detection of the version of PISA tools, and compatibility of configs with it.
+//!
+//! The version is reported by the tools themselves with `--version` if they support it,
+//! and is otherwise derived from the closest tag of the local repository of a git source,
+//! e.g., `v0.8.2` for any commit following it. Sessions refuse to run tools older than
+//! `min_pisa_version`, and run tools that do not support `--scorer` without it.
+
+use crate::config::{RawConfig, Source};
+use crate::error::Error;
+use crate::executor::Executor;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Release version of PISA.
+///
+/// # Examples
+///
+/// ```
+/// # use stdbench::version::PisaVersion;
+/// let version: PisaVersion = "v0.8".parse().unwrap();
+/// assert_eq!(version, PisaVersion { major: 0, minor: 8, patch: 0 });
+/// assert_eq!(version.to_string(), "0.8.0");
+/// assert!(version < "0.8.2".parse().unwrap());
+/// assert!("0.8.x".parse::<PisaVersion>().is_err());
+/// ```
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PisaVersion {
+    /// Major version.
+    pub major: u32,
+    /// Minor version.
+    pub minor: u32,
+    /// Patch version.
+    pub patch: u32,
+}
+
+/// First version of PISA whose tools accept `--scorer`.
+pub const SCORER_VERSION: PisaVersion = PisaVersion {
+    major: 0,
+    minor: 8,
+    patch: 0,
+};
+
+impl PisaVersion {
+    /// Finds the first version number in `text`, e.g., the output of `--version` or a tag.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use stdbench::version::PisaVersion;
+    /// let version = |major, minor, patch| Some(PisaVersion { major, minor, patch });
+    /// assert_eq!(PisaVersion::find("v0.8.2-14-g1a2b3c4"), version(0, 8, 2));
+    /// assert_eq!(PisaVersion::find("PISA 1.0\n"), version(1, 0, 0));
+    /// assert_eq!(PisaVersion::find("unknown option"), None);
+    /// ```
+    pub fn find(text: &str) -> Option<Self> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"\bv?(\d+)\.(\d+)(?:\.(\d+))?").unwrap();
+        }
+        let captures = RE.captures(text)?;
+        let number = |idx| captures.get(idx).map_or(Ok(0), |n| n.as_str().parse());
+        Some(Self {
+            major: number(1).ok()?,
+            minor: number(2).ok()?,
+            patch: number(3).ok()?,
+        })
+    }
+}
+
+impl FromStr for PisaVersion {
+    type Err = Error;
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::from(format!("Invalid PISA version: {}", version));
+        let trimmed = version.trim();
+        let numbers = trimmed
+            .strip_prefix('v')
+            .unwrap_or(trimmed)
+            .split('.')
+            .map(str::parse)
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|_| invalid())?;
+        match numbers.as_slice() {
+            [major, minor] => Ok(Self {
+                major: *major,
+                minor: *minor,
+                patch: 0,
+            }),
+            [major, minor, patch] => Ok(Self {
+                major: *major,
+                minor: *minor,
+                patch: *patch,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl TryFrom<String> for PisaVersion {
+    type Error = Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for PisaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl From<PisaVersion> for String {
+    fn from(version: PisaVersion) -> Self {
+        version.to_string()
+    }
+}
+
+/// Detects the version of the tools of `executor`, compiled from `source`,
+/// or returns `None` if it cannot be determined.
+pub fn detect(executor: &Executor, source: &Source, workdir: &Path) -> Option<PisaVersion> {
+    executor.tool_version("queries").or_else(|| {
+        let repo = git2::Repository::open(source.git_dir(workdir)?).ok()?;
+        let described = repo
+            .describe(git2::DescribeOptions::new().describe_tags())
+            .ok()?;
+        PisaVersion::find(&described.format(None).ok()?)
+    })
+}
+
+/// Adjusts `config` to the detected `version` of its tools, returning warnings
+/// about the adjustments.
+///
+/// Fails if the version is older than `min_pisa_version`, or if it does not support
+/// quantized collections, and disables `--scorer` if the tools do not support it yet. If the version is unknown, nothing is adjusted.
+pub fn check_compatibility(
+    config: &mut RawConfig,
+    version: Option<PisaVersion>,
+) -> Result<Vec<String>, Error> {
+    let mut warnings = Vec::new();
+    let version = match (version, config.min_pisa_version) {
+        (Some(version), Some(min)) if version < min => {
+            return Err(Error::from(format!(
+                "PISA version {} is older than the required {}",
+                version, min
+            )));
+        }
+        (Some(version), _) => version,
+        (None, Some(min)) => {
+            warnings.push(format!(
+                "Unable to detect PISA version to verify it is at least {}",
+                min
+            ));
+            return Ok(warnings);
+        }
+        (None, None) => return Ok(warnings),
+    };
+    if version < SCORER_VERSION {
+        if let Some(collection) = config.collections.iter().find(|c| c.quantize) {
+            return Err(Error::from(format!(
+                "PISA version {} does not support quantized collection: {}",
+                version, collection.name
+            )));
+        }
+    }
+    if version < SCORER_VERSION && config.use_scorer {
+        config.use_scorer = false;
+        warnings.push(format!(
+            "PISA version {} does not support --scorer, running without it",
+            version
+        ));
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FakeBackend;
+    use failure::ResultExt;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_check_compatibility() -> Result<(), Error> {
+        let version = |text: &str| text.parse::<PisaVersion>();
+        let mut config = RawConfig {
+            use_scorer: true,
+            min_pisa_version: Some(version("0.7")?),
+            ..RawConfig::default()
+        };
+        assert!(check_compatibility(&mut config, Some(version("0.8.2")?))?.is_empty());
+        assert!(config.use_scorer);
+        assert_eq!(check_compatibility(&mut config, None)?.len(), 1);
+        assert!(config.use_scorer);
+        assert_eq!(
+            check_compatibility(&mut config, Some(version("0.7.1")?))?,
+            vec!["PISA version 0.7.1 does not support --scorer, running without it"]
+        );
+        assert!(!config.use_scorer);
+        assert_eq!(
+            check_compatibility(&mut config, Some(version("0.6")?)),
+            Err(Error::from(
+                "PISA version 0.6.0 is older than the required 0.7.0"
+            ))
+        );
+        config.collections.push(
+            serde_yaml::from_str(
+                "name: robust\nkind: robust\nfwd_index: fwd\ninv_index: inv\nquantize: true",
+            )
+            .context("Invalid collection")?,
+        );
+        assert_eq!(
+            check_compatibility(&mut config, Some(version("0.7.1")?)),
+            Err(Error::from(
+                "PISA version 0.7.1 does not support quantized collection: robust"
+            ))
+        );
+
+        let config: RawConfig =
+            serde_yaml::from_str("workdir: /tmp\ncollections: []\nmin_pisa_version: v0.8.1")
+                .context("Invalid config")?;
+        assert_eq!(config.min_pisa_version, Some(version("0.8.1")?));
+
+        let executor = Executor::new().with_backend(Arc::new(
+            FakeBackend::new().with_output("queries", "queries v0.9.1\n"),
+        ));
+        assert_eq!(
+            detect(&executor, &Source::System, Path::new(".")),
+            Some(version("0.9.1")?)
+        );
+        let executor = Executor::new()
+            .with_backend(Arc::new(FakeBackend::new().with_failure("queries", 1, "")));
+        assert_eq!(detect(&executor, &Source::System, Path::new(".")), None);
+        Ok(())
+    }
+}